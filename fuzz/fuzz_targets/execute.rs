@@ -0,0 +1,47 @@
+#![no_main]
+
+// Reads whatever parses for a while, answering Coadjuvancy with anything after the first NUL.
+// Catastrophes are how a spellbook is meant to go wrong, so only other panics are crashes
+
+use libfuzzer_sys::fuzz_target;
+use spellbook::errors::SpellbookError;
+use spellbook::interpreter::Interpreter;
+use spellbook::program::Program;
+use spellbook::syntax::{self, StatementKind};
+
+use std::io::{self, Cursor};
+use std::panic;
+use std::sync::Once;
+
+const MAX_STATEMENTS: usize = 10000;
+
+static QUIET_CATASTROPHES: Once = Once::new();
+
+fuzz_target!(|data: &str| {
+	QUIET_CATASTROPHES.call_once(|| {
+		let fuzzer_hook = panic::take_hook();
+		panic::set_hook(Box::new(move |info| {
+			if !info.payload().is::<usize>() && !info.payload().is::<SpellbookError>() {
+				fuzzer_hook(info);
+			}
+		}));
+	});
+
+	let (source, input) = data.split_once('\0').unwrap_or((data, ""));
+	let book = syntax::parse_source(source);
+
+	// Publishing to a file would write wherever the fuzzer thinks to name
+	if book.lines.iter().flat_map(|l| l.statements()).any(|s| matches!(s.kind, StatementKind::Publish(Some(_)))) {
+		return;
+	}
+
+	let mut program = Program::new(false);
+	program.input = Box::new(Cursor::new(input.to_string()));
+	program.output = Box::new(io::sink());
+	let mut interpreter = Interpreter::new(book, program);
+	for _ in 0..MAX_STATEMENTS {
+		if interpreter.is_finished() || interpreter.try_step(Interpreter::step_statement).is_err() {
+			break;
+		}
+	}
+});
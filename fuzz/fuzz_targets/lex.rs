@@ -0,0 +1,11 @@
+#![no_main]
+
+// Any text at all should come out as lines that parse or lines with a syntax error
+
+use libfuzzer_sys::fuzz_target;
+use spellbook::{formatter, syntax};
+
+fuzz_target!(|source: &str| {
+	syntax::parse_source(source);
+	formatter::format_source(source);
+});
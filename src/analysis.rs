@@ -0,0 +1,1130 @@
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+//        SPELLBOOK INTERPRETER
+//           BY DIANE SPARKS
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+
+use crate::cauldron::CauldronMixMode;
+use crate::constants::*;
+use crate::page::Page;
+use crate::syntax::*;
+use crate::variant::Variant;
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+// Past these sizes the analysis gives up on tracking things exactly
+const MAX_TRACKED_VALUES: usize = 16;
+const MAX_TRACKED_CONTROLS: usize = 64;
+const MAX_TRACKED_DRAWER: usize = 16;
+
+pub type StatementId = (usize, usize);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Tri {
+	No,
+	Yes,
+	Maybe,
+}
+
+impl Tri {
+	pub fn join(self, other: Tri) -> Tri {
+		if self == other { self } else { Tri::Maybe }
+	}
+
+	pub fn possible(self) -> bool {
+		self != Tri::No
+	}
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Possible {
+	Values(BTreeSet<usize>),
+	Any,
+}
+
+impl Possible {
+	pub fn one(value: usize) -> Self {
+		Possible::Values(std::iter::once(value).collect())
+	}
+
+	fn widened(values: BTreeSet<usize>) -> Self {
+		if values.len() > MAX_TRACKED_VALUES {
+			Possible::Any
+		} else {
+			Possible::Values(values)
+		}
+	}
+
+	pub fn join(&self, other: &Possible) -> Possible {
+		match (self, other) {
+			(Possible::Values(a), Possible::Values(b)) => Possible::widened(a.union(b).cloned().collect()),
+			_ => Possible::Any,
+		}
+	}
+
+	pub fn map(&self, f: impl Fn(usize) -> usize) -> Possible {
+		match self {
+			Possible::Values(values) => Possible::widened(values.iter().map(|v| f(*v)).collect()),
+			Possible::Any => Possible::Any,
+		}
+	}
+
+	pub fn contains(&self, value: usize) -> bool {
+		match self {
+			Possible::Values(values) => values.contains(&value),
+			Possible::Any => true,
+		}
+	}
+
+}
+
+impl fmt::Display for Possible {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Possible::Values(values) if values.len() == 1 => write!(f, "{}", values.iter().next().unwrap()),
+			Possible::Values(values) => write!(f, "{{{}}}", values.iter().map(|v| v.to_string()).collect::<Vec<String>>().join(", ")),
+			Possible::Any => write!(f, "unknown"),
+		}
+	}
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct SlotFacts {
+	pub fill: Tri,
+	// The statements that could have written this slot, if they can be told apart
+	pub writers: Option<BTreeSet<StatementId>>,
+}
+
+impl SlotFacts {
+	fn empty() -> Self {
+		Self{ fill: Tri::No, writers: Some(BTreeSet::new()) }
+	}
+
+	fn written_by(id: StatementId) -> Self {
+		Self{ fill: Tri::Yes, writers: Some(std::iter::once(id).collect()) }
+	}
+
+	fn join(&self, other: &SlotFacts) -> SlotFacts {
+		SlotFacts{
+			fill: self.fill.join(other.fill),
+			writers: match (&self.writers, &other.writers) {
+				(Some(a), Some(b)) => Some(a.union(b).cloned().collect()),
+				_ => None,
+			},
+		}
+	}
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct PageFacts {
+	pub slots: [SlotFacts; 3],
+	pub write_index: Possible,
+	pub signed: Tri,
+}
+
+impl PageFacts {
+	fn blank() -> Self {
+		Self{
+			slots: [SlotFacts::empty(), SlotFacts::empty(), SlotFacts::empty()],
+			write_index: Possible::one(0),
+			signed: Tri::No,
+		}
+	}
+
+	fn join(&self, other: &PageFacts) -> PageFacts {
+		PageFacts{
+			slots: [self.slots[0].join(&other.slots[0]), self.slots[1].join(&other.slots[1]), self.slots[2].join(&other.slots[2])],
+			write_index: self.write_index.join(&other.write_index),
+			signed: self.signed.join(other.signed),
+		}
+	}
+
+	pub fn full(&self) -> Tri {
+		if self.slots.iter().all(|s| s.fill == Tri::Yes) {
+			Tri::Yes
+		} else if self.slots.iter().any(|s| s.fill == Tri::No) {
+			Tri::No
+		} else {
+			Tri::Maybe
+		}
+	}
+
+	fn filled_count(&self) -> Possible {
+		let certain = self.slots.iter().filter(|s| s.fill == Tri::Yes).count();
+		let maybe = self.slots.iter().filter(|s| s.fill == Tri::Maybe).count();
+		Possible::Values((certain..=certain + maybe).collect())
+	}
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct CauldronFacts {
+	pub holds: Tri,
+	// Which chapters the page in the cauldron could have come from, which decides its type
+	pub chapters: BTreeSet<usize>,
+	pub page: PageFacts,
+}
+
+impl CauldronFacts {
+	fn empty() -> Self {
+		Self{ holds: Tri::No, chapters: BTreeSet::new(), page: PageFacts::blank() }
+	}
+
+	fn join(&self, other: &CauldronFacts) -> CauldronFacts {
+		let page = match (self.holds, other.holds) {
+			(Tri::No, _) => other.page.clone(),
+			(_, Tri::No) => self.page.clone(),
+			_ => self.page.join(&other.page),
+		};
+
+		CauldronFacts{
+			holds: self.holds.join(other.holds),
+			chapters: self.chapters.union(&other.chapters).cloned().collect(),
+			page,
+		}
+	}
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct DrawerFacts {
+	pub depth: Possible,
+	// The pages in the drawer, bottom first, while it's still clear which ones they are
+	pub pages: Option<Vec<PageFacts>>,
+}
+
+impl DrawerFacts {
+	fn join(&self, other: &DrawerFacts) -> DrawerFacts {
+		let pages = match (&self.pages, &other.pages) {
+			(Some(a), Some(b)) if a.len() == b.len() => Some(a.iter().zip(b).map(|(x, y)| x.join(y)).collect()),
+			_ => None,
+		};
+
+		DrawerFacts{ depth: self.depth.join(&other.depth), pages }
+	}
+}
+
+// Everything that decides where the reader goes next, kept exactly
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Control {
+	pub cursor: usize,
+	pub charge: usize,
+	pub amplifier: usize,
+	pub amplifies_in_a_row: usize,
+	pub stack: Vec<(usize, usize)>,
+}
+
+// What casting a spell did to the reader's place
+pub enum Moved {
+	Stayed,
+	// Judgement, which skips ahead by this much if the cauldron says no
+	MightSkip(usize),
+	JumpedBack(usize),
+	Overloaded,
+	Nowhere(usize),
+}
+
+impl Control {
+	pub fn cast(&mut self, spell: &str) -> Moved {
+		if spell != "Amplify" && spell != "Vacancy" {
+			self.amplifies_in_a_row = 0;
+		}
+
+		match spell {
+			"Amplify" => {
+				self.amplifier += 1;
+				self.amplifies_in_a_row += 1;
+				if self.amplifies_in_a_row > 3 {
+					return Moved::Overloaded;
+				}
+			},
+			"Squelch" => {
+				self.charge = 0;
+				self.amplifier = 1;
+			},
+			"Diminish" => {
+				self.charge = self.charge.saturating_sub(1);
+			},
+			"Reverberate" => {
+				let charge = self.charge;
+				// There's nothing before the first line to go back to
+				let entry = match self.stack.get(charge) {
+					Some(entry) if entry.0 > 0 => *entry,
+					_ => return Moved::Nowhere(charge),
+				};
+
+				self.cursor = entry.0 - 1;
+				while self.stack.len() > charge + 1 {
+					let amplifier = self.stack.last().unwrap().1;
+					self.charge = self.charge.saturating_sub(amplifier);
+					self.stack.remove(charge + 1);
+				}
+
+				self.amplifier = 1;
+				return Moved::JumpedBack(charge);
+			},
+			_ => {
+				let charge = self.charge;
+				self.stack.push((self.cursor, self.amplifier));
+				self.charge += self.amplifier;
+				if spell == "Judgement" && charge > 0 {
+					return Moved::MightSkip(charge);
+				}
+			},
+		}
+
+		Moved::Stayed
+	}
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct DeskFacts {
+	// None stands for not having turned to any chapter yet
+	pub chapters: BTreeSet<Option<usize>>,
+	pub pages: [PageFacts; 4],
+	pub cauldron: CauldronFacts,
+	pub drawer: DrawerFacts,
+	pub memory: Tri,
+	// How the cauldron could be mixing, which lasts even when it's knocked over
+	pub mixes: BTreeSet<CauldronMixMode>,
+	pub controls: Option<BTreeSet<Control>>,
+}
+
+impl DeskFacts {
+	fn new() -> Self {
+		Self{
+			chapters: std::iter::once(None).collect(),
+			pages: [PageFacts::blank(), PageFacts::blank(), PageFacts::blank(), PageFacts::blank()],
+			cauldron: CauldronFacts::empty(),
+			drawer: DrawerFacts{ depth: Possible::one(0), pages: Some(vec![]) },
+			memory: Tri::No,
+			mixes: std::iter::once(CauldronMixMode::Add).collect(),
+			controls: Some(std::iter::once(Control{ cursor: 0, charge: 0, amplifier: 1, amplifies_in_a_row: 0, stack: vec![] }).collect()),
+		}
+	}
+
+	fn join(&self, other: &DeskFacts) -> DeskFacts {
+		let controls = match (&self.controls, &other.controls) {
+			(Some(a), Some(b)) => {
+				let union = a.union(b).cloned().collect::<BTreeSet<Control>>();
+				if union.len() > MAX_TRACKED_CONTROLS { None } else { Some(union) }
+			},
+			_ => None,
+		};
+
+		DeskFacts{
+			chapters: self.chapters.union(&other.chapters).cloned().collect(),
+			pages: [self.pages[0].join(&other.pages[0]), self.pages[1].join(&other.pages[1]), self.pages[2].join(&other.pages[2]), self.pages[3].join(&other.pages[3])],
+			cauldron: self.cauldron.join(&other.cauldron),
+			drawer: self.drawer.join(&other.drawer),
+			memory: self.memory.join(other.memory),
+			mixes: self.mixes.union(&other.mixes).cloned().collect(),
+			controls,
+		}
+	}
+
+	pub fn charge(&self) -> Possible {
+		match &self.controls {
+			Some(controls) => Possible::widened(controls.iter().map(|c| c.charge).collect()),
+			None => Possible::Any,
+		}
+	}
+
+	fn with_chapter(&self, chapter: Option<usize>) -> DeskFacts {
+		let mut desk = self.clone();
+		desk.chapters = std::iter::once(chapter).collect();
+		desk
+	}
+}
+
+fn describe_page(page: &PageFacts) -> String {
+	let slots = page.slots.iter().map(|s| match s.fill {
+		Tri::No => "empty",
+		Tri::Yes => "filled",
+		Tri::Maybe => "maybe filled",
+	}).collect::<Vec<&str>>().join(", ");
+
+	match page.signed {
+		Tri::No => format!("[{}]", slots),
+		Tri::Yes => format!("[{}], signed", slots),
+		Tri::Maybe => format!("[{}], maybe signed", slots),
+	}
+}
+
+impl fmt::Display for DeskFacts {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let chapters = self.chapters.iter().map(|c| match c {
+			Some(chapter) => BUILTINS_CHAPTERS[*chapter],
+			None => "(none yet)",
+		}).collect::<Vec<&str>>().join(" or ");
+		writeln!(f, "turned to: {}", chapters)?;
+
+		for (i, page) in self.pages.iter().enumerate() {
+			writeln!(f, "{}: {}", BUILTINS_CHAPTERS[i], describe_page(page))?;
+		}
+
+		let from = self.cauldron.chapters.iter().map(|c| BUILTINS_CHAPTERS[*c]).collect::<Vec<&str>>().join(" or ");
+		match self.cauldron.holds {
+			Tri::No => writeln!(f, "cauldron: empty")?,
+			Tri::Yes => writeln!(f, "cauldron: {} chapter {}", from, describe_page(&self.cauldron.page))?,
+			Tri::Maybe => writeln!(f, "cauldron: maybe {} chapter {}", from, describe_page(&self.cauldron.page))?,
+		}
+
+		writeln!(f, "drawer: {} chapter(s)", self.drawer.depth)?;
+		writeln!(f, "memory: {}", match self.memory {
+			Tri::No => "nothing",
+			Tri::Yes => "something",
+			Tri::Maybe => "maybe something",
+		})?;
+		write!(f, "charge: {}", self.charge())
+	}
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EdgeKind {
+	Fallthrough,
+	Skip,
+	JumpBack,
+	Flip,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Target {
+	Line(usize),
+	End,
+	Catastrophe,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Edge {
+	pub from: StatementId,
+	pub to: Target,
+	pub kind: EdgeKind,
+	pub charge: Option<usize>,
+	// Set when the charge wasn't known, so this is only one of the places the reader might go
+	pub dynamic: bool,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Problem {
+	pub id: StatementId,
+	pub rule: &'static str,
+	pub message: String,
+	pub certain: bool,
+}
+
+pub struct Analysis {
+	pub line_entry: Vec<Option<DeskFacts>>,
+	pub before: Vec<Vec<Option<DeskFacts>>>,
+	pub edges: Vec<Edge>,
+	pub problems: Vec<Problem>,
+}
+
+impl Analysis {
+	pub fn facts_before(&self, id: StatementId) -> Option<&DeskFacts> {
+		self.before.get(id.0)?.get(id.1)?.as_ref()
+	}
+}
+
+#[derive(Clone, Copy)]
+struct Cause {
+	id: StatementId,
+	kind: EdgeKind,
+	// Nothing for going to a bookmark, which lands in the same place whatever the charge
+	charge: Option<usize>,
+}
+
+// Where the reader can end up after a line, and why
+struct LineOutcome {
+	successors: Vec<(usize, DeskFacts)>,
+	edges: Vec<Edge>,
+	problems: Vec<Problem>,
+	before: Vec<Option<DeskFacts>>,
+}
+
+#[derive(Clone)]
+struct LineState {
+	desk: DeskFacts,
+	controls: Option<Vec<(Control, Option<Cause>)>>,
+	// Judgements and the line their skips count from, since a flip earlier in the line moves it
+	wild_skips: Vec<(StatementId, usize)>,
+	wild_jumps: Vec<StatementId>,
+	bookmark_skips: Vec<(StatementId, usize)>,
+	flipped: Option<(StatementId, usize)>,
+	exits: bool,
+}
+
+struct Analyzer<'a> {
+	book: &'a Spellbook,
+	pushing_lines: Vec<usize>,
+}
+
+fn spell_pushes(spell: &str) -> bool {
+	!matches!(spell, "Amplify" | "Squelch" | "Diminish" | "Reverberate")
+}
+
+pub fn converts_to(value: &Variant, chapter: usize) -> bool {
+	let mut page = Page::new(page_type(chapter));
+	page.write_value(String::new(), Some(value.clone()), false, 0)
+}
+
+pub fn page_type(chapter: usize) -> crate::page::PageType {
+	use crate::page::PageType;
+	match chapter {
+		0 => PageType::Boolean,
+		1 => PageType::Integer,
+		2 => PageType::Float,
+		_ => PageType::Str,
+	}
+}
+
+impl<'a> Analyzer<'a> {
+	fn heading_of(&self, id: StatementId) -> Option<&str> {
+		self.book.lines[id.0].statements().get(id.1)?.heading()
+	}
+
+	// Whether a heading is definitely, maybe or never on a page
+	fn has_heading(&self, page: &PageFacts, heading: &str) -> Tri {
+		let mut result = Tri::No;
+		for slot in &page.slots {
+			if slot.fill == Tri::No {
+				continue;
+			}
+
+			match &slot.writers {
+				Some(writers) if !writers.is_empty() => {
+					let matching = writers.iter().filter(|w| self.heading_of(**w) == Some(heading)).count();
+					if matching == writers.len() && slot.fill == Tri::Yes {
+						return Tri::Yes;
+					} else if matching > 0 {
+						result = Tri::Maybe;
+					}
+				},
+				_ => {
+					result = Tri::Maybe;
+				},
+			}
+		}
+
+		result
+	}
+
+	fn problem(&self, problems: &mut Vec<Problem>, id: StatementId, rule: &'static str, certainty: Tri, message: String) {
+		if certainty.possible() {
+			problems.push(Problem{ id, rule, message, certain: certainty == Tri::Yes });
+		}
+	}
+
+	fn write_slot(page: &mut PageFacts, slot: SlotFacts) {
+		let indices = page.write_index.clone();
+		match &indices {
+			Possible::Values(values) if values.len() == 1 => {
+				let index = *values.iter().next().unwrap();
+				if index < 3 {
+					page.slots[index] = slot;
+				}
+			},
+			_ => {
+				for i in 0..3 {
+					if indices.contains(i) {
+						page.slots[i] = page.slots[i].join(&slot);
+					}
+				}
+			},
+		}
+
+		page.write_index = page.write_index.map(|i| i + 1);
+	}
+
+	fn put_back(page: &mut PageFacts, taken: &PageFacts) {
+		page.write_index = match (&page.write_index, &taken.filled_count()) {
+			(Possible::Values(a), Possible::Values(b)) => Possible::widened(a.iter().flat_map(|x| b.iter().map(move |y| x + y)).collect()),
+			_ => Possible::Any,
+		};
+
+		page.slots = taken.slots.clone();
+	}
+
+	fn apply_to_page(&self, desk: &DeskFacts, id: StatementId, statement: &Statement, problems: &mut Vec<Problem>) -> DeskFacts {
+		let chapter = match desk.chapters.iter().next() {
+			Some(Some(chapter)) => *chapter,
+			_ => {
+				self.problem(problems, id, "UNTURNED-PAGE", Tri::Yes, "no chapter has been turned to yet".into());
+				return desk.clone();
+			},
+		};
+
+		let mut desk = desk.clone();
+		let name = BUILTINS_CHAPTERS[chapter];
+		match &statement.kind {
+			StatementKind::Write{ source, heading } => {
+				let page = &desk.pages[chapter];
+				self.problem(problems, id, "OVERFULL-CHAPTER", page.full(), match page.full() {
+					Tri::Yes => format!("the {} chapter is already full", name),
+					_ => format!("the {} chapter may already be full", name),
+				});
+
+				let duplicate = self.has_heading(page, heading);
+				if duplicate == Tri::Yes {
+					self.problem(problems, id, "DUPLICATE-HEADING", Tri::Yes, format!("`{}` is already written in the {} chapter", heading, name));
+				}
+
+				match source {
+					WriteSource::Literal(value) => {
+						if !converts_to(value, chapter) {
+							self.problem(problems, id, "UNCONVERTIBLE-VALUE", Tri::Yes, format!("{} can't be written in the {} chapter", value.print(), name));
+						}
+					},
+					WriteSource::Memory => {
+						self.problem(problems, id, "EMPTY-MEMORY", match desk.memory {
+							Tri::No => Tri::Yes,
+							_ => Tri::No,
+						}, "nothing has been memorized yet".into());
+					},
+					WriteSource::Copy(from) => {
+						if self.has_heading(page, from) == Tri::No {
+							self.problem(problems, id, "UNKNOWN-HEADING", Tri::Yes, format!("`{}` isn't written in the {} chapter", from, name));
+						}
+					},
+				}
+
+				Self::write_slot(&mut desk.pages[chapter], SlotFacts::written_by(id));
+			},
+			StatementKind::Memorize(MemorizeSource::Heading(heading)) => {
+				if self.has_heading(&desk.pages[chapter], heading) == Tri::No {
+					self.problem(problems, id, "UNKNOWN-HEADING", Tri::Yes, format!("`{}` isn't written in the {} chapter", heading, name));
+				}
+
+				desk.memory = Tri::Yes;
+			},
+			StatementKind::Memorize(MemorizeSource::Entry(index)) => {
+				desk.memory = desk.pages[chapter].slots[*index].fill;
+			},
+			StatementKind::TearOutChapter(destination) => {
+				let page = desk.pages[chapter].clone();
+				match destination {
+					Destination::Drawer => {
+						desk.drawer.depth = desk.drawer.depth.map(|d| d + 1);
+						desk.drawer.pages = match desk.drawer.pages.take() {
+							Some(mut pages) if pages.len() < MAX_TRACKED_DRAWER => {
+								pages.push(page);
+								Some(pages)
+							},
+							_ => None,
+						};
+					},
+					Destination::Cauldron => {
+						let mut tossed = desk.cauldron.clone();
+						match desk.cauldron.holds {
+							Tri::No => {
+								tossed = CauldronFacts{ holds: Tri::Yes, chapters: std::iter::once(chapter).collect(), page };
+							},
+							_ => {
+								for i in 0..3 {
+									let incoming = &page.slots[i];
+									let current = &mut tossed.page.slots[i];
+									match incoming.fill {
+										Tri::Yes => *current = SlotFacts{ fill: Tri::Yes, writers: None },
+										Tri::Maybe => *current = SlotFacts{ fill: if current.fill == Tri::Yes { Tri::Yes } else { Tri::Maybe }, writers: None },
+										Tri::No => {},
+									}
+								}
+
+								if desk.cauldron.holds == Tri::Maybe {
+									let fresh = CauldronFacts{ holds: Tri::Yes, chapters: std::iter::once(chapter).collect(), page };
+									tossed = CauldronFacts{ holds: Tri::Yes, ..tossed.join(&fresh) };
+								}
+							},
+						}
+
+						desk.cauldron = tossed;
+					},
+					Destination::Trash => {},
+				}
+
+				desk.pages[chapter] = PageFacts::blank();
+			},
+			StatementKind::TakeOutFromDrawer => {
+				self.problem(problems, id, "EMPTY-DRAWER", if desk.drawer.depth == Possible::one(0) { Tri::Yes } else { Tri::No }, "there's nothing in the drawer".into());
+				let taken = match desk.drawer.pages.as_mut().and_then(|p| p.pop()) {
+					Some(page) => page,
+					None => {
+						desk.drawer.pages = None;
+						PageFacts{
+							slots: [
+								SlotFacts{ fill: Tri::Maybe, writers: None },
+								SlotFacts{ fill: Tri::Maybe, writers: None },
+								SlotFacts{ fill: Tri::Maybe, writers: None },
+							],
+							write_index: Possible::Any,
+							signed: Tri::Maybe,
+						}
+					},
+				};
+
+				desk.drawer.depth = match &desk.drawer.depth {
+					Possible::Values(values) => Possible::Values(values.iter().filter(|d| **d > 0).map(|d| d - 1).collect()),
+					Possible::Any => Possible::Any,
+				};
+
+				Self::put_back(&mut desk.pages[chapter], &taken);
+			},
+			StatementKind::TakeOutFromCauldron => {
+				self.problem(problems, id, "EMPTY-CAULDRON", if desk.cauldron.holds == Tri::No { Tri::Yes } else { Tri::No }, "there's nothing in the cauldron".into());
+				let taken = desk.cauldron.page.clone();
+				Self::put_back(&mut desk.pages[chapter], &taken);
+				desk.cauldron = CauldronFacts::empty();
+			},
+			StatementKind::SignChapter(_) => {
+				if desk.pages[chapter].signed == Tri::Yes {
+					self.problem(problems, id, "DOUBLE-SIGNATURE", Tri::Yes, format!("the {} chapter is already signed", name));
+				}
+
+				desk.pages[chapter].signed = Tri::Yes;
+			},
+			_ => {},
+		}
+
+		desk
+	}
+
+	// Judgement can land on a bookmark instead of skipping by its charge, which goes nowhere
+	// if the bookmark was never placed
+	fn cast(&self, state: &mut LineState, id: StatementId, spell: &str, bookmark: Option<&Bookmark>, problems: &mut Vec<Problem>) {
+		let cauldron = &mut state.desk.cauldron;
+		let needs_page = matches!(spell, "Coadjuvancy" | "Antipodize" | "Juxtapose" | "Judgement");
+		if needs_page && cauldron.holds == Tri::No {
+			self.problem(problems, id, "EMPTY-CAULDRON", Tri::Yes, format!("{} needs a chapter in the cauldron", spell));
+		}
+
+		match spell {
+			"Coadjuvancy" => {
+				Self::write_slot(&mut cauldron.page, SlotFacts::written_by(id));
+			},
+			"Antipodize" => {
+				for slot in &mut cauldron.page.slots {
+					slot.writers = None;
+				}
+			},
+			"Juxtapose" => {
+				if cauldron.holds.possible() {
+					cauldron.chapters = std::iter::once(0).collect();
+					cauldron.page = PageFacts{
+						slots: [SlotFacts::written_by(id), SlotFacts::written_by(id), SlotFacts::written_by(id)],
+						..PageFacts::blank()
+					};
+				}
+			},
+			"Entwinement" | "Belittlement" | "Reenactment" | "Apportionment" => {
+				state.desk.mixes = std::iter::once(match spell {
+					"Entwinement" => CauldronMixMode::Add,
+					"Belittlement" => CauldronMixMode::Sub,
+					"Reenactment" => CauldronMixMode::Mul,
+					_ => CauldronMixMode::Div,
+				}).collect();
+			},
+			"Judgement" if cauldron.holds.possible() && !cauldron.chapters.contains(&0) => {
+				let from = cauldron.chapters.iter().map(|c| BUILTINS_CHAPTERS[*c]).collect::<Vec<&str>>().join(" or ");
+				self.problem(problems, id, "JUDGEMENT-WITHOUT-PRESAGES", Tri::Yes, format!("the cauldron holds a {} chapter", from));
+			},
+			_ => {},
+		}
+
+		let book_len = self.book.lines.len();
+		let controls = match state.controls.take() {
+			Some(controls) => controls,
+			None => {
+				Self::cast_wild(state, id, spell, bookmark);
+				return;
+			},
+		};
+
+		let mut next = vec![];
+		let mut overloaded = false;
+		let mut nowhere = None;
+		for (mut control, mut cause) in controls {
+			match control.cast(spell) {
+				Moved::Stayed | Moved::MightSkip(_) if bookmark.is_some() => {
+					if let Some(line) = bookmark.unwrap().line {
+						let mut skipped = control.clone();
+						skipped.cursor = line.wrapping_sub(1);
+						next.push((skipped, Some(Cause{ id, kind: EdgeKind::Skip, charge: None })));
+					}
+				},
+				Moved::Stayed => {},
+				// The chapter in the cauldron is unknown here, so both ways are possible
+				Moved::MightSkip(charge) => {
+					let mut skipped = control.clone();
+					skipped.cursor = skipped.cursor.wrapping_add(charge).min(book_len);
+					next.push((skipped, Some(Cause{ id, kind: EdgeKind::Skip, charge: Some(charge) })));
+				},
+				Moved::JumpedBack(charge) => {
+					cause = Some(Cause{ id, kind: EdgeKind::JumpBack, charge: Some(charge) });
+				},
+				Moved::Overloaded => {
+					overloaded = true;
+					continue;
+				},
+				Moved::Nowhere(charge) => {
+					nowhere = Some(charge);
+					continue;
+				},
+			}
+
+			next.push((control, cause));
+		}
+
+		let certainty = if next.is_empty() { Tri::Yes } else { Tri::Maybe };
+		if overloaded {
+			self.problem(problems, id, "OVERLOADED-CAULDRON", certainty, "Amplify is cast more than three times in a row".into());
+		}
+
+		if let Some(charge) = nowhere {
+			self.problem(problems, id, "NOWHERE-TO-REVERBERATE", certainty, format!("no spell was cast after the first line to reverberate to with a charge of {}", charge));
+		}
+
+		state.controls = if next.len() > MAX_TRACKED_CONTROLS {
+			Self::cast_wild(state, id, spell, bookmark);
+			None
+		} else {
+			Some(next)
+		};
+	}
+
+	fn cast_wild(state: &mut LineState, id: StatementId, spell: &str, bookmark: Option<&Bookmark>) {
+		let cursor = state.flipped.map_or(id.0, |(_, line)| line.wrapping_sub(1));
+		match (spell, bookmark) {
+			("Judgement", Some(bookmark)) => {
+				if let Some(line) = bookmark.line {
+					state.bookmark_skips.push((id, line));
+				}
+			},
+			("Judgement", None) => state.wild_skips.push((id, cursor)),
+			("Reverberate", _) => state.wild_jumps.push(id),
+			_ => {},
+		}
+	}
+
+	// Flipping only moves the reader once the line is finished, but it does away with wherever
+	// else the line was going to send it. A bookmark that was never placed is a catastrophe
+	fn flip(state: &mut LineState, id: StatementId, line: Option<usize>) {
+		let line = match line {
+			Some(line) => line,
+			None => {
+				state.controls = Some(vec![]);
+				return;
+			},
+		};
+
+		match &mut state.controls {
+			Some(controls) => {
+				for (control, cause) in controls {
+					control.cursor = line.wrapping_sub(1);
+					*cause = Some(Cause{ id, kind: EdgeKind::Flip, charge: None });
+				}
+			},
+			None => {
+				state.wild_skips.clear();
+				state.wild_jumps.clear();
+				state.bookmark_skips.clear();
+				state.flipped = Some((id, line));
+			},
+		}
+	}
+
+	fn apply(&self, state: &mut LineState, id: StatementId, statement: &Statement, problems: &mut Vec<Problem>) {
+		match &statement.kind {
+			StatementKind::TurnToChapter(chapter) => {
+				state.desk.chapters = std::iter::once(Some(*chapter)).collect();
+			},
+			StatementKind::Write{ .. }
+			| StatementKind::Memorize(MemorizeSource::Heading(_))
+			| StatementKind::Memorize(MemorizeSource::Entry(_))
+			| StatementKind::TearOutChapter(_)
+			| StatementKind::TakeOutFromDrawer
+			| StatementKind::TakeOutFromCauldron
+			| StatementKind::SignChapter(_) => {
+				let mut joined: Option<DeskFacts> = None;
+				for chapter in state.desk.chapters.clone() {
+					let result = self.apply_to_page(&state.desk.with_chapter(chapter), id, statement, problems);
+					joined = Some(match joined {
+						Some(desk) => desk.join(&result),
+						None => result,
+					});
+				}
+
+				if let Some(desk) = joined {
+					state.desk = DeskFacts{ controls: state.desk.controls.clone(), ..desk };
+				}
+			},
+			StatementKind::Memorize(MemorizeSource::Literal(_)) => {
+				state.desk.memory = Tri::Yes;
+			},
+			StatementKind::KnockOverCauldron => {
+				state.desk.cauldron = CauldronFacts::empty();
+			},
+			StatementKind::Cast(spell) => {
+				if BUILTINS_SPELLS.contains_key(spell.as_str()) {
+					self.cast(state, id, spell, None, problems);
+				}
+			},
+			StatementKind::JudgeToBookmark(bookmark) => {
+				self.cast(state, id, "Judgement", Some(bookmark), problems);
+			},
+			StatementKind::Flip{ bookmark, .. } => {
+				Self::flip(state, id, bookmark.line);
+			},
+			StatementKind::SlamShut => {
+				state.exits = true;
+			},
+			StatementKind::Publish(_) | StatementKind::SignAcknowledgements(_) | StatementKind::PlaceBookmark(_) => {},
+			// Whether the rest of the line is read is up to the line
+			StatementKind::If(_) => {},
+		}
+	}
+
+	// Whether an `if` lets the rest of its line be read
+	fn presage(&self, desk: &DeskFacts, id: StatementId, condition: &Condition, problems: &mut Vec<Problem>) -> Tri {
+		if *condition == Condition::CauldronFull {
+			return desk.cauldron.holds;
+		}
+
+		let every = |bad: &dyn Fn(Option<usize>) -> bool| {
+			match desk.chapters.iter().filter(|c| bad(**c)).count() {
+				0 => Tri::No,
+				n if n == desk.chapters.len() => Tri::Yes,
+				_ => Tri::Maybe,
+			}
+		};
+
+		self.problem(problems, id, "UNTURNED-PAGE", every(&|c| c.is_none()), "no chapter has been turned to yet".into());
+		let elsewhere = desk.chapters.iter().flatten().filter(|c| **c != 0).map(|c| BUILTINS_CHAPTERS[*c]).collect::<Vec<&str>>();
+		self.problem(problems, id, "CONDITION-WITHOUT-PRESAGES", every(&|c| c.is_some_and(|c| c != 0)),
+			format!("the {} chapter is turned to", elsewhere.join(" or ")));
+
+		if desk.chapters.contains(&Some(0)) {
+			let page = &desk.pages[0];
+			let (rule, missing, message) = match condition {
+				Condition::Heading(heading) => {
+					let missing = match self.has_heading(page, heading) {
+						Tri::Yes => Tri::No,
+						Tri::No => Tri::Yes,
+						Tri::Maybe => Tri::Maybe,
+					};
+
+					("UNKNOWN-HEADING", missing, format!("`{}` isn't written in the Presages chapter", heading))
+				},
+				Condition::Entry(index) => {
+					let missing = match page.slots[*index].fill {
+						Tri::Yes => Tri::No,
+						Tri::No => Tri::Yes,
+						Tri::Maybe => Tri::Maybe,
+					};
+
+					("EMPTY-CONDITION", missing, format!("the {} entry of the Presages chapter is empty", BUILTINS_ORDINALS[*index]))
+				},
+				Condition::CauldronFull => unreachable!(),
+			};
+
+			let certainty = if desk.chapters.len() == 1 { missing } else { missing.join(Tri::No) };
+			self.problem(problems, id, rule, certainty, message);
+		}
+
+		Tri::Maybe
+	}
+
+	fn run_line(&self, index: usize, desk: &DeskFacts) -> LineOutcome {
+		let line = &self.book.lines[index];
+		let controls = desk.controls.as_ref().map(|controls| controls.iter().map(|c| (Control{ cursor: index, ..c.clone() }, None)).collect());
+		let mut state = Some(LineState{ desk: desk.clone(), controls, wild_skips: vec![], wild_jumps: vec![], bookmark_skips: vec![], flipped: None, exits: false });
+		let mut problems = vec![];
+		let mut before = vec![];
+
+		// Every way the line can end, along with the statement it ends after. An `if` that
+		// might not hold ends it early
+		let mut endings = vec![];
+
+		// A line that can't be read is stepped over so the lines after it still get facts
+		for (i, statement) in line.statements().iter().enumerate() {
+			let current = match state.as_mut() {
+				Some(current) => current,
+				None => {
+					before.push(None);
+					continue;
+				},
+			};
+
+			current.desk.controls = current.controls.as_ref().map(|c| c.iter().map(|(control, _)| control.clone()).collect());
+			before.push(Some(current.desk.clone()));
+			if let StatementKind::If(condition) = &statement.kind {
+				let holds = self.presage(&current.desk, (index, i), condition, &mut problems);
+				if holds != Tri::Yes {
+					endings.push(((index, i), current.clone()));
+				}
+
+				if holds == Tri::No {
+					state = None;
+				}
+
+				continue;
+			}
+
+			self.apply(current, (index, i), statement, &mut problems);
+		}
+
+		if let Some(state) = state {
+			endings.push(((index, line.statements().len().saturating_sub(1)), state));
+		}
+
+		let mut edges = vec![];
+		let mut successors: Vec<(usize, DeskFacts)> = vec![];
+		for (last, state) in &endings {
+			self.end_line(index, *last, state, &mut edges, &mut successors);
+		}
+
+		LineOutcome{ successors, edges, problems, before }
+	}
+
+	fn end_line(&self, index: usize, last: StatementId, state: &LineState, edges: &mut Vec<Edge>, successors: &mut Vec<(usize, DeskFacts)>) {
+		let book_len = self.book.lines.len();
+		let mut add_successor = |target: usize, controls: Option<BTreeSet<Control>>| {
+			let desk = DeskFacts{ controls, ..state.desk.clone() };
+			match successors.iter_mut().find(|(t, _)| *t == target) {
+				Some((_, existing)) => *existing = existing.join(&desk),
+				None => successors.push((target, desk)),
+			}
+		};
+
+		let mut add_edge = |edge: Edge| {
+			if !edges.contains(&edge) {
+				edges.push(edge);
+			}
+		};
+
+		match &state.controls {
+			Some(controls) => {
+				for (control, cause) in controls {
+					let (from, kind, charge) = match cause {
+						Some(cause) => (cause.id, cause.kind, cause.charge),
+						None => (last, EdgeKind::Fallthrough, None),
+					};
+
+					// Flipping to a bookmark on the very first line wraps all the way around, just
+					// like the interpreter does
+					let next = control.cursor.wrapping_add(1);
+					let to = if state.exits {
+						Target::End
+					} else if next > book_len {
+						Target::Catastrophe
+					} else if next == book_len {
+						Target::End
+					} else {
+						Target::Line(next)
+					};
+
+					add_edge(Edge{ from, to, kind, charge, dynamic: false });
+					if let Target::Line(target) = to {
+						let mut carried = control.clone();
+						carried.cursor = target;
+						add_successor(target, Some(std::iter::once(carried).collect()));
+					}
+				}
+			},
+			None => {
+				let line_target = |target: usize| if target < book_len { Target::Line(target) } else { Target::End };
+				if state.exits {
+					add_edge(Edge{ from: last, to: Target::End, kind: EdgeKind::Fallthrough, charge: None, dynamic: false });
+				} else {
+					if state.wild_jumps.is_empty() {
+						let (from, next, kind) = match state.flipped {
+							Some((id, line)) => (id, line, EdgeKind::Flip),
+							None => (last, index + 1, EdgeKind::Fallthrough),
+						};
+
+						add_edge(Edge{ from, to: line_target(next), kind, charge: None, dynamic: false });
+						if next < book_len {
+							add_successor(next, None);
+						}
+					}
+
+					for (id, cursor) in &state.wild_skips {
+						for target in cursor.wrapping_add(2)..=book_len {
+							add_edge(Edge{ from: *id, to: line_target(target), kind: EdgeKind::Skip, charge: None, dynamic: true });
+							if target < book_len {
+								add_successor(target, None);
+							}
+						}
+
+						add_edge(Edge{ from: *id, to: Target::Catastrophe, kind: EdgeKind::Skip, charge: None, dynamic: true });
+					}
+
+					for (id, line) in &state.bookmark_skips {
+						add_edge(Edge{ from: *id, to: Target::Line(*line), kind: EdgeKind::Skip, charge: None, dynamic: false });
+						add_successor(*line, None);
+					}
+
+					for id in &state.wild_jumps {
+						for target in &self.pushing_lines {
+							add_edge(Edge{ from: *id, to: Target::Line(*target), kind: EdgeKind::JumpBack, charge: None, dynamic: true });
+							add_successor(*target, None);
+						}
+					}
+				}
+			},
+		}
+	}
+}
+
+pub fn analyze(book: &Spellbook) -> Analysis {
+	let analyzer = Analyzer{
+		book,
+		pushing_lines: book.lines.iter().enumerate()
+			.filter(|(_, l)| l.statements().iter().any(|s| s.spell().is_some_and(spell_pushes)))
+			.map(|(i, _)| i)
+			.collect(),
+	};
+
+	let mut line_entry: Vec<Option<DeskFacts>> = vec![None; book.lines.len()];
+	let mut worklist = BTreeSet::new();
+	if !book.lines.is_empty() {
+		line_entry[0] = Some(DeskFacts::new());
+		worklist.insert(0);
+	}
+
+	while let Some(index) = worklist.pop_first() {
+		let desk = line_entry[index].clone().unwrap();
+		for (target, facts) in analyzer.run_line(index, &desk).successors {
+			let joined = match &line_entry[target] {
+				Some(existing) => existing.join(&facts),
+				None => facts,
+			};
+
+			if line_entry[target].as_ref() != Some(&joined) {
+				line_entry[target] = Some(joined);
+				worklist.insert(target);
+			}
+		}
+	}
+
+	let mut before = vec![];
+	let mut edges = vec![];
+	let mut problems: Vec<Problem> = vec![];
+	for (index, entry) in line_entry.iter().enumerate() {
+		match entry {
+			Some(desk) => {
+				let outcome = analyzer.run_line(index, desk);
+				before.push(outcome.before);
+				edges.extend(outcome.edges);
+				for problem in outcome.problems {
+					match problems.iter_mut().find(|p| p.id == problem.id && p.rule == problem.rule) {
+						Some(existing) => existing.certain &= problem.certain,
+						None => problems.push(problem),
+					}
+				}
+			},
+			None => {
+				before.push(vec![None; book.lines[index].statements().len()]);
+			},
+		}
+	}
+
+	Analysis{ line_entry, before, edges, problems }
+}
@@ -5,7 +5,7 @@
 
 use crate::variant::Variant;
 use crate::page::*;
-use std::io;
+use std::io::BufRead;
 
 #[derive(Clone, PartialEq)]
 pub enum CauldronSpell {
@@ -35,6 +35,7 @@ pub enum CauldronSpellResult {
 	JumpBack(usize),
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub enum CauldronMixMode {
 	Add,
 	Sub,
@@ -42,14 +43,21 @@ pub enum CauldronMixMode {
 	Div,
 }
 
+#[derive(Clone)]
 pub struct Cauldron {
-	page: Option<Page>,
+	pub page: Option<Page>,
 
-	spell_charge: usize,
-	spell_charge_amplifier: usize,
+	pub spell_charge: usize,
+	pub spell_charge_amplifier: usize,
 	consecutive_amplifies: usize,
 
-	mix_mode: CauldronMixMode,
+	pub mix_mode: CauldronMixMode,
+}
+
+impl Default for Cauldron {
+	fn default() -> Self {
+		Self::new()
+	}
 }
 
 impl Cauldron {
@@ -131,7 +139,7 @@ impl Cauldron {
 		page
 	}
 
-	pub fn cast_spell(&mut self, spell: &CauldronSpell) -> Option<CauldronSpellResult> {
+	pub fn cast_spell(&mut self, spell: &CauldronSpell, reader: &mut dyn BufRead) -> Option<CauldronSpellResult> {
 		if *spell != CauldronSpell::Amplify && *spell != CauldronSpell::Vacation {
 			self.consecutive_amplifies = 0;
 		}
@@ -146,8 +154,10 @@ impl Cauldron {
 
 						loop {
 							let mut input = String::with_capacity(10);
-							if io::stdin().read_line(&mut input).is_err() {
-								return None;
+							// Running out of input is as bad as not being able to read it
+							match reader.read_line(&mut input) {
+								Ok(0) | Err(_) => return None,
+								Ok(_) => {},
 							}
 
 							let input_trimmed = input.trim_end(); 
@@ -193,8 +203,9 @@ impl Cauldron {
 					Some(ref mut pg) => {
 						for i in 0..3 {
 							pg.entry_names[i] = pg.entry_names[i].chars().rev().collect();
-							pg.values[i] = match &pg.values[i] {
-								Some(val) => val.inverted(),
+							pg.values[i] = match pg.values[i].as_ref().map(|v| v.inverted()) {
+								Some(Some(inverted)) => Some(inverted),
+								Some(None) => return None,
 								None => None,
 							}
 						}
@@ -0,0 +1,201 @@
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+//        SPELLBOOK INTERPRETER
+//           BY DIANE SPARKS
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+
+use crate::analysis;
+use crate::constants::*;
+use crate::errors::{closest, column};
+use crate::syntax::{self, *};
+
+use std::collections::HashSet;
+use std::ops::Range;
+use std::process;
+
+// Rule names are shouted so that they can be written in comments
+pub const RULES: [(&str, &str); 17] = [
+	("SYNTAX", "The line can't be read as spellbook statements."),
+	("UNTURNED-PAGE", "A chapter is written in or torn out before turning to any chapter."),
+	("OVERFULL-CHAPTER", "An entry is written to a chapter that is already full."),
+	("DUPLICATE-HEADING", "The same heading is written twice in one chapter."),
+	("JUDGEMENT-WITHOUT-PRESAGES", "Judgement is cast while the cauldron holds a chapter that isn't Presages."),
+	("DOUBLE-SIGNATURE", "A chapter is signed twice without tearing it out."),
+	("EMPTY-DRAWER", "A chapter is taken out of the drawer when the drawer is empty."),
+	("UNKNOWN-HEADING", "A heading is memorized, copied or looked at by `if` that isn't written in the chapter turned to."),
+	("UNKNOWN-SPELL", "A spell is cast that doesn't exist."),
+	("EMPTY-CAULDRON", "A chapter is taken out of the cauldron, or a spell needs one, when the cauldron is empty."),
+	("EMPTY-MEMORY", "A value is written from memory before anything is memorized."),
+	("UNCONVERTIBLE-VALUE", "A value is written to a chapter that can't hold it."),
+	("OVERLOADED-CAULDRON", "Amplify is cast more than three times in a row."),
+	("NOWHERE-TO-REVERBERATE", "Reverberate is cast with more charge than there are spells to go back to."),
+	("CONDITION-WITHOUT-PRESAGES", "`if` looks at an entry while a chapter other than Presages is turned to."),
+	("EMPTY-CONDITION", "`if` looks at an entry that hasn't been written in."),
+	("UNKNOWN-BOOKMARK", "A bookmark is flipped or skipped to that's never placed."),
+];
+
+// Writing this word in a line's comments, followed by rule names, hushes those rules for the line
+pub const HUSH_PRAGMA: &str = "HUSH";
+
+pub struct Finding {
+	pub rule: &'static str,
+	pub line: usize,
+	pub span: Range<usize>,
+	pub message: String,
+}
+
+fn with_suggestion(message: &str, suggestion: &Option<String>) -> String {
+	match suggestion {
+		Some(suggestion) => format!("{} (did you mean `{}`?)", message, suggestion),
+		None => message.into(),
+	}
+}
+
+fn hushed_rules(line: &SourceLine) -> Vec<&str> {
+	line.comments().skip_while(|c| *c != HUSH_PRAGMA).skip(1).collect()
+}
+
+pub fn check(book: &Spellbook) -> Vec<Finding> {
+	let analysis = analysis::analyze(book);
+	let written_headings = book.lines.iter()
+		.flat_map(|l| l.statements())
+		.filter_map(|s| s.heading())
+		.collect::<HashSet<&str>>();
+
+	let bookmarks = book.lines.iter()
+		.flat_map(|l| l.statements())
+		.filter_map(|s| match &s.kind {
+			StatementKind::PlaceBookmark(name) => Some(name.as_str()),
+			_ => None,
+		})
+		.collect::<Vec<&str>>();
+
+	let mut findings = vec![];
+	for (index, line) in book.lines.iter().enumerate() {
+		let mut found = vec![];
+		if let Err(error) = &line.statements {
+			found.push(Finding{ rule: "SYNTAX", line: line.number, span: error.span.clone(), message: with_suggestion(&error.message, &error.suggestion) });
+		}
+
+		for (i, statement) in line.statements().iter().enumerate() {
+			let mut report = |rule: &'static str, message: String| {
+				found.push(Finding{ rule, line: line.number, span: statement.span.clone(), message });
+			};
+
+			let read = match &statement.kind {
+				StatementKind::Write{ source: WriteSource::Copy(from), .. } => Some(from),
+				StatementKind::Memorize(MemorizeSource::Heading(heading)) => Some(heading),
+				StatementKind::If(Condition::Heading(heading)) => Some(heading),
+				_ => None,
+			};
+
+			if let Some(heading) = read {
+				if !written_headings.contains(heading.as_str()) {
+					report("UNKNOWN-HEADING", format!("`{}` is never written anywhere", heading));
+				}
+			}
+
+			if let Some(spell) = statement.spell() {
+				if !BUILTINS_SPELLS.contains_key(spell) {
+					let suggestion = closest(spell, BUILTINS_SPELLS.keys().copied()).map(String::from);
+					report("UNKNOWN-SPELL", with_suggestion(&format!("there's no spell called `{}`", spell), &suggestion));
+				}
+			}
+
+			if let Some(bookmark) = statement.bookmark().filter(|b| b.line.is_none()) {
+				let suggestion = closest(&bookmark.name, bookmarks.iter().copied()).map(String::from);
+				report("UNKNOWN-BOOKMARK", with_suggestion(&format!("no bookmark named `{}` is placed anywhere", bookmark.name), &suggestion));
+			}
+
+			// Only what goes wrong on every way of reaching the statement is worth complaining about
+			for problem in analysis.problems.iter().filter(|p| p.id == (index, i) && p.certain) {
+				if problem.rule != "UNKNOWN-HEADING" || read.is_some_and(|h| written_headings.contains(h.as_str())) {
+					report(problem.rule, problem.message.clone());
+				}
+			}
+		}
+
+		let hushed = hushed_rules(line);
+		findings.extend(found.into_iter().filter(|f| !hushed.contains(&f.rule)));
+	}
+
+	findings
+}
+
+fn print_facts(book: &Spellbook) {
+	let analysis = analysis::analyze(book);
+	for (index, line) in book.lines.iter().enumerate() {
+		println!("{:<4}  {}", line.number, line.text.trim());
+		if analysis.line_entry[index].is_none() {
+			println!("      never reached");
+			continue;
+		}
+
+		for (i, statement) in line.statements().iter().enumerate() {
+			let facts = match analysis.facts_before((index, i)) {
+				Some(facts) => facts,
+				None => {
+					println!("      never read past `if`");
+					break;
+				},
+			};
+
+			println!("      before `{}`:", line.text[statement.span.clone()].split_whitespace().collect::<Vec<&str>>().join(" "));
+			for fact in facts.to_string().lines() {
+				println!("        {}", fact);
+			}
+		}
+
+		for edge in analysis.edges.iter().filter(|e| e.from.0 == index) {
+			let to = match edge.to {
+				analysis::Target::Line(target) => format!("line {}", book.lines[target].number),
+				analysis::Target::End => "the end".into(),
+				analysis::Target::Catastrophe => "a catastrophe".into(),
+			};
+
+			let maybe = if edge.dynamic { "maybe " } else { "" };
+			match edge.kind {
+				analysis::EdgeKind::Fallthrough => println!("      then {}", to),
+				analysis::EdgeKind::Skip => println!("      then {}{} if Judgement skips", maybe, to),
+				analysis::EdgeKind::JumpBack => println!("      then {}{} by reverberating", maybe, to),
+				analysis::EdgeKind::Flip => println!("      then {} by flipping to a bookmark", to),
+			}
+		}
+	}
+}
+
+pub fn run(args: &[String]) {
+	if args.is_empty() {
+		panic!();
+	}
+
+	if args[0] == "--rules" {
+		for (rule, description) in &RULES {
+			println!("{:<28}{}", rule, description);
+		}
+
+		return;
+	}
+
+	if args[0] == "--facts" {
+		for path in &args[1..] {
+			print_facts(&syntax::parse_source(&syntax::read_source(path).unwrap()));
+		}
+
+		return;
+	}
+
+	let mut found_any = false;
+	for path in args {
+		let source = syntax::read_source(path).unwrap();
+		let book = syntax::parse_source(&source);
+		for finding in check(&book) {
+			let text = &book.lines.iter().find(|l| l.number == finding.line).unwrap().text;
+			println!("{}:{}:{}: {}: {}", path, finding.line, column(text, finding.span.start), finding.rule, finding.message);
+			found_any = true;
+		}
+	}
+
+	if found_any {
+		process::exit(1);
+	}
+}
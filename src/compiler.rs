@@ -0,0 +1,178 @@
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+//        SPELLBOOK INTERPRETER
+//           BY DIANE SPARKS
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+
+use crate::constants::*;
+use crate::syntax::{self, *};
+use crate::transpiler;
+use crate::variant::Variant;
+
+use std::fs;
+use std::process;
+
+pub const TARGETS: [&str; 2] = [
+	"c",
+	"rust",
+];
+
+const C_RUNTIME: &str = include_str!("runtime/runtime.c");
+
+// Every byte that isn't plain to read gets an octal escape, which never runs into what
+// comes after it. Question marks too, so nothing can turn into a trigraph
+pub fn c_string(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len() + 2);
+	escaped.push('"');
+	for byte in text.bytes() {
+		match byte {
+			b'"' | b'\\' | b'?' => escaped.push_str(&format!("\\{:03o}", byte)),
+			b' '..=b'~' => escaped.push(byte as char),
+			_ => escaped.push_str(&format!("\\{:03o}", byte)),
+		}
+	}
+
+	escaped.push('"');
+	escaped
+}
+
+fn c_comment(text: &str) -> String {
+	format!("/* {} */", text.trim().replace("*/", "* /").replace("/*", "/ *"))
+}
+
+fn c_value(value: &Variant) -> String {
+	match value {
+		Variant::Boolean(b) => format!("sb_boolean({})", b),
+		Variant::Integer(i) if *i == i64::MIN => "sb_integer(INT64_MIN)".into(),
+		Variant::Integer(i) => format!("sb_integer(INT64_C({}))", i),
+		Variant::Float(f) if f.is_nan() => "sb_float(NAN)".into(),
+		Variant::Float(f) if f.is_infinite() => format!("sb_float({}INFINITY)", if *f < 0.0 { "-" } else { "" }),
+		Variant::Float(f) => format!("sb_float({:?})", f),
+		Variant::Str(s) => format!("sb_str(SB_STR({}))", c_string(s)),
+	}
+}
+
+fn c_bookmark(bookmark: &Bookmark) -> String {
+	bookmark.line.map_or("SB_NO_BOOKMARK".into(), |line| line.to_string())
+}
+
+fn c_statement(statement: &Statement) -> String {
+	match &statement.kind {
+		StatementKind::TurnToChapter(chapter) => format!("sb_turn_to_page({});", chapter),
+		StatementKind::TearOutChapter(destination) => format!("sb_tear_out_page({}, {});",
+			*destination == Destination::Drawer, *destination == Destination::Cauldron),
+		StatementKind::TakeOutFromDrawer => "sb_put_back_page(true);".into(),
+		StatementKind::TakeOutFromCauldron => "sb_put_back_page(false);".into(),
+		StatementKind::Write{ source, heading } => match source {
+			WriteSource::Literal(value) => format!("sb_write_value(SB_STR({}), {});", c_string(heading), c_value(value)),
+			WriteSource::Memory => format!("sb_write_memory_value(SB_STR({}));", c_string(heading)),
+			WriteSource::Copy(from) => format!("sb_copy_value(SB_STR({}), SB_STR({}));", c_string(heading), c_string(from)),
+		},
+		StatementKind::Memorize(source) => match source {
+			MemorizeSource::Heading(heading) => format!("sb_memorize_heading(SB_STR({}));", c_string(heading)),
+			MemorizeSource::Literal(value) => format!("sb_memorize_value({});", c_value(value)),
+			MemorizeSource::Entry(index) => format!("sb_memorize_entry({});", index),
+		},
+		StatementKind::Cast(spell) => match BUILTINS_SPELLS.get(spell.as_str()) {
+			Some(_) => format!("sb_cast(SB_{});", spell.to_uppercase()),
+			None => "sb_catastrophe();".into(),
+		},
+		StatementKind::KnockOverCauldron => "sb_knock_over_cauldron();".into(),
+		StatementKind::Publish(target) => match target {
+			Some(target) => format!("sb_publish_to_file(SB_STR({}));", c_string(target)),
+			None => "sb_publish_to_console();".into(),
+		},
+		StatementKind::SignChapter(signature) => format!("sb_sign_page(SB_STR({}));", c_string(signature)),
+		StatementKind::SignAcknowledgements(signature) => format!("sb_set_signature(SB_STR({}));", c_string(signature)),
+		StatementKind::SlamShut => "sb.exit = true;".into(),
+		// Leaving the switch finishes the line early
+		StatementKind::If(condition) => match condition {
+			Condition::Heading(heading) => format!("if (!sb_presage_heading(SB_STR({}))) break;", c_string(heading)),
+			Condition::Entry(index) => format!("if (!sb_presage_entry({})) break;", index),
+			Condition::CauldronFull => "if (!sb.cauldron_full) break;".into(),
+		},
+		StatementKind::PlaceBookmark(name) => c_comment(&format!("bookmark {}", name)),
+		StatementKind::Flip{ bookmark, .. } => format!("sb_flip_to({});", c_bookmark(bookmark)),
+		StatementKind::JudgeToBookmark(bookmark) => format!("sb_cast_judgement_to({});", c_bookmark(bookmark)),
+	}
+}
+
+// The runtime, then the spellbook as a switch with a case for every line, so Judgement and
+// Reverberate can land anywhere just like they do in the interpreter
+pub fn compile_c(book: &Spellbook) -> String {
+	let mut output = String::new();
+	output.push_str("#define SB_ERROR_MESSAGES \\\n");
+	for message in &ERROR_MESSAGES[..10] {
+		output.push_str(&format!("\t{}, \\\n", c_string(message)));
+	}
+
+	output.push_str(&format!("\n#define SB_DEFAULT_WRAPUP {}\n", c_string(DEFAULT_WRAPUP)));
+	output.push_str(&format!("#define SB_DEFAULT_WRAPUP_QED {}\n\n", c_string(DEFAULT_WRAPUP_QED)));
+	output.push_str(C_RUNTIME);
+
+	let numbers = book.lines.iter().map(|l| l.number.to_string()).collect::<Vec<String>>();
+	output.push_str(&format!("\n#define SB_LINE_COUNT {}\n\n", book.lines.len()));
+	output.push_str(&format!("static const size_t sb_line_numbers[] = {{ {} }};\n\n", if numbers.is_empty() { "0".into() } else { numbers.join(", ") }));
+	output.push_str("int main(void) {\n");
+	output.push_str("\tsb_start();\n");
+	output.push_str("\twhile (sb.line_internal < SB_LINE_COUNT) {\n");
+	output.push_str("\t\tsb_line_number = sb_line_numbers[sb.line_internal];\n");
+	output.push_str("\t\tswitch (sb.line_internal) {\n");
+	for (index, line) in book.lines.iter().enumerate() {
+		output.push_str(&format!("\t\tcase {}:\n", index));
+		output.push_str(&format!("\t\t\t{}\n", c_comment(&format!("{}: {}", line.number, line.text))));
+		match &line.statements {
+			Ok(statements) if statements.is_empty() => {
+				output.push_str("\t\t\tsb_step();\n");
+			},
+			Ok(statements) => {
+				for statement in statements {
+					output.push_str(&format!("\t\t\tsb_step();\n\t\t\t{}\n", c_statement(statement)));
+				}
+			},
+			Err(_) => {
+				output.push_str("\t\t\tsb_step();\n\t\t\tsb_catastrophe();\n");
+			},
+		}
+
+		output.push_str("\t\t\tbreak;\n");
+	}
+
+	output.push_str("\t\t}\n\n");
+	output.push_str("\t\tif (!sb_finish_line(SB_LINE_COUNT)) {\n");
+	output.push_str("\t\t\tbreak;\n");
+	output.push_str("\t\t}\n");
+	output.push_str("\t}\n\n");
+	output.push_str("\treturn 0;\n");
+	output.push_str("}\n");
+	output
+}
+
+pub fn run(args: &[String]) {
+	let mut target = String::from("c");
+	let mut path = None;
+	let mut output_path = None;
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--target" => target = args.next().unwrap().clone(),
+			"-o" => output_path = Some(args.next().unwrap().clone()),
+			_ => path = Some(arg.clone()),
+		}
+	}
+
+	let source = syntax::read_source(path.unwrap()).unwrap();
+	let book = syntax::parse_source(&source);
+	let compiled = match target.as_str() {
+		"c" => compile_c(&book),
+		"rust" => transpiler::transpile(&book),
+		_ => {
+			eprintln!("There's no target called {}. Try {}.", target, TARGETS.join(", "));
+			process::exit(1);
+		},
+	};
+
+	match output_path {
+		Some(output_path) => fs::write(output_path, compiled).unwrap(),
+		None => print!("{}", compiled),
+	}
+}
@@ -41,6 +41,30 @@ lazy_static! {
 	};
 }
 
+lazy_static! {
+	pub static ref BUILTINS_SPELL_DESCRIPTIONS: HashMap<&'static str, &'static str> = {
+		hashmap!{
+			"Coadjuvancy" => "Asks for input and writes it into the chapter in the cauldron.",
+			"Judgement" => "Needs a Presages chapter in the cauldron. If any of its values are false, skips as many lines as the current charge.",
+
+			"Entwinement" => "Changes the cauldron's mixing mode to Entwinement.",
+			"Belittlement" => "Changes the cauldron's mixing mode to Belittlement.",
+			"Reenactment" => "Changes the cauldron's mixing mode to Reenactment.",
+			"Apportionment" => "Changes the cauldron's mixing mode to Apportionment.",
+
+			"Reverberate" => "Jumps back to the first spell cast, plus one spell per level of charge.",
+			"Amplify" => "Makes spells increase the charge by one more. Overloads the cauldron if cast more than three times in a row.",
+			"Squelch" => "Resets the charge to 0 and the amplifier to 1.",
+			"Diminish" => "Decreases the charge by 1.",
+
+			"Juxtapose" => "Turns the chapter in the cauldron into a Presages chapter by comparing its values.",
+			"Antipodize" => "Inverts all the values and entry names of the chapter in the cauldron.",
+
+			"Vacancy" => "Does absolutely nothing. Useful as something to Reverberate to.",
+		}
+	};
+}
+
 pub const BUILTINS_ORDINALS: [&'static str; 3] = [
 	"first",
 	"second",
@@ -0,0 +1,132 @@
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+//        SPELLBOOK INTERPRETER
+//           BY DIANE SPARKS
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+
+use crate::analysis::{self, EdgeKind, StatementId, Target};
+use crate::interpreter::Interpreter;
+use crate::syntax::{Spellbook, StatementKind};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::fs;
+use std::panic;
+
+// How many times each statement was read, and where Judgement and Reverberate went from it
+#[derive(Default)]
+pub struct Coverage {
+	statements: BTreeMap<StatementId, usize>,
+	judgements: BTreeMap<StatementId, [usize; 2]>,
+	reverberations: BTreeMap<StatementId, BTreeMap<usize, usize>>,
+}
+
+impl Coverage {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn step(&mut self, interpreter: &mut Interpreter) -> Result<(), usize> {
+		let id = match interpreter.position() {
+			Some(id) => id,
+			None => return Ok(()),
+		};
+
+		let spell = interpreter.book.lines[id.0].statements().get(id.1).and_then(|s| s.spell()).unwrap_or_default().to_string();
+
+		*self.statements.entry(id).or_default() += 1;
+		let charge = interpreter.program.cauldron.spell_charge;
+		let stack = interpreter.program.spell_line_stack.clone();
+		let line_before = interpreter.program.line_internal;
+		interpreter.try_step(Interpreter::step_statement)?;
+
+		match spell.as_str() {
+			// A skip without any charge goes nowhere, so only count where the reader really went,
+			// keeping in mind the line has already moved on if Judgement was the last statement in it
+			"Judgement" => {
+				let finished_line = interpreter.position().is_none_or(|(_, statement)| statement == 0);
+				let skipped = interpreter.program.line_internal != line_before.wrapping_add(finished_line as usize);
+				self.judgements.entry(id).or_default()[skipped as usize] += 1;
+			},
+			"Reverberate" => {
+				if let Some((target, _)) = stack.get(charge) {
+					*self.reverberations.entry(id).or_default().entry(*target).or_default() += 1;
+				}
+			},
+			_ => {},
+		}
+
+		Ok(())
+	}
+
+	pub fn lcov(&self, book: &Spellbook, path: &str) -> String {
+		let analysis = analysis::analyze(book);
+		let mut lcov = format!("TN:\nSF:{}\n", path);
+		let mut branches = 0;
+		let mut branches_hit = 0;
+		for (line, source_line) in book.lines.iter().enumerate() {
+			for (statement_index, statement) in source_line.statements().iter().enumerate() {
+				let id = (line, statement_index);
+				let reached = self.statements.contains_key(&id);
+				let mut outcomes = vec![];
+				match &statement.kind {
+					_ if statement.spell() == Some("Judgement") => {
+						let counts = self.judgements.get(&id).cloned().unwrap_or_default();
+						outcomes.push((0, counts[0]));
+						outcomes.push((1, counts[1]));
+					},
+					// Branches are numbered by the line they go back to, counting the places the
+					// analysis knows it could go even if it never did
+					StatementKind::Cast(spell) if spell == "Reverberate" => {
+						let hit = self.reverberations.get(&id).cloned().unwrap_or_default();
+						let mut targets = analysis.edges.iter()
+							.filter(|e| e.from == id && e.kind == EdgeKind::JumpBack && !e.dynamic)
+							.filter_map(|e| match e.to {
+								Target::Line(target) => Some(target),
+								_ => None,
+							})
+							.collect::<BTreeSet<usize>>();
+
+						targets.extend(hit.keys());
+						for target in targets.into_iter().filter(|t| *t < book.lines.len()) {
+							outcomes.push((book.lines[target].number, hit.get(&target).cloned().unwrap_or_default()));
+						}
+					},
+					_ => {},
+				}
+
+				for (branch, count) in outcomes {
+					let taken = if reached { count.to_string() } else { "-".into() };
+					writeln!(lcov, "BRDA:{},{},{},{}", source_line.number, statement_index, branch, taken).unwrap();
+					branches += 1;
+					branches_hit += (count > 0) as usize;
+				}
+			}
+		}
+
+		let mut lines_hit = 0;
+		for (line, source_line) in book.lines.iter().enumerate() {
+			let count = self.statements.get(&(line, 0)).cloned().unwrap_or_default();
+			writeln!(lcov, "DA:{},{}", source_line.number, count).unwrap();
+			lines_hit += (count > 0) as usize;
+		}
+
+		writeln!(lcov, "BRF:{}\nBRH:{}\nLF:{}\nLH:{}\nend_of_record", branches, branches_hit, book.lines.len(), lines_hit).unwrap();
+		lcov
+	}
+}
+
+// Runs the whole spellbook and writes what was covered, even if a catastrophe ended it
+pub fn run(mut interpreter: Interpreter, path: &str, coverage_path: &str) {
+	let mut coverage = Coverage::new();
+	let mut catastrophe = None;
+	while !interpreter.is_finished() {
+		if let Err(line) = coverage.step(&mut interpreter) {
+			catastrophe = Some(line);
+		}
+	}
+
+	fs::write(coverage_path, coverage.lcov(&interpreter.book, path)).unwrap();
+	if let Some(line) = catastrophe {
+		panic::resume_unwind(Box::new(line));
+	}
+}
@@ -0,0 +1,381 @@
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+//        SPELLBOOK INTERPRETER
+//           BY DIANE SPARKS
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+
+use crate::constants::*;
+use crate::errors;
+use crate::interpreter::Interpreter;
+use crate::page::Page;
+use crate::program::{CapturedOutput, Program};
+use crate::rpc;
+use crate::syntax;
+
+use rand::prelude::*;
+use serde_json::{json, Value};
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, Cursor, Write};
+use std::sync::{Arc, Mutex};
+
+const THREAD_ID: u64 = 1;
+
+// Variable references. Pages get their own, counted up from these
+const CHAPTERS_REFERENCE: u64 = 1;
+const CAULDRON_REFERENCE: u64 = 2;
+const DRAWER_REFERENCE: u64 = 3;
+const DESK_REFERENCE: u64 = 4;
+const CHAPTER_PAGE_REFERENCE: u64 = 10;
+const CAULDRON_PAGE_REFERENCE: u64 = 20;
+const DRAWER_PAGE_REFERENCE: u64 = 100;
+
+enum Stepping {
+	Continue,
+	Line,
+	Statement,
+}
+
+fn page_variables(page: &Page) -> Value {
+	Value::Array((0..3).map(|i| json!({ "name": page.entry_label(i), "value": page.entry_text(i), "variablesReference": 0 })).collect())
+}
+
+struct Session {
+	seq: u64,
+	interpreter: Option<Interpreter>,
+	path: Option<String>,
+	output: CapturedOutput,
+	// Editors usually set breakpoints before launching, so they're kept for each spellbook
+	// until one is launched
+	requested_breakpoints: HashMap<String, Vec<usize>>,
+	breakpoints: HashSet<usize>,
+	stop_on_entry: bool,
+	pause_on_catastrophe: bool,
+	catastrophe: Option<usize>,
+	diagnostic: Arc<Mutex<Option<String>>>,
+}
+
+impl Session {
+	fn send(&mut self, output: &mut impl Write, mut message: Value) -> io::Result<()> {
+		self.seq += 1;
+		message["seq"] = json!(self.seq);
+		rpc::write_message(output, &message)
+	}
+
+	fn event(&mut self, output: &mut impl Write, event: &str, body: Value) -> io::Result<()> {
+		self.send(output, json!({ "type": "event", "event": event, "body": body }))
+	}
+
+	fn flush_output(&mut self, output: &mut impl Write) -> io::Result<()> {
+		let published = self.output.take_text();
+		if !published.is_empty() {
+			self.event(output, "output", json!({ "category": "stdout", "output": published }))?;
+		}
+
+		Ok(())
+	}
+
+	fn launch(&mut self, arguments: &Value) -> Result<Value, String> {
+		let path = arguments["program"].as_str().ok_or("no spellbook to debug was given")?;
+		let source = syntax::read_source(path).map_err(|e| format!("couldn't read {}: {}", path, e))?;
+
+		let mut program = Program::new(false);
+		program.input = Box::new(Cursor::new(arguments["input"].as_str().unwrap_or_default().to_string()));
+		program.output = Box::new(self.output.clone());
+		self.interpreter = Some(Interpreter::new(syntax::parse_source(&source), program));
+		self.path = Some(path.into());
+		self.arm_breakpoints();
+		self.diagnostic = errors::keep_catastrophes(path.into());
+		self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or_default();
+		self.catastrophe = None;
+		Ok(Value::Null)
+	}
+
+	fn set_breakpoints(&mut self, arguments: &Value) -> Value {
+		let path = arguments["source"]["path"].as_str().unwrap_or_default().to_string();
+		let lines = arguments["breakpoints"].as_array().cloned().unwrap_or_default();
+		let lines = lines.iter().map(|breakpoint| breakpoint["line"].as_u64().unwrap_or_default() as usize).collect::<Vec<usize>>();
+
+		// Whether a breakpoint will hold is up to the spellbook, launched or not
+		let readable = match syntax::read_source(&path) {
+			Ok(source) => syntax::parse_source(&source).lines.iter().map(|l| l.number).collect::<HashSet<usize>>(),
+			Err(_) => HashSet::new(),
+		};
+
+		let breakpoints = lines.iter().map(|line| json!({ "verified": readable.contains(line), "line": line })).collect::<Vec<Value>>();
+		self.requested_breakpoints.insert(path, lines);
+		self.arm_breakpoints();
+		json!({ "breakpoints": breakpoints })
+	}
+
+	// Only the breakpoints in the launched spellbook, on lines with something to read, can stop it
+	fn arm_breakpoints(&mut self) {
+		self.breakpoints.clear();
+		if let (Some(path), Some(interpreter)) = (&self.path, &self.interpreter) {
+			for line in self.requested_breakpoints.get(path).into_iter().flatten() {
+				if interpreter.book.lines.iter().any(|l| l.number == *line) {
+					self.breakpoints.insert(*line);
+				}
+			}
+		}
+	}
+
+	fn stop(&mut self, output: &mut impl Write, reason: &str) -> io::Result<()> {
+		self.event(output, "stopped", json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }))
+	}
+
+	fn at_breakpoint(&self) -> bool {
+		match &self.interpreter {
+			Some(interpreter) => match interpreter.position() {
+				Some((line, 0)) => self.breakpoints.contains(&interpreter.book.lines[line].number),
+				_ => false,
+			},
+			None => false,
+		}
+	}
+
+	// Runs until something worth stopping for, then tells the editor why
+	fn resume(&mut self, output: &mut impl Write, stepping: Stepping) -> io::Result<()> {
+		let mut reason = None;
+		if let Some(interpreter) = &mut self.interpreter {
+			while !interpreter.is_finished() {
+				if let Err(line) = interpreter.try_step(Interpreter::step_statement) {
+					self.catastrophe = Some(line);
+					break;
+				}
+
+				match (&stepping, interpreter.position()) {
+					(Stepping::Statement, Some(_)) | (Stepping::Line, Some((_, 0))) => {
+						reason = Some("step");
+						break;
+					},
+					(Stepping::Continue, Some((line, 0))) if self.breakpoints.contains(&interpreter.book.lines[line].number) => {
+						reason = Some("breakpoint");
+						break;
+					},
+					_ => {},
+				}
+			}
+		}
+
+		self.flush_output(output)?;
+		let diagnostic = self.diagnostic.lock().unwrap().take();
+		if let Some(diagnostic) = diagnostic {
+			self.event(output, "output", json!({ "category": "stderr", "output": diagnostic }))?;
+		}
+
+		match (reason, self.catastrophe) {
+			(_, Some(line)) if self.pause_on_catastrophe => {
+				let message = ERROR_MESSAGES[thread_rng().gen_range(0..10)];
+				self.event(output, "stopped", json!({
+					"reason": "exception",
+					"description": format!("Catastrophe! (Line {})", line),
+					"text": message,
+					"threadId": THREAD_ID,
+					"allThreadsStopped": true,
+				}))
+			},
+			(Some(reason), None) => self.stop(output, reason),
+			_ => self.terminate(output),
+		}
+	}
+
+	fn terminate(&mut self, output: &mut impl Write) -> io::Result<()> {
+		self.interpreter = None;
+		let exit_code = if self.catastrophe.is_some() { 1 } else { 0 };
+		self.event(output, "exited", json!({ "exitCode": exit_code }))?;
+		self.event(output, "terminated", json!({}))
+	}
+
+	fn stack_trace(&self) -> Value {
+		let (interpreter, path) = match (&self.interpreter, &self.path) {
+			(Some(interpreter), Some(path)) => (interpreter, path),
+			_ => return json!({ "stackFrames": [], "totalFrames": 0 }),
+		};
+
+		let source = json!({ "path": path });
+
+		let mut frames = vec![];
+		match (interpreter.position(), self.catastrophe) {
+			(Some((line, statement)), _) => {
+				let source_line = &interpreter.book.lines[line];
+				let (name, column) = match source_line.statements().get(statement) {
+					Some(s) => (source_line.text[s.span.clone()].to_string(), s.span.start),
+					None => (source_line.text.trim().to_string(), 0),
+				};
+
+				frames.push(json!({ "id": 0, "name": name, "source": source, "line": source_line.number, "column": column + 1 }));
+			},
+			(None, Some(line)) => {
+				frames.push(json!({ "id": 0, "name": "Catastrophe!", "source": source, "line": line, "column": 1 }));
+			},
+			(None, None) => {},
+		}
+
+		// Each spell Reverberate could go back to is shown like a caller, the most recent first
+		for (i, (cast_line, amplifier)) in interpreter.program.spell_line_stack.iter().enumerate().rev() {
+			if let Some(source_line) = interpreter.book.lines.get(*cast_line) {
+				frames.push(json!({
+					"id": i + 1,
+					"name": format!("{} (charge {}, amplifier {})", source_line.text.trim(), i, amplifier),
+					"source": source,
+					"line": source_line.number,
+					"column": 1,
+				}));
+			}
+		}
+
+		json!({ "stackFrames": frames, "totalFrames": frames.len() })
+	}
+
+	fn variables(&self, reference: u64) -> Value {
+		let program = match &self.interpreter {
+			Some(interpreter) => &interpreter.program,
+			None => return json!({ "variables": [] }),
+		};
+
+		let variables = match reference {
+			CHAPTERS_REFERENCE => Value::Array(program.pages.iter().enumerate().map(|(i, page)| json!({
+				"name": BUILTINS_CHAPTERS[i],
+				"value": page.describe(),
+				"variablesReference": CHAPTER_PAGE_REFERENCE + i as u64,
+			})).collect()),
+			CAULDRON_REFERENCE => {
+				let cauldron = &program.cauldron;
+				let (page, page_reference) = match &cauldron.page {
+					Some(page) => (format!("{} {}", BUILTINS_CHAPTERS[page.page_type.clone() as usize], page.describe()), CAULDRON_PAGE_REFERENCE),
+					None => ("empty".into(), 0),
+				};
+
+				json!([
+					{ "name": "chapter", "value": page, "variablesReference": page_reference },
+					{ "name": "charge", "value": cauldron.spell_charge.to_string(), "variablesReference": 0 },
+					{ "name": "amplifier", "value": cauldron.spell_charge_amplifier.to_string(), "variablesReference": 0 },
+					{ "name": "mix mode", "value": format!("{:?}", cauldron.mix_mode), "variablesReference": 0 },
+				])
+			},
+			DRAWER_REFERENCE => Value::Array(program.drawer.iter().enumerate().rev().map(|(i, page)| json!({
+				"name": if i + 1 == program.drawer.len() { format!("{} (top)", i + 1) } else { (i + 1).to_string() },
+				"value": format!("{} {}", BUILTINS_CHAPTERS[page.page_type.clone() as usize], page.describe()),
+				"variablesReference": DRAWER_PAGE_REFERENCE + i as u64,
+			})).collect()),
+			DESK_REFERENCE => json!([
+				{
+					"name": "turned to",
+					"value": if program.turned_to_any_page { BUILTINS_CHAPTERS[program.current_page] } else { "nothing yet" },
+					"variablesReference": 0,
+				},
+				{
+					"name": "memory",
+					"value": match &program.memory {
+						Some(value) => value.print(),
+						None => "nothing".into(),
+					},
+					"variablesReference": 0,
+				},
+			]),
+			reference if (CHAPTER_PAGE_REFERENCE..CHAPTER_PAGE_REFERENCE + 4).contains(&reference) => {
+				page_variables(&program.pages[(reference - CHAPTER_PAGE_REFERENCE) as usize])
+			},
+			CAULDRON_PAGE_REFERENCE => match &program.cauldron.page {
+				Some(page) => page_variables(page),
+				None => json!([]),
+			},
+			reference if reference >= DRAWER_PAGE_REFERENCE => match program.drawer.get((reference - DRAWER_PAGE_REFERENCE) as usize) {
+				Some(page) => page_variables(page),
+				None => json!([]),
+			},
+			_ => json!([]),
+		};
+
+		json!({ "variables": variables })
+	}
+}
+
+pub fn serve(mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+	let mut session = Session{
+		seq: 0,
+		interpreter: None,
+		path: None,
+		output: CapturedOutput::default(),
+		requested_breakpoints: HashMap::new(),
+		breakpoints: HashSet::new(),
+		stop_on_entry: false,
+		pause_on_catastrophe: true,
+		catastrophe: None,
+		diagnostic: Arc::default(),
+	};
+
+	while let Some(request) = rpc::read_message(&mut input)? {
+		let command = request["command"].as_str().unwrap_or_default().to_string();
+		let arguments = &request["arguments"];
+		let body = match command.as_str() {
+			"initialize" => Ok(json!({
+				"supportsConfigurationDoneRequest": true,
+				"exceptionBreakpointFilters": [{ "filter": "catastrophe", "label": "Catastrophes", "default": true }],
+			})),
+			"launch" => session.launch(arguments),
+			"setBreakpoints" => Ok(session.set_breakpoints(arguments)),
+			"setExceptionBreakpoints" => {
+				let filters = arguments["filters"].as_array().cloned().unwrap_or_default();
+				session.pause_on_catastrophe = filters.iter().any(|f| f == "catastrophe");
+				Ok(Value::Null)
+			},
+			"configurationDone" | "continue" | "next" | "stepIn" | "stepOut" => Ok(Value::Null),
+			"threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "spellbook" }] })),
+			"stackTrace" => Ok(session.stack_trace()),
+			"scopes" => Ok(json!({ "scopes": [
+				{ "name": "Chapters", "variablesReference": CHAPTERS_REFERENCE, "expensive": false },
+				{ "name": "Cauldron", "variablesReference": CAULDRON_REFERENCE, "expensive": false },
+				{ "name": "Drawer", "variablesReference": DRAWER_REFERENCE, "expensive": false },
+				{ "name": "Desk", "variablesReference": DESK_REFERENCE, "expensive": false },
+			] })),
+			"variables" => Ok(session.variables(arguments["variablesReference"].as_u64().unwrap_or_default())),
+			"disconnect" => Ok(Value::Null),
+			_ => Err(format!("there's no command called `{}`", command)),
+		};
+
+		let response = match &body {
+			Ok(body) => json!({ "type": "response", "request_seq": request["seq"], "success": true, "command": command, "body": body }),
+			Err(message) => json!({ "type": "response", "request_seq": request["seq"], "success": false, "command": command, "message": message }),
+		};
+
+		session.send(&mut output, response)?;
+
+		match command.as_str() {
+			"initialize" => session.event(&mut output, "initialized", json!({}))?,
+			"configurationDone" => {
+				if session.stop_on_entry {
+					session.stop(&mut output, "entry")?;
+				} else if session.at_breakpoint() {
+					session.stop(&mut output, "breakpoint")?;
+				} else {
+					session.resume(&mut output, Stepping::Continue)?;
+				}
+			},
+			"continue" | "next" | "stepIn" | "stepOut" if session.interpreter.is_some() => {
+				// Once a catastrophe has been looked at, there's nothing left to run
+				if session.catastrophe.is_some() {
+					session.terminate(&mut output)?;
+				} else {
+					session.resume(&mut output, match command.as_str() {
+						"continue" => Stepping::Continue,
+						"stepIn" => Stepping::Statement,
+						_ => Stepping::Line,
+					})?;
+				}
+			},
+			"disconnect" => return Ok(()),
+			_ => {},
+		}
+	}
+
+	Ok(())
+}
+
+pub fn run(_args: &[String]) {
+	// Catastrophes are reported to the editor, and stdout belongs to the protocol
+	std::panic::set_hook(Box::new(|_| {}));
+
+	let stdin = io::stdin();
+	serve(stdin.lock(), io::stdout()).unwrap();
+}
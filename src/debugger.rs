@@ -0,0 +1,575 @@
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+//        SPELLBOOK INTERPRETER
+//           BY DIANE SPARKS
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+
+use crate::constants::*;
+use crate::errors::set_catastrophe_hook;
+use crate::interpreter::{Interpreter, Snapshot};
+use crate::page::Page;
+use crate::program::Program;
+use crate::syntax;
+use crate::variant::Variant;
+
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::process;
+
+const HELP: &str = "break <line>                    stop before reading a line
+break on spell <spell>          stop before casting a spell
+break when <value> <op> <x>     stop when a comparison becomes true, like `break when cauldron charge >= 3`
+break                           list breakpoints and watches
+delete <n>                      forget breakpoint n
+watch <chapter>.<heading>       stop whenever an entry changes
+step [n]                        read the next statement, or the next n
+next                            read the rest of the line
+continue                        read until something stops you
+reverse-step [n]                go back a statement, or n of them
+reverse-next                    go back to the start of the line
+reverse-continue                go back until something stops you
+last-write <chapter>.<heading>  find when an entry was last written
+print <chapter>                 show a chapter's entries
+print <chapter>.<heading>       show one entry
+print drawer|cauldron|memory    show the rest of your desk
+where                           show the spells Reverberate could go back to
+quit                            close the spellbook
+
+Values to compare are `cauldron charge`, `cauldron amplifier`, `drawer size`, `memory`, or <chapter>.<heading>.";
+
+#[derive(Clone, Copy)]
+enum Stepping {
+	Continue,
+	Line,
+	Statement,
+}
+
+enum Quantity {
+	Charge,
+	Amplifier,
+	DrawerSize,
+	Memory,
+	Entry(usize, String),
+}
+
+enum Breakpoint {
+	Line(usize),
+	Spell(String),
+	// Whether the comparison held after the last statement, so it only stops once each time it becomes true
+	When{ quantity: Quantity, comparison: Ordering, or_equal: bool, negated: bool, value: String, text: String, held: bool },
+}
+
+// The desk at one point in the spellbook's history, and how it got there from the point before
+struct Moment {
+	snapshot: Snapshot,
+	line: usize,
+	wrote: Vec<(usize, String)>,
+	catastrophe: Option<usize>,
+	// What each watch and breakpoint saw here, so leaving this moment in either direction
+	// compares against it. Ones set later than this moment aren't in it
+	watched: Vec<Option<Variant>>,
+	held: Vec<bool>,
+	size: usize,
+}
+
+impl Moment {
+	fn new(snapshot: Snapshot, line: usize, wrote: Vec<(usize, String)>, catastrophe: Option<usize>, watched: Vec<Option<Variant>>, held: Vec<bool>) -> Self {
+		let size = std::mem::size_of::<Moment>() + snapshot.size() + wrote.iter().map(|(_, heading)| heading.len()).sum::<usize>();
+		Self{ snapshot, line, wrote, catastrophe, watched, held, size }
+	}
+}
+
+enum Travel {
+	Moved,
+	Stuck,
+	Catastrophe,
+}
+
+const DEFAULT_HISTORY: usize = 10000;
+
+// However few statements that makes, the history never takes up more than this many bytes
+const HISTORY_BYTES: usize = 256 * 1024 * 1024;
+
+struct Watch {
+	chapter: usize,
+	heading: String,
+	last: Option<Variant>,
+}
+
+fn find_chapter(name: &str) -> Option<usize> {
+	BUILTINS_CHAPTERS.iter().position(|c| c.eq_ignore_ascii_case(name))
+}
+
+fn parse_entry(text: &str) -> Result<(usize, String), String> {
+	match text.split_once('.') {
+		Some((chapter, heading)) if !heading.is_empty() => match find_chapter(chapter) {
+			Some(chapter) => Ok((chapter, heading.to_string())),
+			None => Err(format!("there's no chapter called `{}`", chapter)),
+		},
+		_ => Err(format!("`{}` should look like <chapter>.<heading>", text)),
+	}
+}
+
+fn parse_quantity(words: &[&str]) -> Result<Quantity, String> {
+	match words {
+		["cauldron", "charge"] => Ok(Quantity::Charge),
+		["cauldron", "amplifier"] => Ok(Quantity::Amplifier),
+		["drawer", "size"] => Ok(Quantity::DrawerSize),
+		["memory"] => Ok(Quantity::Memory),
+		[entry] => parse_entry(entry).map(|(chapter, heading)| Quantity::Entry(chapter, heading)),
+		_ => Err(format!("I don't know how to measure `{}`", words.join(" "))),
+	}
+}
+
+fn parse_condition(words: &[&str]) -> Result<Breakpoint, String> {
+	let operator = words.iter().position(|w| ["==", "!=", "<", "<=", ">", ">="].contains(w)).ok_or("expected a comparison like `>=`")?;
+	let (comparison, or_equal, negated) = match words[operator] {
+		"==" => (Ordering::Equal, false, false),
+		"!=" => (Ordering::Equal, false, true),
+		"<" => (Ordering::Less, false, false),
+		"<=" => (Ordering::Less, true, false),
+		">" => (Ordering::Greater, false, false),
+		_ => (Ordering::Greater, true, false),
+	};
+
+	if operator + 1 >= words.len() {
+		return Err(format!("expected something to compare to after `{}`", words[operator]));
+	}
+
+	Ok(Breakpoint::When{
+		quantity: parse_quantity(&words[..operator])?,
+		comparison,
+		or_equal,
+		negated,
+		value: words[operator + 1..].join(" ").trim_matches('"').to_string(),
+		text: words.join(" "),
+		held: false,
+	})
+}
+
+fn compare(left: &Variant, right: &str) -> Ordering {
+	match (left.to_float(), right.parse::<f64>()) {
+		(Some(left), Ok(right)) => left.partial_cmp(&right).unwrap_or(Ordering::Equal),
+		_ => left.print().as_str().cmp(right),
+	}
+}
+
+fn parse_count(text: &str) -> Result<usize, String> {
+	text.parse::<usize>().map_err(|_| format!("`{}` isn't a number of statements", text))
+}
+
+fn describe_value(value: &Option<Variant>) -> String {
+	match value {
+		Some(value) => value.print(),
+		None => "nothing".into(),
+	}
+}
+
+fn print_page(name: &str, page: &Page) {
+	println!("{}", name);
+	for i in 0..3 {
+		println!("  {}: {}", page.entry_label(i), page.entry_text(i));
+	}
+}
+
+struct Debugger {
+	interpreter: Interpreter,
+	breakpoints: Vec<Breakpoint>,
+	watches: Vec<Watch>,
+
+	// Only the last `window` statements are remembered, and fewer when they'd take up more than
+	// HISTORY_BYTES. The cursor is the moment on the desk, which is behind the end of the history
+	// after going back
+	history: VecDeque<Moment>,
+	history_size: usize,
+	cursor: usize,
+	window: usize,
+}
+
+impl Debugger {
+	fn quantity(&self, quantity: &Quantity) -> Option<Variant> {
+		let program = &self.interpreter.program;
+		match quantity {
+			Quantity::Charge => Some(Variant::Integer(program.cauldron.spell_charge as i64)),
+			Quantity::Amplifier => Some(Variant::Integer(program.cauldron.spell_charge_amplifier as i64)),
+			Quantity::DrawerSize => Some(Variant::Integer(program.drawer.len() as i64)),
+			Quantity::Memory => program.memory.clone(),
+			Quantity::Entry(chapter, heading) => program.pages[*chapter].read_value_by_name(heading),
+		}
+	}
+
+	fn holds(&self, breakpoint: &Breakpoint) -> bool {
+		match breakpoint {
+			Breakpoint::When{ quantity, comparison, or_equal, negated, value: right, .. } => self.quantity(quantity).is_some_and(|left| {
+				let ordering = compare(&left, right);
+				(ordering == *comparison || *or_equal && ordering == Ordering::Equal) != *negated
+			}),
+			_ => false,
+		}
+	}
+
+	fn watched(&self) -> Vec<Option<Variant>> {
+		self.watches.iter().map(|w| self.interpreter.program.pages[w.chapter].read_value_by_name(&w.heading)).collect()
+	}
+
+	fn holding(&self) -> Vec<bool> {
+		self.breakpoints.iter().map(|b| self.holds(b)).collect()
+	}
+
+	// Picks up what the watches and breakpoints saw at a moment, before moving away from it
+	fn recall(&mut self, index: usize) {
+		let moment = &self.history[index];
+		for (watch, value) in self.watches.iter_mut().zip(&moment.watched) {
+			watch.last = value.clone();
+		}
+
+		for (breakpoint, holds) in self.breakpoints.iter_mut().zip(&moment.held) {
+			if let Breakpoint::When{ held, .. } = breakpoint {
+				*held = *holds;
+			}
+		}
+	}
+
+	fn remember(&mut self, moment: Moment) {
+		self.history_size += moment.size;
+		self.history.push_back(moment);
+		while self.history.len() > self.window + 1 || self.history_size > HISTORY_BYTES && self.history.len() > 1 {
+			if let Some(forgotten) = self.history.pop_front() {
+				self.history_size -= forgotten.size;
+			}
+		}
+
+		self.cursor = self.history.len() - 1;
+	}
+
+	fn show_position(&self) {
+		let (line, statement) = match self.interpreter.position() {
+			Some(position) => position,
+			None => return,
+		};
+
+		let source_line = &self.interpreter.book.lines[line];
+		println!("{:>4} | {}", source_line.number, source_line.text);
+
+		// Point out the statement when the line has more than one
+		let statements = source_line.statements();
+		if let (true, Some(statement)) = (statements.len() > 1, statements.get(statement)) {
+			let indent = source_line.text[..statement.span.start].chars().count();
+			let width = source_line.text[statement.span.clone()].chars().count();
+			println!("     | {}{}", " ".repeat(indent), "^".repeat(width));
+		}
+	}
+
+	// Checks everything that could stop the spellbook after a statement was read
+	fn should_stop(&mut self, stepping: Stepping) -> bool {
+		let mut stop = false;
+		let watched = self.watched();
+		for (watch, value) in self.watches.iter_mut().zip(watched) {
+			if watch.last != value {
+				println!("{}.{}: {} -> {}", BUILTINS_CHAPTERS[watch.chapter], watch.heading, describe_value(&watch.last), describe_value(&value));
+				watch.last = value;
+				stop = true;
+			}
+		}
+
+		let position = match self.interpreter.position() {
+			Some(position) => position,
+			None => return stop,
+		};
+
+		let number = self.interpreter.book.lines[position.0].number;
+		let statement = self.interpreter.book.lines[position.0].statements().get(position.1).cloned();
+		let holding = self.holding();
+		for (i, (breakpoint, holds)) in self.breakpoints.iter_mut().zip(holding).enumerate() {
+			let hit = match breakpoint {
+				Breakpoint::Line(line) => position.1 == 0 && *line == number,
+				Breakpoint::Spell(spell) => statement.as_ref().is_some_and(|s| s.spell() == Some(spell.as_str())),
+				Breakpoint::When{ held, .. } => {
+					let became_true = holds && !*held;
+					*held = holds;
+					became_true
+				},
+			};
+
+			if hit {
+				println!("Breakpoint {}", i + 1);
+				stop = true;
+			}
+		}
+
+		stop || match stepping {
+			Stepping::Statement => true,
+			Stepping::Line => position.1 == 0,
+			Stepping::Continue => false,
+		}
+	}
+
+	fn forward(&mut self) -> Travel {
+		// After going back, the way forward is already known, and reading it again would read
+		// the input and publish twice
+		if self.cursor + 1 < self.history.len() {
+			self.cursor += 1;
+			let moment = &self.history[self.cursor];
+			self.interpreter.restore(moment.snapshot.clone());
+			if let Some(line) = moment.catastrophe {
+				println!("Catastrophe! (Line {})", line);
+				return Travel::Catastrophe;
+			}
+
+			return Travel::Moved;
+		}
+
+		let line = match self.interpreter.position() {
+			Some((line, _)) => self.interpreter.book.lines[line].number,
+			None => return Travel::Stuck,
+		};
+
+		let before = self.interpreter.program.pages.clone();
+
+		// The catastrophe itself is announced by the panic hook
+		let catastrophe = self.interpreter.try_step(Interpreter::step_statement).err();
+
+		// Putting a page back writes every entry on it just as much as a write does
+		let pages = &self.interpreter.program.pages;
+		let wrote = pages.iter().zip(&before).enumerate()
+			.flat_map(|(chapter, (page, before))| page.written_since(before).into_iter().map(move |i| (chapter, page.entry_names[i].clone())))
+			.collect();
+		let moment = Moment::new(self.interpreter.snapshot(), line, wrote, catastrophe, self.watched(), self.holding());
+		self.remember(moment);
+		match catastrophe {
+			Some(_) => Travel::Catastrophe,
+			None => Travel::Moved,
+		}
+	}
+
+	fn backward(&mut self) -> Travel {
+		if self.cursor == 0 {
+			return Travel::Stuck;
+		}
+
+		self.cursor -= 1;
+		self.interpreter.restore(self.history[self.cursor].snapshot.clone());
+		Travel::Moved
+	}
+
+	fn resume(&mut self, stepping: Stepping, backwards: bool, times: usize) {
+		let mut travel = Travel::Moved;
+		let mut steps = 0;
+		while steps < times {
+			self.recall(self.cursor);
+			travel = if backwards { self.backward() } else { self.forward() };
+			if !matches!(travel, Travel::Moved) {
+				break;
+			}
+
+			if self.should_stop(stepping) {
+				steps += 1;
+			}
+		}
+
+		let _ = io::stdout().flush();
+		match travel {
+			Travel::Catastrophe => println!("Nothing more can be read, but you can still look around your desk or go back."),
+			Travel::Stuck if backwards => println!("That's as far back as the history goes."),
+			_ if self.interpreter.is_finished() => println!("The spellbook has been closed."),
+			_ => {},
+		}
+
+		self.show_position();
+	}
+
+	fn last_write(&self, entry: &str) -> Result<(), String> {
+		let (chapter, heading) = parse_entry(entry)?;
+		let found = (1..=self.cursor).rev().find(|&i| self.history[i].wrote.iter().any(|(c, h)| *c == chapter && *h == heading));
+		match found {
+			Some(i) => println!("{}.{} was last written on line {}, {} statement(s) ago.", BUILTINS_CHAPTERS[chapter], heading, self.history[i].line, self.cursor + 1 - i),
+			None => println!("{}.{} wasn't written in the {} statement(s) remembered.", BUILTINS_CHAPTERS[chapter], heading, self.cursor),
+		}
+
+		Ok(())
+	}
+
+	fn print(&self, what: &[&str]) -> Result<(), String> {
+		let program = &self.interpreter.program;
+		match what {
+			["drawer"] => {
+				if program.drawer.is_empty() {
+					println!("The drawer is empty.");
+				}
+
+				for (i, page) in program.drawer.iter().enumerate().rev() {
+					let top = if i + 1 == program.drawer.len() { " (top)" } else { "" };
+					println!("{}{}: {} {}", i + 1, top, BUILTINS_CHAPTERS[page.page_type.clone() as usize], page.describe());
+				}
+			},
+			["cauldron"] => {
+				let cauldron = &program.cauldron;
+				match &cauldron.page {
+					Some(page) => print_page(&format!("Cauldron, holding {}", BUILTINS_CHAPTERS[page.page_type.clone() as usize]), page),
+					None => println!("Cauldron, empty"),
+				}
+
+				println!("  charge: {}", cauldron.spell_charge);
+				println!("  amplifier: {}", cauldron.spell_charge_amplifier);
+				println!("  mix mode: {:?}", cauldron.mix_mode);
+			},
+			["memory"] => println!("{}", describe_value(&program.memory)),
+			[name] if !name.contains('.') => match find_chapter(name) {
+				Some(chapter) => {
+					let turned_to = if program.turned_to_any_page && program.current_page == chapter { " (turned to)" } else { "" };
+					print_page(&format!("{}{}", BUILTINS_CHAPTERS[chapter], turned_to), &program.pages[chapter]);
+				},
+				None => return Err(format!("there's no chapter called `{}`", name)),
+			},
+			[entry] => {
+				let (chapter, heading) = parse_entry(entry)?;
+				println!("{}", describe_value(&program.pages[chapter].read_value_by_name(&heading)));
+			},
+			_ => return Err("print a chapter, an entry, the drawer, the cauldron, or memory".into()),
+		}
+
+		Ok(())
+	}
+
+	fn where_am_i(&self) {
+		let interpreter = &self.interpreter;
+		if let Some((line, _)) = interpreter.position() {
+			let source_line = &interpreter.book.lines[line];
+			println!("-> line {}: {}", source_line.number, source_line.text.trim());
+		}
+
+		for (i, (cast_line, amplifier)) in interpreter.program.spell_line_stack.iter().enumerate().rev() {
+			if let Some(source_line) = interpreter.book.lines.get(*cast_line) {
+				println!("   line {}: {} (charge {}, amplifier {})", source_line.number, source_line.text.trim(), i, amplifier);
+			}
+		}
+	}
+
+	fn list_breakpoints(&self) {
+		if self.breakpoints.is_empty() && self.watches.is_empty() {
+			println!("No breakpoints or watches.");
+		}
+
+		for (i, breakpoint) in self.breakpoints.iter().enumerate() {
+			match breakpoint {
+				Breakpoint::Line(line) => println!("{}: line {}", i + 1, line),
+				Breakpoint::Spell(spell) => println!("{}: on spell {}", i + 1, spell),
+				Breakpoint::When{ text, .. } => println!("{}: when {}", i + 1, text),
+			}
+		}
+
+		for watch in &self.watches {
+			println!("watching {}.{}", BUILTINS_CHAPTERS[watch.chapter], watch.heading);
+		}
+	}
+
+	fn command(&mut self, words: &[&str]) -> Result<bool, String> {
+		match words {
+			[] => {},
+			["break"] => self.list_breakpoints(),
+			["break", "on", "spell", spell] => {
+				let spell = BUILTINS_SPELLS.keys().find(|s| s.eq_ignore_ascii_case(spell)).ok_or(format!("there's no spell called `{}`", spell))?;
+				self.breakpoints.push(Breakpoint::Spell(spell.to_string()));
+				println!("Breakpoint {}: on spell {}", self.breakpoints.len(), spell);
+			},
+			["break", "when", condition @ ..] => {
+				self.breakpoints.push(parse_condition(condition)?);
+				println!("Breakpoint {}: when {}", self.breakpoints.len(), condition.join(" "));
+			},
+			["break", line] => {
+				let line = line.parse::<usize>().map_err(|_| format!("`{}` isn't a line number", line))?;
+				if !self.interpreter.book.lines.iter().any(|l| l.number == line) {
+					return Err(format!("there's nothing to read on line {}", line));
+				}
+
+				self.breakpoints.push(Breakpoint::Line(line));
+				println!("Breakpoint {}: line {}", self.breakpoints.len(), line);
+			},
+			["delete", n] => match n.parse::<usize>() {
+				Ok(n) if (1..=self.breakpoints.len()).contains(&n) => {
+					self.breakpoints.remove(n - 1);
+					for moment in self.history.iter_mut().filter(|m| m.held.len() >= n) {
+						moment.held.remove(n - 1);
+					}
+
+					println!("Deleted breakpoint {}", n);
+				},
+				_ => return Err(format!("there's no breakpoint {}", n)),
+			},
+			["watch", entry] => {
+				let (chapter, heading) = parse_entry(entry)?;
+				let last = self.interpreter.program.pages[chapter].read_value_by_name(&heading);
+				println!("Watching {}.{}, now {}", BUILTINS_CHAPTERS[chapter], heading, describe_value(&last));
+				self.watches.push(Watch{ chapter, heading, last });
+			},
+			["step"] | ["s"] => self.resume(Stepping::Statement, false, 1),
+			["step", n] | ["s", n] => self.resume(Stepping::Statement, false, parse_count(n)?),
+			["next"] | ["n"] => self.resume(Stepping::Line, false, 1),
+			["continue"] | ["c"] => self.resume(Stepping::Continue, false, 1),
+			["reverse-step"] | ["rs"] => self.resume(Stepping::Statement, true, 1),
+			["reverse-step", n] | ["rs", n] => self.resume(Stepping::Statement, true, parse_count(n)?),
+			["reverse-next"] | ["rn"] => self.resume(Stepping::Line, true, 1),
+			["reverse-continue"] | ["rc"] => self.resume(Stepping::Continue, true, 1),
+			["last-write", entry] => self.last_write(entry)?,
+			["print", what @ ..] | ["p", what @ ..] => self.print(what)?,
+			["where"] => self.where_am_i(),
+			["help"] => println!("{}", HELP),
+			["quit"] | ["q"] => return Ok(false),
+			_ => return Err(format!("I don't know how to `{}`. Type `help` for commands.", words.join(" "))),
+		}
+
+		Ok(true)
+	}
+}
+
+pub fn run(args: &[String]) {
+	let mut path = String::new();
+	let mut window = DEFAULT_HISTORY;
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--history" => match args.next().and_then(|n| n.parse().ok()) {
+				Some(n) => window = n,
+				None => {
+					eprintln!("--history needs a number of statements to remember, like `--history {}`.", DEFAULT_HISTORY);
+					process::exit(1);
+				},
+			},
+			_ => {
+				path = arg.into();
+			},
+		}
+	}
+
+	if path.is_empty() {
+		panic!();
+	}
+
+	set_catastrophe_hook(path.clone());
+	let source = syntax::read_source(&path).unwrap();
+	let interpreter = Interpreter::new(syntax::parse_source(&source), Program::new(false));
+	let start = Moment::new(interpreter.snapshot(), 0, vec![], None, vec![], vec![]);
+	let mut debugger = Debugger{ interpreter, breakpoints: vec![], watches: vec![], history: VecDeque::new(), history_size: 0, cursor: 0, window };
+	debugger.remember(start);
+
+	println!("Debugging {}. Type `help` for commands.", path);
+	debugger.show_position();
+	loop {
+		print!("(spellbook) ");
+		let _ = io::stdout().flush();
+
+		// Commands come from the same place as the spellbook's own input, so Coadjuvancy reads
+		// the next line typed at the prompt
+		let mut line = String::new();
+		match debugger.interpreter.program.input.read_line(&mut line) {
+			Ok(0) | Err(_) => break,
+			Ok(_) => {},
+		}
+
+		match debugger.command(&line.split_whitespace().collect::<Vec<&str>>()) {
+			Ok(true) => {},
+			Ok(false) => break,
+			Err(message) => println!("{}", message),
+		}
+	}
+}
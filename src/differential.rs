@@ -0,0 +1,227 @@
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+//        SPELLBOOK INTERPRETER
+//           BY DIANE SPARKS
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+
+use crate::compiler;
+use crate::formatter;
+use crate::generator;
+use crate::interpreter::Interpreter;
+use crate::optimizer;
+use crate::program::{CapturedOutput, Program};
+use crate::syntax::{self, Spellbook};
+
+use rand::prelude::*;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::mem;
+use std::panic;
+use std::path::Path;
+use std::process::{self, Command, Stdio};
+
+// Every way there is to run a spellbook, which should all end up publishing the same thing.
+// Minifying isn't one of them, since it puts lines together and a catastrophe can't be on
+// the same line afterwards
+pub const ENGINES: [&str; 5] = [
+	"interpreter",
+	"snapshot",
+	"formatted",
+	"optimized",
+	"c",
+];
+
+// The ones that don't need anything besides the interpreter itself
+const DEFAULT_ENGINES: [&str; 4] = [
+	"interpreter",
+	"snapshot",
+	"formatted",
+	"optimized",
+];
+
+#[derive(PartialEq)]
+pub struct Outcome {
+	pub published: String,
+	pub catastrophe: Option<usize>,
+	// Still going after as many statements as it was allowed
+	pub exhausted: bool,
+}
+
+impl Outcome {
+	// A spellbook still going could just have been stopped sooner, since not every engine
+	// reads the same number of statements to get as far
+	fn agrees(&self, other: &Outcome) -> bool {
+		self == other
+			|| (self.exhausted && other.published.starts_with(&self.published))
+			|| (other.exhausted && self.published.starts_with(&other.published))
+	}
+
+	// Just the part of what was published around where it stops agreeing with the other
+	fn describe(&self, other: &Outcome) -> String {
+		let ending = match (self.catastrophe, self.exhausted) {
+			(Some(line), _) => format!("catastrophe on line {}", line),
+			(None, true) => "still going".into(),
+			(None, false) => "finished".into(),
+		};
+
+		let same = self.published.chars().zip(other.published.chars()).take_while(|(a, b)| a == b).count();
+		let start = same.saturating_sub(20);
+		let excerpt = self.published.chars().skip(start).take(60).collect::<String>();
+		let more = self.published.chars().count() > start + 60;
+		format!("{}, published {}{:?}{} ({} characters)", ending, if start > 0 { "…" } else { "" }, excerpt, if more { "…" } else { "" }, self.published.chars().count())
+	}
+}
+
+fn program(output: &CapturedOutput) -> Program {
+	let mut program = Program::new(false);
+	program.forgetful = false;
+	program.input = Box::new(io::empty());
+	program.output = Box::new(output.clone());
+	program
+}
+
+// Moves the desk onto a brand new program before every statement, so anything the desk
+// doesn't remember shows up as a difference
+fn step_on_fresh_desk(interpreter: &mut Interpreter) {
+	let snapshot = interpreter.snapshot();
+	let mut program = Program::new(false);
+	program.forgetful = false;
+	program.input = mem::replace(&mut interpreter.program.input, Box::new(io::empty()));
+	program.output = mem::replace(&mut interpreter.program.output, Box::new(io::sink()));
+	interpreter.program = program;
+	interpreter.restore(snapshot);
+	interpreter.step_statement();
+}
+
+fn interpret(book: Spellbook, fuel: usize, step: fn(&mut Interpreter)) -> Outcome {
+	let output = CapturedOutput::default();
+	let mut interpreter = Interpreter::new(book, program(&output));
+	let mut catastrophe = None;
+	let mut statements = 0;
+	while !interpreter.is_finished() && statements < fuel {
+		if let Err(line) = interpreter.try_step(step) {
+			catastrophe = Some(line);
+		}
+
+		statements += 1;
+	}
+
+	Outcome{ published: output.take_text(), catastrophe, exhausted: !interpreter.is_finished() }
+}
+
+// Compiled with the system C compiler, counting statements and never forgetting just like
+// the interpreter does here
+fn compile_and_run_c(source: &str, fuel: usize) -> Outcome {
+	let dir = env::temp_dir().join(format!("spellbook-differential-{}", process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	let c_path = dir.join("spellbook.c");
+	let exe_path = dir.join("spellbook");
+	fs::write(&c_path, compiler::compile_c(&syntax::parse_source(source))).unwrap();
+
+	let compiled = Command::new("cc")
+		.args(["-O1", "-w", &format!("-DSB_FUEL={}", fuel), "-DSB_FORGETFUL=0", "-o"])
+		.arg(&exe_path)
+		.arg(&c_path)
+		.arg("-lm")
+		.status()
+		.unwrap();
+	assert!(compiled.success(), "the C compiler couldn't compile {}", c_path.display());
+
+	let output = Command::new(&exe_path).stdin(Stdio::null()).output().unwrap();
+	let stderr = String::from_utf8_lossy(&output.stderr);
+	let catastrophe = match output.status.code() {
+		Some(101) => stderr.rsplit("(Line ").next().and_then(|l| l.trim().trim_end_matches(')').parse().ok()),
+		_ => None,
+	};
+
+	Outcome{ published: String::from_utf8_lossy(&output.stdout).to_string(), catastrophe, exhausted: output.status.code() == Some(3) }
+}
+
+pub fn run_engine(engine: &str, source: &str, fuel: usize) -> Outcome {
+	match engine {
+		"interpreter" => interpret(syntax::parse_source(source), fuel, Interpreter::step_statement),
+		"snapshot" => interpret(syntax::parse_source(source), fuel, step_on_fresh_desk),
+		"formatted" => interpret(syntax::parse_source(&formatter::format_source(source).output), fuel, Interpreter::step_statement),
+		"optimized" => {
+			let mut book = syntax::parse_source(source);
+			optimizer::optimize(&mut book);
+			interpret(book, fuel, Interpreter::step_statement)
+		},
+		"c" => compile_and_run_c(source, fuel),
+		_ => unreachable!(),
+	}
+}
+
+pub fn run(args: &[String]) {
+	let mut count = 1000;
+	let mut seed: u64 = thread_rng().gen();
+	let mut lines = 40;
+	let mut fuel = 10000;
+	let mut engines = DEFAULT_ENGINES.iter().map(|e| e.to_string()).collect::<Vec<String>>();
+	let mut save_dir = None;
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--count" => count = args.next().unwrap().parse().unwrap(),
+			"--seed" => seed = args.next().unwrap().parse().unwrap(),
+			"--lines" => lines = args.next().unwrap().parse().unwrap(),
+			"--fuel" => fuel = args.next().unwrap().parse().unwrap(),
+			"--engines" => engines = args.next().unwrap().split(',').map(String::from).collect(),
+			"--save" => save_dir = Some(args.next().unwrap().clone()),
+			_ => panic!(),
+		}
+	}
+
+	if let Some(engine) = engines.iter().find(|e| !ENGINES.contains(&e.as_str())) {
+		eprintln!("There's no engine called {}. Try {}.", engine, ENGINES.join(", "));
+		process::exit(1);
+	}
+
+	if engines.len() < 2 {
+		eprintln!("Comparing needs at least two engines.");
+		process::exit(1);
+	}
+
+	// Catastrophes are expected here, and counted instead of told
+	panic::set_hook(Box::new(|_| {}));
+	let (mut finished, mut catastrophes, mut exhausted, mut disagreed) = (0, 0, 0, 0);
+	for i in 0..count {
+		let program_seed = seed.wrapping_add(i);
+		let source = generator::generate(program_seed, lines);
+		let reference = run_engine(&engines[0], &source, fuel);
+		let mut agreed = true;
+		for engine in &engines[1..] {
+			let outcome = run_engine(engine, &source, fuel);
+			if outcome.agrees(&reference) {
+				continue;
+			}
+
+			agreed = false;
+			println!("seed {}: {} and {} disagree", program_seed, engines[0], engine);
+			println!("  {}: {}", engines[0], reference.describe(&outcome));
+			println!("  {}: {}", engine, outcome.describe(&reference));
+		}
+
+		if !agreed {
+			disagreed += 1;
+			println!("  see it with `spellbook generate --seed {} --lines {}`", program_seed, lines);
+			if let Some(save_dir) = &save_dir {
+				fs::write(Path::new(save_dir).join(format!("{}.spb", program_seed)), &source).unwrap();
+			}
+		} else if reference.catastrophe.is_some() {
+			catastrophes += 1;
+		} else if reference.exhausted {
+			exhausted += 1;
+		} else {
+			finished += 1;
+		}
+	}
+
+	println!("{} spellbooks from seed {}: {} finished, {} ended in a catastrophe, {} were still going after {} statements, {} disagreed",
+		count, seed, finished, catastrophes, exhausted, fuel, disagreed);
+
+	if disagreed > 0 {
+		process::exit(1);
+	}
+}
@@ -3,10 +3,104 @@
 //           BY DIANE SPARKS
 // *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
 
-#[allow(unused)]
+use crate::constants::ERROR_MESSAGES;
+use crate::syntax::{SourceLine, Statement, SyntaxError};
+
+use rand::prelude::*;
+
+use std::ops::Range;
+use std::panic;
+use std::sync::{Arc, Mutex};
+
+// A catastrophe that knows exactly which words on its line caused it, thrown instead of a
+// bare line number so it can be pointed out
 pub struct SpellbookError {
 	pub message: String,
 	pub line: usize,
+	pub text: String,
+	pub span: Range<usize>,
+	pub suggestion: Option<String>,
+}
+
+impl SpellbookError {
+	pub fn from_syntax(line: &SourceLine, error: &SyntaxError) -> Self {
+		Self{
+			message: error.message.clone(),
+			line: line.number,
+			text: line.text.clone(),
+			span: error.span.clone(),
+			suggestion: error.suggestion.clone(),
+		}
+	}
+
+	pub fn unknown_spell(line: &SourceLine, statement: &Statement, spell: &str) -> Self {
+		let span = line.lexemes.iter()
+			.find(|l| l.span.start > statement.span.start && l.span.end <= statement.span.end && &line.text[l.span.clone()] == spell)
+			.map_or(statement.span.clone(), |l| l.span.clone());
+
+		Self{
+			message: format!("there's no spell called `{}`", spell),
+			line: line.number,
+			text: line.text.clone(),
+			span,
+			suggestion: closest(spell, crate::constants::BUILTINS_SPELLS.keys().copied()).map(String::from),
+		}
+	}
+
+	// Shown the way rustc shows its errors, with the line and a caret under the words
+	pub fn render(&self, path: &str) -> String {
+		let number = self.line.to_string();
+		let gutter = " ".repeat(number.len());
+		let start = self.span.start.min(self.text.len());
+		let column = column(&self.text, start);
+
+		// Tabs stay tabs so the caret still lines up under them
+		let indent = self.text[..start].chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect::<String>();
+		let width = self.text[start..self.span.end.clamp(start, self.text.len())].chars().count().max(1);
+
+		let mut rendered = format!("\x1b[0;91merror\x1b[0m: {}\n", self.message);
+		rendered.push_str(&format!("{}--> {}:{}:{}\n", gutter, path, self.line, column));
+		rendered.push_str(&format!("{} |\n", gutter));
+		rendered.push_str(&format!("{} | {}\n", number, self.text));
+		rendered.push_str(&format!("{} | {}{}\n", gutter, indent, "^".repeat(width)));
+		if let Some(suggestion) = &self.suggestion {
+			rendered.push_str(&format!("{} |\n", gutter));
+			rendered.push_str(&format!("{} = help: did you mean `{}`?\n", gutter, suggestion));
+		}
+
+		rendered
+	}
+}
+
+// Catastrophes that know where they happened are pointed out in the source, with the usual
+// flavor underneath
+pub fn set_catastrophe_hook(path: String) {
+	panic::set_hook(Box::new(move |info| {
+		let mut rng = thread_rng();
+		let index: usize = rng.gen_range(0..10);
+		let message = ERROR_MESSAGES[index];
+		if let Some(error) = info.payload().downcast_ref::<SpellbookError>() {
+			eprintln!("{}\x1b[0;91mCatastrophe!\x1b[0m\n{}", error.render(&path), message);
+		} else if let Some(line) = info.payload().downcast_ref::<usize>() {
+			eprintln!("\x1b[0;91mCatastrophe!\x1b[0m\n{}\n(Line {})", message, line);
+		} else {
+			eprintln!("\x1b[0;91mCatastrophe!\x1b[0m\n{}", message);
+		}
+	}));
+}
+
+// For when stderr isn't free to write on, like while the screen is taken over. Catastrophes
+// are kept quiet, but any that can be pointed out are kept to show later
+pub fn keep_catastrophes(path: String) -> Arc<Mutex<Option<String>>> {
+	let kept = Arc::new(Mutex::new(None));
+	let keeper = kept.clone();
+	panic::set_hook(Box::new(move |info| {
+		if let Some(error) = info.payload().downcast_ref::<SpellbookError>() {
+			*keeper.lock().unwrap() = Some(error.render(&path));
+		}
+	}));
+
+	kept
 }
 
 #[macro_export]
@@ -15,3 +109,46 @@ macro_rules! sb_panic {
 		std::panic::panic_any($line)
 	};
 }
+
+// Which character a byte offset into the line lands on, counting from one like editors do
+pub fn column(text: &str, offset: usize) -> usize {
+	text[..offset.min(text.len())].chars().count() + 1
+}
+
+// How many letters have to be added, taken away, changed or swapped around to get from one
+// word to the other
+pub fn edit_distance(a: &str, b: &str) -> usize {
+	let (a, b) = (a.chars().collect::<Vec<char>>(), b.chars().collect::<Vec<char>>());
+	let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+	for (i, row) in rows.iter_mut().enumerate() {
+		row[0] = i;
+	}
+
+	for (j, cell) in rows[0].iter_mut().enumerate() {
+		*cell = j;
+	}
+
+	for i in 1..=a.len() {
+		for j in 1..=b.len() {
+			let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+			let mut best = (rows[i - 1][j] + 1).min(rows[i][j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+			if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+				best = best.min(rows[i - 2][j - 2] + 1);
+			}
+
+			rows[i][j] = best;
+		}
+	}
+
+	rows[a.len()][b.len()]
+}
+
+// The candidate that's probably what was meant, if any of them are close enough to be a typo
+pub fn closest<'a>(word: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+	let allowed = (word.chars().count() / 3).max(1);
+	candidates.into_iter()
+		.map(|c| (edit_distance(word, c), c))
+		.filter(|(distance, _)| *distance > 0 && *distance <= allowed)
+		.min()
+		.map(|(_, c)| c)
+}
@@ -0,0 +1,55 @@
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+//        SPELLBOOK INTERPRETER
+//           BY DIANE SPARKS
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+
+use crate::lexer::LexemeKind;
+use crate::syntax::{self, *};
+use crate::trace::statement_json;
+
+use serde_json::{json, Value};
+
+use std::ops::Range;
+
+// Spans are byte offsets into the line's text, end not included
+fn span_json(span: &Range<usize>) -> Value {
+	json!({ "start": span.start, "end": span.end })
+}
+
+fn line_json(line: &SourceLine) -> Value {
+	let statements = line.statements().iter()
+		.map(|s| {
+			let mut statement = statement_json(&s.kind);
+			statement["span"] = span_json(&s.span);
+			statement
+		})
+		.collect::<Vec<Value>>();
+
+	let comments = line.lexemes.iter()
+		.filter(|l| l.kind == LexemeKind::Comment)
+		.map(|l| json!({ "text": &line.text[l.span.clone()], "span": span_json(&l.span) }))
+		.collect::<Vec<Value>>();
+
+	let error = match &line.statements {
+		Ok(_) => Value::Null,
+		Err(error) => json!({ "message": error.message, "span": span_json(&error.span), "suggestion": error.suggestion }),
+	};
+
+	json!({ "line": line.number, "text": line.text, "statements": statements, "comments": comments, "error": error })
+}
+
+// The whole spellbook the way the interpreter reads it, blank lines left out just like they are
+// when Judgement counts
+pub fn export(book: &Spellbook) -> Value {
+	json!({ "lines": book.lines.iter().map(line_json).collect::<Vec<Value>>() })
+}
+
+pub fn run(args: &[String]) {
+	let path = match args {
+		[flag, path] if flag == "--json" => path,
+		_ => panic!(),
+	};
+
+	let book = syntax::parse_source(&syntax::read_source(path).unwrap());
+	println!("{}", serde_json::to_string_pretty(&export(&book)).unwrap());
+}
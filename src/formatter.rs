@@ -0,0 +1,264 @@
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+//        SPELLBOOK INTERPRETER
+//           BY DIANE SPARKS
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+
+use crate::analysis::{self, EdgeKind, Target};
+use crate::lexer::{self, Lexeme, LexemeKind};
+use crate::parser::Keyphrase;
+use crate::syntax;
+use crate::variant::Variant;
+
+use std::collections::HashSet;
+use std::fs;
+use std::process;
+
+// Casts that neither record a spell line nor move the reader around
+const INERT_SPELLS: [&str; 3] = [
+	"Amplify",
+	"Squelch",
+	"Diminish",
+];
+
+pub struct RefusedSplit {
+	pub line: usize,
+	pub statements: usize,
+	pub reason: &'static str,
+}
+
+pub struct FormatResult {
+	pub output: String,
+	pub refused: Vec<RefusedSplit>,
+}
+
+pub fn escape_string(value: &str) -> String {
+	let mut escaped = String::with_capacity(value.len() + 2);
+	escaped.push('"');
+	for chr in value.chars() {
+		match chr {
+			'\\' => escaped.push_str("\\\\"),
+			'"' => escaped.push_str("\\\""),
+			'\n' => escaped.push_str("\\n"),
+			'\r' => escaped.push_str("\\r"),
+			'\t' => escaped.push_str("\\t"),
+			'\0' => escaped.push_str("\\0"),
+			_ => escaped.push(chr),
+		}
+	}
+
+	escaped.push('"');
+	escaped
+}
+
+fn starts_statement(kind: &LexemeKind) -> bool {
+	matches!(kind, LexemeKind::Keyphrase(
+		Keyphrase::TurnToChapter
+		| Keyphrase::TearOutChapter
+		| Keyphrase::TakeOutAChapterFromTheDrawerAndPutItBack
+		| Keyphrase::TakeOutChapterFromTheCauldronAndPutItBack
+		| Keyphrase::Write
+		| Keyphrase::Copy
+		| Keyphrase::Memorize
+		| Keyphrase::Cast
+		| Keyphrase::KnockOverCauldron
+		| Keyphrase::PublishSpellbook
+		| Keyphrase::PublishSpellbookTo
+		| Keyphrase::SignChapterWith
+		| Keyphrase::SignAcknowledgementsPageWith
+		| Keyphrase::SlamSpellbookShut
+		| Keyphrase::PlaceABookmarkNamed
+		| Keyphrase::FlipBackToBookmark
+		| Keyphrase::FlipAheadToBookmark
+	))
+}
+
+// Groups the line's lexemes into statements, keeping comments with whatever they follow
+fn split_statements(lexemes: Vec<Lexeme>) -> Vec<Vec<Lexeme>> {
+	let mut statements: Vec<Vec<Lexeme>> = vec![];
+	let mut seen_significant = false;
+	for lexeme in lexemes {
+		if lexeme.kind == LexemeKind::Whitespace {
+			continue;
+		}
+
+		if starts_statement(&lexeme.kind) && seen_significant {
+			statements.push(vec![]);
+		}
+
+		if !lexeme.is_trivia() {
+			seen_significant = true;
+		}
+
+		match statements.last_mut() {
+			Some(statement) => statement.push(lexeme),
+			None => statements.push(vec![lexeme]),
+		}
+	}
+
+	statements
+}
+
+fn render_statement(line: &str, statement: &[Lexeme]) -> String {
+	statement.iter().map(|l| match &l.kind {
+		LexemeKind::Literal(Variant::Str(string)) => escape_string(string),
+		_ => line[l.span.clone()].split_whitespace().collect::<Vec<&str>>().join(" "),
+	}).collect::<Vec<String>>().join(" ")
+}
+
+fn cast_spell(statement: &[Lexeme]) -> Option<&str> {
+	let mut significant = statement.iter().filter(|l| !l.is_trivia());
+	match (significant.next().map(|l| &l.kind), significant.next().map(|l| &l.kind)) {
+		(Some(LexemeKind::Keyphrase(Keyphrase::Cast)), Some(LexemeKind::Builtin(spell))) => Some(spell),
+		(Some(LexemeKind::Keyphrase(Keyphrase::Cast)), _) => Some(""),
+		_ => None,
+	}
+}
+
+// The lines a Judgement might skip over by counting, by line number. Splitting one of them
+// would make the skip land somewhere else, while a skip to a bookmark lands there regardless
+fn within_judgement_reach(source: &str) -> HashSet<usize> {
+	let book = syntax::parse_source(source);
+	let mut reach = HashSet::new();
+	for edge in analysis::analyze(&book).edges {
+		if edge.kind != EdgeKind::Skip || (edge.charge.is_none() && !edge.dynamic) {
+			continue;
+		}
+
+		let to = match edge.to {
+			Target::Line(line) => line,
+			Target::End | Target::Catastrophe => book.lines.len(),
+		};
+
+		reach.extend(book.lines.iter().take(to).skip(edge.from.0 + 1).map(|l| l.number));
+	}
+
+	reach
+}
+
+fn unsafe_split_reason(statements: &[Vec<Lexeme>], in_reach: bool) -> Option<&'static str> {
+	if in_reach {
+		return Some("the line is within reach of a Judgement skip");
+	}
+
+	for (i, statement) in statements.iter().enumerate() {
+		if let Some(spell) = cast_spell(statement) {
+			if !INERT_SPELLS.contains(&spell) {
+				return Some("the line casts a spell that can skip or jump lines");
+			}
+		}
+
+		if statement.iter().any(|l| l.kind == LexemeKind::Keyphrase(Keyphrase::SlamSpellbookShut)) && i + 1 < statements.len() {
+			return Some("statements after slamming the spellbook shut would no longer run");
+		}
+
+		let flips = statement.iter().any(|l| matches!(l.kind, LexemeKind::Keyphrase(Keyphrase::FlipBackToBookmark | Keyphrase::FlipAheadToBookmark)));
+		if flips && i + 1 < statements.len() {
+			return Some("statements after flipping to a bookmark would no longer run");
+		}
+
+		if statement.iter().any(|l| l.kind == LexemeKind::Keyphrase(Keyphrase::PlaceABookmarkNamed)) && i > 0 {
+			return Some("flipping to the bookmark would no longer land on the statements before it");
+		}
+
+		if statement.iter().any(|l| l.kind == LexemeKind::Conditional) {
+			return Some("the line contains a conditional");
+		}
+	}
+
+	None
+}
+
+pub fn format_source(source: &str) -> FormatResult {
+	let mut output = String::with_capacity(source.len());
+	let mut refused = vec![];
+	let reach = within_judgement_reach(source);
+	let mut pending_blank = false;
+
+	for (i, line) in source.lines().enumerate() {
+		if line.trim().is_empty() {
+			pending_blank = !output.is_empty();
+			continue;
+		}
+
+		if pending_blank {
+			output.push('\n');
+			pending_blank = false;
+		}
+
+		let lexemes = match lexer::lex_line(line) {
+			Ok(lexemes) => lexemes,
+			Err(_) => {
+				output.push_str(line.trim());
+				output.push('\n');
+				continue;
+			},
+		};
+
+		// A line that can't be read runs none of its statements, but the ones before the mistake
+		// would run once they had lines of their own
+		let readable = syntax::parse_lexemes(&lexemes).is_ok();
+		let statements = split_statements(lexemes);
+		if statements.len() > 1 {
+			let reason = if readable { unsafe_split_reason(&statements, reach.contains(&(i + 1))) } else { Some("the line can't be read") };
+			match reason {
+				Some(reason) => {
+					refused.push(RefusedSplit{ line: i + 1, statements: statements.len(), reason });
+					output.push_str(&statements.iter().map(|s| render_statement(line, s)).collect::<Vec<String>>().join(" "));
+					output.push('\n');
+				},
+				None => {
+					for statement in &statements {
+						output.push_str(&render_statement(line, statement));
+						output.push('\n');
+					}
+				},
+			}
+		} else if let Some(statement) = statements.first() {
+			output.push_str(&render_statement(line, statement));
+			output.push('\n');
+		}
+	}
+
+	FormatResult{ output, refused }
+}
+
+pub fn run(args: &[String]) {
+	let mut check = false;
+	let mut paths = vec![];
+	for arg in args {
+		match arg.as_str() {
+			"--check" => {
+				check = true;
+			},
+			_ => {
+				paths.push(arg.clone());
+			},
+		}
+	}
+
+	if paths.is_empty() {
+		panic!();
+	}
+
+	let mut unformatted = false;
+	for path in &paths {
+		let source = fs::read_to_string(path).unwrap();
+		let result = format_source(&source);
+		for refusal in &result.refused {
+			eprintln!("{}:{}: left {} statements on one line because {}", path, refusal.line, refusal.statements, refusal.reason);
+		}
+
+		if result.output != source {
+			if check {
+				println!("Would reformat {}", path);
+				unformatted = true;
+			} else {
+				fs::write(path, &result.output).unwrap();
+			}
+		}
+	}
+
+	if unformatted {
+		process::exit(1);
+	}
+}
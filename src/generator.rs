@@ -0,0 +1,509 @@
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+//        SPELLBOOK INTERPRETER
+//           BY DIANE SPARKS
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+
+use crate::constants::*;
+use crate::formatter;
+
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+const HEADINGS: [&str; 8] = ["ink", "quill", "rune", "moth", "ember", "sage", "toad", "wisp"];
+const WORDS: [&str; 10] = ["newt", "bat", "brew", "toil", "Trouble", "é", "ünd", "\n", "\"", "\t"];
+const SIGNATURES: [&str; 5] = ["", "\n", ", ", " | ", "~"];
+const COMMENTS: [&str; 5] = ["NOTE:", "--", "HMM", "(SEE ABOVE)", "!!"];
+const MIXING_SPELLS: [&str; 4] = ["Entwinement", "Belittlement", "Reenactment", "Apportionment"];
+
+// Spells that can go anywhere without needing anything in the cauldron. Coadjuvancy is left
+// out since there's no one to answer it
+const SPELLS: [&str; 11] = [
+	"Judgement",
+	"Entwinement",
+	"Belittlement",
+	"Reenactment",
+	"Apportionment",
+	"Reverberate",
+	"Amplify",
+	"Squelch",
+	"Diminish",
+	"Juxtapose",
+	"Antipodize",
+];
+
+// Writes random spellbooks that read like someone meant them. It keeps a rough idea of the
+// desk as it goes so most statements make sense where they are, but Judgement and
+// Reverberate move the reader around, so what really happens is up to the interpreter
+struct Generator {
+	rng: StdRng,
+	lines: Vec<String>,
+	// Headings written in each chapter, empty ones standing for entries it lost track of
+	chapters: [Vec<String>; 4],
+	turned_to: Option<usize>,
+	drawer: Vec<Vec<String>>,
+	// The chapter the memorized value came from
+	memorized: Option<usize>,
+	amplifies: usize,
+	bookmarks: usize,
+}
+
+impl Generator {
+	fn chance(&mut self, p: f64) -> bool {
+		self.rng.gen_bool(p)
+	}
+
+	fn pick<'a>(&mut self, options: &[&'a str]) -> &'a str {
+		options[self.rng.gen_range(0..options.len())]
+	}
+
+	// Whitespace and comments the formatter should clean up without changing anything
+	fn emit_line(&mut self, statements: Vec<String>) {
+		if self.lines.last().is_some_and(|l| !l.is_empty()) && self.chance(0.03) {
+			self.lines.push(String::new());
+		}
+
+		let mut line = statements.join(" ");
+		if !line.contains('"') && self.chance(0.1) {
+			let words = line.split(' ').map(String::from).collect::<Vec<String>>();
+			line = words.iter().map(|w| if self.rng.gen_bool(0.3) { format!("{}  ", w) } else { format!("{} ", w) }).collect::<String>();
+			line.truncate(line.trim_end().len());
+		}
+
+		if self.chance(0.05) {
+			line.insert(0, '\t');
+		}
+
+		if self.chance(0.08) {
+			line = format!("{} {}", line, self.pick(&COMMENTS));
+		}
+
+		self.lines.push(line);
+	}
+
+	fn emit(&mut self, statement: String) {
+		self.emit_line(vec![statement]);
+	}
+
+	fn cast(&mut self, spell: &str) {
+		self.amplifies = if spell == "Amplify" { self.amplifies + 1 } else { 0 };
+		self.emit(format!("cast {} on the cauldron", spell));
+	}
+
+	fn literal(&mut self, chapter: usize) -> String {
+		let chapter = if self.chance(0.05) { self.rng.gen_range(0..4) } else { chapter };
+		match chapter {
+			0 => self.chance(0.5).to_string(),
+			1 => self.rng.gen_range(-20..100).to_string(),
+			2 => format!("{}.{}", self.rng.gen_range(-20..50), self.rng.gen_range(0..10)),
+			_ => {
+				let count = self.rng.gen_range(0..4);
+				let value = (0..count).map(|_| self.pick(&WORDS)).collect::<String>();
+				formatter::escape_string(&value)
+			},
+		}
+	}
+
+	fn fresh_heading(&mut self, chapter: usize) -> String {
+		let unused = HEADINGS.iter().filter(|h| !self.chapters[chapter].iter().any(|w| w == *h)).cloned().collect::<Vec<&str>>();
+		self.pick(&unused).into()
+	}
+
+	fn known_heading(&mut self, chapter: usize) -> Option<String> {
+		let known = self.chapters[chapter].iter().filter(|h| !h.is_empty()).cloned().collect::<Vec<String>>();
+		known.choose(&mut self.rng).cloned()
+	}
+
+	fn turn(&mut self, chapter: usize) {
+		self.turned_to = Some(chapter);
+		self.emit(format!("turn to chapter {}", BUILTINS_CHAPTERS[chapter]));
+	}
+
+	fn ensure_turned(&mut self) -> usize {
+		match self.turned_to {
+			Some(chapter) => chapter,
+			None => {
+				let chapter = self.rng.gen_range(0..4);
+				self.turn(chapter);
+				chapter
+			},
+		}
+	}
+
+	fn tear_out(&mut self, destination: &str) {
+		let chapter = self.ensure_turned();
+		let page = std::mem::take(&mut self.chapters[chapter]);
+		if destination == "drawer" {
+			self.drawer.push(page);
+		}
+
+		let phrase = match destination {
+			"drawer" => "put it in the drawer",
+			"cauldron" => "toss it in the cauldron",
+			_ => "throw it in the trash",
+		};
+
+		self.emit(format!("tear out chapter and {}", phrase));
+	}
+
+	fn clear(&mut self) {
+		let chapter = self.ensure_turned();
+		if !self.chapters[chapter].is_empty() {
+			self.tear_out("trash");
+		}
+	}
+
+	fn write(&mut self) {
+		let statement = self.write_statement();
+		self.emit(statement);
+	}
+
+	// Turns to a chapter with room first if it has to
+	fn write_statement(&mut self) -> String {
+		let chapter = self.ensure_turned();
+		if self.chapters[chapter].len() >= 3 {
+			self.tear_out("trash");
+		}
+
+		let heading = self.fresh_heading(chapter);
+		let source = match self.rng.gen_range(0..10) {
+			// Presages and Incantations take anything
+			0 | 1 if self.memorized.is_some_and(|m| m == chapter || chapter == 0 || chapter == 3) => "from memory".into(),
+			2 => match self.known_heading(chapter) {
+				Some(from) => {
+					self.chapters[chapter].push(heading.clone());
+					return format!("copy {} under {}", from, heading);
+				},
+				None => self.literal(chapter),
+			},
+			_ => self.literal(chapter),
+		};
+
+		self.chapters[chapter].push(heading.clone());
+		format!("write {} under {}", source, heading)
+	}
+
+	fn memorize(&mut self) {
+		let chapter = self.ensure_turned();
+		self.memorized = Some(chapter);
+		let what = match self.rng.gen_range(0..3) {
+			0 => match self.known_heading(chapter) {
+				Some(heading) => heading,
+				None => self.literal(chapter),
+			},
+			1 if !self.chapters[chapter].is_empty() => {
+				let entry = self.rng.gen_range(0..self.chapters[chapter].len());
+				format!("{} entry", BUILTINS_ORDINALS[entry])
+			},
+			_ => self.literal(chapter),
+		};
+
+		self.emit(format!("memorize {}", what));
+	}
+
+	fn put_back_from_drawer(&mut self) {
+		let chapter = self.ensure_turned();
+		self.chapters[chapter] = self.drawer.pop().unwrap_or_default();
+		self.emit("take out a chapter from the drawer and put it back".into());
+	}
+
+	fn put_back_from_cauldron(&mut self) {
+		let chapter = self.ensure_turned();
+		self.chapters[chapter] = vec![String::new(); 3];
+		self.emit("take out chapter from the cauldron and put it back".into());
+	}
+
+	fn knock_over(&mut self) {
+		self.emit("knock over cauldron".into());
+	}
+
+	// Starts the charge over with a little in it, so spells that skip or jump don't go too far
+	fn small_charge(&mut self) {
+		self.cast("Squelch");
+		for _ in 0..self.rng.gen_range(0..3) {
+			self.cast("Vacancy");
+		}
+	}
+
+	fn fill_page(&mut self) {
+		let chapter = self.rng.gen_range(0..4);
+		self.turn(chapter);
+		self.clear();
+		for _ in 0..3 {
+			self.write();
+		}
+	}
+
+	fn drawer_round_trip(&mut self) {
+		for _ in 0..self.rng.gen_range(1..4) {
+			self.write();
+		}
+
+		let chapter = self.ensure_turned();
+		self.tear_out("drawer");
+		for _ in 0..self.rng.gen_range(0..3) {
+			self.statement();
+		}
+
+		let chapter = if self.chance(0.7) { chapter } else { self.rng.gen_range(0..4) };
+		self.turn(chapter);
+		self.put_back_from_drawer();
+		if self.chance(0.5) {
+			self.emit("publish spellbook".into());
+		}
+	}
+
+	fn mix(&mut self) {
+		if self.chance(0.8) {
+			self.knock_over();
+		}
+
+		// Only Entwinement does anything with strings, and nothing mixes booleans
+		let spell = self.pick(&MIXING_SPELLS);
+		self.cast(spell);
+		let chapter = if spell == "Entwinement" { self.rng.gen_range(1..4) } else { self.rng.gen_range(1..3) };
+		for _ in 0..self.rng.gen_range(2..4) {
+			let next = if self.chance(0.1) { self.rng.gen_range(0..4) } else { chapter };
+			self.turn(next);
+			self.clear();
+			for _ in 0..self.rng.gen_range(1..4) {
+				self.write();
+			}
+
+			self.tear_out("cauldron");
+		}
+
+		if self.chance(0.2) {
+			let spell = self.pick(&["Juxtapose", "Antipodize"]);
+			self.cast(spell);
+		}
+
+		if self.chance(0.6) {
+			self.put_back_from_cauldron();
+			self.emit("publish spellbook".into());
+		} else {
+			self.knock_over();
+		}
+	}
+
+	// A Presages chapter in the cauldron for Judgement to look at, and a little charge
+	fn judgement_setup(&mut self) {
+		self.knock_over();
+		if self.chance(0.3) {
+			self.turn(1);
+			self.clear();
+			for _ in 0..3 {
+				self.write();
+			}
+
+			self.tear_out("cauldron");
+			self.cast("Juxtapose");
+		} else {
+			self.turn(0);
+			self.clear();
+			for _ in 0..self.rng.gen_range(1..4) {
+				self.write();
+			}
+
+			self.tear_out("cauldron");
+		}
+
+		self.small_charge();
+	}
+
+	fn judgement(&mut self) {
+		self.judgement_setup();
+		if self.chance(0.3) {
+			self.emit_line(vec!["cast Judgement on the cauldron".into(), "publish spellbook".into()]);
+		} else {
+			self.cast("Judgement");
+		}
+	}
+
+	// Vacancy first, so there's always a spell as far back as the charge reaches
+	fn reverberate(&mut self) {
+		self.cast("Vacancy");
+		self.small_charge();
+		self.cast("Reverberate");
+	}
+
+	fn remember(&mut self) {
+		self.memorize();
+		let chapter = match (self.rng.gen_range(0..10), self.memorized) {
+			(0..=6, Some(chapter)) => chapter,
+			(7..=8, _) => 3,
+			_ => self.rng.gen_range(0..4),
+		};
+
+		self.turn(chapter);
+		self.clear();
+		let heading = self.fresh_heading(chapter);
+		self.chapters[chapter].push(heading.clone());
+		self.emit(format!("write from memory under {}", heading));
+	}
+
+	// Mostly about a Presages entry that's there, so the rest of the line is read only sometimes
+	fn conditional(&mut self) {
+		let mut conditions = vec![];
+		for _ in 0..if self.chance(0.2) { 2 } else { 1 } {
+			if self.chance(0.3) {
+				conditions.push("if the cauldron is full".to_string());
+				continue;
+			}
+
+			if self.turned_to != Some(0) && self.chance(0.9) {
+				self.turn(0);
+			}
+
+			conditions.push(match self.known_heading(0) {
+				Some(heading) if self.chance(0.5) => format!("if {}", heading),
+				_ => format!("if {} entry", BUILTINS_ORDINALS[self.rng.gen_range(0..3)]),
+			});
+		}
+
+		let guarded = self.pick(&["publish spellbook", "cast Judgement on the cauldron", "cast Vacancy on the cauldron", "knock over cauldron", "memorize true", "slam spellbook shut"]);
+		conditions.push(guarded.into());
+		self.emit_line(conditions);
+	}
+
+	// Mostly bookmarks further on to skip or flip ahead to. Going back makes a loop, which
+	// nothing may ever get out of, so that's rarer
+	fn bookmark(&mut self) {
+		let name = format!("mark{}", self.bookmarks);
+		self.bookmarks += 1;
+
+		// Now and then it's never placed at all
+		let placed = if self.chance(0.03) { "publish spellbook".into() } else { format!("place a bookmark named {}", name) };
+		if self.chance(0.8) {
+			let flip = if self.chance(0.5) {
+				self.judgement_setup();
+				format!("cast Judgement on the cauldron and skip to bookmark {}", name)
+			} else {
+				format!("flip ahead to bookmark {}", name)
+			};
+
+			if self.chance(0.3) {
+				self.emit_line(vec!["if the cauldron is full".into(), flip]);
+			} else {
+				self.emit(flip);
+			}
+
+			for _ in 0..self.rng.gen_range(1..4) {
+				self.statement();
+			}
+
+			self.emit(placed);
+		} else {
+			self.emit(placed);
+			for _ in 0..self.rng.gen_range(1..4) {
+				self.statement();
+			}
+
+			self.judgement_setup();
+			self.emit(format!("cast Judgement on the cauldron and skip to bookmark {}", name));
+		}
+	}
+
+	fn sign(&mut self) {
+		let signature = formatter::escape_string(self.pick(&SIGNATURES));
+		if self.chance(0.5) {
+			self.ensure_turned();
+			self.emit(format!("sign chapter with {}", signature));
+		} else {
+			self.emit(format!("sign acknowledgements page with {}", signature));
+		}
+	}
+
+	// Anything at all, including things that are likely to be a catastrophe
+	fn statement(&mut self) {
+		match self.rng.gen_range(0..100) {
+			0..=24 => self.write(),
+			25..=34 => self.memorize(),
+			35..=44 => {
+				let chapter = self.rng.gen_range(0..4);
+				self.turn(chapter);
+			},
+			45..=54 => {
+				let destination = self.pick(&["trash", "drawer", "cauldron"]);
+				self.tear_out(destination);
+			},
+			55..=59 => self.put_back_from_drawer(),
+			60..=64 => self.put_back_from_cauldron(),
+			65..=69 => self.knock_over(),
+			70..=89 => {
+				let spell = self.pick(&SPELLS);
+				if spell == "Amplify" && self.amplifies >= 3 {
+					return;
+				}
+
+				self.cast(spell);
+			},
+			90..=97 => {
+				// The formatter leaves lines alone when they cast a spell like this,
+				// so they stay on the same line everywhere
+				let spell = self.pick(&["Vacancy", "Entwinement", "Reenactment"]);
+				let statement = self.write_statement();
+				self.emit_line(vec![format!("cast {} on the cauldron", spell), statement]);
+			},
+			_ => self.emit("slam spellbook shut".into()),
+		}
+	}
+
+	fn fragment(&mut self) {
+		match self.rng.gen_range(0..100) {
+			0..=14 => self.fill_page(),
+			15..=24 => self.drawer_round_trip(),
+			25..=39 => self.mix(),
+			40..=49 => self.judgement(),
+			50..=54 => self.reverberate(),
+			55..=64 => self.remember(),
+			65..=74 => self.emit("publish spellbook".into()),
+			75..=79 => self.sign(),
+			80..=84 => self.conditional(),
+			85..=89 => self.bookmark(),
+			_ => self.statement(),
+		}
+	}
+}
+
+pub fn generate(seed: u64, lines: usize) -> String {
+	let mut generator = Generator{
+		rng: StdRng::seed_from_u64(seed),
+		lines: vec![],
+		chapters: Default::default(),
+		turned_to: None,
+		drawer: vec![],
+		memorized: None,
+		amplifies: 0,
+		bookmarks: 0,
+	};
+
+	while generator.lines.len() < lines {
+		generator.fragment();
+	}
+
+	generator.emit("publish spellbook".into());
+	let mut source = generator.lines.join("\n");
+	source.push('\n');
+	source
+}
+
+pub fn run(args: &[String]) {
+	let mut seed = thread_rng().gen();
+	let mut lines = 40;
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--seed" => {
+				seed = args.next().unwrap().parse().unwrap();
+			},
+			"--lines" => {
+				lines = args.next().unwrap().parse().unwrap();
+			},
+			_ => {
+				panic!();
+			},
+		}
+	}
+
+	print!("{}", generate(seed, lines));
+}
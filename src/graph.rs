@@ -0,0 +1,112 @@
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+//        SPELLBOOK INTERPRETER
+//           BY DIANE SPARKS
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+
+use crate::analysis::{self, Analysis, EdgeKind, StatementId, Target};
+use crate::syntax::{self, Spellbook};
+
+use std::fmt::Write;
+
+fn escape_label(text: &str) -> String {
+	text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Lines that can't be read have no statements, so the whole line stands in for them
+fn node_name(book: &Spellbook, id: StatementId) -> String {
+	if book.lines[id.0].statements().is_empty() {
+		format!("l{}", id.0)
+	} else {
+		format!("s{}_{}", id.0, id.1)
+	}
+}
+
+fn target_name(book: &Spellbook, target: Target) -> String {
+	match target {
+		Target::Line(index) => node_name(book, (index, 0)),
+		Target::End => "end".into(),
+		Target::Catastrophe => "catastrophe".into(),
+	}
+}
+
+pub fn render(book: &Spellbook, analysis: &Analysis) -> String {
+	let mut dot = String::new();
+	writeln!(dot, "digraph spellbook {{").unwrap();
+	writeln!(dot, "\tnode [shape=box, fontname=\"monospace\"];").unwrap();
+	writeln!(dot, "\tstart [shape=circle, label=\"\"];").unwrap();
+	writeln!(dot, "\tend [shape=doublecircle, label=\"\"];").unwrap();
+
+	let targets = analysis.edges.iter().map(|e| e.to).collect::<Vec<Target>>();
+	if targets.contains(&Target::Catastrophe) {
+		writeln!(dot, "\tcatastrophe [shape=octagon, label=\"Catastrophe!\"];").unwrap();
+	}
+
+	for (index, line) in book.lines.iter().enumerate() {
+		let style = if analysis.line_entry[index].is_some() { "" } else { ", style=dashed, color=gray" };
+		match &line.statements {
+			Ok(statements) if !statements.is_empty() => {
+				for (i, statement) in statements.iter().enumerate() {
+					let text = line.text[statement.span.clone()].split_whitespace().collect::<Vec<&str>>().join(" ");
+					writeln!(dot, "\t{} [label=\"{}: {}\"{}];", node_name(book, (index, i)), line.number, escape_label(&text), style).unwrap();
+				}
+
+				for i in 1..statements.len() {
+					writeln!(dot, "\t{} -> {};", node_name(book, (index, i - 1)), node_name(book, (index, i))).unwrap();
+				}
+			},
+			Ok(_) => {
+				writeln!(dot, "\t{} [label=\"{}: {}\"{}];", node_name(book, (index, 0)), line.number, escape_label(line.text.trim()), style).unwrap();
+			},
+			Err(_) => {
+				writeln!(dot, "\t{} [label=\"{}: {}\", color=red{}];", node_name(book, (index, 0)), line.number, escape_label(line.text.trim()), style).unwrap();
+			},
+		}
+	}
+
+	if !book.lines.is_empty() {
+		writeln!(dot, "\tstart -> {};", node_name(book, (0, 0))).unwrap();
+	}
+
+	for edge in &analysis.edges {
+		let from = node_name(book, edge.from);
+		let to = target_name(book, edge.to);
+		let bookmark = book.lines[edge.from.0].statements().get(edge.from.1).and_then(|s| s.bookmark()).map(|b| b.name.as_str());
+		let label = match (edge.kind, edge.charge) {
+			(EdgeKind::Fallthrough, _) => String::new(),
+			(EdgeKind::Flip, _) => format!("flip to {}", bookmark.unwrap_or_default()),
+			(EdgeKind::Skip, _) if bookmark.is_some() => format!("skip to {}", bookmark.unwrap()),
+			(EdgeKind::Skip, Some(charge)) => format!("skip, charge {}", charge),
+			(EdgeKind::JumpBack, Some(charge)) => format!("jump back, charge {}", charge),
+			(EdgeKind::Skip, None) => "skip, charge unknown".into(),
+			(EdgeKind::JumpBack, None) => "jump back, charge unknown".into(),
+		};
+
+		let style = match edge.kind {
+			EdgeKind::Fallthrough => "",
+			EdgeKind::Skip => ", color=blue",
+			EdgeKind::JumpBack => ", color=darkgreen",
+			EdgeKind::Flip => ", color=purple",
+		};
+
+		let style = if edge.dynamic { format!("{}, style=dashed", style) } else { style.into() };
+		if label.is_empty() && style.is_empty() {
+			writeln!(dot, "\t{} -> {};", from, to).unwrap();
+		} else {
+			writeln!(dot, "\t{} -> {} [label=\"{}\"{}];", from, to, label, style).unwrap();
+		}
+	}
+
+	writeln!(dot, "}}").unwrap();
+	dot
+}
+
+pub fn run(args: &[String]) {
+	if args.is_empty() {
+		panic!();
+	}
+
+	let source = syntax::read_source(&args[0]).unwrap();
+	let book = syntax::parse_source(&source);
+	let analysis = analysis::analyze(&book);
+	print!("{}", render(&book, &analysis));
+}
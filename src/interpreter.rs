@@ -0,0 +1,241 @@
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+//        SPELLBOOK INTERPRETER
+//           BY DIANE SPARKS
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+
+use crate::analysis::StatementId;
+use crate::constants::*;
+use crate::errors::SpellbookError;
+use crate::program::{Desk, Program, BEFORE_FIRST_LINE};
+use crate::sb_panic;
+use crate::syntax::*;
+
+use std::panic::{self, AssertUnwindSafe};
+
+// Gives back whether the rest of the line is still read
+pub fn execute_statement(program: &mut Program, statement: &Statement) -> bool {
+	match &statement.kind {
+		StatementKind::TurnToChapter(chapter) => {
+			program.turn_to_page(*chapter);
+		},
+		StatementKind::TearOutChapter(destination) => {
+			program.tear_out_page(*destination == Destination::Drawer, *destination == Destination::Cauldron);
+		},
+		StatementKind::TakeOutFromDrawer => {
+			program.put_back_page(true);
+		},
+		StatementKind::TakeOutFromCauldron => {
+			program.put_back_page(false);
+		},
+		StatementKind::Write{ source, heading } => {
+			match source {
+				WriteSource::Literal(value) => program.write_literal_value(heading.clone(), Some(value.clone())),
+				WriteSource::Memory => program.write_memory_value(heading.clone()),
+				WriteSource::Copy(from) => {
+					match program.try_get_value(from) {
+						Some(value) => program.write_literal_value(heading.clone(), Some(value)),
+						None => {
+							sb_panic!(program.line_number);
+						},
+					}
+				},
+			}
+		},
+		StatementKind::Memorize(source) => {
+			let value = match source {
+				MemorizeSource::Heading(heading) => match program.try_get_value(heading) {
+					Some(value) => Some(value),
+					None => {
+						sb_panic!(program.line_number);
+					},
+				},
+				MemorizeSource::Literal(value) => Some(value.clone()),
+				MemorizeSource::Entry(index) => program.get_value_by_index(*index),
+			};
+
+			program.memorize_value(value);
+		},
+		StatementKind::Cast(spell) => {
+			match BUILTINS_SPELLS.get(spell.as_str()) {
+				Some(spell) => program.cast_cauldron_spell(spell),
+				None => {
+					sb_panic!(program.line_number);
+				},
+			}
+		},
+		StatementKind::KnockOverCauldron => {
+			program.knock_over_cauldron();
+		},
+		StatementKind::Publish(target) => {
+			match target {
+				Some(target) => program.publish(true, target.clone()),
+				None => program.publish(false, String::new()),
+			}
+		},
+		StatementKind::SignChapter(signature) => {
+			program.sign_page(signature.clone());
+		},
+		StatementKind::SignAcknowledgements(signature) => {
+			program.set_signature(signature.clone());
+		},
+		StatementKind::SlamShut => {
+			program.exit = true;
+		},
+		StatementKind::If(condition) => {
+			return program.presage(condition);
+		},
+		StatementKind::PlaceBookmark(_) => {},
+		StatementKind::Flip{ bookmark, .. } => {
+			program.flip_to(bookmark.line);
+		},
+		StatementKind::JudgeToBookmark(bookmark) => {
+			program.cast_judgement_to(bookmark.line);
+		},
+	}
+
+	true
+}
+
+#[derive(Clone)]
+pub struct Snapshot {
+	desk: Desk,
+	position: Option<StatementId>,
+	finished: bool,
+}
+
+impl Snapshot {
+	pub fn size(&self) -> usize {
+		self.desk.size()
+	}
+}
+
+// Runs a spellbook a statement at a time. Spells can move the program's line around in
+// the middle of a line, but the rest of that line is still read before going anywhere,
+// so the interpreter keeps its own place in the line it started
+pub struct Interpreter {
+	pub book: Spellbook,
+	pub program: Program,
+	position: Option<StatementId>,
+	finished: bool,
+}
+
+impl Interpreter {
+	pub fn new(book: Spellbook, program: Program) -> Self {
+		let mut interpreter = Self{ book, program, position: None, finished: false };
+		interpreter.start_line();
+		interpreter
+	}
+
+	pub fn is_finished(&self) -> bool {
+		self.finished
+	}
+
+	// The statement that will be read next
+	pub fn position(&self) -> Option<StatementId> {
+		self.position
+	}
+
+	pub fn snapshot(&self) -> Snapshot {
+		Snapshot{ desk: self.program.save_desk(), position: self.position, finished: self.finished }
+	}
+
+	pub fn restore(&mut self, snapshot: Snapshot) {
+		self.program.restore_desk(snapshot.desk);
+		self.position = snapshot.position;
+		self.finished = snapshot.finished;
+	}
+
+	fn start_line(&mut self) {
+		let index = self.program.line_internal;
+		if index >= self.book.lines.len() {
+			self.position = None;
+			self.finished = true;
+			return;
+		}
+
+		self.program.line_number = self.book.lines[index].number;
+		self.position = Some((index, 0));
+		if self.program.debug_mode {
+			let tokens = self.book.lines[index].lexemes.iter().filter_map(|l| l.to_token()).collect::<Vec<_>>();
+			eprintln!("{:<4}  {:?}", self.program.line_number, tokens);
+		}
+	}
+
+	fn finish_line(&mut self) {
+		if self.program.exit {
+			self.position = None;
+			self.finished = true;
+			return;
+		}
+
+		let next = match self.program.line_internal {
+			BEFORE_FIRST_LINE => 0,
+			line => match line.checked_add(1) {
+				Some(next) if next <= self.book.lines.len() => next,
+				_ => {
+					sb_panic!(self.program.line_number);
+				},
+			},
+		};
+
+		self.program.line_internal = next;
+		self.start_line();
+	}
+
+	pub fn step_statement(&mut self) {
+		let (line, index) = match self.position {
+			Some(position) => position,
+			None => return,
+		};
+
+		// A line that can't be read doesn't get read up to the mistake either, which is also what
+		// every compiled spellbook does with it
+		let source_line = &self.book.lines[line];
+		let statements = match &source_line.statements {
+			Ok(statements) => statements,
+			Err(error) => {
+				sb_panic!(SpellbookError::from_syntax(source_line, error));
+			},
+		};
+
+		let mut reading = true;
+		if let Some(statement) = statements.get(index) {
+			// Caught here rather than when casting, since only here is the line at hand to point at
+			if let Some(spell) = statement.spell().filter(|s| !BUILTINS_SPELLS.contains_key(s)) {
+				sb_panic!(SpellbookError::unknown_spell(source_line, statement, spell));
+			}
+
+			reading = execute_statement(&mut self.program, statement);
+		}
+
+		if reading && index + 1 < statements.len() {
+			self.position = Some((line, index + 1));
+		} else {
+			self.finish_line();
+		}
+	}
+
+	pub fn run(&mut self) {
+		while !self.finished {
+			self.step_statement();
+		}
+	}
+
+	// Like stepping, but a catastrophe ends the spellbook instead of the whole interpreter,
+	// giving back the line it happened on
+	pub fn try_step(&mut self, step: fn(&mut Interpreter)) -> Result<(), usize> {
+		match panic::catch_unwind(AssertUnwindSafe(|| step(self))) {
+			Ok(()) => Ok(()),
+			Err(payload) => {
+				self.position = None;
+				self.finished = true;
+				let line = match payload.downcast_ref::<SpellbookError>() {
+					Some(error) => Some(error.line),
+					None => payload.downcast_ref::<usize>().cloned(),
+				};
+
+				Err(line.unwrap_or(self.program.line_number))
+			},
+		}
+	}
+}
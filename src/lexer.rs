@@ -0,0 +1,335 @@
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+//        SPELLBOOK INTERPRETER
+//           BY DIANE SPARKS
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+
+use peekmore::{PeekMore, PeekMoreIterator};
+
+use crate::constants::*;
+use crate::errors::edit_distance;
+use crate::parser::{Keyphrase, Token};
+use crate::variant::Variant;
+
+use std::ops::Range;
+use std::slice::Iter;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum LexemeKind {
+	Keyphrase(Keyphrase),
+	Literal(Variant),
+	Identifier(String),
+	Builtin(String),
+	Conditional,
+	// What's left of a keyphrase after a comment in the middle of it
+	KeyphraseContinued,
+
+	Comment,
+	Whitespace,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Lexeme {
+	pub kind: LexemeKind,
+	pub span: Range<usize>,
+}
+
+impl Lexeme {
+	// The rest of a keyphrase counts too, since the keyphrase is already known where it started
+	pub fn is_trivia(&self) -> bool {
+		matches!(self.kind, LexemeKind::Comment | LexemeKind::Whitespace | LexemeKind::KeyphraseContinued)
+	}
+
+	pub fn to_token(&self) -> Option<Token> {
+		match &self.kind {
+			LexemeKind::Keyphrase(kp) => Some(Token::Keyphrase(kp.clone())),
+			LexemeKind::Literal(lit) => Some(Token::Literal(lit.clone())),
+			LexemeKind::Identifier(ident) => Some(Token::Identifier(ident.clone())),
+			LexemeKind::Builtin(bt) => Some(Token::Builtin(bt.clone())),
+			LexemeKind::Conditional => Some(Token::Conditional),
+			LexemeKind::KeyphraseContinued | LexemeKind::Comment | LexemeKind::Whitespace => None,
+		}
+	}
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct LexError {
+	pub message: String,
+	pub span: Range<usize>,
+	pub suggestion: Option<String>,
+}
+
+#[derive(PartialEq)]
+enum WordKind {
+	Plain,
+	Str(String),
+	Comment,
+	Whitespace,
+}
+
+struct Word<'a> {
+	text: &'a str,
+	kind: WordKind,
+	span: Range<usize>,
+}
+
+const fn parse_escape_character(chr: char) -> Option<char> {
+	match chr {
+		'\\' => Some('\\'),
+		'"' => Some('"'),
+		'n' => Some('\n'),
+		'r' => Some('\r'),
+		't' => Some('\t'),
+		'0' => Some('\0'),
+		_ => None,
+	}
+}
+
+pub fn is_comment_word(word: &str) -> bool {
+	word.chars().all(|c| c.is_uppercase() || (c.is_ascii_punctuation() && c != '"'))
+}
+
+fn scan_string(line: &str, start: usize) -> Result<(String, usize), LexError> {
+	let mut value = String::with_capacity(20);
+	let mut chars = line[start + 1..].char_indices();
+	while let Some((i, chr)) = chars.next() {
+		match chr {
+			'"' => return Ok((value, start + 1 + i + 1)),
+			'\\' => {
+				match chars.next() {
+					Some((_, esc)) => {
+						if let Some(esc) = parse_escape_character(esc) {
+							value.push(esc);
+						}
+					},
+					None => break,
+				}
+			},
+			_ => value.push(chr),
+		}
+	}
+
+	Err(LexError{
+		message: "unterminated string literal".into(),
+		span: start..line.len(),
+		suggestion: None,
+	})
+}
+
+fn split_words(line: &str) -> Result<Vec<Word<'_>>, LexError> {
+	let mut words = vec![];
+	let mut pos = 0;
+	while pos < line.len() {
+		let rest = &line[pos..];
+		let first = rest.chars().next().unwrap();
+		if first.is_whitespace() {
+			let len = rest.find(|c: char| !c.is_whitespace()).unwrap_or(rest.len());
+			words.push(Word{ text: &rest[..len], kind: WordKind::Whitespace, span: pos..pos + len });
+			pos += len;
+		} else if first == '"' {
+			let (value, end) = scan_string(line, pos)?;
+			words.push(Word{ text: &line[pos..end], kind: WordKind::Str(value), span: pos..end });
+			pos = end;
+		} else {
+			// A quote in the middle of a word keeps it going through any spaces up to the
+			// closing quote, and a string ends its word right away, like the old parser did
+			let mut len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+			if let Some(quote) = rest[..len].find('"') {
+				len = rest[quote + 1..].find('"').map_or(rest.len(), |end| quote + 1 + end + 1);
+			}
+
+			let text = &rest[..len];
+			let kind = if is_comment_word(text) { WordKind::Comment } else { WordKind::Plain };
+
+			words.push(Word{ text, kind, span: pos..pos + len });
+			pos += len;
+		}
+	}
+
+	Ok(words)
+}
+
+fn expect_subtokens(iter: &mut PeekMoreIterator<Iter<&Word>>, subtokens: &[&str]) -> Option<usize> {
+	for (index, st) in subtokens.iter().enumerate() {
+		match iter.peek_nth(index) {
+			Some(word) if word.kind == WordKind::Plain && word.text == *st => {},
+			_ => {
+				return None;
+			},
+		}
+	}
+
+	let end = iter.peek_nth(subtokens.len() - 1).map(|w| w.span.end);
+	iter.advance_by(subtokens.len()).unwrap();
+	end
+}
+
+// Points at the first word that isn't what the keyphrase needed, and guesses which one was
+// meant by comparing it with what was written in its place
+fn keyphrase_error(iter: &mut PeekMoreIterator<Iter<&Word>>, word: &Word, expected: &[&[&str]]) -> LexError {
+	let phrases = expected.iter().map(|p| format!("`{}`", p.join(" "))).collect::<Vec<String>>();
+	let longest = expected.iter().map(|p| p.len()).max().unwrap_or(0);
+	let written = (0..longest).map_while(|i| iter.peek_nth(i).filter(|w| w.kind == WordKind::Plain).map(|w| **w)).collect::<Vec<&Word>>();
+
+	let (distance, closest) = expected.iter()
+		.map(|p| (edit_distance(&written.iter().take(p.len()).map(|w| w.text).collect::<Vec<&str>>().join(" "), &p.join(" ")), *p))
+		.min()
+		.unwrap();
+
+	let wrong = written.iter().zip(closest).position(|(w, st)| w.text != *st).unwrap_or(written.len());
+	let span = match written.get(wrong) {
+		Some(first) => first.span.start..written[written.len().min(closest.len()) - 1].span.end,
+		None => {
+			let end = written.last().map_or(word.span.end, |w| w.span.end);
+			end..end
+		},
+	};
+
+	LexError{
+		message: format!("expected {} after `{}`", phrases.join(" or "), word.text),
+		span,
+		suggestion: if distance > 0 && distance <= (closest.join(" ").len() / 3).max(1) { Some(format!("{} {}", word.text, closest.join(" "))) } else { None },
+	}
+}
+
+fn match_keyphrase(iter: &mut PeekMoreIterator<Iter<&Word>>, word: &Word, options: &[(&[&str], Keyphrase)]) -> Result<(Keyphrase, usize), LexError> {
+	for (subtokens, keyphrase) in options {
+		if let Some(end) = expect_subtokens(iter, subtokens) {
+			return Ok((keyphrase.clone(), end));
+		}
+	}
+
+	Err(keyphrase_error(iter, word, &options.iter().map(|o| o.0).collect::<Vec<&[&str]>>()))
+}
+
+fn classify_word(word: &Word) -> LexemeKind {
+	match &word.kind {
+		WordKind::Str(value) => LexemeKind::Literal(Variant::Str(value.clone())),
+		_ => {
+			let st = word.text;
+			if BUILTINS_ORDINALS.contains(&st)
+			|| BUILTINS_CHAPTERS.contains(&st)
+			|| BUILTINS_MISC.contains(&st)
+			|| BUILTINS_SPELLS.contains_key(st) {
+				LexemeKind::Builtin(st.to_string())
+			} else if let Ok(int) = st.parse::<i64>() {
+				LexemeKind::Literal(Variant::Integer(int))
+			} else if let Ok(float) = st.parse::<f64>() {
+				LexemeKind::Literal(Variant::Float(float))
+			} else if let Ok(boolean) = st.parse::<bool>() {
+				LexemeKind::Literal(Variant::Boolean(boolean))
+			} else {
+				LexemeKind::Identifier(st.to_string())
+			}
+		},
+	}
+}
+
+pub fn lex_line(line: &str) -> Result<Vec<Lexeme>, LexError> {
+	let words = split_words(line)?;
+	let significant = words.iter().filter(|w| matches!(w.kind, WordKind::Plain | WordKind::Str(_))).collect::<Vec<&Word>>();
+
+	// Keyphrases can span several words, so figure out where each one ends first,
+	// then lay the trivia in between back around them
+	let mut phrases = vec![];
+	let mut subtokens = significant.iter().peekmore();
+	while let Some(word) = subtokens.next() {
+		let single = |kp: Keyphrase| Some((kp, word.span.end));
+
+		// Headings and bookmarks can be called "place" or "flip" too, so those only start a
+		// statement where a name isn't expected
+		let naming = phrases.last().is_some_and(|p: &Lexeme| matches!(p.kind, LexemeKind::Conditional | LexemeKind::Keyphrase(
+			Keyphrase::Under | Keyphrase::Copy | Keyphrase::Memorize | Keyphrase::PlaceABookmarkNamed
+			| Keyphrase::FlipBackToBookmark | Keyphrase::FlipAheadToBookmark | Keyphrase::AndSkipToBookmark
+		)));
+		let phrase = if word.kind != WordKind::Plain {
+			None
+		} else {
+			match word.text {
+				"turn" => Some(match_keyphrase(&mut subtokens, word, &[(&["to", "chapter"], Keyphrase::TurnToChapter)])?),
+				"tear" => Some(match_keyphrase(&mut subtokens, word, &[(&["out", "chapter"], Keyphrase::TearOutChapter)])?),
+				"take" => Some(match_keyphrase(&mut subtokens, word, &[
+					(&["out", "a", "chapter", "from", "the", "drawer", "and", "put", "it", "back"], Keyphrase::TakeOutAChapterFromTheDrawerAndPutItBack),
+					(&["out", "chapter", "from", "the", "cauldron", "and", "put", "it", "back"], Keyphrase::TakeOutChapterFromTheCauldronAndPutItBack),
+				])?),
+				"write" => single(Keyphrase::Write),
+				"copy" => single(Keyphrase::Copy),
+				"entry" => single(Keyphrase::Entry),
+				"under" => single(Keyphrase::Under),
+				"cast" => single(Keyphrase::Cast),
+				"memorize" => single(Keyphrase::Memorize),
+				"on" => Some(match_keyphrase(&mut subtokens, word, &[(&["the", "cauldron"], Keyphrase::OnTheCauldron)])?),
+				"knock" => Some(match_keyphrase(&mut subtokens, word, &[(&["over", "cauldron"], Keyphrase::KnockOverCauldron)])?),
+				"from" => Some(match_keyphrase(&mut subtokens, word, &[(&["memory"], Keyphrase::FromMemory)])?),
+				"publish" => Some(match_keyphrase(&mut subtokens, word, &[
+					(&["spellbook", "to"], Keyphrase::PublishSpellbookTo),
+					(&["spellbook"], Keyphrase::PublishSpellbook),
+				])?),
+				"and" => Some(match_keyphrase(&mut subtokens, word, &[
+					(&["put", "it", "in", "the", "drawer"], Keyphrase::AndPutItInTheDrawer),
+					(&["throw", "it", "in", "the", "trash"], Keyphrase::AndThrowItInTheTrash),
+					(&["toss", "it", "in", "the", "cauldron"], Keyphrase::AndTossItInTheCauldron),
+					(&["skip", "to", "bookmark"], Keyphrase::AndSkipToBookmark),
+				])?),
+				"sign" => Some(match_keyphrase(&mut subtokens, word, &[
+					(&["chapter", "with"], Keyphrase::SignChapterWith),
+					(&["acknowledgements", "page", "with"], Keyphrase::SignAcknowledgementsPageWith),
+				])?),
+				"place" if !naming => Some(match_keyphrase(&mut subtokens, word, &[(&["a", "bookmark", "named"], Keyphrase::PlaceABookmarkNamed)])?),
+				"flip" if !naming => Some(match_keyphrase(&mut subtokens, word, &[
+					(&["back", "to", "bookmark"], Keyphrase::FlipBackToBookmark),
+					(&["ahead", "to", "bookmark"], Keyphrase::FlipAheadToBookmark),
+				])?),
+				"slam" => Some(match_keyphrase(&mut subtokens, word, &[(&["spellbook", "shut"], Keyphrase::SlamSpellbookShut)])?),
+				// Only a condition, so "the" can still be a heading anywhere else
+				"the" if phrases.last().is_some_and(|p: &Lexeme| p.kind == LexemeKind::Conditional) => {
+					Some(match_keyphrase(&mut subtokens, word, &[(&["cauldron", "is", "full"], Keyphrase::TheCauldronIsFull)])?)
+				},
+				_ => None,
+			}
+		};
+
+		match phrase {
+			Some((kp, end)) => phrases.push(Lexeme{ kind: LexemeKind::Keyphrase(kp), span: word.span.start..end }),
+			None if word.kind == WordKind::Plain && word.text == "if" => {
+				phrases.push(Lexeme{ kind: LexemeKind::Conditional, span: word.span.clone() });
+			},
+			None => phrases.push(Lexeme{ kind: classify_word(word), span: word.span.clone() }),
+		}
+	}
+
+	// Comments in the middle of a keyphrase are still comments, which leaves the keyphrase
+	// in pieces around them
+	let mut lexemes: Vec<Lexeme> = Vec::with_capacity(words.len());
+	let mut phrases = phrases.into_iter().peekable();
+	let mut phrase_end = 0;
+	for word in &words {
+		if let Some(phrase) = phrases.peek() {
+			if word.span.start == phrase.span.start {
+				let mut phrase = phrases.next().unwrap();
+				phrase_end = phrase.span.end;
+				phrase.span.end = word.span.end;
+				lexemes.push(phrase);
+				continue;
+			}
+		}
+
+		let kind = match word.kind {
+			WordKind::Whitespace => LexemeKind::Whitespace,
+			WordKind::Plain if word.span.start < phrase_end => LexemeKind::KeyphraseContinued,
+			_ => LexemeKind::Comment,
+		};
+
+		// Words of the same piece of a keyphrase go together with the space between them
+		let piece = lexemes.len() >= 2 && lexemes[lexemes.len() - 1].kind == LexemeKind::Whitespace
+			&& matches!(lexemes[lexemes.len() - 2].kind, LexemeKind::Keyphrase(_) | LexemeKind::KeyphraseContinued);
+		if kind == LexemeKind::KeyphraseContinued && piece {
+			lexemes.pop();
+			lexemes.last_mut().unwrap().span.end = word.span.end;
+			continue;
+		}
+
+		lexemes.push(Lexeme{ kind, span: word.span.clone() });
+	}
+
+	Ok(lexemes)
+}
@@ -0,0 +1,38 @@
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+//        SPELLBOOK INTERPRETER
+//           BY DIANE SPARKS
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+
+#![feature(iter_advance_by)]
+#![feature(string_remove_matches)]
+
+pub mod analysis;
+pub mod cauldron;
+pub mod checker;
+pub mod compiler;
+pub mod constants;
+pub mod coverage;
+pub mod dap;
+pub mod debugger;
+pub mod differential;
+pub mod errors;
+pub mod exporter;
+pub mod formatter;
+pub mod generator;
+pub mod graph;
+pub mod interpreter;
+pub mod lexer;
+pub mod lsp;
+pub mod minifier;
+pub mod optimizer;
+pub mod page;
+pub mod parser;
+pub mod profiler;
+pub mod program;
+pub mod rpc;
+pub mod scribe;
+pub mod syntax;
+pub mod trace;
+pub mod transpiler;
+pub mod variant;
+pub mod visualizer;
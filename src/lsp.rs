@@ -0,0 +1,456 @@
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+//        SPELLBOOK INTERPRETER
+//           BY DIANE SPARKS
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+
+use crate::analysis::{self, Analysis, DeskFacts, StatementId, Tri};
+use crate::checker;
+use crate::constants::*;
+use crate::lexer::{self, Lexeme, LexemeKind};
+use crate::parser::Keyphrase;
+use crate::rpc;
+use crate::syntax::{self, *};
+use crate::variant::Variant;
+
+use serde_json::{json, Value};
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+
+const TOKEN_TYPES: [&str; 7] = ["comment", "keyword", "string", "number", "variable", "function", "enumMember"];
+
+const STATEMENT_KEYPHRASES: [Keyphrase; 17] = [
+	Keyphrase::TurnToChapter,
+	Keyphrase::TearOutChapter,
+	Keyphrase::TakeOutAChapterFromTheDrawerAndPutItBack,
+	Keyphrase::TakeOutChapterFromTheCauldronAndPutItBack,
+	Keyphrase::Write,
+	Keyphrase::Copy,
+	Keyphrase::Memorize,
+	Keyphrase::Cast,
+	Keyphrase::KnockOverCauldron,
+	Keyphrase::PublishSpellbook,
+	Keyphrase::PublishSpellbookTo,
+	Keyphrase::SignChapterWith,
+	Keyphrase::SignAcknowledgementsPageWith,
+	Keyphrase::SlamSpellbookShut,
+	Keyphrase::PlaceABookmarkNamed,
+	Keyphrase::FlipBackToBookmark,
+	Keyphrase::FlipAheadToBookmark,
+];
+
+const COMPLETION_KEYWORD: u32 = 14;
+const COMPLETION_FUNCTION: u32 = 3;
+const COMPLETION_VARIABLE: u32 = 6;
+const COMPLETION_ENUM_MEMBER: u32 = 20;
+
+// Editors count columns in UTF-16 code units, while spans count bytes
+
+fn utf16_column(text: &str, byte: usize) -> usize {
+	text[..byte.min(text.len())].encode_utf16().count()
+}
+
+fn byte_offset(text: &str, column: usize) -> usize {
+	let mut units = 0;
+	for (i, chr) in text.char_indices() {
+		if units >= column {
+			return i;
+		}
+
+		units += chr.len_utf16();
+	}
+
+	text.len()
+}
+
+fn chapter_contents(chapter: &str) -> &'static str {
+	match chapter {
+		"Presages" => "Boolean values, `true` or `false`.",
+		"Hexes" => "Integer numbers.",
+		"Illusions" => "Floating-point numbers.",
+		_ => "Character strings.",
+	}
+}
+
+struct Document {
+	text: String,
+	book: Spellbook,
+	analysis: Analysis,
+}
+
+impl Document {
+	fn new(text: String) -> Self {
+		let book = syntax::parse_source(&text);
+		let analysis = analysis::analyze(&book);
+		Self{ text, book, analysis }
+	}
+
+	fn line_index(&self, line: usize) -> Option<usize> {
+		self.book.lines.iter().position(|l| l.number == line + 1)
+	}
+
+	fn range(&self, index: usize, span: &Range<usize>) -> Value {
+		let line = &self.book.lines[index];
+		json!({
+			"start": { "line": line.number - 1, "character": utf16_column(&line.text, span.start) },
+			"end": { "line": line.number - 1, "character": utf16_column(&line.text, span.end) },
+		})
+	}
+
+	// Blank lines aren't part of the spellbook, so they borrow the facts of the line after them
+	fn facts_at(&self, line: usize) -> Option<&DeskFacts> {
+		let index = self.book.lines.iter().position(|l| l.number > line)?;
+		self.analysis.line_entry[index].as_ref()
+	}
+
+	fn heading_of(&self, id: StatementId) -> Option<&str> {
+		self.book.lines[id.0].statements().get(id.1)?.heading()
+	}
+
+	// The writes that could have put each heading in the chapters turned to
+	fn written_headings(&self, facts: &DeskFacts) -> Vec<(String, StatementId)> {
+		let mut headings = vec![];
+		for chapter in facts.chapters.iter().flatten() {
+			for slot in &facts.pages[*chapter].slots {
+				if slot.fill == Tri::No {
+					continue;
+				}
+
+				for writer in slot.writers.iter().flatten() {
+					if let Some(heading) = self.heading_of(*writer) {
+						if !headings.iter().any(|(h, w)| h == heading && w == writer) {
+							headings.push((heading.to_string(), *writer));
+						}
+					}
+				}
+			}
+		}
+
+		headings
+	}
+
+	fn lexeme_at(&self, index: usize, offset: usize) -> Option<&Lexeme> {
+		self.book.lines[index].lexemes.iter().find(|l| l.span.start <= offset && offset < l.span.end && l.kind != LexemeKind::Whitespace)
+	}
+
+	fn statement_at(&self, index: usize, offset: usize) -> Option<(usize, &Statement)> {
+		self.book.lines[index].statements().iter().enumerate().find(|(_, s)| s.span.start <= offset && offset < s.span.end)
+	}
+}
+
+fn diagnostics(document: &Document) -> Value {
+	let findings = checker::check(&document.book);
+	Value::Array(findings.iter().map(|finding| {
+		let index = document.book.lines.iter().position(|l| l.number == finding.line).unwrap();
+		json!({
+			"range": document.range(index, &finding.span),
+			"severity": if finding.rule == "SYNTAX" { 1 } else { 2 },
+			"code": finding.rule,
+			"source": "spellbook",
+			"message": finding.message,
+		})
+	}).collect())
+}
+
+fn completion_items(document: &Document, line: usize, column: usize) -> Value {
+	let line_text = document.text.lines().nth(line).unwrap_or("");
+	let before = &line_text[..byte_offset(line_text, column)];
+	let mut words = before.split_whitespace().filter(|w| !lexer::is_comment_word(w)).collect::<Vec<&str>>();
+	if !before.ends_with(char::is_whitespace) {
+		words.pop();
+	}
+
+	let item = |label: &str, kind: u32, detail: Option<&str>| match detail {
+		Some(detail) => json!({ "label": label, "kind": kind, "detail": detail }),
+		None => json!({ "label": label, "kind": kind }),
+	};
+
+	let ends_with = |phrase: &[&str]| words.ends_with(phrase);
+	let previous = words.len().checked_sub(2).map(|i| words[i]);
+	let items = if ends_with(&["turn", "to", "chapter"]) {
+		BUILTINS_CHAPTERS.iter().map(|c| item(c, COMPLETION_ENUM_MEMBER, Some(chapter_contents(c)))).collect()
+	} else if ends_with(&["cast"]) {
+		let mut spells = BUILTINS_SPELLS.keys().collect::<Vec<&&str>>();
+		spells.sort();
+		spells.iter().map(|s| item(s, COMPLETION_FUNCTION, BUILTINS_SPELL_DESCRIPTIONS.get(**s).cloned())).collect()
+	} else if ends_with(&["copy"]) || ends_with(&["memorize"]) || ends_with(&["if"]) {
+		let mut items = vec![];
+		if let Some(facts) = document.facts_at(line) {
+			for (heading, _) in document.written_headings(facts) {
+				if !items.iter().any(|i: &Value| i["label"] == heading) {
+					items.push(item(&heading, COMPLETION_VARIABLE, None));
+				}
+			}
+		}
+
+		if ends_with(&["memorize"]) || ends_with(&["if"]) {
+			items.extend(BUILTINS_ORDINALS.iter().map(|o| item(&format!("{} entry", o), COMPLETION_KEYWORD, None)));
+		}
+
+		if ends_with(&["if"]) {
+			items.push(item(Keyphrase::TheCauldronIsFull.text(), COMPLETION_KEYWORD, None));
+		}
+
+		items
+	} else if ends_with(&["to", "bookmark"]) {
+		let mut items = vec![];
+		for statement in document.book.lines.iter().flat_map(|l| l.statements()) {
+			if let StatementKind::PlaceBookmark(name) = &statement.kind {
+				items.push(item(name, COMPLETION_VARIABLE, None));
+			}
+		}
+
+		items
+	} else if ends_with(&["Judgement", "on", "the", "cauldron"]) {
+		vec![item(Keyphrase::AndSkipToBookmark.text(), COMPLETION_KEYWORD, None)]
+	} else if ends_with(&["tear", "out", "chapter"]) {
+		[Keyphrase::AndPutItInTheDrawer, Keyphrase::AndThrowItInTheTrash, Keyphrase::AndTossItInTheCauldron].iter()
+			.map(|k| item(k.text(), COMPLETION_KEYWORD, None))
+			.collect()
+	} else if ends_with(&["write"]) {
+		vec![item(Keyphrase::FromMemory.text(), COMPLETION_KEYWORD, None)]
+	} else if previous == Some("cast") {
+		vec![item(Keyphrase::OnTheCauldron.text(), COMPLETION_KEYWORD, None)]
+	} else if previous == Some("memorize") && BUILTINS_ORDINALS.contains(words.last().unwrap()) {
+		vec![item(Keyphrase::Entry.text(), COMPLETION_KEYWORD, None)]
+	} else if matches!(previous, Some("write") | Some("copy")) || ends_with(&["from", "memory"]) {
+		vec![item(Keyphrase::Under.text(), COMPLETION_KEYWORD, None)]
+	} else if ends_with(&["under"]) {
+		vec![]
+	} else {
+		let mut items = STATEMENT_KEYPHRASES.iter().map(|k| item(k.text(), COMPLETION_KEYWORD, None)).collect::<Vec<Value>>();
+		items.push(item("if", COMPLETION_KEYWORD, None));
+		items
+	};
+
+	Value::Array(items)
+}
+
+fn hover(document: &Document, line: usize, column: usize) -> Value {
+	let index = match document.line_index(line) {
+		Some(index) => index,
+		None => return Value::Null,
+	};
+
+	let offset = byte_offset(&document.book.lines[index].text, column);
+	let lexeme = match document.lexeme_at(index, offset) {
+		Some(lexeme) => lexeme,
+		None => return Value::Null,
+	};
+
+	let mut parts = vec![];
+	if let LexemeKind::Builtin(builtin) = &lexeme.kind {
+		if let Some(description) = BUILTINS_SPELL_DESCRIPTIONS.get(builtin.as_str()) {
+			parts.push(format!("**{}**: {}", builtin, description));
+		} else if BUILTINS_CHAPTERS.contains(&builtin.as_str()) {
+			parts.push(format!("**{}**: {}", builtin, chapter_contents(builtin)));
+		}
+	}
+
+	if let Some((i, _)) = document.statement_at(index, offset) {
+		match document.analysis.facts_before((index, i)) {
+			Some(facts) => parts.push(format!("Before this statement:\n```\n{}\n```", facts)),
+			None => parts.push("This line is never reached.".into()),
+		}
+	}
+
+	if parts.is_empty() {
+		return Value::Null;
+	}
+
+	json!({
+		"contents": { "kind": "markdown", "value": parts.join("\n\n") },
+		"range": document.range(index, &lexeme.span),
+	})
+}
+
+fn definition(document: &Document, uri: &str, line: usize, column: usize) -> Value {
+	let index = match document.line_index(line) {
+		Some(index) => index,
+		None => return Value::Null,
+	};
+
+	let offset = byte_offset(&document.book.lines[index].text, column);
+	let heading = match document.lexeme_at(index, offset).map(|l| &l.kind) {
+		Some(LexemeKind::Identifier(heading)) => heading,
+		_ => return Value::Null,
+	};
+
+	let (i, statement) = match document.statement_at(index, offset) {
+		Some(found) => found,
+		None => return Value::Null,
+	};
+
+	// A bookmark is found where it was placed
+	if let Some(bookmark) = statement.bookmark().filter(|b| b.name == *heading) {
+		let placed = bookmark.line.and_then(|l| document.book.lines[l].statements().iter()
+			.find(|s| s.kind == StatementKind::PlaceBookmark(bookmark.name.clone()))
+			.map(|s| (l, s)));
+
+		return match placed {
+			Some((l, placement)) => json!([{ "uri": uri, "range": document.range(l, &placement.span) }]),
+			None => Value::Null,
+		};
+	}
+
+	let read = match &statement.kind {
+		StatementKind::Write{ source: WriteSource::Copy(from), .. } => from,
+		StatementKind::Memorize(MemorizeSource::Heading(heading)) => heading,
+		_ => return Value::Null,
+	};
+
+	if read != heading {
+		return Value::Null;
+	}
+
+	let mut writers = document.analysis.facts_before((index, i))
+		.map(|facts| document.written_headings(facts).into_iter().filter(|(h, _)| h == heading).map(|(_, w)| w).collect::<Vec<StatementId>>())
+		.unwrap_or_default();
+
+	// Without anything better to go on, any write under the heading will do
+	if writers.is_empty() {
+		for (l, source_line) in document.book.lines.iter().enumerate() {
+			for (s, statement) in source_line.statements().iter().enumerate() {
+				if statement.heading() == Some(heading) {
+					writers.push((l, s));
+				}
+			}
+		}
+	}
+
+	writers.sort();
+	Value::Array(writers.iter().map(|(l, s)| json!({
+		"uri": uri,
+		"range": document.range(*l, &document.book.lines[*l].statements()[*s].span),
+	})).collect())
+}
+
+fn token_type(kind: &LexemeKind) -> Option<usize> {
+	match kind {
+		LexemeKind::Comment => Some(0),
+		LexemeKind::Keyphrase(_) | LexemeKind::KeyphraseContinued | LexemeKind::Conditional => Some(1),
+		LexemeKind::Literal(Variant::Str(_)) => Some(2),
+		LexemeKind::Literal(_) => Some(3),
+		LexemeKind::Identifier(_) => Some(4),
+		LexemeKind::Builtin(builtin) if BUILTINS_SPELLS.contains_key(builtin.as_str()) => Some(5),
+		LexemeKind::Builtin(builtin) if BUILTINS_CHAPTERS.contains(&builtin.as_str()) => Some(6),
+		LexemeKind::Builtin(_) => Some(1),
+		LexemeKind::Whitespace => None,
+	}
+}
+
+fn semantic_tokens(document: &Document) -> Value {
+	let mut data = vec![];
+	let mut last_line = 0;
+	let mut last_start = 0;
+	for line in &document.book.lines {
+		for lexeme in &line.lexemes {
+			let kind = match token_type(&lexeme.kind) {
+				Some(kind) => kind,
+				None => continue,
+			};
+
+			let number = line.number - 1;
+			let start = utf16_column(&line.text, lexeme.span.start);
+			let length = utf16_column(&line.text, lexeme.span.end) - start;
+			data.extend([number - last_line, if number == last_line { start - last_start } else { start }, length, kind, 0]);
+			last_line = number;
+			last_start = start;
+		}
+	}
+
+	json!({ "data": data })
+}
+
+fn capabilities() -> Value {
+	json!({
+		"capabilities": {
+			"textDocumentSync": 1,
+			"completionProvider": { "triggerCharacters": [" "] },
+			"hoverProvider": true,
+			"definitionProvider": true,
+			"semanticTokensProvider": {
+				"legend": { "tokenTypes": TOKEN_TYPES, "tokenModifiers": [] },
+				"full": true,
+			},
+		},
+		"serverInfo": { "name": "spellbook" },
+	})
+}
+
+pub fn serve(mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+	let mut documents: HashMap<String, Document> = HashMap::new();
+
+	while let Some(message) = rpc::read_message(&mut input)? {
+		let method = message["method"].as_str().unwrap_or_default();
+		let params = &message["params"];
+		let uri = params["textDocument"]["uri"].as_str().unwrap_or_default().to_string();
+		let line = params["position"]["line"].as_u64().unwrap_or_default() as usize;
+		let column = params["position"]["character"].as_u64().unwrap_or_default() as usize;
+
+		let opened = match method {
+			"textDocument/didOpen" => Some(params["textDocument"]["text"].as_str().unwrap_or_default().to_string()),
+			"textDocument/didChange" => params["contentChanges"].as_array()
+				.and_then(|changes| changes.last())
+				.and_then(|change| change["text"].as_str())
+				.map(String::from),
+			_ => None,
+		};
+
+		if let Some(text) = opened {
+			let document = Document::new(text);
+			rpc::write_message(&mut output, &json!({
+				"jsonrpc": "2.0",
+				"method": "textDocument/publishDiagnostics",
+				"params": { "uri": uri, "diagnostics": diagnostics(&document) },
+			}))?;
+
+			documents.insert(uri.clone(), document);
+		}
+
+		if method == "textDocument/didClose" {
+			documents.remove(&uri);
+			rpc::write_message(&mut output, &json!({
+				"jsonrpc": "2.0",
+				"method": "textDocument/publishDiagnostics",
+				"params": { "uri": uri, "diagnostics": [] },
+			}))?;
+		}
+
+		if method == "exit" {
+			return Ok(());
+		}
+
+		let id = match message.get("id") {
+			Some(id) if !method.is_empty() => id.clone(),
+			_ => continue,
+		};
+
+		let result = match (method, documents.get(&uri)) {
+			("initialize", _) => Ok(capabilities()),
+			("shutdown", _) => Ok(Value::Null),
+			("textDocument/completion", Some(document)) => Ok(completion_items(document, line, column)),
+			("textDocument/hover", Some(document)) => Ok(hover(document, line, column)),
+			("textDocument/definition", Some(document)) => Ok(definition(document, &uri, line, column)),
+			("textDocument/semanticTokens/full", Some(document)) => Ok(semantic_tokens(document)),
+			("textDocument/completion", None)
+			| ("textDocument/hover", None)
+			| ("textDocument/definition", None)
+			| ("textDocument/semanticTokens/full", None) => Ok(Value::Null),
+			_ => Err(json!({ "code": -32601, "message": format!("there's no method called `{}`", method) })),
+		};
+
+		rpc::write_message(&mut output, &match result {
+			Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+			Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+		})?;
+	}
+
+	Ok(())
+}
+
+pub fn run(_args: &[String]) {
+	let stdin = io::stdin();
+	serve(stdin.lock(), io::stdout()).unwrap();
+}
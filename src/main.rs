@@ -3,51 +3,67 @@
 //           BY DIANE SPARKS
 // *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
 
-#![feature(iter_advance_by)]
-#![feature(string_remove_matches)]
-
-mod cauldron;
-mod constants;
-mod errors;
-mod page;
-mod parser;
-mod program;
-mod variant;
-
-use constants::ERROR_MESSAGES;
-use program::Program;
-
-use rand::prelude::*;
+use spellbook::errors::set_catastrophe_hook;
+use spellbook::program::Program;
+use spellbook::{checker, compiler, coverage, dap, debugger, differential, exporter, formatter, generator, graph, interpreter, lsp, minifier, optimizer, profiler, scribe, syntax, trace, visualizer};
 
 use std::env;
-use std::fs::File;
-use std::io::{self, BufRead};
-use std::panic;
+use std::process;
 
 fn main() {
-	panic::set_hook(Box::new(|info| {
-		let mut rng = thread_rng();
-		let index: usize = rng.gen_range(0..10);
-		let message = ERROR_MESSAGES[index];
-		if let Some(line) = info.payload().downcast_ref::<usize>() {
-			eprintln!("\x1b[0;91mCatastrophe!\x1b[0m\n{}\n(Line {})", message, line);
-		} else {
-			eprintln!("\x1b[0;91mCatastrophe!\x1b[0m\n{}", message);
-		}
-	}));
+	set_catastrophe_hook("<spellbook>".into());
 
 	let args = env::args().collect::<Vec<String>>();
 	if args.len() < 2 {
 		panic!();
 	}
 
+	match args[1].as_str() {
+		"fmt" => formatter::run(&args[2..]),
+		"minify" => minifier::run(&args[2..]),
+		"check" => checker::run(&args[2..]),
+		"parse" => exporter::run(&args[2..]),
+		"graph" => graph::run(&args[2..]),
+		"lsp" => lsp::run(&args[2..]),
+		"dap" => dap::run(&args[2..]),
+		"debug" => debugger::run(&args[2..]),
+		"profile" => profiler::run(&args[2..]),
+		"compile" => compiler::run(&args[2..]),
+		"generate" => generator::run(&args[2..]),
+		"differential" => differential::run(&args[2..]),
+		"scribe" => scribe::run(&args[2..]),
+		"run" => run(&args[2..]),
+		"watch-run" => visualizer::run(&args[2..]),
+		_ => run(&args[1..]),
+	}
+}
+
+fn run(args: &[String]) {
 	let mut path = String::new();
 	let mut debug_mode = false;
-	for arg in &args[1..] {
+	let mut trace = None;
+	let mut coverage_path = None;
+	let mut optimize = false;
+	let mut explain = false;
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
 		match arg.as_str() {
 			"--trace" => {
 				debug_mode = true;
 			},
+			"--trace=jsonl" | "--trace=diff" => {
+				trace = Some(arg.as_str());
+			},
+			"--coverage" => {
+				coverage_path = Some(args.next().unwrap().clone());
+			},
+			"--optimize" => {
+				optimize = true;
+			},
+			"--explain-opt" => {
+				optimize = true;
+				explain = true;
+			},
 			_ => {
 				path = arg.into();
 			},
@@ -58,41 +74,30 @@ fn main() {
 		panic!();
 	}
 
-	let infile = File::open(&path).unwrap();
-
-	let mut program = Program::new(debug_mode);
-	let mut code = vec![];
-	for line in io::BufReader::new(infile).lines().enumerate() {
-		match line {
-			(i, Ok(ln)) => {
-				if !ln.trim().is_empty() {
-					code.push((i + 1, ln));
-				}
-			},
-			(_, Err(_)) => {},
-		}
+	// Both of them want to read the whole spellbook themselves
+	if let (Some(flag), Some(_)) = (trace, &coverage_path) {
+		eprintln!("--coverage and {} can't be used together, so run the spellbook once for each.", flag);
+		process::exit(1);
 	}
 
-	while program.line_internal < code.len() {
-		program.line_number = code[program.line_internal].0;
-		let tokenized = match parser::tokenize_line(code[program.line_internal].1.clone()) {
-			Some(vec) => vec,
-			None => {
-				sb_panic!(program.line_number);
-			},
-		};
-
-		if !tokenized.is_empty() {
-			parser::execute_token_vector(&mut program, tokenized);
-			if program.exit {
-				return;
+	set_catastrophe_hook(path.clone());
+	let source = syntax::read_source(&path).unwrap();
+	let mut book = syntax::parse_source(&source);
+	if optimize {
+		for change in optimizer::optimize(&mut book) {
+			if explain {
+				eprintln!("{}:{}: {}: {}", path, change.line, change.pass, change.description);
 			}
 		}
+	}
 
-		if program.line_internal >= code.len() {
-			sb_panic!(program.line_number);
-		}
-		
-		program.line_internal += 1;
+	let mut interpreter = interpreter::Interpreter::new(book, Program::new(debug_mode));
+	match coverage_path {
+		Some(coverage_path) => coverage::run(interpreter, &path, &coverage_path),
+		None => match trace {
+			Some("--trace=jsonl") => trace::run_jsonl(interpreter),
+			Some("--trace=diff") => trace::run_diff(interpreter),
+			_ => interpreter.run(),
+		},
 	}
 }
@@ -0,0 +1,293 @@
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+//        SPELLBOOK INTERPRETER
+//           BY DIANE SPARKS
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+
+use crate::analysis::{self, Analysis, Edge, EdgeKind, StatementId, Target};
+use crate::formatter::escape_string;
+use crate::lexer::LexemeKind;
+use crate::syntax::{self, *};
+use crate::variant::Variant;
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::process;
+
+// Lines with nothing left on them still have to be there for Judgement to count
+const PLACEHOLDER: &str = "-";
+
+pub struct RefusedMerge {
+	pub line: usize,
+	pub reason: String,
+}
+
+pub struct MinifyResult {
+	pub output: String,
+	pub refused: Vec<RefusedMerge>,
+}
+
+fn render_statement(line: &SourceLine, statement: &Statement) -> String {
+	line.lexemes.iter()
+		.filter(|l| !l.is_trivia() && l.span.start >= statement.span.start && l.span.end <= statement.span.end)
+		.map(|l| match &l.kind {
+			LexemeKind::Keyphrase(kp) => kp.text().to_string(),
+			LexemeKind::Literal(Variant::Str(string)) => escape_string(string),
+			_ => line.text[l.span.clone()].to_string(),
+		})
+		.collect::<Vec<String>>()
+		.join(" ")
+}
+
+// Which output line each line ends up on, and how many there are
+fn group(apart: &[Option<String>]) -> (Vec<usize>, usize) {
+	let mut groups = Vec::with_capacity(apart.len());
+	let mut current = 0;
+	for (i, reason) in apart.iter().enumerate() {
+		if i > 0 && reason.is_some() {
+			current += 1;
+		}
+
+		groups.push(current);
+	}
+
+	(groups, if apart.is_empty() { 0 } else { current + 1 })
+}
+
+// Whether a Judgement's charge can be changed without anyone noticing, which is when every
+// way on from it squelches before the charge gets used again
+fn charge_forgotten(book: &Spellbook, analysis: &Analysis, id: StatementId) -> bool {
+	let mut seen = HashSet::new();
+	let mut pending = vec![(id.0, id.1 + 1)];
+	while let Some((line, start)) = pending.pop() {
+		let mut squelched = false;
+		let mut left_early = false;
+		for statement in book.lines[line].statements().iter().skip(start) {
+			if matches!(statement.kind, StatementKind::If(_)) {
+				left_early = true;
+			}
+
+			match statement.spell() {
+				// Unless an `if` might have ended the line before getting here
+				Some("Squelch") if !left_early => {
+					squelched = true;
+					break;
+				},
+				Some("Judgement") | Some("Reverberate") => return false,
+				_ => {},
+			}
+		}
+
+		if squelched {
+			continue;
+		}
+
+		for edge in analysis.edges.iter().filter(|e| e.from.0 == line) {
+			if let Target::Line(target) = edge.to {
+				if seen.insert(target) {
+					pending.push((target, 0));
+				}
+			}
+		}
+	}
+
+	true
+}
+
+// How many Diminishes a Judgement needs in front of it to still land where it used to
+fn recharge(book: &Spellbook, analysis: &Analysis, id: StatementId, skips: &[&Edge], groups: &[usize], count: usize) -> Result<usize, String> {
+	let number = book.lines[id.0].number;
+	if skips.iter().any(|e| e.dynamic || e.charge.is_none()) {
+		return Err(format!("Judgement on line {} skips by a charge that isn't known ahead of time", number));
+	}
+
+	let group_of = |line: usize| if line < groups.len() { groups[line] } else { count };
+	let mut delta = None;
+	let mut overshoots = vec![];
+	for skip in skips {
+		let charge = skip.charge.unwrap();
+		let landing = match skip.to {
+			Target::Line(target) => target,
+			Target::End => book.lines.len(),
+			Target::Catastrophe => {
+				overshoots.push(charge);
+				continue;
+			},
+		};
+
+		let needed = charge - (group_of(landing) - groups[id.0] - 1);
+		if delta.is_some_and(|d| d != needed) {
+			return Err(format!("Judgement on line {} skips by different amounts that can't all be made shorter together", number));
+		}
+
+		delta = Some(needed);
+	}
+
+	let delta = delta.unwrap_or(0);
+	if delta == 0 {
+		return Ok(0);
+	}
+
+	if overshoots.iter().any(|charge| groups[id.0] + charge - delta < count) {
+		return Err(format!("Judgement on line {} would no longer skip past the end", number));
+	}
+
+	if !charge_forgotten(book, analysis, id) {
+		return Err(format!("the charge left after Judgement on line {} is used again later", number));
+	}
+
+	Ok(delta)
+}
+
+pub fn minify(book: &Spellbook) -> MinifyResult {
+	let analysis = analysis::analyze(book);
+	let mut apart: Vec<Option<String>> = vec![None; book.lines.len()];
+
+	// Anything the reader can land on has to start a line, and nothing can come after a
+	// statement that sends the reader somewhere else, since the rest of its line still runs
+	for edge in &analysis.edges {
+		let number = book.lines[edge.from.0].number;
+		match (edge.kind, edge.to) {
+			(EdgeKind::Skip, Target::Line(target)) if apart[target].is_none() => {
+				apart[target] = Some(format!("Judgement on line {} can land on it", number));
+			},
+			(EdgeKind::JumpBack, Target::Line(target)) if apart[target].is_none() => {
+				apart[target] = Some(format!("Reverberate on line {} can go back to it", number));
+			},
+			(EdgeKind::Flip, Target::Line(target)) if apart[target].is_none() => {
+				apart[target] = Some(format!("line {} flips to a bookmark on it", number));
+			},
+			_ => {},
+		}
+	}
+
+	// Even a bookmark nothing flips to yet has to stay where it was, so every way of
+	// flipping to it still goes the way it said
+	for (line, slot) in book.lines.iter().zip(apart.iter_mut()) {
+		if slot.is_none() && line.statements().iter().any(|s| matches!(s.kind, StatementKind::PlaceBookmark(_))) {
+			*slot = Some("it places a bookmark".into());
+		}
+	}
+
+	for (before, slot) in book.lines.iter().zip(apart.iter_mut().skip(1)) {
+		let sender = before.statements().iter().find_map(|s| match &s.kind {
+			_ if matches!(s.spell(), Some("Judgement" | "Reverberate")) => Some(format!("casts {}, and the rest of its line would still be read", s.spell().unwrap())),
+			StatementKind::Flip{ .. } => Some("flips to a bookmark, and the rest of its line would still be read".to_string()),
+			StatementKind::SlamShut => Some("slams the spellbook shut, and the rest of its line would still be read".to_string()),
+			StatementKind::If(_) => Some("has an `if`, which would guard whatever came after it too".to_string()),
+			_ => None,
+		});
+
+		if let (Some(sender), None) = (sender, &slot) {
+			*slot = Some(format!("line {} {}", before.number, sender));
+		}
+	}
+
+	// Merging lines a Judgement skips over makes it skip too far. That's fixed by taking
+	// charge away right before it, and where that can't be done, the lines stay apart.
+	// Keeping lines apart only ever makes skips shorter to fix, so this settles down.
+	// Skipping to a bookmark always lands on it, so there's nothing to fix there
+	let judgements = book.lines.iter().enumerate()
+		.flat_map(|(i, l)| l.statements().iter().enumerate().filter(|(_, s)| matches!(&s.kind, StatementKind::Cast(spell) if spell == "Judgement")).map(move |(j, _)| (i, j)))
+		.collect::<Vec<StatementId>>();
+
+	let (groups, diminishes) = loop {
+		let (groups, count) = group(&apart);
+		let mut diminishes = HashMap::new();
+		let mut kept = false;
+		for &id in &judgements {
+			let skips = analysis.edges.iter().filter(|e| e.from == id && e.kind == EdgeKind::Skip).collect::<Vec<&Edge>>();
+			if skips.is_empty() {
+				continue;
+			}
+
+			match recharge(book, &analysis, id, &skips, &groups, count) {
+				Ok(0) => {},
+				Ok(delta) => {
+					diminishes.insert(id, delta);
+				},
+				Err(reason) => {
+					let last = skips.iter().map(|e| match e.to {
+						Target::Line(target) => target,
+						_ => book.lines.len(),
+					}).max().unwrap();
+
+					for slot in apart.iter_mut().take(last).skip(id.0 + 1) {
+						if slot.is_none() {
+							*slot = Some(reason.clone());
+							kept = true;
+						}
+					}
+				},
+			}
+		}
+
+		if !kept {
+			break (groups, diminishes);
+		}
+	};
+
+	let mut lines: Vec<Vec<String>> = vec![];
+	for (index, line) in book.lines.iter().enumerate() {
+		if lines.len() <= groups[index] {
+			lines.push(vec![]);
+		}
+
+		let merged = lines.last_mut().unwrap();
+		for (i, statement) in line.statements().iter().enumerate() {
+			for _ in 0..diminishes.get(&(index, i)).copied().unwrap_or(0) {
+				merged.push("cast Diminish on the cauldron".into());
+			}
+
+			merged.push(render_statement(line, statement));
+		}
+	}
+
+	let mut output = String::new();
+	for line in lines {
+		if line.is_empty() {
+			output.push_str(PLACEHOLDER);
+		} else {
+			output.push_str(&line.join(" "));
+		}
+
+		output.push('\n');
+	}
+
+	let refused = apart.into_iter().enumerate().skip(1)
+		.filter_map(|(i, reason)| reason.map(|reason| RefusedMerge{ line: book.lines[i].number, reason }))
+		.collect();
+
+	MinifyResult{ output, refused }
+}
+
+pub fn run(args: &[String]) {
+	let mut path = None;
+	let mut output_path = None;
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"-o" => output_path = Some(args.next().unwrap().clone()),
+			_ => path = Some(arg.clone()),
+		}
+	}
+
+	let path = path.unwrap();
+	let source = syntax::read_source(&path).unwrap();
+	let book = syntax::parse_source(&source);
+
+	// Without knowing what a line does, there's no telling what merging it would break
+	if let Some(line) = book.lines.iter().find(|l| l.statements.is_err()) {
+		eprintln!("{}:{}: can't be read, so nothing was minified", path, line.number);
+		process::exit(1);
+	}
+
+	let result = minify(&book);
+	for refusal in &result.refused {
+		eprintln!("{}:{}: kept on its own line because {}", path, refusal.line, refusal.reason);
+	}
+
+	match output_path {
+		Some(output_path) => fs::write(output_path, result.output).unwrap(),
+		None => print!("{}", result.output),
+	}
+}
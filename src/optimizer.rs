@@ -0,0 +1,441 @@
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+//        SPELLBOOK INTERPRETER
+//           BY DIANE SPARKS
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+
+use crate::analysis::{self, Analysis, Control, DeskFacts, EdgeKind, Moved, StatementId, Target, Tri};
+use crate::cauldron::{Cauldron, CauldronMixMode};
+use crate::constants::*;
+use crate::page::Page;
+use crate::syntax::*;
+use crate::variant::Variant;
+
+use std::collections::{BTreeSet, HashSet};
+
+// Past this many places the reader could be, nobody can say whether a Vacancy matters
+const MAX_TRACKED_PAIRS: usize = 4096;
+
+// Makes one change if it can find one, giving back each line it changed and how
+type Pass = fn(&mut Spellbook, &Analysis) -> Vec<(usize, String)>;
+
+// Every pass, in the order they're run. Each one makes a single change at a time, so the
+// analysis it looks at is always of the spellbook as it is right then
+const PASSES: [(&str, Pass); 3] = [
+	("constant-mixes", fold_constant_mix),
+	("dead-stores", drop_dead_store),
+	("idle-vacancies", drop_idle_vacancy),
+];
+
+pub struct Change {
+	pub pass: &'static str,
+	pub line: usize,
+	pub description: String,
+}
+
+fn literal_write(statement: &Statement) -> Option<(&Variant, &str)> {
+	match &statement.kind {
+		StatementKind::Write{ source: WriteSource::Literal(value), heading } => Some((value, heading)),
+		_ => None,
+	}
+}
+
+fn only_chapter(facts: &DeskFacts) -> Option<usize> {
+	match facts.chapters.iter().collect::<Vec<_>>()[..] {
+		[Some(chapter)] => Some(*chapter),
+		_ => None,
+	}
+}
+
+fn text_of(book: &Spellbook, id: StatementId) -> &str {
+	let line = &book.lines[id.0];
+	&line.text[line.statements()[id.1].span.clone()]
+}
+
+fn remove(book: &mut Spellbook, id: StatementId) -> Statement {
+	book.lines[id.0].statements.as_mut().unwrap().remove(id.1)
+}
+
+// A chapter written with these entries, if every one of them can be written
+fn fill(chapter: usize, writes: &[(Variant, String)]) -> Option<Page> {
+	let mut page = Page::new(analysis::page_type(chapter));
+	for (value, heading) in writes {
+		if page.entry_names.contains(heading) || !page.write_value(heading.clone(), Some(value.clone()), false, 0) {
+			return None;
+		}
+	}
+
+	Some(page)
+}
+
+// Whether the reader can only ever get from one line to the next, one after another
+fn straight(analysis: &Analysis, from: usize, to: usize) -> bool {
+	(from..to).all(|line| analysis.edges.iter().all(|e| {
+		let leaves = e.from.0 == line;
+		let arrives = e.to == Target::Line(line + 1);
+		leaves == arrives && (!leaves || e.kind == EdgeKind::Fallthrough)
+	}))
+}
+
+struct Mix {
+	mode: CauldronMixMode,
+	first: Vec<StatementId>,
+	first_toss: StatementId,
+	second: Vec<StatementId>,
+	second_toss: StatementId,
+	folded: Vec<Statement>,
+}
+
+// Two chapters of nothing but literal entries tossed into an empty cauldron, one after the
+// other, always mix into the same thing
+fn find_mix(book: &Spellbook, analysis: &Analysis, order: &[StatementId], start: usize) -> Option<Mix> {
+	let statement = |k: usize| order.get(k).map(|id| &book.lines[id.0].statements()[id.1]);
+	let writes = |k: &mut usize| {
+		let mut found = vec![];
+		while let Some((value, heading)) = statement(*k).and_then(literal_write) {
+			found.push((order[*k], (value.clone(), heading.to_string())));
+			*k += 1;
+		}
+
+		found
+	};
+	let tossed = |k: usize| statement(k).map(|s| &s.kind) == Some(&StatementKind::TearOutChapter(Destination::Cauldron));
+
+	let facts = analysis.facts_before(order[start])?;
+	let chapter = only_chapter(facts)?;
+	if facts.cauldron.holds != Tri::No || facts.pages[chapter].slots.iter().any(|s| s.fill != Tri::No) || facts.mixes.len() != 1 {
+		return None;
+	}
+
+	let mut k = start;
+	let first = writes(&mut k);
+	if first.is_empty() || !tossed(k) {
+		return None;
+	}
+
+	let first_toss = order[k];
+	k += 1;
+	let other = match statement(k).map(|s| &s.kind) {
+		Some(StatementKind::TurnToChapter(other)) => {
+			k += 1;
+			*other
+		},
+		_ => chapter,
+	};
+
+	let second_start = k;
+	let second = writes(&mut k);
+	if second.is_empty() || !tossed(k) {
+		return None;
+	}
+
+	let second_toss = order[k];
+	if other != chapter && analysis.facts_before(order[second_start])?.pages[other].slots.iter().any(|s| s.fill != Tri::No) {
+		return None;
+	}
+
+	// Nothing in between can be left out by an `if`, or the chapters might not get mixed at all
+	let guarded = (order[start].0..=second_toss.0).any(|line| book.lines[line].statements().iter().any(|s| matches!(s.kind, StatementKind::If(_))));
+	if guarded || !straight(analysis, order[start].0, second_toss.0) {
+		return None;
+	}
+
+	let mode = *facts.mixes.iter().next().unwrap();
+	let mut cauldron = Cauldron::new();
+	cauldron.mix_mode = mode;
+	cauldron.add_page(&fill(chapter, &first.iter().map(|w| w.1.clone()).collect::<Vec<_>>())?);
+	if !cauldron.add_page(&fill(other, &second.iter().map(|w| w.1.clone()).collect::<Vec<_>>())?) {
+		return None;
+	}
+
+	// It has to come out exactly the same when written straight into the first chapter
+	let mixed = cauldron.knock_over().unwrap();
+	let entries = (0..3).map_while(|i| mixed.values[i].clone().map(|v| (v, mixed.entry_names[i].clone()))).collect::<Vec<_>>();
+	let refilled = fill(chapter, &entries)?;
+	if refilled.values != mixed.values || refilled.entry_names != mixed.entry_names {
+		return None;
+	}
+
+	let span = book.lines[first_toss.0].statements()[first_toss.1].span.clone();
+	Some(Mix{
+		mode,
+		first: first.iter().map(|w| w.0).collect(),
+		first_toss,
+		second: second.iter().map(|w| w.0).collect(),
+		second_toss,
+		folded: entries.into_iter().map(|(value, heading)| Statement{
+			kind: StatementKind::Write{ source: WriteSource::Literal(value), heading },
+			span: span.clone(),
+		}).collect(),
+	})
+}
+
+fn fold_constant_mix(book: &mut Spellbook, analysis: &Analysis) -> Vec<(usize, String)> {
+	let order = book.lines.iter().enumerate()
+		.flat_map(|(i, l)| (0..l.statements().len()).map(move |j| (i, j)))
+		.collect::<Vec<StatementId>>();
+
+	let mix = match (0..order.len()).find_map(|start| find_mix(book, analysis, &order, start)) {
+		Some(mix) => mix,
+		None => return vec![],
+	};
+
+	let number = |line: usize| book.lines[line].number;
+	let (first_line, second_line) = (number(mix.first_toss.0), number(mix.second_toss.0));
+	let verb = match mix.mode {
+		CauldronMixMode::Add => "adds",
+		CauldronMixMode::Sub => "subtracts",
+		CauldronMixMode::Mul => "multiplies",
+		CauldronMixMode::Div => "divides",
+	};
+	let values = mix.folded.iter().filter_map(|s| literal_write(s).map(|(v, h)| format!("{} under {}", v.print(), h))).collect::<Vec<String>>();
+
+	let mut changes = vec![(first_line, format!("{} the chapters tossed in the cauldron on lines {} and {} ahead of time, writing {} instead", verb, first_line, second_line, values.join(", ")))];
+	let mut removed = mix.first.iter().chain(&mix.second).cloned().chain(std::iter::once(mix.second_toss)).collect::<Vec<StatementId>>();
+	for line in removed.iter().map(|id| number(id.0)) {
+		if !changes.iter().any(|c| c.0 == line) {
+			changes.push((line, format!("its part of the mix is done on line {} now", first_line)));
+		}
+	}
+
+	removed.sort();
+	for id in removed.iter().rev() {
+		remove(book, *id);
+	}
+
+	let at = mix.first_toss.1 - mix.first.iter().filter(|id| id.0 == mix.first_toss.0).count();
+	let statements = book.lines[mix.first_toss.0].statements.as_mut().unwrap();
+	statements.splice(at..at, mix.folded);
+	changes
+}
+
+// Nothing can go wrong writing this, so leaving it out won't stop a catastrophe
+fn write_cannot_fail(book: &Spellbook, facts: &DeskFacts, chapter: usize, value: &Variant, heading: &str) -> bool {
+	let page = &facts.pages[chapter];
+	page.full() == Tri::No && analysis::converts_to(value, chapter) && page.slots.iter().filter(|s| s.fill != Tri::No).all(|s| match &s.writers {
+		Some(writers) => writers.iter().all(|w| book.lines[w.0].statements().get(w.1).and_then(|s| s.heading()) != Some(heading)),
+		None => false,
+	})
+}
+
+// Whether every way on from a statement throws the chapter in the trash before anything
+// looks at it, or the spellbook ends first
+fn trashed_unread(book: &Spellbook, analysis: &Analysis, id: StatementId, chapter: usize) -> bool {
+	let mut seen = HashSet::new();
+	let mut pending = vec![(id.0, id.1 + 1)];
+	while let Some((line, start)) = pending.pop() {
+		let mut trashed = false;
+		let mut left_early = false;
+		for (i, statement) in book.lines[line].statements().iter().enumerate().skip(start) {
+			let facts = match analysis.facts_before((line, i)) {
+				Some(facts) => facts,
+				None => return false,
+			};
+
+			let maybe_here = facts.chapters.contains(&Some(chapter));
+			match &statement.kind {
+				StatementKind::Publish(_) => return false,
+				// Unless an `if` might have ended the line before getting here
+				StatementKind::TearOutChapter(Destination::Trash) if only_chapter(facts) == Some(chapter) && !left_early => {
+					trashed = true;
+					break;
+				},
+				StatementKind::If(condition) if *condition == Condition::CauldronFull || !maybe_here => {
+					left_early = true;
+				},
+				StatementKind::TurnToChapter(_)
+				| StatementKind::Cast(_)
+				| StatementKind::KnockOverCauldron
+				| StatementKind::SignChapter(_)
+				| StatementKind::SignAcknowledgements(_)
+				| StatementKind::SlamShut
+				| StatementKind::PlaceBookmark(_)
+				| StatementKind::Flip{ .. }
+				| StatementKind::JudgeToBookmark(_)
+				| StatementKind::Memorize(MemorizeSource::Literal(_)) => {},
+				_ if maybe_here => return false,
+				_ => {},
+			}
+		}
+
+		if trashed {
+			continue;
+		}
+
+		for edge in analysis.edges.iter().filter(|e| e.from.0 == line) {
+			if let Target::Line(target) = edge.to {
+				if seen.insert(target) {
+					pending.push((target, 0));
+				}
+			}
+		}
+	}
+
+	true
+}
+
+fn drop_dead_store(book: &mut Spellbook, analysis: &Analysis) -> Vec<(usize, String)> {
+	let found = book.lines.iter().enumerate().find_map(|(index, line)| {
+		line.statements().iter().enumerate().find_map(|(i, statement)| {
+			let (value, heading) = literal_write(statement)?;
+			let facts = analysis.facts_before((index, i))?;
+			let chapter = only_chapter(facts)?;
+			if write_cannot_fail(book, facts, chapter, value, heading) && trashed_unread(book, analysis, (index, i), chapter) {
+				Some(((index, i), chapter))
+			} else {
+				None
+			}
+		})
+	});
+
+	let (id, chapter) = match found {
+		Some(found) => found,
+		None => return vec![],
+	};
+
+	let description = format!("removed `{}`, since the {} chapter is thrown in the trash before anything reads it", text_of(book, id), BUILTINS_CHAPTERS[chapter]);
+	let line = book.lines[id.0].number;
+	remove(book, id);
+	vec![(line, description)]
+}
+
+// Follows the reader through the spellbook with and without a Vacancy at once, making sure
+// every Judgement would skip just as far and every Reverberate would go back to the same line
+fn vacancy_idle(book: &Spellbook, vacancy: StatementId) -> bool {
+	let start = Control{ cursor: 0, charge: 0, amplifier: 1, amplifies_in_a_row: 0, stack: vec![] };
+	let mut seen = BTreeSet::new();
+	let mut pending = vec![(start.clone(), start)];
+	while let Some(pair) = pending.pop() {
+		if !seen.insert(pair.clone()) {
+			continue;
+		}
+
+		if seen.len() > MAX_TRACKED_PAIRS {
+			return false;
+		}
+
+		let line = pair.0.cursor;
+		let mut pairs = vec![pair];
+		let mut exits = false;
+		// Conditions never depend on the charge, so both leave the line early together
+		let mut left_early = vec![];
+		for (i, statement) in book.lines[line].statements().iter().enumerate() {
+			let (spell, bookmark) = match &statement.kind {
+				StatementKind::Cast(spell) if BUILTINS_SPELLS.contains_key(spell.as_str()) => (spell.as_str(), None),
+				StatementKind::JudgeToBookmark(bookmark) => ("Judgement", Some(bookmark)),
+				// Flipping sends both to the same place, or both into the same catastrophe
+				StatementKind::Flip{ bookmark, .. } => {
+					match bookmark.line {
+						Some(target) => {
+							for (with, without) in &mut pairs {
+								with.cursor = target.wrapping_sub(1);
+								without.cursor = with.cursor;
+							}
+						},
+						None => pairs.clear(),
+					}
+
+					continue;
+				},
+				StatementKind::SlamShut => {
+					exits = true;
+					continue;
+				},
+				StatementKind::If(_) if !exits => {
+					left_early.extend(pairs.iter().cloned());
+					continue;
+				},
+				_ => continue,
+			};
+
+			let mut next = vec![];
+			for (mut with, mut without) in pairs {
+				let moved = with.cast(spell);
+				let moved_without = if (line, i) == vacancy { Moved::Stayed } else { without.cast(spell) };
+				match (moved, moved_without) {
+					// However far the charge would skip, a bookmark is always the same place
+					(Moved::Stayed | Moved::MightSkip(_), Moved::Stayed | Moved::MightSkip(_)) if bookmark.is_some() => {
+						if let Some(target) = bookmark.unwrap().line {
+							let (mut skipped, mut skipped_without) = (with.clone(), without.clone());
+							skipped.cursor = target.wrapping_sub(1);
+							skipped_without.cursor = skipped.cursor;
+							next.push((skipped, skipped_without));
+						}
+					},
+					(Moved::Stayed, Moved::Stayed) => {},
+					(Moved::MightSkip(a), Moved::MightSkip(b)) if a == b => {
+						let (mut skipped, mut skipped_without) = (with.clone(), without.clone());
+						skipped.cursor = skipped.cursor.wrapping_add(a);
+						skipped_without.cursor = skipped.cursor;
+						next.push((skipped, skipped_without));
+					},
+					(Moved::JumpedBack(_), Moved::JumpedBack(_)) if with.cursor == without.cursor => {},
+					(Moved::Overloaded, Moved::Overloaded) | (Moved::Nowhere(_), Moved::Nowhere(_)) => continue,
+					_ => return false,
+				}
+
+				next.push((with, without));
+			}
+
+			pairs = next;
+		}
+
+		if exits {
+			pairs.clear();
+		}
+
+		for (mut with, mut without) in pairs.into_iter().chain(left_early) {
+			with.cursor = with.cursor.wrapping_add(1);
+			without.cursor = with.cursor;
+			if with.cursor < book.lines.len() {
+				pending.push((with, without));
+			}
+		}
+	}
+
+	true
+}
+
+fn drop_idle_vacancy(book: &mut Spellbook, _analysis: &Analysis) -> Vec<(usize, String)> {
+	let found = book.lines.iter().enumerate()
+		.flat_map(|(i, l)| l.statements().iter().enumerate().filter(|(_, s)| s.spell() == Some("Vacancy")).map(move |(j, _)| (i, j)))
+		.find(|id| vacancy_idle(book, *id));
+
+	let id = match found {
+		Some(id) => id,
+		None => return vec![],
+	};
+
+	let description = format!("removed `{}`, which is never reverberated to and whose charge nothing counts on", text_of(book, id));
+	let line = book.lines[id.0].number;
+	remove(book, id);
+	vec![(line, description)]
+}
+
+// Makes the spellbook do less work while publishing exactly the same things, stopping on
+// the same catastrophes, on the same lines. Lines are never added or taken away, only the
+// statements on them, so Judgement still counts the same
+pub fn optimize(book: &mut Spellbook) -> Vec<Change> {
+	// Without knowing what every line does, there's no telling what a change would break
+	if book.lines.iter().any(|l| l.statements.is_err()) {
+		return vec![];
+	}
+
+	let mut changes = vec![];
+	for (pass, run) in PASSES {
+		let mut made = vec![];
+		loop {
+			let analysis = analysis::analyze(book);
+			let more = run(book, &analysis);
+			if more.is_empty() {
+				break;
+			}
+
+			made.extend(more.into_iter().map(|(line, description)| Change{ pass, line, description }));
+		}
+
+		made.sort_by_key(|c| c.line);
+		changes.extend(made);
+	}
+
+	changes
+}
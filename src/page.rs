@@ -3,6 +3,7 @@
 //           BY DIANE SPARKS
 // *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
 
+use crate::constants::*;
 use crate::variant::Variant;
 
 #[derive(Clone, PartialEq, Debug)]
@@ -47,7 +48,13 @@ impl Page {
 	}
 
 	pub fn write_value(&mut self, name: String, value: Option<Variant>, index_override: bool, index: usize) -> bool {
+		// Putting a page back counts its entries as written, so the next write can run off the
+		// end of a page that isn't full
 		let ind = if index_override { index } else { self.write_index };
+		if ind >= 3 {
+			return false;
+		}
+
 		self.entry_names[ind] = name;
 		match value {
 			Some(val) => {
@@ -88,6 +95,23 @@ impl Page {
 		}
 	}
 
+	// Entries that hold something they didn't hold before, whether it got there by a write, by
+	// putting a page back over this one or by Coadjuvancy. Tearing out only ever empties them
+	pub fn written_since(&self, before: &Page) -> Vec<usize> {
+		(0..3).filter(|&i| self.values[i].is_some() && (self.entry_names[i] != before.entry_names[i] || self.values[i] != before.values[i])).collect()
+	}
+
+	// Roughly how many bytes the page takes up, along with the text written on it
+	pub fn size(&self) -> usize {
+		let names = self.entry_names.iter().map(String::len).sum::<usize>();
+		let values = self.values.iter().map(|v| match v {
+			Some(Variant::Str(string)) => string.len(),
+			_ => 0,
+		}).sum::<usize>();
+
+		std::mem::size_of::<Page>() + names + values + self.signature.len()
+	}
+
 	pub fn has_any_contents(&self) -> bool {
 		self.values.iter().any(|v| v.is_some())
 	}
@@ -105,4 +129,25 @@ impl Page {
 		self.write_index = 0;
 		self.changed_signature = false;
 	}
+
+	// How an entry is shown while debugging, named by its heading or else by where it is
+	pub fn entry_label(&self, index: usize) -> String {
+		if self.entry_names[index].is_empty() {
+			format!("({} entry)", BUILTINS_ORDINALS[index])
+		} else {
+			self.entry_names[index].clone()
+		}
+	}
+
+	pub fn entry_text(&self, index: usize) -> String {
+		match &self.values[index] {
+			Some(value) => value.print(),
+			None => "—".into(),
+		}
+	}
+
+	pub fn describe(&self) -> String {
+		let entries = (0..3).map(|i| self.entry_text(i)).collect::<Vec<String>>();
+		format!("[{}]", entries.join(", "))
+	}
 }
@@ -0,0 +1,122 @@
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+//        SPELLBOOK INTERPRETER
+//           BY DIANE SPARKS
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+
+use crate::errors::set_catastrophe_hook;
+use crate::interpreter::Interpreter;
+use crate::program::Program;
+use crate::syntax;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+// Bytes of string built by the operations most likely to make a spellbook slow. They're
+// always counted, since an atomic add costs next to nothing next to the allocation itself
+pub static ADD_BYTES: AtomicUsize = AtomicUsize::new(0);
+pub static MUL_BYTES: AtomicUsize = AtomicUsize::new(0);
+pub static MEMORY_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+pub fn count_bytes(counter: &AtomicUsize, bytes: usize) {
+	counter.fetch_add(bytes, Ordering::Relaxed);
+}
+
+fn total_bytes() -> usize {
+	ADD_BYTES.load(Ordering::Relaxed) + MUL_BYTES.load(Ordering::Relaxed) + MEMORY_BYTES.load(Ordering::Relaxed)
+}
+
+#[derive(Default, Clone)]
+struct LineProfile {
+	count: usize,
+	time: Duration,
+	bytes: usize,
+}
+
+fn milliseconds(time: Duration) -> f64 {
+	time.as_secs_f64() * 1000.0
+}
+
+pub fn run(args: &[String]) {
+	let mut path = String::new();
+	let mut folded_path = None;
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--folded" => {
+				folded_path = Some(args.next().unwrap().clone());
+			},
+			_ => {
+				path = arg.into();
+			},
+		}
+	}
+
+	if path.is_empty() {
+		panic!();
+	}
+
+	set_catastrophe_hook(path.clone());
+	let source = syntax::read_source(&path).unwrap();
+	// Lexing happens line by line as each one is parsed, so it's all counted here
+	let reading_start = Instant::now();
+	let book = syntax::parse_source(&source);
+	let reading = reading_start.elapsed();
+
+	let mut lines = vec![LineProfile::default(); book.lines.len()];
+	let mut stacks: BTreeMap<String, u128> = BTreeMap::new();
+	let mut interpreter = Interpreter::new(book, Program::new(false));
+	let mut catastrophe = false;
+	while !interpreter.is_finished() {
+		let (line, statement) = interpreter.position().unwrap();
+
+		// The spells Reverberate could go back to are the callers of the line being read
+		let frames = interpreter.program.spell_line_stack.iter().map(|(l, _)| *l).chain([line]).filter_map(|l| interpreter.book.lines.get(l))
+			.map(|l| format!("{}: {}", l.number, l.text.trim().replace(';', ",")))
+			.collect::<Vec<String>>();
+
+		let bytes_before = total_bytes();
+		let start = Instant::now();
+		catastrophe = interpreter.try_step(Interpreter::step_statement).is_err();
+		let time = start.elapsed();
+
+		let profile = &mut lines[line];
+		if statement == 0 {
+			profile.count += 1;
+		}
+
+		profile.time += time;
+		profile.bytes += total_bytes() - bytes_before;
+		*stacks.entry(frames.join(";")).or_default() += time.as_nanos();
+	}
+
+	let executing = lines.iter().map(|l| l.time).sum::<Duration>();
+	eprintln!();
+	eprintln!("{:>8} {:>10} {:>6} {:>10}", "count", "ms", "%", "bytes");
+	for (profile, source_line) in lines.iter().zip(&interpreter.book.lines) {
+		if profile.count == 0 {
+			eprintln!("{:>8} {:>10} {:>6} {:>10} {:>4} | {}", "", "", "", "", source_line.number, source_line.text);
+			continue;
+		}
+
+		let share = if executing.is_zero() { 0.0 } else { profile.time.as_secs_f64() / executing.as_secs_f64() * 100.0 };
+		eprintln!("{:>8} {:>10.3} {:>6.1} {:>10} {:>4} | {}", profile.count, milliseconds(profile.time), share, profile.bytes, source_line.number, source_line.text);
+	}
+
+	eprintln!();
+	eprintln!("lexing and parsing: {:.3} ms", milliseconds(reading));
+	eprintln!("executing:          {:.3} ms", milliseconds(executing));
+	eprintln!("string bytes built: {} by adding, {} by multiplying, {} by writing from memory",
+		ADD_BYTES.load(Ordering::Relaxed), MUL_BYTES.load(Ordering::Relaxed), MEMORY_BYTES.load(Ordering::Relaxed));
+
+	if let Some(folded_path) = folded_path {
+		let folded = stacks.iter().map(|(stack, time)| format!("{} {}\n", stack, time)).collect::<String>();
+		fs::write(folded_path, folded).unwrap();
+	}
+
+	if catastrophe {
+		process::exit(1);
+	}
+}
@@ -7,23 +7,83 @@ use crate::variant::Variant;
 use crate::cauldron::*;
 use crate::page::*;
 use crate::constants::*;
+use crate::profiler;
 use crate::sb_panic;
+use crate::syntax::Condition;
 
 use rand::prelude::*;
 use std::fs::OpenOptions;
-use std::io::{self, Write};
+use std::cell::RefCell;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+
+// Where the reader is after flipping to a bookmark on the very first line, one line before it
+pub const BEFORE_FIRST_LINE: usize = usize::MAX;
+
+// Whatever the spellbook publishes, kept for showing somewhere other than the console
+#[derive(Clone, Default)]
+pub struct CapturedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl CapturedOutput {
+	pub fn take_text(&self) -> String {
+		let text = String::from_utf8_lossy(&self.0.borrow()).to_string();
+		self.0.borrow_mut().clear();
+		text
+	}
+}
+
+impl Write for CapturedOutput {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.0.borrow_mut().extend_from_slice(buf);
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+// Everything on the desk, leaving out the console, so it can be put back the way it was
+#[derive(Clone)]
+pub struct Desk {
+	pub pages: [Page; 4],
+	pub cauldron: Cauldron,
+	pub spell_line_stack: Vec<(usize, usize)>,
+	pub current_page: usize,
+	pub turned_to_any_page: bool,
+	pub drawer: Vec<Page>,
+	pub memory: Option<Variant>,
+	custom_signature: String,
+	use_custom_signature: bool,
+	pub line_internal: usize,
+	pub line_number: usize,
+	pub exit: bool,
+}
+
+impl Desk {
+	// Roughly how many bytes it takes to remember the desk
+	pub fn size(&self) -> usize {
+		let pages = self.pages.iter().chain(&self.drawer).chain(&self.cauldron.page).map(Page::size).sum::<usize>();
+		let memory = match &self.memory {
+			Some(Variant::Str(string)) => string.len(),
+			_ => 0,
+		};
+
+		std::mem::size_of::<Desk>() + pages + memory + self.custom_signature.len() + self.spell_line_stack.len() * std::mem::size_of::<(usize, usize)>()
+	}
+}
 
 pub struct Program {
-	pages: [Page; 4],
-	cauldron: Cauldron,
+	pub pages: [Page; 4],
+	pub cauldron: Cauldron,
 
-	spell_line_stack: Vec<(usize, usize)>,
+	pub spell_line_stack: Vec<(usize, usize)>,
 
-	current_page: usize,
-	turned_to_any_page: bool,
+	pub current_page: usize,
+	pub turned_to_any_page: bool,
 
-	drawer: Vec<Page>,
-	memory: Option<Variant>,
+	pub drawer: Vec<Page>,
+	pub memory: Option<Variant>,
 
 	custom_signature: String,
 	use_custom_signature: bool,
@@ -33,6 +93,12 @@ pub struct Program {
 	pub exit: bool,
 
 	pub debug_mode: bool,
+
+	// Off when runs have to be repeatable, like when comparing engines
+	pub forgetful: bool,
+
+	pub input: Box<dyn BufRead>,
+	pub output: Box<dyn Write>,
 }
 
 impl Program {
@@ -60,9 +126,45 @@ impl Program {
 			exit: false,
 
 			debug_mode,
+			forgetful: true,
+
+			input: Box::new(io::BufReader::new(io::stdin())),
+			output: Box::new(io::stdout()),
+		}
+	}
+
+	pub fn save_desk(&self) -> Desk {
+		Desk{
+			pages: self.pages.clone(),
+			cauldron: self.cauldron.clone(),
+			spell_line_stack: self.spell_line_stack.clone(),
+			current_page: self.current_page,
+			turned_to_any_page: self.turned_to_any_page,
+			drawer: self.drawer.clone(),
+			memory: self.memory.clone(),
+			custom_signature: self.custom_signature.clone(),
+			use_custom_signature: self.use_custom_signature,
+			line_internal: self.line_internal,
+			line_number: self.line_number,
+			exit: self.exit,
 		}
 	}
 
+	pub fn restore_desk(&mut self, desk: Desk) {
+		self.pages = desk.pages;
+		self.cauldron = desk.cauldron;
+		self.spell_line_stack = desk.spell_line_stack;
+		self.current_page = desk.current_page;
+		self.turned_to_any_page = desk.turned_to_any_page;
+		self.drawer = desk.drawer;
+		self.memory = desk.memory;
+		self.custom_signature = desk.custom_signature;
+		self.use_custom_signature = desk.use_custom_signature;
+		self.line_internal = desk.line_internal;
+		self.line_number = desk.line_number;
+		self.exit = desk.exit;
+	}
+
 	pub fn is_totally_empty(&self) -> bool {
 		!self.pages.iter().any(|p| p.has_any_contents())
 	}
@@ -112,7 +214,9 @@ impl Program {
 						}
 					}).fold(String::with_capacity(10), |mut st, c| { st.push(c); st });
 
-					Some(Variant::Integer(modified.parse::<i64>().unwrap()))
+					profiler::count_bytes(&profiler::MEMORY_BYTES, as_str.len() + modified.len());
+					// Forgetting can make a number too big to remember at all
+					Some(Variant::Integer(modified.parse::<i64>().unwrap_or_else(|_| sb_panic!(self.line_number))))
 				},
 				Variant::Float(float) => {
 					let as_str = float.to_string();
@@ -125,22 +229,28 @@ impl Program {
 						}
 					}).fold(String::with_capacity(10), |mut st, c| { st.push(c); st });
 
-					Some(Variant::Float(modified.parse::<f64>().unwrap()))
+					profiler::count_bytes(&profiler::MEMORY_BYTES, as_str.len() + modified.len());
+					Some(Variant::Float(modified.parse::<f64>().unwrap_or_else(|_| sb_panic!(self.line_number))))
 				},
 				Variant::Str(ref string) => {
 					let mut modified = string.clone();
 					for word in string.split_whitespace().enumerate() {
 						if rng.gen_bool(self.forget_chance(word.0)) {
 							if rng.gen_bool(0.25) {
-								let index = modified.find(word.1).unwrap();
-								modified = modified.replace(word.1, "");
-								modified.remove(index - 1);
+								// Along with whatever came before it, unless it's already gone
+								if let Some(index) = modified.find(word.1) {
+									modified = modified.replace(word.1, "");
+									if let Some((before, _)) = modified[..index].char_indices().next_back() {
+										modified.remove(before);
+									}
+								}
 							} else {
 								modified = modified.replace(word.1, "something");
 							}
 						}
 					}
 
+					profiler::count_bytes(&profiler::MEMORY_BYTES, modified.len());
 					Some(Variant::Str(modified))
 				},
 				_ => Some(val.clone()),
@@ -164,7 +274,7 @@ impl Program {
 	}
 
 	pub fn cast_cauldron_spell(&mut self, spell: &CauldronSpell) {
-		match self.cauldron.cast_spell(spell) {
+		match self.cauldron.cast_spell(spell, &mut self.input) {
 			Some(CauldronSpellResult::DoNothing) => {},
 			Some(CauldronSpellResult::NoCharge) => {
 				return;
@@ -172,12 +282,14 @@ impl Program {
 			Some(CauldronSpellResult::SkipLine(charge)) => {
 				self.spell_line_stack.push((self.line_internal, self.cauldron.get_amplifier()));
 				self.cauldron.increase_charge(false, 0);
-				self.line_internal += charge;
+				self.line_internal = self.line_internal.wrapping_add(charge);
 				return;
 			},
 			Some(CauldronSpellResult::JumpBack(charge)) => {
-				self.line_internal = match self.spell_line_stack.get(charge) {
-					Some(line) => line.0 - 1,
+				// One before, since finishing this line moves on to the next. There's nothing
+				// before the first line to go back to
+				self.line_internal = match self.spell_line_stack.get(charge).and_then(|line| line.0.checked_sub(1)) {
+					Some(line) => line,
 					None => {
 						sb_panic!(self.line_number);
 					},
@@ -202,6 +314,55 @@ impl Program {
 		self.cauldron.increase_charge(false, 0);
 	}
 
+	// Judgement that lands on a bookmark's line when it fails, however much charge there is
+	pub fn cast_judgement_to(&mut self, line: Option<usize>) {
+		let skip = match self.cauldron.cast_spell(&CauldronSpell::Judgement, &mut self.input) {
+			Some(CauldronSpellResult::DoNothing) => false,
+			Some(CauldronSpellResult::SkipLine(_)) => true,
+			_ => {
+				sb_panic!(self.line_number);
+			},
+		};
+
+		self.spell_line_stack.push((self.line_internal, self.cauldron.get_amplifier()));
+		self.cauldron.increase_charge(false, 0);
+		if skip {
+			self.flip_to(line);
+		}
+	}
+
+	// Moves to a bookmark's line once this one is finished. A bookmark on the very first line
+	// leaves the reader before it, which is the only time finishing a line wraps around
+	pub fn flip_to(&mut self, line: Option<usize>) {
+		self.line_internal = match line {
+			Some(line) => line.checked_sub(1).unwrap_or(BEFORE_FIRST_LINE),
+			None => {
+				sb_panic!(self.line_number);
+			},
+		};
+	}
+
+	// Whether an `if` lets the rest of its line be read. Only a Presages entry that's been
+	// written in can say so
+	pub fn presage(&self, condition: &Condition) -> bool {
+		let value = match condition {
+			Condition::CauldronFull => return self.cauldron.page.is_some(),
+			Condition::Heading(heading) => self.try_get_value(heading),
+			Condition::Entry(index) => self.get_value_by_index(*index),
+		};
+
+		if !self.turned_to_any_page || self.pages[self.current_page].page_type != PageType::Boolean {
+			sb_panic!(self.line_number);
+		}
+
+		match value {
+			Some(value) => value.to_bool(),
+			None => {
+				sb_panic!(self.line_number);
+			},
+		}
+	}
+
 	pub fn knock_over_cauldron(&mut self) {
 		self.cauldron.knock_over();
 	}
@@ -215,7 +376,7 @@ impl Program {
 		self.pages[self.current_page].changed_signature = true;
 	}
 
-	pub fn publish(&self, not_console: bool, target: String) {
+	pub fn publish(&mut self, not_console: bool, target: String) {
 		let mut output = String::with_capacity(100);
 		for p in 0..4 {
 			for v in 0..3 {
@@ -240,8 +401,8 @@ impl Program {
 		};
 
 		if !not_console {
-			print!("{}{}", output, signature);
-			io::stdout().flush().unwrap_or_else(|_| sb_panic!(self.line_number));
+			write!(self.output, "{}{}", output, signature).unwrap_or_else(|_| sb_panic!(self.line_number));
+			self.output.flush().unwrap_or_else(|_| sb_panic!(self.line_number));
 		} else {
 			let mut outfile = OpenOptions::new()
 				.create(true)
@@ -307,7 +468,7 @@ impl Program {
 	}
 
 	fn forget_chance(&self, x: usize) -> f64 {
-		if x < 5 {
+		if x < 5 || !self.forgetful {
 			0.0
 		} else {
 			1.0 - (1.0 / (((x - 4) as f64).powf(0.3)))
@@ -0,0 +1,39 @@
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+//        SPELLBOOK INTERPRETER
+//           BY DIANE SPARKS
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+
+use serde_json::Value;
+
+use std::io::{self, BufRead, Write};
+
+// Both editor protocols frame their JSON messages the same way, with headers like HTTP
+
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+	let mut length = None;
+	loop {
+		let mut header = String::new();
+		if input.read_line(&mut header)? == 0 {
+			return Ok(None);
+		}
+
+		let header = header.trim_end();
+		if header.is_empty() {
+			break;
+		}
+
+		if let Some(value) = header.strip_prefix("Content-Length:") {
+			length = value.trim().parse::<usize>().ok();
+		}
+	}
+
+	let mut body = vec![0; length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?];
+	input.read_exact(&mut body)?;
+	serde_json::from_slice(&body).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+	let body = message.to_string();
+	write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+	output.flush()
+}
@@ -0,0 +1,1606 @@
+/* *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+ *        SPELLBOOK INTERPRETER
+ *           BY DIANE SPARKS
+ * *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+ *
+ * Everything a compiled spellbook needs to run, doing just what the interpreter does.
+ * The spellbook itself comes after this, as one case of a switch for every line. */
+
+#include <inttypes.h>
+#include <math.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <time.h>
+
+/* How many statements to read before giving up, or 0 to keep going forever */
+#ifndef SB_FUEL
+#define SB_FUEL 0
+#endif
+
+/* Off when runs have to be repeatable, like when comparing with the interpreter */
+#ifndef SB_FORGETFUL
+#define SB_FORGETFUL 1
+#endif
+
+#define SB_CATASTROPHE_STATUS 101
+#define SB_EXHAUSTED_STATUS 3
+
+#define SB_MAX_STRING_BYTES ((size_t)1 << 28)
+
+/* Stands in for the line of a bookmark that was never placed */
+#define SB_NO_BOOKMARK SIZE_MAX
+
+/* Not every spellbook needs every part of the runtime */
+#if defined(__GNUC__)
+#pragma GCC diagnostic ignored "-Wunused-function"
+#endif
+
+static const char *const sb_error_messages[10] = {
+SB_ERROR_MESSAGES
+};
+
+/* Strings */
+
+typedef struct {
+	char *data;
+	size_t len;
+} SbString;
+
+#define SB_STR(literal) sb_string(literal, sizeof(literal) - 1)
+/* Only for reading, never to be freed */
+#define SB_STR_VIEW(literal) ((SbString){ (char *)(literal), sizeof(literal) - 1 })
+
+static size_t sb_line_number = 1;
+
+static uint64_t sb_random(void);
+
+static void sb_catastrophe(void) {
+	fflush(stdout);
+	fprintf(stderr, "\033[0;91mCatastrophe!\033[0m\n%s\n(Line %zu)\n",
+		sb_error_messages[sb_random() % 10], sb_line_number);
+	exit(SB_CATASTROPHE_STATUS);
+}
+
+static void *sb_alloc(size_t bytes) {
+	void *memory = malloc(bytes ? bytes : 1);
+	if (!memory) {
+		sb_catastrophe();
+	}
+
+	return memory;
+}
+
+static SbString sb_string(const char *data, size_t len) {
+	SbString string = { sb_alloc(len), len };
+	memcpy(string.data, data, len);
+	return string;
+}
+
+static SbString sb_string_empty(void) {
+	return sb_string("", 0);
+}
+
+static SbString sb_string_clone(SbString string) {
+	return sb_string(string.data, string.len);
+}
+
+static void sb_string_free(SbString *string) {
+	free(string->data);
+	string->data = NULL;
+	string->len = 0;
+}
+
+static void sb_string_push(SbString *string, const char *data, size_t len) {
+	char *grown = realloc(string->data, string->len + len + 1);
+	if (!grown) {
+		sb_catastrophe();
+	}
+
+	memcpy(grown + string->len, data, len);
+	string->data = grown;
+	string->len += len;
+}
+
+static bool sb_string_eq(SbString a, SbString b) {
+	return a.len == b.len && memcmp(a.data, b.data, a.len) == 0;
+}
+
+static const char *sb_string_find(SbString haystack, size_t from, SbString needle) {
+	if (needle.len == 0) {
+		return haystack.data + from;
+	}
+
+	for (size_t i = from; i + needle.len <= haystack.len; i++) {
+		if (memcmp(haystack.data + i, needle.data, needle.len) == 0) {
+			return haystack.data + i;
+		}
+	}
+
+	return NULL;
+}
+
+/* Every match, left to right, swapped for something else */
+static SbString sb_string_replace(SbString string, SbString from, SbString to) {
+	SbString replaced = sb_string_empty();
+	size_t start = 0;
+	const char *match;
+	while (from.len > 0 && (match = sb_string_find(string, start, from))) {
+		size_t index = (size_t)(match - string.data);
+		sb_string_push(&replaced, string.data + start, index - start);
+		sb_string_push(&replaced, to.data, to.len);
+		start = index + from.len;
+	}
+
+	sb_string_push(&replaced, string.data + start, string.len - start);
+	return replaced;
+}
+
+static bool sb_is_continuation(char byte) {
+	return ((unsigned char)byte & 0xC0) == 0x80;
+}
+
+static size_t sb_char_count(SbString string) {
+	size_t count = 0;
+	for (size_t i = 0; i < string.len; i++) {
+		count += !sb_is_continuation(string.data[i]);
+	}
+
+	return count;
+}
+
+static SbString sb_string_reversed(SbString string) {
+	SbString reversed = { sb_alloc(string.len), string.len };
+	size_t i = 0;
+	while (i < string.len) {
+		size_t width = 1;
+		while (i + width < string.len && sb_is_continuation(string.data[i + width])) {
+			width++;
+		}
+
+		memcpy(reversed.data + string.len - i - width, string.data + i, width);
+		i += width;
+	}
+
+	return reversed;
+}
+
+static bool sb_valid_utf8(const char *data, size_t len) {
+	size_t i = 0;
+	while (i < len) {
+		unsigned char byte = (unsigned char)data[i];
+		size_t width;
+		uint32_t point;
+		if (byte < 0x80) {
+			i++;
+			continue;
+		} else if ((byte & 0xE0) == 0xC0) {
+			width = 2;
+			point = byte & 0x1F;
+		} else if ((byte & 0xF0) == 0xE0) {
+			width = 3;
+			point = byte & 0x0F;
+		} else if ((byte & 0xF8) == 0xF0) {
+			width = 4;
+			point = byte & 0x07;
+		} else {
+			return false;
+		}
+
+		if (i + width > len) {
+			return false;
+		}
+
+		for (size_t j = 1; j < width; j++) {
+			if (!sb_is_continuation(data[i + j])) {
+				return false;
+			}
+
+			point = (point << 6) | ((unsigned char)data[i + j] & 0x3F);
+		}
+
+		if ((width == 2 && point < 0x80) || (width == 3 && point < 0x800) || (width == 4 && point < 0x10000)
+			|| point > 0x10FFFF || (point >= 0xD800 && point <= 0xDFFF)) {
+			return false;
+		}
+
+		i += width;
+	}
+
+	return true;
+}
+
+/* How long the whitespace character at the start is, or 0 if it isn't one. Whitespace
+ * here is whatever Rust thinks it is */
+static size_t sb_whitespace_width(const char *data, size_t len) {
+	unsigned char byte = (unsigned char)data[0];
+	if (byte == ' ' || (byte >= '\t' && byte <= '\r')) {
+		return 1;
+	}
+
+	if (len >= 2 && byte == 0xC2 && ((unsigned char)data[1] == 0x85 || (unsigned char)data[1] == 0xA0)) {
+		return 2;
+	}
+
+	if (len >= 3 && (byte == 0xE1 || byte == 0xE2 || byte == 0xE3)) {
+		uint32_t point = ((uint32_t)(byte & 0x0F) << 12) | ((uint32_t)((unsigned char)data[1] & 0x3F) << 6)
+			| ((unsigned char)data[2] & 0x3F);
+		if (point == 0x1680 || (point >= 0x2000 && point <= 0x200A) || point == 0x2028 || point == 0x2029
+			|| point == 0x202F || point == 0x205F || point == 0x3000) {
+			return 3;
+		}
+	}
+
+	return 0;
+}
+
+static size_t sb_trimmed_len(SbString string) {
+	size_t end = 0;
+	size_t i = 0;
+	while (i < string.len) {
+		size_t width = sb_whitespace_width(string.data + i, string.len - i);
+		if (width == 0) {
+			width = 1;
+			while (i + width < string.len && sb_is_continuation(string.data[i + width])) {
+				width++;
+			}
+
+			end = i + width;
+		}
+
+		i += width;
+	}
+
+	return end;
+}
+
+/* Numbers, written and read the way Rust does it */
+
+static SbString sb_print_int(int64_t integer) {
+	char buffer[24];
+	int len = snprintf(buffer, sizeof buffer, "%" PRId64, integer);
+	return sb_string(buffer, (size_t)len);
+}
+
+/* The fewest digits that still read back as the same float, never with an exponent */
+static SbString sb_print_float(double f) {
+	if (isnan(f)) {
+		return SB_STR("NaN");
+	}
+
+	if (isinf(f)) {
+		return f > 0 ? SB_STR("inf") : SB_STR("-inf");
+	}
+
+	char scientific[40];
+	for (int precision = 0; precision <= 17; precision++) {
+		snprintf(scientific, sizeof scientific, "%.*e", precision, f);
+		if (strtod(scientific, NULL) == f) {
+			break;
+		}
+	}
+
+	char digits[24];
+	size_t count = 0;
+	const char *c = scientific;
+	bool negative = *c == '-';
+	if (negative) {
+		c++;
+	}
+
+	for (; *c != 'e'; c++) {
+		if (*c != '.') {
+			digits[count++] = *c;
+		}
+	}
+
+	long exponent = strtol(c + 1, NULL, 10);
+	SbString printed = sb_string_empty();
+	if (negative) {
+		sb_string_push(&printed, "-", 1);
+	}
+
+	if (exponent < 0) {
+		sb_string_push(&printed, "0.", 2);
+		for (long i = 0; i < -exponent - 1; i++) {
+			sb_string_push(&printed, "0", 1);
+		}
+
+		sb_string_push(&printed, digits, count);
+	} else if ((size_t)exponent + 1 >= count) {
+		sb_string_push(&printed, digits, count);
+		for (size_t i = count; i < (size_t)exponent + 1; i++) {
+			sb_string_push(&printed, "0", 1);
+		}
+	} else {
+		sb_string_push(&printed, digits, (size_t)exponent + 1);
+		sb_string_push(&printed, ".", 1);
+		sb_string_push(&printed, digits + exponent + 1, count - (size_t)exponent - 1);
+	}
+
+	return printed;
+}
+
+static bool sb_parse_bool(SbString string, bool *parsed) {
+	if (sb_string_eq(string, SB_STR_VIEW("true"))) {
+		*parsed = true;
+		return true;
+	}
+
+	if (sb_string_eq(string, SB_STR_VIEW("false"))) {
+		*parsed = false;
+		return true;
+	}
+
+	return false;
+}
+
+static bool sb_parse_int(SbString string, int64_t *parsed) {
+	size_t i = 0;
+	bool negative = false;
+	if (string.len > 0 && (string.data[0] == '+' || string.data[0] == '-')) {
+		negative = string.data[0] == '-';
+		i++;
+	}
+
+	if (i == string.len) {
+		return false;
+	}
+
+	int64_t value = 0;
+	for (; i < string.len; i++) {
+		if (string.data[i] < '0' || string.data[i] > '9') {
+			return false;
+		}
+
+		int digit = string.data[i] - '0';
+		if (negative) {
+			if (value < (INT64_MIN + digit) / 10) {
+				return false;
+			}
+
+			value = value * 10 - digit;
+		} else {
+			if (value > (INT64_MAX - digit) / 10) {
+				return false;
+			}
+
+			value = value * 10 + digit;
+		}
+	}
+
+	*parsed = value;
+	return true;
+}
+
+static bool sb_matches_ignoring_case(const char *data, size_t len, const char *word) {
+	size_t word_len = strlen(word);
+	if (len != word_len) {
+		return false;
+	}
+
+	for (size_t i = 0; i < len; i++) {
+		char c = data[i];
+		if (c >= 'A' && c <= 'Z') {
+			c = (char)(c - 'A' + 'a');
+		}
+
+		if (c != word[i]) {
+			return false;
+		}
+	}
+
+	return true;
+}
+
+static bool sb_parse_float(SbString string, double *parsed) {
+	const char *data = string.data;
+	size_t len = string.len;
+	size_t i = 0;
+	if (len > 0 && (data[0] == '+' || data[0] == '-')) {
+		i++;
+	}
+
+	bool valid;
+	if (sb_matches_ignoring_case(data + i, len - i, "inf") || sb_matches_ignoring_case(data + i, len - i, "infinity")
+		|| sb_matches_ignoring_case(data + i, len - i, "nan")) {
+		valid = true;
+	} else {
+		size_t digits = 0;
+		while (i < len && data[i] >= '0' && data[i] <= '9') {
+			i++;
+			digits++;
+		}
+
+		if (i < len && data[i] == '.') {
+			i++;
+			while (i < len && data[i] >= '0' && data[i] <= '9') {
+				i++;
+				digits++;
+			}
+		}
+
+		valid = digits > 0;
+		if (valid && i < len && (data[i] == 'e' || data[i] == 'E')) {
+			i++;
+			if (i < len && (data[i] == '+' || data[i] == '-')) {
+				i++;
+			}
+
+			size_t exponent_digits = 0;
+			while (i < len && data[i] >= '0' && data[i] <= '9') {
+				i++;
+				exponent_digits++;
+			}
+
+			valid = exponent_digits > 0;
+		}
+
+		valid = valid && i == len;
+	}
+
+	if (!valid) {
+		return false;
+	}
+
+	char *terminated = sb_alloc(len + 1);
+	memcpy(terminated, data, len);
+	terminated[len] = '\0';
+	*parsed = strtod(terminated, NULL);
+	free(terminated);
+	return true;
+}
+
+/* Like Rust's `as`, which stops at the ends instead of overflowing */
+static int64_t sb_float_to_int(double f) {
+	if (isnan(f)) {
+		return 0;
+	}
+
+	if (f >= 9223372036854775808.0) {
+		return INT64_MAX;
+	}
+
+	if (f <= -9223372036854775808.0) {
+		return INT64_MIN;
+	}
+
+	return (int64_t)f;
+}
+
+/* Values */
+
+typedef enum {
+	SB_NONE,
+	SB_BOOLEAN,
+	SB_INTEGER,
+	SB_FLOAT,
+	SB_STRING,
+} SbKind;
+
+typedef struct {
+	SbKind kind;
+	bool boolean;
+	int64_t integer;
+	double floating;
+	SbString string;
+} SbValue;
+
+static SbValue sb_none(void) {
+	SbValue value = { SB_NONE, false, 0, 0.0, { NULL, 0 } };
+	return value;
+}
+
+static SbValue sb_boolean(bool boolean) {
+	SbValue value = sb_none();
+	value.kind = SB_BOOLEAN;
+	value.boolean = boolean;
+	return value;
+}
+
+static SbValue sb_integer(int64_t integer) {
+	SbValue value = sb_none();
+	value.kind = SB_INTEGER;
+	value.integer = integer;
+	return value;
+}
+
+static SbValue sb_float(double floating) {
+	SbValue value = sb_none();
+	value.kind = SB_FLOAT;
+	value.floating = floating;
+	return value;
+}
+
+/* Takes the string */
+static SbValue sb_str(SbString string) {
+	SbValue value = sb_none();
+	value.kind = SB_STRING;
+	value.string = string;
+	return value;
+}
+
+static SbValue sb_value_clone(const SbValue *value) {
+	SbValue clone = *value;
+	if (value->kind == SB_STRING) {
+		clone.string = sb_string_clone(value->string);
+	}
+
+	return clone;
+}
+
+static void sb_value_free(SbValue *value) {
+	if (value->kind == SB_STRING) {
+		sb_string_free(&value->string);
+	}
+
+	*value = sb_none();
+}
+
+static SbString sb_print(const SbValue *value) {
+	switch (value->kind) {
+	case SB_BOOLEAN:
+		return value->boolean ? SB_STR("true") : SB_STR("false");
+	case SB_INTEGER:
+		return sb_print_int(value->integer);
+	case SB_FLOAT:
+		return sb_print_float(value->floating);
+	case SB_STRING:
+		return sb_string_clone(value->string);
+	default:
+		return sb_string_empty();
+	}
+}
+
+static bool sb_to_bool(const SbValue *value) {
+	switch (value->kind) {
+	case SB_BOOLEAN:
+		return value->boolean;
+	case SB_INTEGER:
+		return value->integer != 0;
+	case SB_FLOAT:
+		return value->floating != 0.0;
+	default:
+		return value->string.len > 0;
+	}
+}
+
+static bool sb_to_int(const SbValue *value, int64_t *converted) {
+	switch (value->kind) {
+	case SB_BOOLEAN:
+		*converted = value->boolean;
+		return true;
+	case SB_INTEGER:
+		*converted = value->integer;
+		return true;
+	case SB_FLOAT:
+		*converted = sb_float_to_int(value->floating);
+		return true;
+	default:
+		return sb_parse_int(value->string, converted);
+	}
+}
+
+static bool sb_to_float(const SbValue *value, double *converted) {
+	switch (value->kind) {
+	case SB_BOOLEAN:
+		*converted = value->boolean;
+		return true;
+	case SB_INTEGER:
+		*converted = (double)value->integer;
+		return true;
+	case SB_FLOAT:
+		*converted = value->floating;
+		return true;
+	default:
+		return sb_parse_float(value->string, converted);
+	}
+}
+
+/* Ordered by kind first, with nothing before everything, then by value. 2 when there's
+ * no ordering them at all, which only happens to NaN */
+static int sb_compare(const SbValue *a, const SbValue *b) {
+	if (a->kind != b->kind) {
+		return a->kind < b->kind ? -1 : 1;
+	}
+
+	switch (a->kind) {
+	case SB_BOOLEAN:
+		return (a->boolean > b->boolean) - (a->boolean < b->boolean);
+	case SB_INTEGER:
+		return (a->integer > b->integer) - (a->integer < b->integer);
+	case SB_FLOAT:
+		if (isnan(a->floating) || isnan(b->floating)) {
+			return 2;
+		}
+
+		return (a->floating > b->floating) - (a->floating < b->floating);
+	case SB_STRING: {
+		size_t shorter = a->string.len < b->string.len ? a->string.len : b->string.len;
+		int compared = shorter ? memcmp(a->string.data, b->string.data, shorter) : 0;
+		if (compared != 0) {
+			return compared < 0 ? -1 : 1;
+		}
+
+		return (a->string.len > b->string.len) - (a->string.len < b->string.len);
+	}
+	default:
+		return 0;
+	}
+}
+
+static bool sb_checked_add(int64_t a, int64_t b, int64_t *result) {
+	if ((b > 0 && a > INT64_MAX - b) || (b < 0 && a < INT64_MIN - b)) {
+		return false;
+	}
+
+	*result = a + b;
+	return true;
+}
+
+static bool sb_checked_sub(int64_t a, int64_t b, int64_t *result) {
+	if ((b < 0 && a > INT64_MAX + b) || (b > 0 && a < INT64_MIN + b)) {
+		return false;
+	}
+
+	*result = a - b;
+	return true;
+}
+
+static bool sb_checked_mul(int64_t a, int64_t b, int64_t *result) {
+	if (a > 0 ? (b > 0 ? a > INT64_MAX / b : b < INT64_MIN / a)
+		: (b > 0 ? a < INT64_MIN / b : (a != 0 && b < INT64_MAX / a))) {
+		return false;
+	}
+
+	*result = a * b;
+	return true;
+}
+
+typedef enum {
+	SB_MIX_ADD,
+	SB_MIX_SUB,
+	SB_MIX_MUL,
+	SB_MIX_DIV,
+} SbMixMode;
+
+/* What mixing two values makes, or false when they can't be mixed that way */
+static bool sb_mix(SbMixMode mode, const SbValue *left, const SbValue *right, SbValue *result) {
+	SbKind kind = left->kind == right->kind ? left->kind : SB_NONE;
+	int64_t integer;
+	switch (mode) {
+	case SB_MIX_ADD:
+		if (kind == SB_BOOLEAN) {
+			*result = sb_boolean(left->boolean || right->boolean);
+			return true;
+		} else if (kind == SB_INTEGER) {
+			if (!sb_checked_add(left->integer, right->integer, &integer)) {
+				return false;
+			}
+
+			*result = sb_integer(integer);
+			return true;
+		} else if (kind == SB_FLOAT) {
+			*result = sb_float(left->floating + right->floating);
+			return true;
+		} else if (kind == SB_STRING) {
+			if (left->string.len + right->string.len > SB_MAX_STRING_BYTES) {
+				return false;
+			}
+
+			SbString joined = sb_string_clone(left->string);
+			sb_string_push(&joined, right->string.data, right->string.len);
+			*result = sb_str(joined);
+			return true;
+		}
+
+		return false;
+	case SB_MIX_SUB:
+		if (kind == SB_INTEGER) {
+			if (!sb_checked_sub(left->integer, right->integer, &integer)) {
+				return false;
+			}
+
+			*result = sb_integer(integer);
+			return true;
+		} else if (kind == SB_FLOAT) {
+			*result = sb_float(left->floating - right->floating);
+			return true;
+		} else if (left->kind == SB_STRING && right->kind == SB_INTEGER) {
+			size_t length = sb_char_count(left->string);
+			if (right->integer < 0 || (uint64_t)right->integer > length) {
+				return false;
+			}
+
+			size_t keep = length - (size_t)right->integer;
+			size_t bytes = 0;
+			for (size_t chars = 0; bytes < left->string.len; bytes++) {
+				if (!sb_is_continuation(left->string.data[bytes]) && chars++ == keep) {
+					break;
+				}
+			}
+
+			*result = sb_str(sb_string(left->string.data, bytes));
+			return true;
+		} else if (kind == SB_STRING) {
+			if (!sb_string_find(left->string, 0, right->string)) {
+				return false;
+			}
+
+			*result = sb_str(sb_string_replace(left->string, right->string, SB_STR_VIEW("")));
+			return true;
+		}
+
+		return false;
+	case SB_MIX_MUL:
+		if (kind == SB_INTEGER) {
+			if (!sb_checked_mul(left->integer, right->integer, &integer)) {
+				return false;
+			}
+
+			*result = sb_integer(integer);
+			return true;
+		} else if (kind == SB_FLOAT) {
+			*result = sb_float(left->floating * right->floating);
+			return true;
+		} else if (left->kind == SB_STRING && right->kind == SB_INTEGER) {
+			if (right->integer < 0 || (left->string.len > 0
+				&& (uint64_t)right->integer > SB_MAX_STRING_BYTES / left->string.len)) {
+				return false;
+			}
+
+			SbString repeated = sb_string_empty();
+			for (int64_t i = 0; left->string.len > 0 && i < right->integer; i++) {
+				sb_string_push(&repeated, left->string.data, left->string.len);
+			}
+
+			*result = sb_str(repeated);
+			return true;
+		}
+
+		return false;
+	default:
+		if (kind == SB_INTEGER) {
+			if (right->integer == 0 || (left->integer == INT64_MIN && right->integer == -1)) {
+				return false;
+			}
+
+			*result = sb_integer(left->integer / right->integer);
+			return true;
+		} else if (kind == SB_FLOAT) {
+			*result = sb_float(left->floating / right->floating);
+			return true;
+		}
+
+		return false;
+	}
+}
+
+static bool sb_inverted(const SbValue *value, SbValue *result) {
+	switch (value->kind) {
+	case SB_BOOLEAN:
+		*result = sb_boolean(!value->boolean);
+		return true;
+	case SB_INTEGER:
+		if (value->integer == INT64_MIN) {
+			return false;
+		}
+
+		*result = sb_integer(-value->integer);
+		return true;
+	case SB_FLOAT:
+		*result = sb_float(-value->floating);
+		return true;
+	default:
+		*result = sb_str(sb_string_reversed(value->string));
+		return true;
+	}
+}
+
+/* Pages */
+
+typedef struct {
+	SbString names[3];
+	SbValue values[3];
+	SbString signature;
+	bool changed_signature;
+	SbKind type;
+	/* Every entry written since the page was last torn out, put back ones included */
+	int written;
+} SbPage;
+
+static SbPage sb_page_new(SbKind type) {
+	SbPage page;
+	for (int i = 0; i < 3; i++) {
+		page.names[i] = sb_string_empty();
+		page.values[i] = sb_none();
+	}
+
+	page.signature = SB_STR("\n");
+	page.changed_signature = false;
+	page.type = type;
+	page.written = 0;
+	return page;
+}
+
+static SbPage sb_page_clone(const SbPage *page) {
+	SbPage clone = *page;
+	for (int i = 0; i < 3; i++) {
+		clone.names[i] = sb_string_clone(page->names[i]);
+		clone.values[i] = sb_value_clone(&page->values[i]);
+	}
+
+	clone.signature = sb_string_clone(page->signature);
+	return clone;
+}
+
+static void sb_page_free(SbPage *page) {
+	for (int i = 0; i < 3; i++) {
+		sb_string_free(&page->names[i]);
+		sb_value_free(&page->values[i]);
+	}
+
+	sb_string_free(&page->signature);
+}
+
+static bool sb_page_has_any_contents(const SbPage *page) {
+	for (int i = 0; i < 3; i++) {
+		if (page->values[i].kind != SB_NONE) {
+			return true;
+		}
+	}
+
+	return false;
+}
+
+static bool sb_page_is_full(const SbPage *page) {
+	for (int i = 0; i < 3; i++) {
+		if (page->values[i].kind == SB_NONE) {
+			return false;
+		}
+	}
+
+	return true;
+}
+
+static bool sb_page_has_name(const SbPage *page, SbString name) {
+	for (int i = 0; i < 3; i++) {
+		if (sb_string_eq(page->names[i], name)) {
+			return true;
+		}
+	}
+
+	return false;
+}
+
+/* The value under the first heading with that name, which might be nothing */
+static SbValue sb_page_read(const SbPage *page, SbString name) {
+	for (int i = 0; i < 3; i++) {
+		if (sb_string_eq(page->names[i], name)) {
+			return sb_value_clone(&page->values[i]);
+		}
+	}
+
+	return sb_none();
+}
+
+/* Takes the name and the value, turning the value into whatever the page holds. A negative
+ * index means after the last entry written */
+static bool sb_page_write(SbPage *page, SbString name, SbValue value, int index) {
+	if (index < 0) {
+		index = page->written;
+	}
+
+	if (index >= 3) {
+		sb_string_free(&name);
+		sb_value_free(&value);
+		return false;
+	}
+
+	sb_string_free(&page->names[index]);
+	page->names[index] = name;
+	if (value.kind == SB_NONE) {
+		sb_string_free(&page->names[index]);
+		page->names[index] = sb_string_empty();
+		sb_value_free(&page->values[index]);
+		return true;
+	}
+
+	SbValue converted;
+	int64_t integer;
+	double floating;
+	switch (page->type) {
+	case SB_BOOLEAN:
+		converted = sb_boolean(sb_to_bool(&value));
+		break;
+	case SB_INTEGER:
+		if (!sb_to_int(&value, &integer)) {
+			sb_value_free(&value);
+			return false;
+		}
+
+		converted = sb_integer(integer);
+		break;
+	case SB_FLOAT:
+		if (!sb_to_float(&value, &floating)) {
+			sb_value_free(&value);
+			return false;
+		}
+
+		converted = sb_float(floating);
+		break;
+	default:
+		converted = sb_str(sb_print(&value));
+		break;
+	}
+
+	sb_value_free(&value);
+	sb_value_free(&page->values[index]);
+	page->values[index] = converted;
+	page->written++;
+	return true;
+}
+
+static void sb_page_clear(SbPage *page) {
+	for (int i = 0; i < 3; i++) {
+		sb_string_free(&page->names[i]);
+		page->names[i] = sb_string_empty();
+		sb_value_free(&page->values[i]);
+	}
+
+	page->changed_signature = false;
+	page->written = 0;
+}
+
+/* The desk */
+
+typedef enum {
+	SB_COADJUVANCY,
+	SB_JUDGEMENT,
+	SB_REVERBERATE,
+	SB_ENTWINEMENT,
+	SB_BELITTLEMENT,
+	SB_REENACTMENT,
+	SB_APPORTIONMENT,
+	SB_ANTIPODIZE,
+	SB_JUXTAPOSE,
+	SB_AMPLIFY,
+	SB_SQUELCH,
+	SB_DIMINISH,
+	SB_VACANCY,
+} SbSpell;
+
+typedef struct {
+	size_t line;
+	size_t amplifier;
+} SbCast;
+
+static struct {
+	SbPage pages[4];
+
+	bool cauldron_full;
+	SbPage cauldron;
+	size_t charge;
+	size_t amplifier;
+	size_t consecutive_amplifies;
+	SbMixMode mix_mode;
+
+	SbCast *casts;
+	size_t cast_count;
+	size_t cast_capacity;
+
+	size_t current_page;
+	bool turned_to_any_page;
+
+	SbPage *drawer;
+	size_t drawer_count;
+	size_t drawer_capacity;
+
+	SbValue memory;
+
+	SbString custom_signature;
+	bool use_custom_signature;
+
+	size_t line_internal;
+	bool exit;
+} sb;
+
+static uint64_t sb_rng_state;
+
+static uint64_t sb_random(void) {
+	uint64_t z = (sb_rng_state += 0x9E3779B97F4A7C15);
+	z = (z ^ (z >> 30)) * 0xBF58476D1CE4E5B9;
+	z = (z ^ (z >> 27)) * 0x94D049BB133111EB;
+	return z ^ (z >> 31);
+}
+
+static bool sb_random_chance(double chance) {
+	return (double)(sb_random() >> 11) * 0x1.0p-53 < chance;
+}
+
+static char sb_random_digit(void) {
+	return (char)('0' + sb_random() % 10);
+}
+
+static void sb_start(void) {
+	const char *seed = getenv("SPELLBOOK_SEED");
+	sb_rng_state = seed ? strtoull(seed, NULL, 10) : (uint64_t)time(NULL) ^ ((uint64_t)clock() << 32);
+	sb_random();
+
+	sb.pages[0] = sb_page_new(SB_BOOLEAN);
+	sb.pages[1] = sb_page_new(SB_INTEGER);
+	sb.pages[2] = sb_page_new(SB_FLOAT);
+	sb.pages[3] = sb_page_new(SB_STRING);
+	sb.amplifier = 1;
+	sb.mix_mode = SB_MIX_ADD;
+	sb.memory = sb_none();
+	sb.custom_signature = sb_string_empty();
+}
+
+static void sb_step(void) {
+	static uint64_t steps = 0;
+	if (SB_FUEL && ++steps > (uint64_t)SB_FUEL) {
+		fflush(stdout);
+		exit(SB_EXHAUSTED_STATUS);
+	}
+}
+
+static SbPage *sb_current_page(void) {
+	return &sb.pages[sb.current_page];
+}
+
+static void sb_turn_to_page(size_t page) {
+	sb.current_page = page;
+	sb.turned_to_any_page = true;
+}
+
+static void sb_check_writable(SbString name) {
+	if (!sb.turned_to_any_page || sb_page_has_name(sb_current_page(), name) || sb_page_is_full(sb_current_page())) {
+		sb_catastrophe();
+	}
+}
+
+static void sb_write_value(SbString name, SbValue value) {
+	sb_check_writable(name);
+	if (!sb_page_write(sb_current_page(), name, value, -1)) {
+		sb_catastrophe();
+	}
+}
+
+static void sb_copy_value(SbString name, SbString from) {
+	SbValue value = sb_page_read(sb_current_page(), from);
+	sb_string_free(&from);
+	if (value.kind == SB_NONE) {
+		sb_catastrophe();
+	}
+
+	sb_write_value(name, value);
+}
+
+static double sb_forget_chance(size_t x) {
+	if (x < 5 || !SB_FORGETFUL) {
+		return 0.0;
+	}
+
+	return 1.0 - 1.0 / pow((double)(x - 4), 0.3);
+}
+
+static SbValue sb_recall_number(SbString remembered, bool is_float) {
+	for (size_t i = 0; i < remembered.len; i++) {
+		if ((!is_float || remembered.data[i] != '.') && sb_random_chance(sb_forget_chance(i))) {
+			remembered.data[i] = sb_random_digit();
+		}
+	}
+
+	int64_t integer;
+	double floating;
+	bool parsed = is_float ? sb_parse_float(remembered, &floating) : sb_parse_int(remembered, &integer);
+	sb_string_free(&remembered);
+	if (!parsed) {
+		sb_catastrophe();
+	}
+
+	return is_float ? sb_float(floating) : sb_integer(integer);
+}
+
+static SbValue sb_recall_string(SbString original) {
+	SbString modified = sb_string_clone(original);
+	size_t word_index = 0;
+	size_t i = 0;
+	while (i < original.len) {
+		size_t space = sb_whitespace_width(original.data + i, original.len - i);
+		if (space > 0) {
+			i += space;
+			continue;
+		}
+
+		size_t start = i;
+		while (i < original.len && sb_whitespace_width(original.data + i, original.len - i) == 0) {
+			i++;
+		}
+
+		SbString word = { original.data + start, i - start };
+		if (sb_random_chance(sb_forget_chance(word_index++))) {
+			SbString replaced;
+			if (sb_random_chance(0.25)) {
+				/* Along with whatever came before it, unless it's already gone */
+				const char *found = sb_string_find(modified, 0, word);
+				if (!found) {
+					continue;
+				}
+
+				size_t index = (size_t)(found - modified.data);
+				replaced = sb_string_replace(modified, word, SB_STR_VIEW(""));
+				if (index > 0) {
+					size_t before = index - 1;
+					while (before > 0 && sb_is_continuation(replaced.data[before])) {
+						before--;
+					}
+
+					memmove(replaced.data + before, replaced.data + index, replaced.len - index);
+					replaced.len -= index - before;
+				}
+			} else {
+				replaced = sb_string_replace(modified, word, SB_STR_VIEW("something"));
+			}
+
+			sb_string_free(&modified);
+			modified = replaced;
+		}
+	}
+
+	return sb_str(modified);
+}
+
+static void sb_write_memory_value(SbString name) {
+	sb_check_writable(name);
+	SbValue recalled;
+	switch (sb.memory.kind) {
+	case SB_NONE:
+		sb_catastrophe();
+		return;
+	case SB_INTEGER:
+		recalled = sb_recall_number(sb_print_int(sb.memory.integer), false);
+		break;
+	case SB_FLOAT:
+		recalled = sb_recall_number(sb_print_float(sb.memory.floating), true);
+		break;
+	case SB_STRING:
+		recalled = sb_recall_string(sb.memory.string);
+		break;
+	default:
+		recalled = sb_value_clone(&sb.memory);
+		break;
+	}
+
+	if (!sb_page_write(sb_current_page(), name, recalled, -1)) {
+		sb_catastrophe();
+	}
+}
+
+static void sb_memorize_value(SbValue value) {
+	sb_value_free(&sb.memory);
+	sb.memory = value;
+}
+
+static void sb_memorize_heading(SbString heading) {
+	SbValue value = sb_page_read(sb_current_page(), heading);
+	sb_string_free(&heading);
+	if (value.kind == SB_NONE) {
+		sb_catastrophe();
+	}
+
+	sb_memorize_value(value);
+}
+
+static void sb_memorize_entry(size_t index) {
+	sb_memorize_value(sb_value_clone(&sb_current_page()->values[index]));
+}
+
+/* Whether an `if` lets the rest of its line be read. Only a Presages entry that's been
+ * written in can say so */
+static bool sb_presage(SbValue value) {
+	if (!sb.turned_to_any_page || sb.current_page != 0 || value.kind != SB_BOOLEAN) {
+		sb_catastrophe();
+	}
+
+	return value.boolean;
+}
+
+static bool sb_presage_heading(SbString heading) {
+	SbValue value = sb_page_read(sb_current_page(), heading);
+	sb_string_free(&heading);
+	return sb_presage(value);
+}
+
+static bool sb_presage_entry(size_t index) {
+	return sb_presage(sb_current_page()->values[index]);
+}
+
+static void sb_tear_out_page(bool to_drawer, bool to_cauldron) {
+	if (!sb.turned_to_any_page) {
+		sb_catastrophe();
+	}
+
+	SbPage *page = sb_current_page();
+	if (to_drawer) {
+		if (sb.drawer_count == sb.drawer_capacity) {
+			sb.drawer_capacity = sb.drawer_capacity ? sb.drawer_capacity * 2 : 8;
+			sb.drawer = realloc(sb.drawer, sb.drawer_capacity * sizeof(SbPage));
+			if (!sb.drawer) {
+				sb_catastrophe();
+			}
+		}
+
+		sb.drawer[sb.drawer_count++] = sb_page_clone(page);
+	}
+
+	if (to_cauldron) {
+		if (!sb.cauldron_full) {
+			sb.cauldron = sb_page_clone(page);
+			sb.cauldron_full = true;
+		} else {
+			for (int i = 0; i < 3; i++) {
+				if (page->values[i].kind == SB_NONE) {
+					continue;
+				}
+
+				sb_string_push(&sb.cauldron.names[i], page->names[i].data, page->names[i].len);
+				if (sb.cauldron.values[i].kind == SB_NONE) {
+					sb.cauldron.values[i] = sb_value_clone(&page->values[i]);
+					continue;
+				}
+
+				SbValue mixed;
+				if (!sb_mix(sb.mix_mode, &sb.cauldron.values[i], &page->values[i], &mixed)) {
+					sb_catastrophe();
+				}
+
+				sb_value_free(&sb.cauldron.values[i]);
+				sb.cauldron.values[i] = mixed;
+			}
+		}
+	}
+
+	sb_page_clear(page);
+}
+
+static void sb_put_back_page(bool from_drawer) {
+	if (!sb.turned_to_any_page) {
+		sb_catastrophe();
+	}
+
+	SbPage page;
+	if (from_drawer) {
+		if (sb.drawer_count == 0) {
+			sb_catastrophe();
+		}
+
+		page = sb.drawer[--sb.drawer_count];
+	} else {
+		if (!sb.cauldron_full) {
+			sb_catastrophe();
+		}
+
+		page = sb.cauldron;
+		sb.cauldron_full = false;
+	}
+
+	for (int i = 0; i < 3; i++) {
+		if (!sb_page_write(sb_current_page(), sb_string_clone(page.names[i]), sb_value_clone(&page.values[i]), i)) {
+			sb_catastrophe();
+		}
+	}
+
+	sb_page_free(&page);
+}
+
+static void sb_knock_over_cauldron(void) {
+	if (sb.cauldron_full) {
+		sb_page_free(&sb.cauldron);
+		sb.cauldron_full = false;
+	}
+}
+
+static void sb_sign_page(SbString signature) {
+	SbPage *page = sb_current_page();
+	if (page->changed_signature) {
+		sb_catastrophe();
+	}
+
+	sb_string_free(&page->signature);
+	page->signature = signature;
+	page->changed_signature = true;
+}
+
+static void sb_set_signature(SbString signature) {
+	sb_string_free(&sb.custom_signature);
+	sb.custom_signature = signature;
+	sb.use_custom_signature = true;
+}
+
+static SbString sb_published(void) {
+	SbString output = sb_string_empty();
+	bool totally_empty = true;
+	for (int p = 0; p < 4; p++) {
+		totally_empty = totally_empty && !sb_page_has_any_contents(&sb.pages[p]);
+		for (int v = 0; v < 3; v++) {
+			SbString printed = sb_print(&sb.pages[p].values[v]);
+			if (printed.len > 0) {
+				sb_string_push(&output, printed.data, printed.len);
+				sb_string_push(&output, sb.pages[p].signature.data, sb.pages[p].signature.len);
+			}
+
+			sb_string_free(&printed);
+		}
+	}
+
+	if (sb.use_custom_signature) {
+		sb_string_push(&output, sb.custom_signature.data, sb.custom_signature.len);
+	} else if (sb_page_has_any_contents(&sb.pages[3]) || totally_empty) {
+		sb_string_push(&output, SB_DEFAULT_WRAPUP, strlen(SB_DEFAULT_WRAPUP));
+	} else {
+		sb_string_push(&output, SB_DEFAULT_WRAPUP_QED, strlen(SB_DEFAULT_WRAPUP_QED));
+	}
+
+	return output;
+}
+
+static void sb_publish_to_console(void) {
+	SbString output = sb_published();
+	if (fwrite(output.data, 1, output.len, stdout) != output.len || fflush(stdout) != 0) {
+		sb_catastrophe();
+	}
+
+	sb_string_free(&output);
+}
+
+static void sb_publish_to_file(SbString target) {
+	if (memchr(target.data, '\0', target.len)) {
+		sb_catastrophe();
+	}
+
+	sb_string_push(&target, "", 0);
+	target.data[target.len] = '\0';
+	FILE *file = fopen(target.data, "ab");
+	if (!file) {
+		sb_catastrophe();
+	}
+
+	SbString output = sb_published();
+	if (fwrite(output.data, 1, output.len, file) != output.len || fclose(file) != 0) {
+		sb_catastrophe();
+	}
+
+	sb_string_free(&output);
+	sb_string_free(&target);
+}
+
+/* A line from the console that isn't empty, or false once there's nothing left to read */
+static bool sb_read_line(SbString *line) {
+	*line = sb_string_empty();
+	int c;
+	while ((c = getchar()) != EOF) {
+		char byte = (char)c;
+		sb_string_push(line, &byte, 1);
+		if (byte == '\n') {
+			break;
+		}
+	}
+
+	if (line->len == 0 || !sb_valid_utf8(line->data, line->len)) {
+		sb_string_free(line);
+		return false;
+	}
+
+	return true;
+}
+
+static bool sb_coadjuvancy(void) {
+	if (!sb.cauldron_full || sb_page_is_full(&sb.cauldron)) {
+		return false;
+	}
+
+	for (;;) {
+		SbString line;
+		if (!sb_read_line(&line)) {
+			return false;
+		}
+
+		line.len = sb_trimmed_len(line);
+		bool boolean;
+		int64_t integer;
+		double floating;
+		SbValue value;
+		if (sb_parse_bool(line, &boolean)) {
+			value = sb_boolean(boolean);
+		} else if (sb_parse_int(line, &integer)) {
+			value = sb_integer(integer);
+		} else if (sb_parse_float(line, &floating)) {
+			value = sb_float(floating);
+		} else {
+			value = sb_str(sb_string(line.data, line.len));
+		}
+
+		sb_string_free(&line);
+		if (sb_page_write(&sb.cauldron, sb_string_empty(), value, -1)) {
+			return true;
+		}
+	}
+}
+
+static void sb_push_cast(void) {
+	if (sb.cast_count == sb.cast_capacity) {
+		sb.cast_capacity = sb.cast_capacity ? sb.cast_capacity * 2 : 16;
+		sb.casts = realloc(sb.casts, sb.cast_capacity * sizeof(SbCast));
+		if (!sb.casts) {
+			sb_catastrophe();
+		}
+	}
+
+	sb.casts[sb.cast_count].line = sb.line_internal;
+	sb.casts[sb.cast_count].amplifier = sb.amplifier;
+	sb.cast_count++;
+	sb.charge += sb.amplifier;
+}
+
+/* Whether Judgement finds anything false in the cauldron */
+static bool sb_judgement_fails(void) {
+	if (!sb.cauldron_full || sb.cauldron.type != SB_BOOLEAN) {
+		sb_catastrophe();
+	}
+
+	for (int i = 0; i < 3; i++) {
+		if (sb.cauldron.values[i].kind != SB_NONE && !sb_to_bool(&sb.cauldron.values[i])) {
+			return true;
+		}
+	}
+
+	return false;
+}
+
+static void sb_cast(SbSpell spell) {
+	if (spell != SB_AMPLIFY && spell != SB_VACANCY) {
+		sb.consecutive_amplifies = 0;
+	}
+
+	switch (spell) {
+	case SB_COADJUVANCY:
+		if (!sb_coadjuvancy()) {
+			sb_catastrophe();
+		}
+
+		break;
+	case SB_ENTWINEMENT:
+		sb.mix_mode = SB_MIX_ADD;
+		break;
+	case SB_BELITTLEMENT:
+		sb.mix_mode = SB_MIX_SUB;
+		break;
+	case SB_REENACTMENT:
+		sb.mix_mode = SB_MIX_MUL;
+		break;
+	case SB_APPORTIONMENT:
+		sb.mix_mode = SB_MIX_DIV;
+		break;
+	case SB_ANTIPODIZE:
+		if (!sb.cauldron_full) {
+			sb_catastrophe();
+		}
+
+		for (int i = 0; i < 3; i++) {
+			SbString reversed = sb_string_reversed(sb.cauldron.names[i]);
+			sb_string_free(&sb.cauldron.names[i]);
+			sb.cauldron.names[i] = reversed;
+			if (sb.cauldron.values[i].kind != SB_NONE) {
+				SbValue inverted;
+				if (!sb_inverted(&sb.cauldron.values[i], &inverted)) {
+					sb_catastrophe();
+				}
+
+				sb_value_free(&sb.cauldron.values[i]);
+				sb.cauldron.values[i] = inverted;
+			}
+		}
+
+		break;
+	case SB_JUXTAPOSE: {
+		if (!sb.cauldron_full) {
+			sb_catastrophe();
+		}
+
+		SbValue *values = sb.cauldron.values;
+		int ordering = sb_compare(&values[0], &values[2]);
+		SbPage compared = sb_page_new(SB_BOOLEAN);
+		compared.values[0] = sb_boolean(values[1].kind != SB_NONE && values[2].kind != SB_NONE);
+		compared.values[1] = sb_boolean(ordering == 0 || ordering == 1);
+		compared.values[2] = sb_boolean(sb_compare(&values[0], &values[1]) == 0);
+		sb_page_free(&sb.cauldron);
+		sb.cauldron = compared;
+		break;
+	}
+	case SB_JUDGEMENT:
+		if (sb_judgement_fails()) {
+			size_t charge = sb.charge;
+			sb_push_cast();
+			sb.line_internal += charge;
+			return;
+		}
+
+		break;
+	case SB_AMPLIFY:
+		sb.amplifier++;
+		if (++sb.consecutive_amplifies > 3) {
+			sb_catastrophe();
+		}
+
+		return;
+	case SB_SQUELCH:
+		sb.charge = 0;
+		sb.amplifier = 1;
+		return;
+	case SB_DIMINISH:
+		sb.charge = sb.charge > 0 ? sb.charge - 1 : 0;
+		return;
+	case SB_REVERBERATE: {
+		size_t charge = sb.charge;
+		if (charge >= sb.cast_count || sb.casts[charge].line == 0) {
+			sb_catastrophe();
+		}
+
+		/* One before, since finishing this line moves on to the next. There's nothing
+		 * before the first line to go back to */
+		sb.line_internal = sb.casts[charge].line - 1;
+		while (sb.cast_count > charge + 1) {
+			size_t amplifier = sb.casts[sb.cast_count - 1].amplifier;
+			sb.charge = sb.charge > amplifier ? sb.charge - amplifier : 0;
+			memmove(&sb.casts[charge + 1], &sb.casts[charge + 2], (sb.cast_count - charge - 2) * sizeof(SbCast));
+			sb.cast_count--;
+		}
+
+		sb.amplifier = 1;
+		return;
+	}
+	case SB_VACANCY:
+		break;
+	}
+
+	sb_push_cast();
+}
+
+/* Moves to a bookmark's line once this one is done */
+static void sb_flip_to(size_t line) {
+	if (line == SB_NO_BOOKMARK) {
+		sb_catastrophe();
+	}
+
+	sb.line_internal = line - 1;
+}
+
+/* Judgement that lands on a bookmark's line when it fails, however much charge there is */
+static void sb_cast_judgement_to(size_t line) {
+	sb.consecutive_amplifies = 0;
+	bool fails = sb_judgement_fails();
+	sb_push_cast();
+	if (fails) {
+		sb_flip_to(line);
+	}
+}
+
+/* Moves on to the next line once this one is done, true while there's one to move on to */
+static bool sb_finish_line(size_t line_count) {
+	if (sb.exit) {
+		return false;
+	}
+
+	size_t next = sb.line_internal + 1;
+	if (next > line_count) {
+		sb_catastrophe();
+	}
+
+	sb.line_internal = next;
+	return true;
+}
@@ -0,0 +1,734 @@
+// Everything a transpiled spellbook needs to run, doing just what the interpreter does
+// without needing anything besides the standard library.
+
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Turned off, memory remembers everything perfectly
+const FORGETFUL: bool = true;
+
+const MAX_STRING_BYTES: usize = 1 << 28;
+
+// Lines are numbered from one, so no bookmark was ever placed on this one
+pub const NO_BOOKMARK: usize = 0;
+
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
+pub enum Variant {
+	Boolean(bool),
+	Integer(i64),
+	Float(f64),
+	Str(String),
+}
+
+impl fmt::Display for Variant {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Variant::Boolean(b) => write!(f, "{}", b),
+			Variant::Integer(i) => write!(f, "{}", i),
+			Variant::Float(x) => write!(f, "{}", x),
+			Variant::Str(s) => write!(f, "{}", s),
+		}
+	}
+}
+
+#[derive(Clone, Copy)]
+enum Mix {
+	Add,
+	Sub,
+	Mul,
+	Div,
+}
+
+impl Variant {
+	fn to_bool(&self) -> bool {
+		match self {
+			Variant::Boolean(b) => *b,
+			Variant::Integer(i) => *i != 0,
+			Variant::Float(f) => *f != 0.0,
+			Variant::Str(s) => !s.is_empty(),
+		}
+	}
+
+	fn to_int(&self) -> Option<i64> {
+		match self {
+			Variant::Boolean(b) => Some(*b as i64),
+			Variant::Integer(i) => Some(*i),
+			Variant::Float(f) => Some(*f as i64),
+			Variant::Str(s) => s.parse().ok(),
+		}
+	}
+
+	fn to_float(&self) -> Option<f64> {
+		match self {
+			Variant::Boolean(b) => Some(*b as i64 as f64),
+			Variant::Integer(i) => Some(*i as f64),
+			Variant::Float(f) => Some(*f),
+			Variant::Str(s) => s.parse().ok(),
+		}
+	}
+
+	fn mix(&self, mix: Mix, rhs: &Variant) -> Option<Variant> {
+		use self::Variant::*;
+		match (mix, self, rhs) {
+			(Mix::Add, Boolean(a), Boolean(b)) => Some(Boolean(*a || *b)),
+			(Mix::Add, Integer(a), Integer(b)) => a.checked_add(*b).map(Integer),
+			(Mix::Add, Float(a), Float(b)) => Some(Float(a + b)),
+			(Mix::Add, Str(a), Str(b)) if a.len() + b.len() <= MAX_STRING_BYTES => Some(Str(format!("{}{}", a, b))),
+			(Mix::Sub, Integer(a), Integer(b)) => a.checked_sub(*b).map(Integer),
+			(Mix::Sub, Float(a), Float(b)) => Some(Float(a - b)),
+			(Mix::Sub, Str(a), Integer(b)) => {
+				let length = a.chars().count();
+				if *b >= 0 && *b as usize <= length {
+					Some(Str(a.chars().take(length - *b as usize).collect()))
+				} else {
+					None
+				}
+			},
+			(Mix::Sub, Str(a), Str(b)) if a.contains(b.as_str()) => Some(Str(a.replace(b.as_str(), ""))),
+			(Mix::Mul, Integer(a), Integer(b)) => a.checked_mul(*b).map(Integer),
+			(Mix::Mul, Float(a), Float(b)) => Some(Float(a * b)),
+			(Mix::Mul, Str(a), Integer(b)) => match a.len().checked_mul(*b as usize) {
+				Some(bytes) if *b >= 0 && bytes <= MAX_STRING_BYTES => Some(Str(a.repeat(*b as usize))),
+				_ => None,
+			},
+			(Mix::Div, Integer(a), Integer(b)) => a.checked_div(*b).map(Integer),
+			(Mix::Div, Float(a), Float(b)) => Some(Float(a / b)),
+			_ => None,
+		}
+	}
+
+	fn inverted(&self) -> Option<Variant> {
+		match self {
+			Variant::Boolean(b) => Some(Variant::Boolean(!b)),
+			Variant::Integer(i) => i.checked_neg().map(Variant::Integer),
+			Variant::Float(f) => Some(Variant::Float(-f)),
+			Variant::Str(s) => Some(Variant::Str(s.chars().rev().collect())),
+		}
+	}
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Chapter {
+	Presages,
+	Hexes,
+	Illusions,
+	Incantations,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Spell {
+	Coadjuvancy,
+	Judgement,
+	Reverberate,
+	Entwinement,
+	Belittlement,
+	Reenactment,
+	Apportionment,
+	Antipodize,
+	Juxtapose,
+	Amplify,
+	Squelch,
+	Diminish,
+	Vacancy,
+}
+
+#[derive(Clone)]
+struct Page {
+	headings: [String; 3],
+	values: [Option<Variant>; 3],
+	signature: String,
+	signed: bool,
+	chapter: Chapter,
+	// Every entry written since the page was last torn out, put back ones included
+	written: usize,
+}
+
+impl Page {
+	fn new(chapter: Chapter) -> Self {
+		Self{ headings: Default::default(), values: [None, None, None], signature: "\n".into(), signed: false, chapter, written: 0 }
+	}
+
+	fn read(&self, heading: &str) -> Option<Variant> {
+		let index = self.headings.iter().position(|h| h == heading)?;
+		self.values[index].clone()
+	}
+
+	fn is_full(&self) -> bool {
+		self.values.iter().all(|v| v.is_some())
+	}
+
+	fn has_any_contents(&self) -> bool {
+		self.values.iter().any(|v| v.is_some())
+	}
+
+	// After the last entry written unless told where, turning the value into whatever this
+	// chapter holds
+	fn write(&mut self, heading: String, value: Option<Variant>, index: Option<usize>) -> bool {
+		let index = index.unwrap_or(self.written);
+		if index >= 3 {
+			return false;
+		}
+
+		self.headings[index] = heading;
+		let value = match value {
+			Some(value) => value,
+			None => {
+				self.headings[index].clear();
+				self.values[index] = None;
+				return true;
+			},
+		};
+
+		self.values[index] = Some(match self.chapter {
+			Chapter::Presages => Variant::Boolean(value.to_bool()),
+			Chapter::Hexes => match value.to_int() {
+				Some(i) => Variant::Integer(i),
+				None => return false,
+			},
+			Chapter::Illusions => match value.to_float() {
+				Some(f) => Variant::Float(f),
+				None => return false,
+			},
+			Chapter::Incantations => Variant::Str(value.to_string()),
+		});
+
+		self.written += 1;
+		true
+	}
+
+	fn clear(&mut self) {
+		self.headings = Default::default();
+		self.values = [None, None, None];
+		self.signed = false;
+		self.written = 0;
+	}
+}
+
+pub struct Desk {
+	lines: &'static [usize],
+	line: usize,
+	line_number: usize,
+	exit: bool,
+
+	pages: [Page; 4],
+	chapter: Chapter,
+	turned_to_any_chapter: bool,
+	drawer: Vec<Page>,
+	memory: Option<Variant>,
+	acknowledgements: Option<String>,
+
+	cauldron: Option<Page>,
+	mix: Mix,
+	charge: usize,
+	amplifier: usize,
+	amplifies_in_a_row: usize,
+	// The line of every spell cast that Reverberate can go back to, and the amplifier it had
+	casts: Vec<(usize, usize)>,
+
+	random: u64,
+}
+
+impl Desk {
+	// Takes the number of every line in the spellbook that isn't blank
+	pub fn new(lines: &'static [usize]) -> Self {
+		let seed = match std::env::var("SPELLBOOK_SEED") {
+			Ok(seed) => seed.parse().unwrap_or(0),
+			Err(_) => SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0),
+		};
+
+		Self{
+			lines,
+			line: 0,
+			line_number: lines.first().copied().unwrap_or(1),
+			exit: false,
+
+			pages: [Page::new(Chapter::Presages), Page::new(Chapter::Hexes), Page::new(Chapter::Illusions), Page::new(Chapter::Incantations)],
+			chapter: Chapter::Presages,
+			turned_to_any_chapter: false,
+			drawer: vec![],
+			memory: None,
+			acknowledgements: None,
+
+			cauldron: None,
+			mix: Mix::Add,
+			charge: 0,
+			amplifier: 1,
+			amplifies_in_a_row: 0,
+			casts: vec![],
+
+			random: seed,
+		}
+	}
+
+	fn random(&mut self) -> u64 {
+		self.random = self.random.wrapping_add(0x9E3779B97F4A7C15);
+		let mut z = self.random;
+		z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+		z ^ (z >> 31)
+	}
+
+	fn random_chance(&mut self, chance: f64) -> bool {
+		((self.random() >> 11) as f64) / ((1u64 << 53) as f64) < chance
+	}
+
+	fn random_digit(&mut self) -> char {
+		(b'0' + (self.random() % 10) as u8) as char
+	}
+
+	pub fn catastrophe(&mut self) -> ! {
+		let message = ERROR_MESSAGES[(self.random() % 10) as usize];
+		let _ = io::stdout().flush();
+		eprintln!("\x1b[0;91mCatastrophe!\x1b[0m\n{}\n(Line {})", message, self.line_number);
+		process::exit(101);
+	}
+
+	pub fn start_line(&mut self, number: usize) {
+		self.line = self.lines.binary_search(&number).unwrap_or(self.lines.len());
+		self.line_number = number;
+	}
+
+	// The number of the line to read next, or nothing once the spellbook is done
+	pub fn finish_line(&mut self) -> Option<usize> {
+		if self.exit {
+			return None;
+		}
+
+		let next = self.line.wrapping_add(1);
+		if next > self.lines.len() {
+			self.catastrophe();
+		}
+
+		self.line = next;
+		self.lines.get(next).copied()
+	}
+
+	fn page(&mut self) -> &mut Page {
+		&mut self.pages[self.chapter as usize]
+	}
+
+	pub fn turn_to(&mut self, chapter: Chapter) {
+		self.chapter = chapter;
+		self.turned_to_any_chapter = true;
+	}
+
+	fn write_value(&mut self, heading: &str, value: Option<Variant>) {
+		if !self.turned_to_any_chapter || self.page().headings.iter().any(|h| h == heading) || self.page().is_full() {
+			self.catastrophe();
+		}
+
+		if !self.page().write(heading.into(), value, None) {
+			self.catastrophe();
+		}
+	}
+
+	pub fn write(&mut self, heading: &str, value: Variant) {
+		self.write_value(heading, Some(value));
+	}
+
+	pub fn copy(&mut self, from: &str, heading: &str) {
+		match self.page().read(from) {
+			Some(value) => self.write_value(heading, Some(value)),
+			None => self.catastrophe(),
+		}
+	}
+
+	fn forget_chance(x: usize) -> f64 {
+		if x < 5 || !FORGETFUL {
+			0.0
+		} else {
+			1.0 - (1.0 / (((x - 4) as f64).powf(0.3)))
+		}
+	}
+
+	fn recall(&mut self, remembered: &Variant) -> Variant {
+		match remembered {
+			Variant::Integer(_) | Variant::Float(_) => {
+				let text = remembered.to_string();
+				let mut modified = String::with_capacity(text.len());
+				for (i, c) in text.chars().enumerate() {
+					if c != '.' && self.random_chance(Self::forget_chance(i)) {
+						modified.push(self.random_digit());
+					} else {
+						modified.push(c);
+					}
+				}
+
+				// Forgetting can make a number too big to remember at all
+				let recalled = match remembered {
+					Variant::Integer(_) => modified.parse().ok().map(Variant::Integer),
+					_ => modified.parse().ok().map(Variant::Float),
+				};
+
+				recalled.unwrap_or_else(|| self.catastrophe())
+			},
+			Variant::Str(text) => {
+				let mut modified = text.clone();
+				for (i, word) in text.split_whitespace().enumerate() {
+					if !self.random_chance(Self::forget_chance(i)) {
+						continue;
+					}
+
+					if self.random_chance(0.25) {
+						// Along with whatever came before it, unless it's already gone
+						if let Some(index) = modified.find(word) {
+							modified = modified.replace(word, "");
+							if let Some((before, _)) = modified[..index].char_indices().next_back() {
+								modified.remove(before);
+							}
+						}
+					} else {
+						modified = modified.replace(word, "something");
+					}
+				}
+
+				Variant::Str(modified)
+			},
+			Variant::Boolean(_) => remembered.clone(),
+		}
+	}
+
+	pub fn write_from_memory(&mut self, heading: &str) {
+		if !self.turned_to_any_chapter || self.page().headings.iter().any(|h| h == heading) || self.page().is_full() {
+			self.catastrophe();
+		}
+
+		let recalled = match self.memory.clone() {
+			Some(remembered) => self.recall(&remembered),
+			None => self.catastrophe(),
+		};
+
+		if !self.page().write(heading.into(), Some(recalled), None) {
+			self.catastrophe();
+		}
+	}
+
+	pub fn memorize(&mut self, heading: &str) {
+		match self.page().read(heading) {
+			Some(value) => self.memory = Some(value),
+			None => self.catastrophe(),
+		}
+	}
+
+	pub fn memorize_value(&mut self, value: Variant) {
+		self.memory = Some(value);
+	}
+
+	pub fn memorize_entry(&mut self, index: usize) {
+		self.memory = self.page().values[index].clone();
+	}
+
+	// Whether an `if` lets the rest of its line be read. Only a Presages entry that's been
+	// written in can say so
+	fn presage(&mut self, value: Option<Variant>) -> bool {
+		if !self.turned_to_any_chapter || self.chapter != Chapter::Presages {
+			self.catastrophe();
+		}
+
+		match value {
+			Some(value) => value.to_bool(),
+			None => self.catastrophe(),
+		}
+	}
+
+	pub fn presage_heading(&mut self, heading: &str) -> bool {
+		let value = self.page().read(heading);
+		self.presage(value)
+	}
+
+	pub fn presage_entry(&mut self, index: usize) -> bool {
+		let value = self.page().values[index].clone();
+		self.presage(value)
+	}
+
+	pub fn cauldron_is_full(&self) -> bool {
+		self.cauldron.is_some()
+	}
+
+	fn tear_out(&mut self) -> Page {
+		if !self.turned_to_any_chapter {
+			self.catastrophe();
+		}
+
+		let page = self.page().clone();
+		self.page().clear();
+		page
+	}
+
+	pub fn tear_out_into_trash(&mut self) {
+		self.tear_out();
+	}
+
+	pub fn tear_out_into_drawer(&mut self) {
+		let page = self.tear_out();
+		self.drawer.push(page);
+	}
+
+	pub fn tear_out_into_cauldron(&mut self) {
+		let page = self.tear_out();
+		let mut mixed = match self.cauldron.take() {
+			Some(mixed) => mixed,
+			None => {
+				self.cauldron = Some(page);
+				return;
+			},
+		};
+
+		for i in 0..3 {
+			let value = match &page.values[i] {
+				Some(value) => value,
+				None => continue,
+			};
+
+			mixed.headings[i].push_str(&page.headings[i]);
+			mixed.values[i] = match &mixed.values[i] {
+				Some(current) => match current.mix(self.mix, value) {
+					Some(result) => Some(result),
+					None => self.catastrophe(),
+				},
+				None => Some(value.clone()),
+			};
+		}
+
+		self.cauldron = Some(mixed);
+	}
+
+	fn put_back(&mut self, page: Option<Page>) {
+		if !self.turned_to_any_chapter {
+			self.catastrophe();
+		}
+
+		let page = page.unwrap_or_else(|| self.catastrophe());
+		for i in 0..3 {
+			if !self.page().write(page.headings[i].clone(), page.values[i].clone(), Some(i)) {
+				self.catastrophe();
+			}
+		}
+	}
+
+	pub fn take_out_from_drawer(&mut self) {
+		let page = if self.turned_to_any_chapter { self.drawer.pop() } else { None };
+		self.put_back(page);
+	}
+
+	pub fn take_out_from_cauldron(&mut self) {
+		let page = if self.turned_to_any_chapter { self.cauldron.take() } else { None };
+		self.put_back(page);
+	}
+
+	pub fn knock_over_cauldron(&mut self) {
+		self.cauldron = None;
+	}
+
+	// Whether everything in the Presages chapter in the cauldron is true
+	fn judge(&mut self) -> bool {
+		match &self.cauldron {
+			Some(page) if page.chapter == Chapter::Presages => page.values.iter().flatten().all(|v| v.to_bool()),
+			_ => self.catastrophe(),
+		}
+	}
+
+	// Judgement that lands on a bookmark's line when it fails, however much charge there is
+	pub fn cast_judgement_to(&mut self, number: usize) {
+		self.amplifies_in_a_row = 0;
+		let passed = self.judge();
+		self.record_cast();
+		if !passed {
+			self.flip_to(number);
+		}
+	}
+
+	// Moves to the line a bookmark was placed on once this one is finished
+	pub fn flip_to(&mut self, number: usize) {
+		self.line = match self.lines.binary_search(&number) {
+			Ok(line) => line.wrapping_sub(1),
+			Err(_) => self.catastrophe(),
+		};
+	}
+
+	fn record_cast(&mut self) {
+		self.casts.push((self.line, self.amplifier));
+		self.charge += self.amplifier;
+	}
+
+	// Answers Coadjuvancy with a line from the console, trying again whenever it doesn't fit
+	fn coadjuvancy(&mut self) -> bool {
+		let page = match &mut self.cauldron {
+			Some(page) if !page.is_full() => page,
+			_ => return false,
+		};
+
+		loop {
+			let mut input = String::new();
+			match io::stdin().read_line(&mut input) {
+				Ok(0) | Err(_) => return false,
+				Ok(_) => {},
+			}
+
+			let input = input.trim_end();
+			let value = if let Ok(b) = input.parse::<bool>() {
+				Variant::Boolean(b)
+			} else if let Ok(i) = input.parse::<i64>() {
+				Variant::Integer(i)
+			} else if let Ok(f) = input.parse::<f64>() {
+				Variant::Float(f)
+			} else {
+				Variant::Str(input.to_string())
+			};
+
+			if page.write(String::new(), Some(value), None) {
+				return true;
+			}
+		}
+	}
+
+	pub fn cast(&mut self, spell: Spell) {
+		if spell != Spell::Amplify && spell != Spell::Vacancy {
+			self.amplifies_in_a_row = 0;
+		}
+
+		match spell {
+			Spell::Coadjuvancy => {
+				if !self.coadjuvancy() {
+					self.catastrophe();
+				}
+			},
+			Spell::Entwinement => self.mix = Mix::Add,
+			Spell::Belittlement => self.mix = Mix::Sub,
+			Spell::Reenactment => self.mix = Mix::Mul,
+			Spell::Apportionment => self.mix = Mix::Div,
+			Spell::Antipodize => {
+				let mut page = self.cauldron.take().unwrap_or_else(|| self.catastrophe());
+				for i in 0..3 {
+					page.headings[i] = page.headings[i].chars().rev().collect();
+					if let Some(value) = &page.values[i] {
+						page.values[i] = Some(value.inverted().unwrap_or_else(|| self.catastrophe()));
+					}
+				}
+
+				self.cauldron = Some(page);
+			},
+			Spell::Juxtapose => {
+				let values = match &self.cauldron {
+					Some(page) => page.values.clone(),
+					None => self.catastrophe(),
+				};
+
+				let mut compared = Page::new(Chapter::Presages);
+				compared.values = [
+					Some(Variant::Boolean(values[1].is_some() && values[2].is_some())),
+					Some(Variant::Boolean(values[0] >= values[2])),
+					Some(Variant::Boolean(values[0] == values[1])),
+				];
+				self.cauldron = Some(compared);
+			},
+			Spell::Judgement => {
+				if !self.judge() {
+					let charge = self.charge;
+					self.record_cast();
+					self.line = self.line.wrapping_add(charge);
+					return;
+				}
+			},
+			Spell::Amplify => {
+				self.amplifier += 1;
+				self.amplifies_in_a_row += 1;
+				if self.amplifies_in_a_row > 3 {
+					self.catastrophe();
+				}
+
+				return;
+			},
+			Spell::Squelch => {
+				self.charge = 0;
+				self.amplifier = 1;
+				return;
+			},
+			Spell::Diminish => {
+				self.charge = self.charge.saturating_sub(1);
+				return;
+			},
+			Spell::Reverberate => {
+				let charge = self.charge;
+				// One before, since finishing this line moves on to the next. There's nothing
+				// before the first line to go back to
+				self.line = match self.casts.get(charge).and_then(|cast| cast.0.checked_sub(1)) {
+					Some(line) => line,
+					None => self.catastrophe(),
+				};
+
+				while self.casts.len() > charge + 1 {
+					let amplifier = self.casts[self.casts.len() - 1].1;
+					self.charge = self.charge.saturating_sub(amplifier);
+					self.casts.remove(charge + 1);
+				}
+
+				self.amplifier = 1;
+				return;
+			},
+			Spell::Vacancy => {},
+		}
+
+		self.record_cast();
+	}
+
+	pub fn sign_chapter(&mut self, signature: &str) {
+		if self.page().signed {
+			self.catastrophe();
+		}
+
+		self.page().signature = signature.into();
+		self.page().signed = true;
+	}
+
+	pub fn sign_acknowledgements(&mut self, signature: &str) {
+		self.acknowledgements = Some(signature.into());
+	}
+
+	fn published(&self) -> String {
+		let mut output = String::new();
+		for page in &self.pages {
+			for value in page.values.iter().flatten() {
+				let printed = value.to_string();
+				if !printed.is_empty() {
+					output.push_str(&printed);
+					output.push_str(&page.signature);
+				}
+			}
+		}
+
+		output.push_str(match &self.acknowledgements {
+			Some(signature) => signature,
+			None if self.pages[3].has_any_contents() || !self.pages.iter().any(|p| p.has_any_contents()) => DEFAULT_WRAPUP,
+			None => DEFAULT_WRAPUP_QED,
+		});
+
+		output
+	}
+
+	pub fn publish(&mut self) {
+		let mut stdout = io::stdout();
+		if stdout.write_all(self.published().as_bytes()).and_then(|_| stdout.flush()).is_err() {
+			self.catastrophe();
+		}
+	}
+
+	pub fn publish_to(&mut self, path: &str) {
+		let written = OpenOptions::new()
+			.create(true)
+			.append(true)
+			.open(path)
+			.and_then(|mut file| file.write_all(self.published().as_bytes()));
+
+		if written.is_err() {
+			self.catastrophe();
+		}
+	}
+
+	pub fn slam_shut(&mut self) {
+		self.exit = true;
+	}
+}
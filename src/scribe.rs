@@ -0,0 +1,1207 @@
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+//        SPELLBOOK INTERPRETER
+//           BY DIANE SPARKS
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+
+// Scribe is a tiny structured language for people who'd rather not count lines by hand.
+// It has whole numbers in variables, `print`, `input`, `+ - * /`, `if` and `while`, and
+// gets written out as a spellbook that the interpreter runs like any other:
+//
+//     input n
+//     i = 0
+//     while i < n {
+//         print "step", i + 1
+//         i = i + 1
+//     }
+//
+// Variables live as strings on two pages, one in Incantations and one at the bottom of
+// the drawer, so that writing them from memory never forgets anything. Hexes, Presages
+// and the cauldron are scratch space for working things out in between.
+
+use crate::constants::BUILTINS_ORDINALS;
+use crate::formatter::escape_string;
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::ops::Range;
+use std::process;
+
+const KEYWORDS: [&str; 5] = ["print", "input", "if", "else", "while"];
+
+// Longer symbols come first so that `<=` isn't read as `<` and then `=`
+const SYMBOLS: [&str; 16] = ["==", "!=", "<=", ">=", "<", ">", "=", "+", "-", "*", "/", "(", ")", "{", "}", ","];
+
+// Every entry the scribe writes is headed by where it goes on the page
+const POSITIONS: [&str; 3] = ["left", "middle", "right"];
+
+const DIGITS: [&str; 10] = ["ZERO", "ONE", "TWO", "THREE", "FOUR", "FIVE", "SIX", "SEVEN", "EIGHT", "NINE"];
+
+// Two pages of three
+const SLOTS: usize = 6;
+
+pub struct ScribeError {
+	pub message: String,
+	pub span: Range<usize>,
+}
+
+fn error<T>(message: String, span: Range<usize>) -> Result<T, ScribeError> {
+	Err(ScribeError{ message, span })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+	Number(i64),
+	Name(String),
+	Text(String),
+	Symbol(&'static str),
+	End,
+}
+
+struct Token {
+	kind: TokenKind,
+	span: Range<usize>,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, ScribeError> {
+	let mut tokens = vec![];
+	let mut chars = source.char_indices().peekable();
+	while let Some(&(start, chr)) = chars.peek() {
+		let word_end = |chars: &mut std::iter::Peekable<std::str::CharIndices>| {
+			let mut end = start;
+			while let Some((i, c)) = chars.next_if(|&(_, c)| c.is_alphanumeric() || c == '_') {
+				end = i + c.len_utf8();
+			}
+
+			end
+		};
+
+		if chr.is_whitespace() {
+			chars.next();
+		} else if chr == '#' {
+			while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+		} else if chr.is_ascii_digit() {
+			let end = word_end(&mut chars);
+			match source[start..end].parse::<i64>() {
+				Ok(value) => tokens.push(Token{ kind: TokenKind::Number(value), span: start..end }),
+				Err(_) => return error(format!("`{}` isn't a number a spellbook can hold", &source[start..end]), start..end),
+			}
+		} else if chr.is_alphabetic() || chr == '_' {
+			let end = word_end(&mut chars);
+			tokens.push(Token{ kind: TokenKind::Name(source[start..end].into()), span: start..end });
+		} else if chr == '"' {
+			chars.next();
+			let mut value = String::new();
+			loop {
+				match chars.next() {
+					Some((i, '"')) => {
+						tokens.push(Token{ kind: TokenKind::Text(value), span: start..i + 1 });
+						break;
+					},
+					Some((i, '\\')) => match chars.next() {
+						Some((_, 'n')) => value.push('\n'),
+						Some((_, 't')) => value.push('\t'),
+						Some((_, '"')) => value.push('"'),
+						Some((_, '\\')) => value.push('\\'),
+						_ => return error("unknown escape in the string".into(), i..i + 2),
+					},
+					Some((_, '\n')) | None => return error("unterminated string".into(), start..start + 1),
+					Some((_, c)) => value.push(c),
+				}
+			}
+		} else {
+			match SYMBOLS.iter().find(|s| source[start..].starts_with(**s)) {
+				Some(symbol) => {
+					for _ in 0..symbol.len() {
+						chars.next();
+					}
+
+					tokens.push(Token{ kind: TokenKind::Symbol(symbol), span: start..start + symbol.len() });
+				},
+				None => return error(format!("`{}` doesn't mean anything to the scribe", chr), start..start + chr.len_utf8()),
+			}
+		}
+	}
+
+	tokens.push(Token{ kind: TokenKind::End, span: source.len()..source.len() });
+	Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+	Add,
+	Subtract,
+	Multiply,
+	Divide,
+}
+
+enum Expression {
+	Number(i64),
+	Variable(String, Range<usize>),
+	Binary(Operator, Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+	Equal,
+	NotEqual,
+	Less,
+	LessOrEqual,
+	Greater,
+	GreaterOrEqual,
+}
+
+struct Condition {
+	left: Expression,
+	comparison: Comparison,
+	right: Expression,
+}
+
+enum Item {
+	Text(String),
+	Value(Expression),
+}
+
+enum StatementKind {
+	Assign(String, Range<usize>, Expression),
+	Print(Vec<Item>),
+	Input(String, Range<usize>),
+	If(Condition, Vec<Statement>, Vec<Statement>),
+	While(Condition, Vec<Statement>),
+}
+
+// For `if` and `while`, the span only covers the part before the block
+struct Statement {
+	kind: StatementKind,
+	span: Range<usize>,
+}
+
+struct Parser {
+	tokens: Vec<Token>,
+	position: usize,
+}
+
+fn describe(kind: &TokenKind) -> String {
+	match kind {
+		TokenKind::Number(n) => format!("`{}`", n),
+		TokenKind::Name(name) => format!("`{}`", name),
+		TokenKind::Text(_) => "a string".into(),
+		TokenKind::Symbol(symbol) => format!("`{}`", symbol),
+		TokenKind::End => "the end of the file".into(),
+	}
+}
+
+impl Parser {
+	fn peek(&self) -> &Token {
+		&self.tokens[self.position]
+	}
+
+	fn next(&mut self) -> &Token {
+		self.position += 1;
+		&self.tokens[self.position - 1]
+	}
+
+	fn last_end(&self) -> usize {
+		self.tokens[self.position - 1].span.end
+	}
+
+	fn at(&self, symbol: &str) -> bool {
+		match &self.peek().kind {
+			TokenKind::Symbol(s) => *s == symbol,
+			TokenKind::Name(name) => name == symbol,
+			_ => false,
+		}
+	}
+
+	fn eat(&mut self, symbol: &str) -> bool {
+		if self.at(symbol) {
+			self.position += 1;
+			return true;
+		}
+
+		false
+	}
+
+	fn unexpected<T>(&self, expected: &str) -> Result<T, ScribeError> {
+		error(format!("expected {} but found {}", expected, describe(&self.peek().kind)), self.peek().span.clone())
+	}
+
+	fn expect(&mut self, symbol: &str) -> Result<(), ScribeError> {
+		if !self.eat(symbol) {
+			return self.unexpected(&format!("`{}`", symbol));
+		}
+
+		Ok(())
+	}
+
+	fn variable(&mut self) -> Result<(String, Range<usize>), ScribeError> {
+		match &self.peek().kind {
+			TokenKind::Name(name) if !KEYWORDS.contains(&name.as_str()) => {
+				let name = name.clone();
+				Ok((name, self.next().span.clone()))
+			},
+			_ => self.unexpected("a variable"),
+		}
+	}
+
+	fn statements(&mut self, in_block: bool) -> Result<Vec<Statement>, ScribeError> {
+		let mut statements = vec![];
+		loop {
+			match self.peek().kind {
+				TokenKind::Symbol("}") if in_block => break,
+				TokenKind::End if in_block => return self.unexpected("`}`"),
+				TokenKind::End => break,
+				_ => statements.push(self.statement()?),
+			}
+		}
+
+		Ok(statements)
+	}
+
+	fn block(&mut self) -> Result<Vec<Statement>, ScribeError> {
+		self.expect("{")?;
+		let statements = self.statements(true)?;
+		self.expect("}")?;
+		Ok(statements)
+	}
+
+	fn statement(&mut self) -> Result<Statement, ScribeError> {
+		let start = self.peek().span.start;
+		let kind = if self.eat("print") {
+			let mut items = vec![];
+			loop {
+				match &self.peek().kind {
+					TokenKind::Text(text) => {
+						items.push(Item::Text(text.clone()));
+						self.next();
+					},
+					_ => items.push(Item::Value(self.expression()?)),
+				}
+
+				if !self.eat(",") {
+					break;
+				}
+			}
+
+			StatementKind::Print(items)
+		} else if self.eat("input") {
+			let (name, span) = self.variable()?;
+			StatementKind::Input(name, span)
+		} else if self.eat("if") {
+			let condition = self.condition()?;
+			let span = start..self.last_end();
+			let then = self.block()?;
+			let otherwise = if !self.eat("else") {
+				vec![]
+			} else if self.at("if") {
+				vec![self.statement()?]
+			} else {
+				self.block()?
+			};
+
+			return Ok(Statement{ kind: StatementKind::If(condition, then, otherwise), span });
+		} else if self.eat("while") {
+			let condition = self.condition()?;
+			let span = start..self.last_end();
+			return Ok(Statement{ kind: StatementKind::While(condition, self.block()?), span });
+		} else if matches!(&self.peek().kind, TokenKind::Name(name) if !KEYWORDS.contains(&name.as_str())) {
+			let (name, span) = self.variable()?;
+			self.expect("=")?;
+			StatementKind::Assign(name, span, self.expression()?)
+		} else {
+			return self.unexpected("a statement");
+		};
+
+		Ok(Statement{ kind, span: start..self.last_end() })
+	}
+
+	fn condition(&mut self) -> Result<Condition, ScribeError> {
+		let left = self.expression()?;
+		let comparison = match self.peek().kind {
+			TokenKind::Symbol("==") => Comparison::Equal,
+			TokenKind::Symbol("!=") => Comparison::NotEqual,
+			TokenKind::Symbol("<") => Comparison::Less,
+			TokenKind::Symbol("<=") => Comparison::LessOrEqual,
+			TokenKind::Symbol(">") => Comparison::Greater,
+			TokenKind::Symbol(">=") => Comparison::GreaterOrEqual,
+			_ => return self.unexpected("a comparison"),
+		};
+
+		self.next();
+		Ok(Condition{ left, comparison, right: self.expression()? })
+	}
+
+	fn expression(&mut self) -> Result<Expression, ScribeError> {
+		let mut left = self.term()?;
+		loop {
+			let operator = match self.peek().kind {
+				TokenKind::Symbol("+") => Operator::Add,
+				TokenKind::Symbol("-") => Operator::Subtract,
+				_ => return Ok(left),
+			};
+
+			self.next();
+			left = Expression::Binary(operator, Box::new(left), Box::new(self.term()?));
+		}
+	}
+
+	fn term(&mut self) -> Result<Expression, ScribeError> {
+		let mut left = self.unary()?;
+		loop {
+			let operator = match self.peek().kind {
+				TokenKind::Symbol("*") => Operator::Multiply,
+				TokenKind::Symbol("/") => Operator::Divide,
+				_ => return Ok(left),
+			};
+
+			self.next();
+			left = Expression::Binary(operator, Box::new(left), Box::new(self.unary()?));
+		}
+	}
+
+	fn unary(&mut self) -> Result<Expression, ScribeError> {
+		if self.eat("-") {
+			return Ok(match self.unary()? {
+				Expression::Number(n) => Expression::Number(-n),
+				operand => Expression::Binary(Operator::Subtract, Box::new(Expression::Number(0)), Box::new(operand)),
+			});
+		}
+
+		match self.peek().kind.clone() {
+			TokenKind::Number(n) => {
+				self.next();
+				Ok(Expression::Number(n))
+			},
+			TokenKind::Symbol("(") => {
+				self.next();
+				let inner = self.expression()?;
+				self.expect(")")?;
+				Ok(inner)
+			},
+			TokenKind::Name(_) => {
+				let (name, span) = self.variable()?;
+				Ok(Expression::Variable(name, span))
+			},
+			_ => self.unexpected("a number or a variable"),
+		}
+	}
+}
+
+// What's left once expressions are taken apart: every operand is a number or a variable, and
+// whatever can't be worked out left to right in the cauldron goes into a temporary first
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Value {
+	Number(i64),
+	Variable(usize),
+}
+
+struct Chain {
+	first: Value,
+	rest: Vec<(Operator, Value)>,
+}
+
+enum Piece {
+	Text(String),
+	Value(Chain),
+}
+
+// Comparisons are turned around so that only these four are left
+struct Test {
+	left: Chain,
+	comparison: Comparison,
+	right: Value,
+}
+
+enum Step {
+	Comment(String),
+	Compute(usize, Chain),
+	Input(usize),
+	Print(Piece, &'static str),
+	If(Test, Vec<Step>, Vec<Step>),
+	While(Vec<Step>, Test, Vec<Step>),
+}
+
+impl Chain {
+	fn variables(&self) -> impl Iterator<Item = usize> + '_ {
+		std::iter::once(self.first).chain(self.rest.iter().map(|r| r.1)).filter_map(|v| match v {
+			Value::Variable(id) => Some(id),
+			Value::Number(_) => None,
+		})
+	}
+}
+
+impl Test {
+	fn variables(&self) -> impl Iterator<Item = usize> + '_ {
+		let right = match self.right {
+			Value::Variable(id) => Some(id),
+			Value::Number(_) => None,
+		};
+
+		self.left.variables().chain(right)
+	}
+}
+
+struct Lowering<'a> {
+	source: &'a str,
+	names: Vec<String>,
+	spans: Vec<Range<usize>>,
+	weights: Vec<u64>,
+	ids: HashMap<String, usize>,
+	assigned: HashSet<usize>,
+	reads: Vec<(usize, Range<usize>)>,
+	depth: u32,
+	statement: Range<usize>,
+}
+
+// Comments can only be shouted, and digits don't count as shouting
+fn shout(text: &str) -> String {
+	let mut words = vec![];
+	for word in text.split_whitespace() {
+		let mut shouted = String::new();
+		let mut last_digit = None;
+		for chr in word.chars() {
+			let digit = chr.is_ascii_digit();
+			let joins = shouted.chars().last().is_some_and(|c| c.is_alphanumeric());
+			if digit {
+				if joins {
+					shouted.push('-');
+				}
+
+				shouted.push_str(DIGITS[chr as usize - '0' as usize]);
+			} else if chr == '"' {
+				shouted.push('\'');
+			} else if chr.is_ascii_punctuation() {
+				shouted.push(chr);
+			} else {
+				let upper = chr.to_uppercase().filter(|c| c.is_uppercase()).collect::<String>();
+				if !upper.is_empty() && joins && last_digit == Some(true) {
+					shouted.push('-');
+				}
+
+				shouted.push_str(&upper);
+			}
+
+			last_digit = Some(digit);
+		}
+
+		if !shouted.is_empty() {
+			words.push(shouted);
+		}
+	}
+
+	words.join(" ")
+}
+
+// Strips the scribe's own comments off a statement's text, minding the strings
+fn statement_text(text: &str) -> String {
+	let mut in_string = false;
+	let mut escaped = false;
+	let mut kept = String::new();
+	for line in text.lines() {
+		for chr in line.chars() {
+			if !in_string && chr == '#' {
+				break;
+			}
+
+			if chr == '"' && !escaped {
+				in_string = !in_string;
+			}
+
+			escaped = in_string && chr == '\\' && !escaped;
+			kept.push(chr);
+		}
+
+		kept.push(' ');
+	}
+
+	kept
+}
+
+impl Lowering<'_> {
+	fn variable(&mut self, name: &str, span: &Range<usize>) -> usize {
+		let id = match self.ids.get(name) {
+			Some(id) => *id,
+			None => {
+				self.ids.insert(name.into(), self.names.len());
+				self.names.push(name.into());
+				self.spans.push(span.clone());
+				self.weights.push(0);
+				self.names.len() - 1
+			},
+		};
+
+		self.weights[id] = self.weights[id].saturating_add(8u64.saturating_pow(self.depth));
+		id
+	}
+
+	fn temporary(&mut self) -> usize {
+		self.names.push(String::new());
+		self.spans.push(self.statement.clone());
+		self.weights.push(8u64.saturating_pow(self.depth));
+		self.assigned.insert(self.names.len() - 1);
+		self.names.len() - 1
+	}
+
+	fn simple(&mut self, expression: &Expression) -> Option<Value> {
+		match expression {
+			Expression::Number(n) => Some(Value::Number(*n)),
+			Expression::Variable(name, span) => {
+				let id = self.variable(name, span);
+				self.reads.push((id, span.clone()));
+				Some(Value::Variable(id))
+			},
+			Expression::Binary(..) => None,
+		}
+	}
+
+	fn value(&mut self, expression: &Expression, steps: &mut Vec<Step>) -> Value {
+		match self.simple(expression) {
+			Some(value) => value,
+			None => {
+				let chain = self.chain(expression, steps);
+				let temporary = self.temporary();
+				steps.push(Step::Compute(temporary, chain));
+				Value::Variable(temporary)
+			},
+		}
+	}
+
+	fn chain(&mut self, expression: &Expression, steps: &mut Vec<Step>) -> Chain {
+		match expression {
+			Expression::Binary(operator, left, right) => {
+				let commutes = matches!(operator, Operator::Add | Operator::Multiply);
+				if !matches!(**right, Expression::Binary(..)) || !commutes || matches!(**left, Expression::Binary(..)) {
+					let right = self.value(right, steps);
+					let mut chain = self.chain(left, steps);
+					chain.rest.push((*operator, right));
+					chain
+				} else {
+					// Flipped around so the cauldron can keep going without a temporary
+					let left = self.simple(left).unwrap();
+					let mut chain = self.chain(right, steps);
+					chain.rest.push((*operator, left));
+					chain
+				}
+			},
+			_ => Chain{ first: self.simple(expression).unwrap(), rest: vec![] },
+		}
+	}
+
+	fn test(&mut self, condition: &Condition, steps: &mut Vec<Step>) -> Test {
+		let (left, comparison, right) = match condition.comparison {
+			Comparison::Greater => (&condition.right, Comparison::Less, &condition.left),
+			Comparison::LessOrEqual => (&condition.right, Comparison::GreaterOrEqual, &condition.left),
+			comparison => (&condition.left, comparison, &condition.right),
+		};
+
+		let right = self.value(right, steps);
+		Test{ left: self.chain(left, steps), comparison, right }
+	}
+
+	fn comment(&self, span: &Range<usize>) -> Step {
+		Step::Comment(shout(&statement_text(&self.source[span.clone()])))
+	}
+
+	fn block(&mut self, statements: &[Statement]) -> Vec<Step> {
+		self.depth += 1;
+		let mut steps = vec![];
+		for statement in statements {
+			self.statement(statement, &mut steps);
+		}
+
+		self.depth -= 1;
+		steps
+	}
+
+	fn statement(&mut self, statement: &Statement, steps: &mut Vec<Step>) {
+		self.statement = statement.span.clone();
+		steps.push(self.comment(&statement.span));
+		match &statement.kind {
+			StatementKind::Assign(name, span, expression) => {
+				let chain = self.chain(expression, steps);
+				let id = self.variable(name, span);
+				self.assigned.insert(id);
+				steps.push(Step::Compute(id, chain));
+			},
+			StatementKind::Print(items) => {
+				// Each piece is printed by itself, followed by a space or by a newline for the last
+				for (i, item) in items.iter().enumerate() {
+					let piece = match item {
+						Item::Text(text) => Piece::Text(text.clone()),
+						Item::Value(expression) => Piece::Value(self.chain(expression, steps)),
+					};
+
+					steps.push(Step::Print(piece, if i + 1 == items.len() { "\n" } else { " " }));
+				}
+			},
+			StatementKind::Input(name, span) => {
+				let id = self.variable(name, span);
+				self.assigned.insert(id);
+				steps.push(Step::Input(id));
+			},
+			StatementKind::If(condition, then, otherwise) => {
+				let test = self.test(condition, steps);
+				let then = self.block(then);
+				let otherwise = self.block(otherwise);
+				steps.push(Step::If(test, then, otherwise));
+			},
+			StatementKind::While(condition, body) => {
+				self.depth += 1;
+				let mut setup = vec![];
+				let test = self.test(condition, &mut setup);
+				self.depth -= 1;
+				steps.push(Step::While(setup, test, self.block(body)));
+			},
+		}
+	}
+}
+
+// Works backwards through the steps to find which variables still matter before each one.
+// Whatever is still needed when a variable gets a new value can't share a slot with it.
+fn live_before(steps: &[Step], mut live: HashSet<usize>, clashes: &mut HashSet<(usize, usize)>) -> HashSet<usize> {
+	let define = |target: usize, live: &HashSet<usize>, clashes: &mut HashSet<(usize, usize)>| {
+		for &other in live.iter().filter(|&&o| o != target) {
+			clashes.insert((target.min(other), target.max(other)));
+		}
+	};
+
+	for step in steps.iter().rev() {
+		match step {
+			Step::Comment(_) => {},
+			Step::Compute(target, chain) => {
+				define(*target, &live, clashes);
+				live.remove(target);
+				live.extend(chain.variables());
+			},
+			Step::Input(target) => {
+				define(*target, &live, clashes);
+				live.remove(target);
+			},
+			Step::Print(piece, _) => {
+				if let Piece::Value(chain) = piece {
+					live.extend(chain.variables());
+				}
+			},
+			Step::If(test, then, otherwise) => {
+				let mut before = live_before(then, live.clone(), clashes);
+				before.extend(live_before(otherwise, live, clashes));
+				before.extend(test.variables());
+				live = before;
+			},
+			Step::While(setup, test, body) => {
+				// Going around again can only ever need more, so this settles down eventually
+				let mut head = HashSet::new();
+				loop {
+					let mut after_setup = live_before(body, head.clone(), clashes);
+					after_setup.extend(live.iter().copied());
+					after_setup.extend(test.variables());
+					let before = live_before(setup, after_setup, clashes);
+					if before == head {
+						break;
+					}
+
+					head = before;
+				}
+
+				live = head;
+			},
+		}
+	}
+
+	live
+}
+
+// Busier variables get the first page, since the one in the drawer takes longer to get at
+fn allocate(lowering: &Lowering, steps: &[Step]) -> Result<Vec<usize>, ScribeError> {
+	let mut clashes = HashSet::new();
+	live_before(steps, HashSet::new(), &mut clashes);
+
+	let mut order = (0..lowering.names.len()).collect::<Vec<usize>>();
+	order.sort_by_key(|&id| std::cmp::Reverse(lowering.weights[id]));
+
+	let mut slots = vec![usize::MAX; lowering.names.len()];
+	for id in order {
+		let taken = clashes.iter()
+			.filter_map(|&(a, b)| if a == id { Some(b) } else if b == id { Some(a) } else { None })
+			.map(|other| slots[other])
+			.collect::<HashSet<usize>>();
+
+		let slot = (0..).find(|s| !taken.contains(s)).unwrap();
+		if slot >= SLOTS {
+			let what = match lowering.names[id].as_str() {
+				"" => "what's worked out along the way".into(),
+				name => format!("`{}`", name),
+			};
+
+			return error(format!("there's no room on the desk for {}: only {} numbers fit at once", what, SLOTS), lowering.spans[id].clone());
+		}
+
+		slots[id] = slot;
+	}
+
+	Ok(slots)
+}
+
+#[derive(Default)]
+struct Code {
+	lines: Vec<String>,
+	length: usize,
+	heads: Vec<(usize, usize)>,
+}
+
+impl Code {
+	fn line(&mut self, line: impl Into<String>) {
+		self.lines.push(line.into());
+		self.length += 1;
+	}
+
+	fn blank(&mut self) {
+		if self.lines.last().is_some_and(|l| !l.is_empty()) {
+			self.lines.push(String::new());
+		}
+	}
+
+	fn append(&mut self, other: Code) {
+		let offset = self.length;
+		self.heads.extend(other.heads.iter().map(|&(id, line)| (id, line + offset)));
+		self.lines.extend(other.lines);
+		self.length += other.length;
+	}
+}
+
+fn mixing_spell(operator: Operator) -> &'static str {
+	match operator {
+		Operator::Add => "Entwinement",
+		Operator::Subtract => "Belittlement",
+		Operator::Multiply => "Reenactment",
+		Operator::Divide => "Apportionment",
+	}
+}
+
+// How many lines it takes to build up a charge with a given amplifier
+fn charge_cost(amplifier: usize, charge: usize) -> usize {
+	let vacancies = charge.div_ceil(amplifier);
+	(amplifier - 1) + amplifier.saturating_sub(2) / 3 + vacancies + (vacancies * amplifier - charge)
+}
+
+// Skips the next `lines` lines unless everything in the cauldron is true
+fn judge(code: &mut Code, lines: usize) {
+	let amplifier = (1..=lines.clamp(1, 100)).min_by_key(|&a| charge_cost(a, lines)).unwrap();
+	code.line("cast Squelch on the cauldron");
+	for i in 1..amplifier {
+		// Three in a row is as far as the cauldron goes
+		if i > 1 && (i - 1) % 3 == 0 {
+			code.line("cast Diminish on the cauldron");
+		}
+
+		code.line("cast Amplify on the cauldron");
+	}
+
+	let vacancies = lines.div_ceil(amplifier);
+	for _ in 0..vacancies {
+		code.line("cast Vacancy on the cauldron");
+	}
+
+	for _ in 0..vacancies * amplifier - lines {
+		code.line("cast Diminish on the cauldron");
+	}
+
+	code.line("cast Judgement on the cauldron");
+}
+
+fn write_truths(code: &mut Code, truths: &[bool]) {
+	code.line("turn to chapter Presages");
+	for (position, truth) in truths.iter().enumerate() {
+		code.line(format!("write {} under {}", truth, POSITIONS[position]));
+	}
+
+	code.line("tear out chapter and toss it in the cauldron");
+}
+
+struct Scribe {
+	slots: Vec<usize>,
+	loops: usize,
+}
+
+impl Scribe {
+	// Puts a variable's value in memory, as a string
+	fn load(&self, code: &mut Code, id: usize) {
+		let slot = self.slots[id];
+		code.line("turn to chapter Incantations");
+		if slot < 3 {
+			code.line(format!("memorize {} entry", BUILTINS_ORDINALS[slot]));
+			return;
+		}
+
+		// The first page waits in Hexes while the second one is read
+		code.line("tear out chapter and put it in the drawer");
+		code.line("turn to chapter Hexes");
+		code.line("take out a chapter from the drawer and put it back");
+		code.line("turn to chapter Incantations");
+		code.line("take out a chapter from the drawer and put it back");
+		code.line(format!("memorize {} entry", BUILTINS_ORDINALS[slot - 3]));
+		code.line("tear out chapter and put it in the drawer");
+		code.line("turn to chapter Hexes");
+		code.line("tear out chapter and put it in the drawer");
+		code.line("turn to chapter Incantations");
+		code.line("take out a chapter from the drawer and put it back");
+	}
+
+	fn load_value(&self, code: &mut Code, value: Value) {
+		match value {
+			Value::Number(n) => code.line(format!("memorize {}", escape_string(&n.to_string()))),
+			Value::Variable(id) => self.load(code, id),
+		}
+	}
+
+	// Leaves a page in Hexes with the value at the given position and zeroes before it
+	fn operand_page(&self, code: &mut Code, value: Value, position: usize) {
+		if let Value::Variable(id) = value {
+			self.load(code, id);
+		}
+
+		code.line("turn to chapter Hexes");
+		for pad in POSITIONS.iter().take(position) {
+			code.line(format!("write 0 under {}", pad));
+		}
+
+		match value {
+			Value::Number(n) => code.line(format!("write {} under {}", n, POSITIONS[position])),
+			Value::Variable(_) => code.line(format!("write from memory under {}", POSITIONS[position])),
+		}
+	}
+
+	// Works a chain out in the cauldron, left to right
+	fn evaluate(&self, code: &mut Code, chain: &Chain) {
+		self.operand_page(code, chain.first, 0);
+		code.line("tear out chapter and toss it in the cauldron");
+		for (operator, value) in &chain.rest {
+			code.line(format!("cast {} on the cauldron", mixing_spell(*operator)));
+			self.operand_page(code, *value, 0);
+			code.line("tear out chapter and toss it in the cauldron");
+		}
+	}
+
+	// Numbers in memory would get forgotten if they're long enough, so go through Incantations
+	fn result_to_memory(&self, code: &mut Code) {
+		code.line("turn to chapter Incantations");
+		code.line("tear out chapter and put it in the drawer");
+		code.line("take out chapter from the cauldron and put it back");
+		code.line("memorize first entry");
+		code.line("tear out chapter and throw it in the trash");
+		code.line("take out a chapter from the drawer and put it back");
+	}
+
+	// Replaces one entry of a page with what's in memory: multiplying by ones and zeroes
+	// clears it out, then adding puts the new value in
+	fn store(&self, code: &mut Code, id: usize) {
+		let slot = self.slots[id];
+		let (chapter, position, blank) = if slot < 3 {
+			("Incantations", slot, "\"\"")
+		} else {
+			("Hexes", slot - 3, "0")
+		};
+
+		code.line(format!("turn to chapter {}", chapter));
+		if slot >= 3 {
+			code.line("take out a chapter from the drawer and put it back");
+		}
+
+		code.line("tear out chapter and toss it in the cauldron");
+		code.line("cast Reenactment on the cauldron");
+		code.line("turn to chapter Hexes");
+		for (p, heading) in POSITIONS.iter().enumerate() {
+			code.line(format!("write {} under {}", if p == position { 0 } else { 1 }, heading));
+		}
+
+		code.line("tear out chapter and toss it in the cauldron");
+		code.line("cast Entwinement on the cauldron");
+		code.line(format!("turn to chapter {}", chapter));
+		for pad in POSITIONS.iter().take(position) {
+			code.line(format!("write {} under {}", blank, pad));
+		}
+
+		code.line(format!("write from memory under {}", POSITIONS[position]));
+		code.line("tear out chapter and toss it in the cauldron");
+		code.line("take out chapter from the cauldron and put it back");
+		if slot >= 3 {
+			code.line("tear out chapter and put it in the drawer");
+		}
+	}
+
+	// The piece gets Incantations to itself while it's published
+	fn print(&self, code: &mut Code, piece: &Piece, separator: &str) {
+		let simple = match piece {
+			Piece::Value(chain) if chain.rest.is_empty() => Some(chain.first),
+			_ => None,
+		};
+
+		match (piece, simple) {
+			(Piece::Value(_), Some(Value::Variable(id))) => self.load(code, id),
+			(Piece::Value(chain), None) => self.evaluate(code, chain),
+			_ => {},
+		}
+
+		code.line("turn to chapter Incantations");
+		code.line("tear out chapter and put it in the drawer");
+		match (piece, simple) {
+			(Piece::Text(text), _) => code.line(format!("write {} under left", escape_string(&format!("{}{}", text, separator)))),
+			(_, Some(Value::Number(n))) => code.line(format!("write {} under left", escape_string(&format!("{}{}", n, separator)))),
+			(_, Some(Value::Variable(_))) => {
+				code.line("write from memory under left");
+				code.line(format!("write {} under middle", escape_string(separator)));
+			},
+			(_, None) => {
+				code.line("take out chapter from the cauldron and put it back");
+				code.line(format!("write {} under middle", escape_string(separator)));
+			},
+		}
+
+		code.line("publish spellbook");
+		code.line("tear out chapter and throw it in the trash");
+		code.line("take out a chapter from the drawer and put it back");
+	}
+
+	// Leaves a Presages chapter in the cauldron that's all true just when the test passes.
+	// Juxtapose only knows about `>=` and `==`, so the rest is patched up with Antipodize and
+	// some trues mixed in where the answer isn't.
+	fn test(&self, code: &mut Code, test: &Test) {
+		self.evaluate(code, &test.left);
+		code.line("cast Entwinement on the cauldron");
+		let (position, antipodize, truths): (usize, bool, &[bool]) = match test.comparison {
+			Comparison::Equal => (1, false, &[true]),
+			Comparison::NotEqual => (1, true, &[false, true]),
+			Comparison::GreaterOrEqual => (2, false, &[false, false, true]),
+			_ => (2, true, &[true, false, true]),
+		};
+
+		self.operand_page(code, test.right, position);
+		code.line("tear out chapter and toss it in the cauldron");
+		code.line("cast Juxtapose on the cauldron");
+		if antipodize {
+			code.line("cast Antipodize on the cauldron");
+		}
+
+		write_truths(code, truths);
+	}
+
+	fn steps(&mut self, steps: &[Step]) -> Code {
+		let mut code = Code::default();
+		for step in steps {
+			if matches!(step, Step::Comment(_)) {
+				code.blank();
+			}
+
+			self.step(&mut code, step);
+		}
+
+		code
+	}
+
+	fn step(&mut self, code: &mut Code, step: &Step) {
+		match step {
+			Step::Comment(comment) => {
+				if !comment.is_empty() {
+					code.line(comment.clone());
+				}
+			},
+			Step::Compute(target, chain) => {
+				if chain.rest.is_empty() {
+					self.load_value(code, chain.first);
+				} else {
+					self.evaluate(code, chain);
+					self.result_to_memory(code);
+				}
+
+				self.store(code, *target);
+			},
+			Step::Input(target) => {
+				code.line("turn to chapter Hexes");
+				code.line("tear out chapter and toss it in the cauldron");
+				code.line("cast Coadjuvancy on the cauldron");
+				self.result_to_memory(code);
+				self.store(code, *target);
+			},
+			Step::Print(piece, separator) => self.print(code, piece, separator),
+			Step::If(test, then, otherwise) => {
+				let mut then_code = Code::default();
+				then_code.line("knock over cauldron");
+				then_code.append(self.steps(then));
+
+				let mut otherwise_code = Code::default();
+				if !otherwise.is_empty() {
+					otherwise_code.line("ELSE");
+					otherwise_code.line("knock over cauldron");
+					otherwise_code.append(self.steps(otherwise));
+
+					// Once the first block is done, a false is all it takes to get past the second
+					then_code.blank();
+					write_truths(&mut then_code, &[false]);
+					judge(&mut then_code, otherwise_code.length);
+				}
+
+				self.test(code, test);
+				judge(code, then_code.length);
+				code.append(then_code);
+				code.append(otherwise_code);
+				code.line("knock over cauldron");
+			},
+			Step::While(setup, test, body) => {
+				self.loops += 1;
+				let id = self.loops;
+
+				let mut body_code = Code::default();
+				body_code.line("knock over cauldron");
+				body_code.append(self.steps(body));
+
+				// Going around again means leaving a note in the drawer saying where to, then
+				// going back to the very first spell
+				body_code.blank();
+				body_code.line("turn to chapter Hexes");
+				body_code.line(format!("write {} under left", id));
+				body_code.line("tear out chapter and put it in the drawer");
+				body_code.line("cast Squelch on the cauldron");
+				body_code.line("cast Reverberate on the cauldron");
+				body_code.blank();
+
+				code.heads.push((id, code.length));
+				code.line("knock over cauldron");
+				code.append(self.steps(setup));
+				self.test(code, test);
+				judge(code, body_code.length);
+				code.append(body_code);
+				code.line("knock over cauldron");
+			},
+		}
+	}
+}
+
+fn generate(steps: &[Step], slots: Vec<usize>) -> String {
+	let mut scribe = Scribe{ slots, loops: 0 };
+	let main = scribe.steps(steps);
+
+	let mut code = Code::default();
+	code.line("WRITTEN OUT BY THE SCRIBE");
+	code.line("sign acknowledgements page with \"\"");
+	code.line("turn to chapter Incantations");
+	code.line("sign chapter with \"\"");
+	let pages = if scribe.slots.iter().any(|&s| s >= 3) { 2 } else { 1 };
+	for page in 0..pages {
+		for position in POSITIONS {
+			code.line(format!("write \"0\" under {}", position));
+		}
+
+		if page + 1 < pages {
+			code.line("tear out chapter and put it in the drawer");
+		}
+	}
+
+	if scribe.loops > 0 {
+		// Every loop comes back here by reverberating, then gets sent on to its start by the
+		// note it left in the drawer. The first time through, the note says to go nowhere.
+		code.blank();
+		code.line("turn to chapter Hexes");
+		code.line("write 0 under left");
+		code.line("tear out chapter and put it in the drawer");
+		code.line("cast Vacancy on the cauldron");
+		code.line("turn to chapter Hexes");
+		code.line("take out a chapter from the drawer and put it back");
+		code.line("memorize first entry");
+		code.line("tear out chapter and throw it in the trash");
+
+		let heads = main.heads.iter().copied().collect::<HashMap<usize, usize>>();
+		let mut tests = vec![];
+		let mut after = 0;
+		for id in (1..=scribe.loops).rev() {
+			let mut test = Code::default();
+			test.line("turn to chapter Hexes");
+			test.line("write from memory under left");
+			test.line(format!("write {} under middle", id));
+			test.line("tear out chapter and toss it in the cauldron");
+			test.line("cast Juxtapose on the cauldron");
+			test.line("cast Antipodize on the cauldron");
+			test.line("cast Entwinement on the cauldron");
+			write_truths(&mut test, &[false, true]);
+			judge(&mut test, 1 + after + heads[&id]);
+			test.line("knock over cauldron");
+			after += test.length;
+			tests.push(test);
+		}
+
+		for test in tests.into_iter().rev() {
+			code.append(test);
+		}
+	}
+
+	code.blank();
+	code.append(main);
+
+	let mut book = code.lines.join("\n");
+	book.push('\n');
+	book
+}
+
+pub fn compile(source: &str) -> Result<String, ScribeError> {
+	let tokens = tokenize(source)?;
+	let statements = Parser{ tokens, position: 0 }.statements(false)?;
+
+	let mut lowering = Lowering{
+		source,
+		names: vec![],
+		spans: vec![],
+		weights: vec![],
+		ids: HashMap::new(),
+		assigned: HashSet::new(),
+		reads: vec![],
+		depth: 0,
+		statement: 0..0,
+	};
+
+	let mut steps = vec![];
+	for statement in &statements {
+		lowering.statement(statement, &mut steps);
+	}
+
+	if let Some((id, span)) = lowering.reads.iter().find(|(id, _)| !lowering.assigned.contains(id)) {
+		return error(format!("`{}` is never given a value", lowering.names[*id]), span.clone());
+	}
+
+	let slots = allocate(&lowering, &steps)?;
+	Ok(generate(&steps, slots))
+}
+
+// Lines and columns count from one, like everywhere else
+fn position(source: &str, offset: usize) -> (usize, usize) {
+	let before = &source[..offset];
+	let line = before.matches('\n').count() + 1;
+	let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+	(line, column)
+}
+
+pub fn run(args: &[String]) {
+	let mut path = None;
+	let mut output_path = None;
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"-o" => output_path = Some(args.next().unwrap().clone()),
+			_ => path = Some(arg.clone()),
+		}
+	}
+
+	let path = path.unwrap();
+	let source = fs::read_to_string(&path).unwrap();
+	let book = match compile(&source) {
+		Ok(book) => book,
+		Err(error) => {
+			let (line, column) = position(&source, error.span.start);
+			eprintln!("{}:{}:{}: {}", path, line, column, error.message);
+			process::exit(1);
+		},
+	};
+
+	match output_path {
+		Some(output_path) => fs::write(output_path, book).unwrap(),
+		None => print!("{}", book),
+	}
+}
@@ -0,0 +1,564 @@
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+//        SPELLBOOK INTERPRETER
+//           BY DIANE SPARKS
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+
+use crate::constants::*;
+use crate::errors::{closest, edit_distance};
+use crate::lexer::{self, Lexeme, LexemeKind};
+use crate::parser::Keyphrase;
+use crate::variant::Variant;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+use std::str;
+
+// The keyphrases a statement can begin with
+const STATEMENT_STARTS: [Keyphrase; 16] = [
+	Keyphrase::TurnToChapter,
+	Keyphrase::TearOutChapter,
+	Keyphrase::TakeOutAChapterFromTheDrawerAndPutItBack,
+	Keyphrase::TakeOutChapterFromTheCauldronAndPutItBack,
+	Keyphrase::Write,
+	Keyphrase::Copy,
+	Keyphrase::Memorize,
+	Keyphrase::Cast,
+	Keyphrase::KnockOverCauldron,
+	Keyphrase::PublishSpellbook,
+	Keyphrase::SignChapterWith,
+	Keyphrase::SignAcknowledgementsPageWith,
+	Keyphrase::SlamSpellbookShut,
+	Keyphrase::PlaceABookmarkNamed,
+	Keyphrase::FlipBackToBookmark,
+	Keyphrase::FlipAheadToBookmark,
+];
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Destination {
+	Trash,
+	Drawer,
+	Cauldron,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum WriteSource {
+	Literal(Variant),
+	Memory,
+	Copy(String),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum MemorizeSource {
+	Heading(String),
+	Literal(Variant),
+	Entry(usize),
+}
+
+// What an `if` looks at to decide whether the rest of its line is read
+#[derive(Debug, PartialEq, Clone)]
+pub enum Condition {
+	Heading(String),
+	Entry(usize),
+	CauldronFull,
+}
+
+// A bookmark being flipped or skipped to, and the line it was placed on once the whole
+// spellbook has been read, if it was placed at all
+#[derive(Debug, PartialEq, Clone)]
+pub struct Bookmark {
+	pub name: String,
+	pub line: Option<usize>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum StatementKind {
+	TurnToChapter(usize),
+	TearOutChapter(Destination),
+	TakeOutFromDrawer,
+	TakeOutFromCauldron,
+	Write{ source: WriteSource, heading: String },
+	Memorize(MemorizeSource),
+	Cast(String),
+	KnockOverCauldron,
+	Publish(Option<String>),
+	SignChapter(String),
+	SignAcknowledgements(String),
+	SlamShut,
+	If(Condition),
+	PlaceBookmark(String),
+	Flip{ bookmark: Bookmark, back: bool },
+	JudgeToBookmark(Bookmark),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Statement {
+	pub kind: StatementKind,
+	pub span: Range<usize>,
+}
+
+impl Statement {
+	pub fn heading(&self) -> Option<&str> {
+		match &self.kind {
+			StatementKind::Write{ heading, .. } => Some(heading),
+			_ => None,
+		}
+	}
+
+	pub fn spell(&self) -> Option<&str> {
+		match &self.kind {
+			StatementKind::Cast(spell) => Some(spell),
+			StatementKind::JudgeToBookmark(_) => Some("Judgement"),
+			_ => None,
+		}
+	}
+
+	pub fn bookmark(&self) -> Option<&Bookmark> {
+		match &self.kind {
+			StatementKind::Flip{ bookmark, .. } | StatementKind::JudgeToBookmark(bookmark) => Some(bookmark),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SyntaxError {
+	pub message: String,
+	pub span: Range<usize>,
+	// What was probably meant, when it looks like a typo
+	pub suggestion: Option<String>,
+}
+
+pub struct SourceLine {
+	pub number: usize,
+	pub text: String,
+	pub lexemes: Vec<Lexeme>,
+	pub statements: Result<Vec<Statement>, SyntaxError>,
+}
+
+impl SourceLine {
+	pub fn statements(&self) -> &[Statement] {
+		match &self.statements {
+			Ok(statements) => statements,
+			Err(_) => &[],
+		}
+	}
+
+	pub fn comments(&self) -> impl Iterator<Item = &str> {
+		self.lexemes.iter().filter(|l| l.kind == LexemeKind::Comment).map(move |l| &self.text[l.span.clone()])
+	}
+}
+
+// Only the lines that aren't blank, since those are the ones Judgement counts
+pub struct Spellbook {
+	pub lines: Vec<SourceLine>,
+}
+
+struct StatementParser<'a> {
+	lexemes: Vec<&'a Lexeme>,
+	index: usize,
+	line_end: usize,
+}
+
+fn describe(kind: &LexemeKind) -> String {
+	match kind {
+		LexemeKind::Keyphrase(kp) => format!("`{}`", kp.text()),
+		LexemeKind::Literal(lit) => format!("the value {}", lit.print()),
+		LexemeKind::Identifier(ident) => format!("the heading `{}`", ident),
+		LexemeKind::Builtin(bt) => format!("`{}`", bt),
+		LexemeKind::Conditional => "`if`".into(),
+		LexemeKind::KeyphraseContinued => "the rest of a keyphrase".into(),
+		LexemeKind::Comment | LexemeKind::Whitespace => "a comment".into(),
+	}
+}
+
+impl<'a> StatementParser<'a> {
+	fn peek(&self) -> Option<&'a Lexeme> {
+		self.lexemes.get(self.index).cloned()
+	}
+
+	fn next(&mut self) -> Option<&'a Lexeme> {
+		let lexeme = self.peek();
+		self.index += 1;
+		lexeme
+	}
+
+	fn error(&self, expected: &str, after: &Lexeme) -> SyntaxError {
+		match self.peek() {
+			Some(found) => SyntaxError{
+				message: format!("expected {}, found {}", expected, describe(&found.kind)),
+				span: found.span.clone(),
+				suggestion: None,
+			},
+			None => SyntaxError{
+				message: format!("expected {} at the end of the line", expected),
+				span: after.span.end..self.line_end.max(after.span.end),
+				suggestion: None,
+			},
+		}
+	}
+
+	// The words from here on, for guessing at what a typo was meant to be
+	fn words(&self, from: usize) -> Vec<&'a str> {
+		self.lexemes[from..].iter()
+			.map_while(|l| match &l.kind {
+				LexemeKind::Identifier(word) | LexemeKind::Builtin(word) => Some(word.as_str()),
+				_ => None,
+			})
+			.collect()
+	}
+
+	fn closest_statement(&self) -> Option<String> {
+		let words = self.words(self.index - 1);
+		STATEMENT_STARTS.iter()
+			.map(|kp| kp.text())
+			.filter_map(|text| {
+				let written = words.iter().take(text.split(' ').count()).copied().collect::<Vec<&str>>().join(" ");
+				let distance = edit_distance(&written.to_lowercase(), text);
+				(distance > 0 && distance <= (text.len() / 3).max(1)).then_some((distance, text))
+			})
+			.min()
+			.map(|(_, text)| text.into())
+	}
+
+	fn expect_keyphrase(&mut self, keyphrase: Keyphrase, expected: &str, after: &Lexeme) -> Result<&'a Lexeme, SyntaxError> {
+		match self.peek() {
+			Some(lexeme) if lexeme.kind == LexemeKind::Keyphrase(keyphrase.clone()) => {
+				self.index += 1;
+				Ok(lexeme)
+			},
+			_ => Err(self.error(expected, after)),
+		}
+	}
+
+	fn expect_name(&mut self, expected: &str, after: &Lexeme) -> Result<(String, &'a Lexeme), SyntaxError> {
+		match self.peek() {
+			Some(lexeme) => match &lexeme.kind {
+				LexemeKind::Identifier(ident) => {
+					self.index += 1;
+					Ok((ident.clone(), lexeme))
+				},
+				_ => Err(self.error(expected, after)),
+			},
+			None => Err(self.error(expected, after)),
+		}
+	}
+
+	fn expect_heading(&mut self, after: &Lexeme) -> Result<(String, &'a Lexeme), SyntaxError> {
+		self.expect_name("a heading", after)
+	}
+
+	fn expect_bookmark(&mut self, after: &Lexeme) -> Result<(Bookmark, &'a Lexeme), SyntaxError> {
+		let (name, last) = self.expect_name("a bookmark name", after)?;
+		Ok((Bookmark{ name, line: None }, last))
+	}
+
+	fn expect_string(&mut self, after: &Lexeme) -> Result<(String, &'a Lexeme), SyntaxError> {
+		match self.peek() {
+			Some(lexeme) => match &lexeme.kind {
+				LexemeKind::Literal(Variant::Str(string)) => {
+					self.index += 1;
+					Ok((string.clone(), lexeme))
+				},
+				_ => Err(self.error("a string", after)),
+			},
+			None => Err(self.error("a string", after)),
+		}
+	}
+
+	fn parse_condition(&mut self, first: &'a Lexeme) -> Result<(Condition, &'a Lexeme), SyntaxError> {
+		let (condition, last) = match self.peek() {
+			Some(lexeme) => match &lexeme.kind {
+				LexemeKind::Keyphrase(Keyphrase::TheCauldronIsFull) => {
+					self.index += 1;
+					(Condition::CauldronFull, lexeme)
+				},
+				LexemeKind::Identifier(ident) => {
+					self.index += 1;
+					(Condition::Heading(ident.clone()), lexeme)
+				},
+				LexemeKind::Builtin(bt) if BUILTINS_ORDINALS.contains(&bt.as_str()) => {
+					self.index += 1;
+					let index = BUILTINS_ORDINALS.iter().position(|o| o == bt).unwrap();
+					(Condition::Entry(index), self.expect_keyphrase(Keyphrase::Entry, "`entry`", lexeme)?)
+				},
+				_ => return Err(self.error("a heading, an ordinal entry or `the cauldron is full`", first)),
+			},
+			None => return Err(self.error("a heading, an ordinal entry or `the cauldron is full`", first)),
+		};
+
+		// A condition with nothing after it to skip is almost certainly a mistake
+		if self.peek().is_none() {
+			return Err(self.error("a statement for the condition to guard", last));
+		}
+
+		Ok((condition, last))
+	}
+
+	fn parse_statement(&mut self) -> Result<Statement, SyntaxError> {
+		let first = self.next().unwrap();
+		let keyphrase = match &first.kind {
+			LexemeKind::Keyphrase(kp) => kp.clone(),
+			LexemeKind::Conditional => {
+				let (condition, last) = self.parse_condition(first)?;
+				return Ok(Statement{ kind: StatementKind::If(condition), span: first.span.start..last.span.end });
+			},
+			kind => {
+				return Err(SyntaxError{
+					message: format!("expected the start of a statement, found {}", describe(kind)),
+					span: first.span.clone(),
+					suggestion: self.closest_statement(),
+				});
+			},
+		};
+
+		let (kind, last) = match keyphrase {
+			Keyphrase::TurnToChapter => {
+				match self.peek() {
+					Some(lexeme) => match &lexeme.kind {
+						LexemeKind::Builtin(bt) if BUILTINS_CHAPTERS.contains(&bt.as_str()) => {
+							self.index += 1;
+							let index = BUILTINS_CHAPTERS.iter().position(|c| c == bt).unwrap();
+							(StatementKind::TurnToChapter(index), lexeme)
+						},
+						_ => {
+							let mut error = self.error("a chapter name", first);
+							error.suggestion = self.words(self.index).first()
+								.and_then(|word| closest(word, BUILTINS_CHAPTERS))
+								.map(String::from);
+							return Err(error);
+						},
+					},
+					None => return Err(self.error("a chapter name", first)),
+				}
+			},
+			Keyphrase::TearOutChapter => {
+				match self.peek() {
+					Some(lexeme) => {
+						let destination = match lexeme.kind {
+							LexemeKind::Keyphrase(Keyphrase::AndThrowItInTheTrash) => Destination::Trash,
+							LexemeKind::Keyphrase(Keyphrase::AndPutItInTheDrawer) => Destination::Drawer,
+							LexemeKind::Keyphrase(Keyphrase::AndTossItInTheCauldron) => Destination::Cauldron,
+							_ => return Err(self.error("where to put the chapter", first)),
+						};
+
+						self.index += 1;
+						(StatementKind::TearOutChapter(destination), lexeme)
+					},
+					None => return Err(self.error("where to put the chapter", first)),
+				}
+			},
+			Keyphrase::TakeOutAChapterFromTheDrawerAndPutItBack => (StatementKind::TakeOutFromDrawer, first),
+			Keyphrase::TakeOutChapterFromTheCauldronAndPutItBack => (StatementKind::TakeOutFromCauldron, first),
+			Keyphrase::Write => {
+				let source = match self.peek().map(|l| &l.kind) {
+					Some(LexemeKind::Literal(lit)) => {
+						self.index += 1;
+						WriteSource::Literal(lit.clone())
+					},
+					Some(LexemeKind::Keyphrase(Keyphrase::FromMemory)) => {
+						self.index += 1;
+						WriteSource::Memory
+					},
+					_ => return Err(self.error("a value or `from memory`", first)),
+				};
+
+				let under = self.expect_keyphrase(Keyphrase::Under, "`under`", self.lexemes[self.index - 1])?;
+				let (heading, last) = self.expect_heading(under)?;
+				(StatementKind::Write{ source, heading }, last)
+			},
+			Keyphrase::Copy => {
+				let (from, from_lexeme) = self.expect_heading(first)?;
+				let under = self.expect_keyphrase(Keyphrase::Under, "`under`", from_lexeme)?;
+				let (heading, last) = self.expect_heading(under)?;
+				(StatementKind::Write{ source: WriteSource::Copy(from), heading }, last)
+			},
+			Keyphrase::Memorize => {
+				match self.peek() {
+					Some(lexeme) => match &lexeme.kind {
+						LexemeKind::Identifier(ident) => {
+							self.index += 1;
+							(StatementKind::Memorize(MemorizeSource::Heading(ident.clone())), lexeme)
+						},
+						LexemeKind::Literal(lit) => {
+							self.index += 1;
+							(StatementKind::Memorize(MemorizeSource::Literal(lit.clone())), lexeme)
+						},
+						LexemeKind::Builtin(bt) if BUILTINS_ORDINALS.contains(&bt.as_str()) => {
+							self.index += 1;
+							let index = BUILTINS_ORDINALS.iter().position(|o| o == bt).unwrap();
+							let last = self.expect_keyphrase(Keyphrase::Entry, "`entry`", lexeme)?;
+							(StatementKind::Memorize(MemorizeSource::Entry(index)), last)
+						},
+						_ => return Err(self.error("a heading, a value or an ordinal entry", first)),
+					},
+					None => return Err(self.error("a heading, a value or an ordinal entry", first)),
+				}
+			},
+			Keyphrase::Cast => {
+				let spell = match self.peek().map(|l| &l.kind) {
+					Some(LexemeKind::Builtin(name)) | Some(LexemeKind::Identifier(name)) => {
+						self.index += 1;
+						name.clone()
+					},
+					_ => return Err(self.error("a spell name", first)),
+				};
+
+				let last = self.expect_keyphrase(Keyphrase::OnTheCauldron, "`on the cauldron`", self.lexemes[self.index - 1])?;
+				match self.peek() {
+					Some(skip) if skip.kind == LexemeKind::Keyphrase(Keyphrase::AndSkipToBookmark) => {
+						if spell != "Judgement" {
+							return Err(SyntaxError{
+								message: format!("only Judgement can skip to a bookmark, not {}", spell),
+								span: skip.span.clone(),
+								suggestion: None,
+							});
+						}
+
+						self.index += 1;
+						let (bookmark, last) = self.expect_bookmark(skip)?;
+						(StatementKind::JudgeToBookmark(bookmark), last)
+					},
+					_ => (StatementKind::Cast(spell), last),
+				}
+			},
+			Keyphrase::KnockOverCauldron => (StatementKind::KnockOverCauldron, first),
+			Keyphrase::PublishSpellbook => (StatementKind::Publish(None), first),
+			Keyphrase::PublishSpellbookTo => {
+				let (target, last) = self.expect_string(first)?;
+				(StatementKind::Publish(Some(target)), last)
+			},
+			Keyphrase::SignChapterWith => {
+				let (signature, last) = self.expect_string(first)?;
+				(StatementKind::SignChapter(signature), last)
+			},
+			Keyphrase::SignAcknowledgementsPageWith => {
+				let (signature, last) = self.expect_string(first)?;
+				(StatementKind::SignAcknowledgements(signature), last)
+			},
+			Keyphrase::SlamSpellbookShut => (StatementKind::SlamShut, first),
+			Keyphrase::PlaceABookmarkNamed => {
+				let (name, last) = self.expect_name("a bookmark name", first)?;
+				(StatementKind::PlaceBookmark(name), last)
+			},
+			Keyphrase::FlipBackToBookmark | Keyphrase::FlipAheadToBookmark => {
+				let (bookmark, last) = self.expect_bookmark(first)?;
+				(StatementKind::Flip{ bookmark, back: keyphrase == Keyphrase::FlipBackToBookmark }, last)
+			},
+			_ => {
+				return Err(SyntaxError{
+					message: format!("expected the start of a statement, found {}", describe(&first.kind)),
+					span: first.span.clone(),
+					suggestion: None,
+				});
+			},
+		};
+
+		Ok(Statement{ kind, span: first.span.start..last.span.end })
+	}
+}
+
+pub fn parse_lexemes(lexemes: &[Lexeme]) -> Result<Vec<Statement>, SyntaxError> {
+	let mut parser = StatementParser{
+		lexemes: lexemes.iter().filter(|l| !l.is_trivia()).collect(),
+		index: 0,
+		line_end: lexemes.last().map_or(0, |l| l.span.end),
+	};
+
+	let mut statements = vec![];
+	while parser.peek().is_some() {
+		statements.push(parser.parse_statement()?);
+	}
+
+	Ok(statements)
+}
+
+pub fn parse_line(number: usize, text: &str) -> SourceLine {
+	let (lexemes, statements) = match lexer::lex_line(text) {
+		Ok(lexemes) => {
+			let statements = parse_lexemes(&lexemes);
+			(lexemes, statements)
+		},
+		Err(error) => (vec![], Err(SyntaxError{ message: error.message, span: error.span, suggestion: error.suggestion })),
+	};
+
+	SourceLine{ number, text: text.into(), lexemes, statements }
+}
+
+// Flipping can go either way, so bookmarks are only looked up once every line has been read.
+// The first bookmark with a name is the one that counts, and the rest are mistakes
+fn resolve_bookmarks(lines: &mut [SourceLine]) {
+	let mut placed = HashMap::new();
+	for index in 0..lines.len() {
+		let duplicate = lines[index].statements().iter().find_map(|statement| match &statement.kind {
+			StatementKind::PlaceBookmark(name) => match placed.get(name) {
+				Some(&first) => Some((name.clone(), statement.span.clone(), first)),
+				None => {
+					placed.insert(name.clone(), index);
+					None
+				},
+			},
+			_ => None,
+		});
+
+		if let Some((name, span, first)) = duplicate {
+			let message = format!("there's already a bookmark named `{}` on line {}", name, lines[first].number);
+			lines[index].statements = Err(SyntaxError{ message, span, suggestion: None });
+		}
+	}
+
+	let numbers = lines.iter().map(|l| l.number).collect::<Vec<usize>>();
+	for (index, line) in lines.iter_mut().enumerate() {
+		let mut wrong_way = None;
+		if let Ok(statements) = &mut line.statements {
+			for statement in statements.iter_mut() {
+				let (bookmark, back) = match &mut statement.kind {
+					StatementKind::Flip{ bookmark, back } => (bookmark, Some(*back)),
+					StatementKind::JudgeToBookmark(bookmark) => (bookmark, None),
+					_ => continue,
+				};
+
+				bookmark.line = placed.get(&bookmark.name).copied();
+				match (bookmark.line, back) {
+					(Some(target), Some(true)) if target > index => {
+						wrong_way = Some((bookmark.name.clone(), statement.span.clone(), target, "flip ahead to bookmark"));
+					},
+					(Some(target), Some(false)) if target <= index => {
+						wrong_way = Some((bookmark.name.clone(), statement.span.clone(), target, "flip back to bookmark"));
+					},
+					_ => continue,
+				}
+
+				break;
+			}
+		}
+
+		if let Some((name, span, target, instead)) = wrong_way {
+			let whereabouts = if target == index { "this line".into() } else { format!("line {}", numbers[target]) };
+			line.statements = Err(SyntaxError{
+				message: format!("the bookmark `{}` is on {}, so there's no flipping that way to it", name, whereabouts),
+				span,
+				suggestion: Some(format!("{} {}", instead, name)),
+			});
+		}
+	}
+}
+
+// Lines that aren't valid UTF-8 can't be read, so like blank lines they're left out, without
+// changing the numbers of the lines after them
+pub fn read_source(path: impl AsRef<Path>) -> io::Result<String> {
+	let bytes = fs::read(path)?;
+	Ok(bytes.split(|&b| b == b'\n').map(|line| str::from_utf8(line).unwrap_or("")).collect::<Vec<&str>>().join("\n"))
+}
+
+pub fn parse_source(source: &str) -> Spellbook {
+	let mut lines = source.lines().enumerate()
+		.filter(|(_, ln)| !ln.trim().is_empty())
+		.map(|(i, ln)| parse_line(i + 1, ln))
+		.collect::<Vec<SourceLine>>();
+
+	resolve_bookmarks(&mut lines);
+	Spellbook{ lines }
+}
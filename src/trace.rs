@@ -0,0 +1,341 @@
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+//        SPELLBOOK INTERPRETER
+//           BY DIANE SPARKS
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+
+use crate::analysis::StatementId;
+use crate::cauldron::CauldronMixMode;
+use crate::constants::*;
+use crate::errors::SpellbookError;
+use crate::interpreter::Interpreter;
+use crate::page::Page;
+use crate::program::Desk;
+use crate::syntax::*;
+use crate::variant::Variant;
+
+use serde_json::{json, Map, Value};
+
+use std::fmt::Display;
+use std::panic;
+
+pub fn variant_json(value: &Variant) -> Value {
+	match value {
+		Variant::Boolean(b) => json!({ "type": "boolean", "value": b }),
+		Variant::Integer(i) => json!({ "type": "integer", "value": i }),
+		Variant::Float(f) => json!({ "type": "float", "value": f }),
+		Variant::Str(s) => json!({ "type": "string", "value": s }),
+	}
+}
+
+fn entry_json(value: &Option<Variant>) -> Value {
+	value.as_ref().map_or(Value::Null, variant_json)
+}
+
+fn page_json(page: &Page) -> Value {
+	json!({
+		"chapter": BUILTINS_CHAPTERS[page.page_type.clone() as usize],
+		"entries": (0..3).map(|i| json!({ "heading": page.entry_names[i], "value": entry_json(&page.values[i]) })).collect::<Vec<_>>(),
+	})
+}
+
+fn written_json(chapter: &str, page: &Page, entry: usize) -> Value {
+	json!({ "chapter": chapter, "entry": entry, "heading": page.entry_names[entry], "value": entry_json(&page.values[entry]) })
+}
+
+fn destination_name(destination: &Destination) -> &'static str {
+	match destination {
+		Destination::Trash => "trash",
+		Destination::Drawer => "drawer",
+		Destination::Cauldron => "cauldron",
+	}
+}
+
+pub fn statement_json(kind: &StatementKind) -> Value {
+	match kind {
+		StatementKind::TurnToChapter(chapter) => json!({ "kind": "turn_to_chapter", "chapter": BUILTINS_CHAPTERS[*chapter] }),
+		StatementKind::TearOutChapter(destination) => json!({ "kind": "tear_out_chapter", "destination": destination_name(destination) }),
+		StatementKind::TakeOutFromDrawer => json!({ "kind": "take_out_from_drawer" }),
+		StatementKind::TakeOutFromCauldron => json!({ "kind": "take_out_from_cauldron" }),
+		StatementKind::Write{ source, heading } => {
+			let source = match source {
+				WriteSource::Literal(value) => variant_json(value),
+				WriteSource::Memory => json!("memory"),
+				WriteSource::Copy(from) => json!({ "heading": from }),
+			};
+
+			json!({ "kind": "write", "heading": heading, "source": source })
+		},
+		StatementKind::Memorize(source) => {
+			let source = match source {
+				MemorizeSource::Heading(heading) => json!({ "heading": heading }),
+				MemorizeSource::Literal(value) => variant_json(value),
+				MemorizeSource::Entry(index) => json!({ "entry": index }),
+			};
+
+			json!({ "kind": "memorize", "source": source })
+		},
+		StatementKind::Cast(spell) => json!({ "kind": "cast", "spell": spell }),
+		StatementKind::KnockOverCauldron => json!({ "kind": "knock_over_cauldron" }),
+		StatementKind::Publish(target) => json!({ "kind": "publish", "target": target }),
+		StatementKind::SignChapter(signature) => json!({ "kind": "sign_chapter", "signature": signature }),
+		StatementKind::SignAcknowledgements(signature) => json!({ "kind": "sign_acknowledgements", "signature": signature }),
+		StatementKind::SlamShut => json!({ "kind": "slam_shut" }),
+		StatementKind::If(condition) => {
+			let condition = match condition {
+				Condition::Heading(heading) => json!({ "heading": heading }),
+				Condition::Entry(index) => json!({ "entry": index }),
+				Condition::CauldronFull => json!("cauldron_full"),
+			};
+
+			json!({ "kind": "if", "condition": condition })
+		},
+		StatementKind::PlaceBookmark(name) => json!({ "kind": "place_bookmark", "bookmark": name }),
+		StatementKind::Flip{ bookmark, back } => json!({ "kind": "flip", "bookmark": bookmark.name, "direction": if *back { "back" } else { "ahead" } }),
+		StatementKind::JudgeToBookmark(bookmark) => json!({ "kind": "cast", "spell": "Judgement", "bookmark": bookmark.name }),
+	}
+}
+
+// Where the reader went after a statement, if it wasn't just on to the next one. Spells only
+// move the line, so when the statement ended its line the interpreter has already moved on
+fn jump_target(before: &Desk, after: &Desk, finished_line: bool) -> Option<usize> {
+	if after.exit || after.line_internal == before.line_internal.wrapping_add(finished_line as usize) {
+		return None;
+	}
+
+	Some(if finished_line { after.line_internal } else { after.line_internal.wrapping_add(1) })
+}
+
+fn effects(book: &Spellbook, statement: &Statement, before: &Desk, after: &Desk, finished_line: bool) -> Map<String, Value> {
+	let mut effects = Map::new();
+	let chapter = before.current_page;
+	match &statement.kind {
+		StatementKind::Write{ source, .. } => {
+			let source = match source {
+				WriteSource::Literal(value) => Some(value.clone()),
+				WriteSource::Memory => before.memory.clone(),
+				WriteSource::Copy(from) => before.pages[chapter].read_value_by_name(from),
+			};
+
+			// Pages only hold their own kind of value, and memory forgets
+			let new = &after.pages[chapter];
+			if let (Some(source), Some(&i)) = (source, new.written_since(&before.pages[chapter]).first()) {
+				if new.values[i].as_ref() != Some(&source) {
+					effects.insert("conversion".into(), json!({ "from": variant_json(&source), "to": entry_json(&new.values[i]) }));
+				}
+			}
+		},
+		StatementKind::TearOutChapter(destination) => {
+			effects.insert("page".into(), json!({ "from": BUILTINS_CHAPTERS[chapter], "to": destination_name(destination) }));
+		},
+		StatementKind::TakeOutFromDrawer => {
+			if let Some(page) = before.drawer.last() {
+				effects.insert("page".into(), json!({ "from": "drawer", "to": BUILTINS_CHAPTERS[page.page_type.clone() as usize] }));
+			}
+		},
+		StatementKind::TakeOutFromCauldron => {
+			if let Some(page) = &before.cauldron.page {
+				effects.insert("page".into(), json!({ "from": "cauldron", "to": BUILTINS_CHAPTERS[page.page_type.clone() as usize] }));
+			}
+		},
+		StatementKind::Cast(_) | StatementKind::JudgeToBookmark(_) => {
+			effects.insert("charge".into(), json!({ "before": before.cauldron.spell_charge, "after": after.cauldron.spell_charge }));
+		},
+		_ => {},
+	}
+
+	// Putting a page back writes everything on it, and Coadjuvancy writes into the cauldron's page.
+	// A page going into the cauldron is already told about as a page or a mix
+	let mut written = vec![];
+	for (chapter, (old, new)) in before.pages.iter().zip(&after.pages).enumerate() {
+		written.extend(new.written_since(old).into_iter().map(|i| written_json(BUILTINS_CHAPTERS[chapter], new, i)));
+	}
+
+	if let (Some(old), Some(new), false) = (&before.cauldron.page, &after.cauldron.page, matches!(statement.kind, StatementKind::TearOutChapter(_))) {
+		written.extend(new.written_since(old).into_iter().map(|i| written_json("cauldron", new, i)));
+	}
+
+	if !written.is_empty() {
+		effects.insert("written".into(), Value::Array(written));
+	}
+
+	let cauldron_values = |desk: &Desk| desk.cauldron.page.as_ref().map(|p| (p.entry_names.clone(), p.values.clone()));
+	if cauldron_values(before) != cauldron_values(after) {
+		effects.insert("cauldron".into(), after.cauldron.page.as_ref().map_or(Value::Null, page_json));
+	}
+
+	if before.memory != after.memory {
+		effects.insert("memory".into(), entry_json(&after.memory));
+	}
+
+	if let Some(target) = jump_target(before, after, finished_line) {
+		effects.insert("jump".into(), json!({ "to": book.lines.get(target).map(|l| l.number) }));
+	}
+
+	effects
+}
+
+fn statement_record(interpreter: &Interpreter, id: StatementId, before: &Desk, after: &Desk) -> Value {
+	let source_line = &interpreter.book.lines[id.0];
+	let mut record = json!({
+		"line": source_line.number,
+		"chapter": if before.turned_to_any_page { json!(BUILTINS_CHAPTERS[before.current_page]) } else { Value::Null },
+	});
+
+	match source_line.statements().get(id.1) {
+		Some(statement) => {
+			let finished_line = interpreter.position().is_none_or(|(_, statement)| statement == 0);
+			record["statement"] = statement_json(&statement.kind);
+			record["effects"] = Value::Object(effects(&interpreter.book, statement, before, after, finished_line));
+		},
+		None => record["statement"] = Value::Null,
+	}
+
+	record
+}
+
+fn show(value: &Option<Variant>) -> String {
+	match value {
+		Some(Variant::Str(s)) => format!("{:?}", s),
+		Some(Variant::Float(f)) => format!("{:?}", f),
+		Some(value) => value.print(),
+		None => "—".into(),
+	}
+}
+
+fn show_page(page: &Page) -> String {
+	format!("[{}]", page.values.iter().map(show).collect::<Vec<String>>().join(", "))
+}
+
+fn mixing_spell(mix_mode: &CauldronMixMode) -> &'static str {
+	match mix_mode {
+		CauldronMixMode::Add => "Entwinement",
+		CauldronMixMode::Sub => "Belittlement",
+		CauldronMixMode::Mul => "Reenactment",
+		CauldronMixMode::Div => "Apportionment",
+	}
+}
+
+fn changes(book: &Spellbook, statement: &Statement, before: &Desk, after: &Desk, finished_line: bool) -> Vec<String> {
+	let mut changes = vec![];
+	if after.turned_to_any_page && (!before.turned_to_any_page || before.current_page != after.current_page) {
+		changes.push(format!("turned to {}", BUILTINS_CHAPTERS[after.current_page]));
+	}
+
+	let torn_out = matches!(statement.kind, StatementKind::TearOutChapter(_));
+	for (chapter, (old, new)) in before.pages.iter().zip(&after.pages).enumerate() {
+		for i in 0..3 {
+			if old.entry_names[i] == new.entry_names[i] && old.values[i] == new.values[i] {
+				continue;
+			}
+
+			// A page put back over another can bring a different heading with it
+			let (old_heading, new_heading) = (&old.entry_names[i], &new.entry_names[i]);
+			let heading = match (old_heading.is_empty(), new_heading.is_empty()) {
+				(true, true) => String::new(),
+				(false, false) if old_heading != new_heading => format!(" {} → {}", old_heading, new_heading),
+				(_, true) => format!(" {}", old_heading),
+				_ => format!(" {}", new_heading),
+			};
+			let value = if torn_out && new.values[i].is_none() { "(torn out)".into() } else { show(&new.values[i]) };
+			changes.push(format!("{}[{}]{}: {} → {}", BUILTINS_CHAPTERS[chapter], i, heading, show(&old.values[i]), value));
+		}
+	}
+
+	match (&before.cauldron.page, &after.cauldron.page) {
+		(None, Some(page)) => {
+			changes.push(format!("cauldron: {} page tossed in → {}", BUILTINS_CHAPTERS[page.page_type.clone() as usize], show_page(page)));
+		},
+		(Some(_), None) => {
+			changes.push("cauldron: emptied".into());
+		},
+		(Some(_), Some(new)) if statement.kind == StatementKind::TearOutChapter(Destination::Cauldron) => {
+			let chapter = BUILTINS_CHAPTERS[before.current_page];
+			changes.push(format!("cauldron: {} page mixed with {} → {}", chapter, mixing_spell(&after.cauldron.mix_mode), show_page(new)));
+		},
+		(Some(old), Some(new)) if old.entry_names != new.entry_names || old.values != new.values => {
+			changes.push(format!("cauldron: {} → {}", show_page(old), show_page(new)));
+		},
+		_ => {},
+	}
+
+	let (old, new) = (&before.cauldron, &after.cauldron);
+	if old.spell_charge != new.spell_charge {
+		changes.push(format!("charge {} → {}", old.spell_charge, new.spell_charge));
+	}
+
+	if old.spell_charge_amplifier != new.spell_charge_amplifier {
+		changes.push(format!("amplifier {} → {}", old.spell_charge_amplifier, new.spell_charge_amplifier));
+	}
+
+	if old.mix_mode != new.mix_mode {
+		changes.push(format!("mixing with {} → {}", mixing_spell(&old.mix_mode), mixing_spell(&new.mix_mode)));
+	}
+
+	if before.drawer.len() != after.drawer.len() {
+		changes.push(format!("drawer depth {} → {}", before.drawer.len(), after.drawer.len()));
+	}
+
+	if before.memory != after.memory {
+		changes.push(format!("memory: {} → {}", show(&before.memory), show(&after.memory)));
+	}
+
+	if let Some(target) = jump_target(before, after, finished_line) {
+		match book.lines.get(target) {
+			Some(line) => changes.push(format!("jumped to line {}", line.number)),
+			None => changes.push("jumped past the last line".into()),
+		}
+	}
+
+	changes
+}
+
+fn statement_changes(interpreter: &Interpreter, id: StatementId, before: &Desk, after: &Desk) -> String {
+	let source_line = &interpreter.book.lines[id.0];
+	let statement = match source_line.statements().get(id.1) {
+		Some(statement) => statement,
+		None => return format!("{:>4} | {}", source_line.number, source_line.text.trim()),
+	};
+
+	let finished_line = interpreter.position().is_none_or(|(_, statement)| statement == 0);
+	let mut text = format!("{:>4} | {}", source_line.number, &source_line.text[statement.span.clone()]);
+	for change in changes(&interpreter.book, statement, before, after, finished_line) {
+		text.push_str(&format!("\n       {}", change));
+	}
+
+	text
+}
+
+fn trace<T: Display>(mut interpreter: Interpreter, record: fn(&Interpreter, StatementId, &Desk, &Desk) -> T) {
+	while !interpreter.is_finished() {
+		let id = interpreter.position().unwrap();
+		let before = interpreter.program.save_desk();
+		if let Err(line) = interpreter.try_step(Interpreter::step_statement) {
+			panic::resume_unwind(Box::new(line));
+		}
+
+		let after = interpreter.program.save_desk();
+		eprintln!("{}", record(&interpreter, id, &before, &after));
+	}
+}
+
+// Prints one JSON object per statement to stderr. Nothing that changes between runs goes in
+// them, so the traces of two runs can be diffed to find where they went different ways
+pub fn run_jsonl(interpreter: Interpreter) {
+	panic::set_hook(Box::new(|info| {
+		let line = match info.payload().downcast_ref::<SpellbookError>() {
+			Some(error) => Some(error.line),
+			None => info.payload().downcast_ref::<usize>().cloned(),
+		};
+
+		if let Some(line) = line {
+			eprintln!("{}", json!({ "line": line, "catastrophe": true }));
+		}
+	}));
+
+	trace(interpreter, statement_record);
+}
+
+// Prints each statement followed by only the parts of the desk it changed
+pub fn run_diff(interpreter: Interpreter) {
+	trace(interpreter, statement_changes);
+}
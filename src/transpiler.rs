@@ -0,0 +1,360 @@
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+//        SPELLBOOK INTERPRETER
+//           BY DIANE SPARKS
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+
+use crate::analysis::{self, Target};
+use crate::constants::*;
+use crate::syntax::*;
+use crate::variant::Variant;
+
+use std::fmt::Write;
+
+const RUST_RUNTIME: &str = include_str!("runtime/runtime.rs");
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum RegionKind {
+	// Lines read again when one of them goes back to the first
+	Loop,
+	// Lines left early when one of them skips to the line after the last
+	Block,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Region {
+	kind: RegionKind,
+	start: usize,
+	end: usize,
+}
+
+// How the transpiled spellbook gets from one line to the next
+enum Layout {
+	// Where every line can go once it's finished, and the loops and blocks that get it there
+	Structured(Vec<Vec<Target>>, Vec<Region>),
+	StateMachine,
+}
+
+fn natural_target(book: &Spellbook, index: usize) -> Target {
+	if index + 1 < book.lines.len() { Target::Line(index + 1) } else { Target::End }
+}
+
+// Loops and blocks can only be nested, never crossed, so they're stretched until they fit
+// inside each other. Skipping into the middle of a loop can't be done at all
+fn nest(mut regions: Vec<Region>) -> Option<Vec<Region>> {
+	loop {
+		let mut changed = false;
+		for a in 0..regions.len() {
+			for b in 0..regions.len() {
+				let (first, second) = (regions[a], regions[b]);
+				if !(first.start < second.start && second.start <= first.end && first.end < second.end) {
+					continue;
+				}
+
+				match (first.kind, second.kind) {
+					(_, RegionKind::Block) => regions[b].start = first.start,
+					(RegionKind::Loop, RegionKind::Loop) => regions[a].end = second.end,
+					(RegionKind::Block, RegionKind::Loop) => return None,
+				}
+
+				changed = true;
+			}
+		}
+
+		if !changed {
+			break;
+		}
+	}
+
+	regions.sort_by_key(|r| (r.start, usize::MAX - r.end, r.kind == RegionKind::Loop));
+	Some(regions)
+}
+
+fn layout(book: &Spellbook) -> Layout {
+	let analysis = analysis::analyze(book);
+	if book.lines.is_empty() || analysis.edges.iter().any(|e| e.dynamic) {
+		return Layout::StateMachine;
+	}
+
+	let mut targets = vec![vec![]; book.lines.len()];
+	for edge in &analysis.edges {
+		if !targets[edge.from.0].contains(&edge.to) {
+			targets[edge.from.0].push(edge.to);
+		}
+	}
+
+	let mut regions: Vec<Region> = vec![];
+	for (index, line_targets) in targets.iter().enumerate() {
+		for target in line_targets {
+			let (kind, start, end) = match target {
+				Target::Line(line) if *line <= index => (RegionKind::Loop, *line, index),
+				Target::Line(line) if *line > index + 1 => (RegionKind::Block, index, *line - 1),
+				_ => continue,
+			};
+
+			// Every loop goes back to its first line and every block ends right before a line
+			match regions.iter_mut().find(|r| r.kind == kind && if kind == RegionKind::Loop { r.start == start } else { r.end == end }) {
+				Some(region) => {
+					region.start = region.start.min(start);
+					region.end = region.end.max(end);
+				},
+				None => regions.push(Region{ kind, start, end }),
+			}
+		}
+	}
+
+	match nest(regions) {
+		Some(regions) => Layout::Structured(targets, regions),
+		None => Layout::StateMachine,
+	}
+}
+
+fn rust_value(value: &Variant) -> String {
+	match value {
+		Variant::Boolean(b) => format!("Boolean({})", b),
+		Variant::Integer(i) if *i == i64::MIN => "Integer(i64::MIN)".into(),
+		Variant::Integer(i) => format!("Integer({})", i),
+		Variant::Float(f) if f.is_nan() => "Float(f64::NAN)".into(),
+		Variant::Float(f) if f.is_infinite() => format!("Float({}f64::INFINITY)", if *f < 0.0 { "-" } else { "" }),
+		Variant::Float(f) => format!("Float({:?})", f),
+		Variant::Str(s) => format!("Str({:?}.into())", s),
+	}
+}
+
+// Bookmarks go by the number of the line they were placed on, just like lines
+fn rust_bookmark(book: &Spellbook, bookmark: &Bookmark) -> String {
+	bookmark.line.map_or("NO_BOOKMARK".into(), |line| book.lines[line].number.to_string())
+}
+
+fn rust_statement(book: &Spellbook, statement: &Statement) -> String {
+	match &statement.kind {
+		StatementKind::TurnToChapter(chapter) => format!("desk.turn_to(Chapter::{});", BUILTINS_CHAPTERS[*chapter]),
+		StatementKind::TearOutChapter(Destination::Trash) => "desk.tear_out_into_trash();".into(),
+		StatementKind::TearOutChapter(Destination::Drawer) => "desk.tear_out_into_drawer();".into(),
+		StatementKind::TearOutChapter(Destination::Cauldron) => "desk.tear_out_into_cauldron();".into(),
+		StatementKind::TakeOutFromDrawer => "desk.take_out_from_drawer();".into(),
+		StatementKind::TakeOutFromCauldron => "desk.take_out_from_cauldron();".into(),
+		StatementKind::Write{ source, heading } => match source {
+			WriteSource::Literal(value) => format!("desk.write({:?}, {});", heading, rust_value(value)),
+			WriteSource::Memory => format!("desk.write_from_memory({:?});", heading),
+			WriteSource::Copy(from) => format!("desk.copy({:?}, {:?});", from, heading),
+		},
+		StatementKind::Memorize(source) => match source {
+			MemorizeSource::Heading(heading) => format!("desk.memorize({:?});", heading),
+			MemorizeSource::Literal(value) => format!("desk.memorize_value({});", rust_value(value)),
+			MemorizeSource::Entry(index) => format!("desk.memorize_entry({});", index),
+		},
+		StatementKind::Cast(spell) if BUILTINS_SPELLS.contains_key(spell.as_str()) => format!("desk.cast(Spell::{});", spell),
+		StatementKind::Cast(_) => "desk.catastrophe();".into(),
+		StatementKind::KnockOverCauldron => "desk.knock_over_cauldron();".into(),
+		StatementKind::Publish(Some(target)) => format!("desk.publish_to({:?});", target),
+		StatementKind::Publish(None) => "desk.publish();".into(),
+		StatementKind::SignChapter(signature) => format!("desk.sign_chapter({:?});", signature),
+		StatementKind::SignAcknowledgements(signature) => format!("desk.sign_acknowledgements({:?});", signature),
+		StatementKind::SlamShut => "desk.slam_shut();".into(),
+		StatementKind::If(Condition::Heading(heading)) => format!("if desk.presage_heading({:?}) {{", heading),
+		StatementKind::If(Condition::Entry(index)) => format!("if desk.presage_entry({}) {{", index),
+		StatementKind::If(Condition::CauldronFull) => "if desk.cauldron_is_full() {".into(),
+		StatementKind::PlaceBookmark(name) => format!("// bookmark {}", name),
+		StatementKind::Flip{ bookmark, .. } => format!("desk.flip_to({});", rust_bookmark(book, bookmark)),
+		StatementKind::JudgeToBookmark(bookmark) => format!("desk.cast_judgement_to({});", rust_bookmark(book, bookmark)),
+	}
+}
+
+// The line as it was written, then what it does
+fn write_line(output: &mut String, book: &Spellbook, line: &SourceLine, indent: usize, start: bool) {
+	let tabs = "\t".repeat(indent);
+	writeln!(output, "{}// {}", tabs, line.text.trim()).unwrap();
+	if start {
+		writeln!(output, "{}desk.start_line({});", tabs, line.number).unwrap();
+	}
+
+	match &line.statements {
+		Ok(statements) => {
+			// Whatever comes after an `if` on the line goes inside it
+			let mut depth = indent;
+			for statement in statements {
+				writeln!(output, "{}{}", "\t".repeat(depth), rust_statement(book, statement)).unwrap();
+				if matches!(statement.kind, StatementKind::If(_)) {
+					depth += 1;
+				}
+			}
+
+			while depth > indent {
+				depth -= 1;
+				writeln!(output, "{}}}", "\t".repeat(depth)).unwrap();
+			}
+		},
+		Err(_) => writeln!(output, "{}desk.catastrophe();", tabs).unwrap(),
+	}
+}
+
+fn region_label(book: &Spellbook, region: &Region) -> String {
+	match region.kind {
+		RegionKind::Loop => format!("'line_{}", book.lines[region.start].number),
+		RegionKind::Block => format!("'skip_to_line_{}", book.lines[region.end + 1].number),
+	}
+}
+
+fn write_structured(output: &mut String, book: &Spellbook, targets: &[Vec<Target>], regions: &[Region]) {
+	let mut open: Vec<&Region> = vec![];
+	for (index, line) in book.lines.iter().enumerate() {
+		for region in regions.iter().filter(|r| r.start == index) {
+			let tabs = "\t".repeat(open.len() + 1);
+			match region.kind {
+				RegionKind::Loop => writeln!(output, "{}{}: loop {{", tabs, region_label(book, region)).unwrap(),
+				RegionKind::Block => writeln!(output, "{}{}: {{", tabs, region_label(book, region)).unwrap(),
+			}
+
+			open.push(region);
+		}
+
+		let indent = open.len() + 1;
+		write_line(output, book, line, indent, true);
+
+		let natural = natural_target(book, index);
+		let falls_through = targets[index].contains(&Target::Line(index + 1));
+		if targets[index].iter().any(|t| *t != natural) {
+			let tabs = "\t".repeat(indent);
+			let mut arms = targets[index].clone();
+			arms.sort_by_key(|t| match t {
+				Target::Line(line) => *line,
+				_ => usize::MAX,
+			});
+
+			// Going past the last line is a catastrophe, which finishing the line sees to
+			arms.retain(|t| *t != Target::Catastrophe);
+			if arms.is_empty() {
+				writeln!(output, "{}desk.finish_line();", tabs).unwrap();
+				writeln!(output, "{}unreachable!();", tabs).unwrap();
+			} else {
+				writeln!(output, "{}match desk.finish_line() {{", tabs).unwrap();
+				for target in arms {
+					let arm = match target {
+						Target::Line(target) if target == index + 1 => "{}".into(),
+						Target::Line(target) if target <= index => format!("continue 'line_{}", book.lines[target].number),
+						Target::Line(target) => format!("break 'skip_to_line_{}", book.lines[target].number),
+						_ => "return".into(),
+					};
+
+					match target {
+						Target::Line(target) => writeln!(output, "{}\tSome({}) => {},", tabs, book.lines[target].number, arm).unwrap(),
+						_ => writeln!(output, "{}\tNone => {},", tabs, arm).unwrap(),
+					}
+				}
+
+				writeln!(output, "{}\t_ => unreachable!(),", tabs).unwrap();
+				writeln!(output, "{}}}", tabs).unwrap();
+			}
+		}
+
+		// Nothing gets past a line that always jumps somewhere, so a loop ending there is only
+		// left if a block inside it skips to its end
+		let mut reached = falls_through;
+		while open.last().is_some_and(|r| r.end == index) {
+			let region = open.pop().unwrap();
+			let tabs = "\t".repeat(open.len() + 1);
+			if region.kind == RegionKind::Block {
+				reached = true;
+			} else if reached {
+				writeln!(output, "{}\tbreak {};", tabs, region_label(book, region)).unwrap();
+			}
+
+			writeln!(output, "{}}}", tabs).unwrap();
+		}
+
+		if index + 1 < book.lines.len() {
+			output.push('\n');
+		}
+	}
+}
+
+fn write_state_machine(output: &mut String, book: &Spellbook) {
+	output.push_str("\tlet mut line = LINES.first().copied();\n");
+	output.push_str("\twhile let Some(number) = line {\n");
+	output.push_str("\t\tdesk.start_line(number);\n");
+	output.push_str("\t\tmatch number {\n");
+	for line in &book.lines {
+		writeln!(output, "\t\t\t{} => {{", line.number).unwrap();
+		write_line(output, book, line, 4, false);
+		output.push_str("\t\t\t},\n");
+	}
+
+	output.push_str("\t\t\t_ => unreachable!(),\n");
+	output.push_str("\t\t}\n\n");
+	output.push_str("\t\tline = desk.finish_line();\n");
+	output.push_str("\t}\n");
+}
+
+// A Rust program that does what the spellbook does, with the runtime tucked away at the end.
+// Judgement and Reverberate become loops and blocks when it's known where they can go,
+// and otherwise every line is a case of a match that's read over and over
+pub fn transpile(book: &Spellbook) -> String {
+	let statements = book.lines.iter().flat_map(|l| l.statements()).collect::<Vec<&Statement>>();
+	let mut imports = vec![];
+	if statements.iter().any(|s| matches!(s.kind, StatementKind::TurnToChapter(_))) {
+		imports.push("Chapter");
+	}
+
+	imports.push("Desk");
+	if statements.iter().any(|s| s.bookmark().is_some_and(|b| b.line.is_none())) {
+		imports.push("NO_BOOKMARK");
+	}
+
+	if statements.iter().any(|s| matches!(&s.kind, StatementKind::Cast(spell) if BUILTINS_SPELLS.contains_key(spell.as_str()))) {
+		imports.push("Spell");
+	}
+
+	if statements.iter().any(|s| matches!(s.kind, StatementKind::Write{ source: WriteSource::Literal(_), .. } | StatementKind::Memorize(MemorizeSource::Literal(_)))) {
+		imports.push("Variant::*");
+	}
+
+	let mut output = String::new();
+	output.push_str("// Transpiled from a spellbook by `spellbook compile --target rust`, with every line\n");
+	output.push_str("// of the spellbook written above what it turned into.\n\n");
+	writeln!(output, "use runtime::{{{}}};\n", imports.join(", ")).unwrap();
+	let numbers = book.lines.iter().map(|l| l.number.to_string()).collect::<Vec<String>>();
+	writeln!(output, "const LINES: [usize; {}] = [{}];\n", numbers.len(), numbers.join(", ")).unwrap();
+
+	// Catastrophes never come back, so whatever's after them on the same line is never read.
+	// Neither is a line after a loop that's never left
+	let layout = layout(book);
+	let catastrophes = book.lines.iter().any(|l| l.statements.is_err())
+		|| statements.iter().any(|s| s.spell().is_some_and(|spell| !BUILTINS_SPELLS.contains_key(spell)));
+	let stranded = match &layout {
+		Layout::Structured(targets, _) => (1..book.lines.len()).any(|i| !targets.iter().any(|t| t.contains(&Target::Line(i)))),
+		Layout::StateMachine => false,
+	};
+
+	if catastrophes || stranded {
+		output.push_str("#[allow(unreachable_code)]\n");
+	}
+
+	output.push_str("fn main() {\n");
+	output.push_str("\tlet mut desk = Desk::new(&LINES);\n");
+	match layout {
+		Layout::Structured(targets, regions) => {
+			output.push('\n');
+			write_structured(&mut output, book, &targets, &regions);
+		},
+		Layout::StateMachine => write_state_machine(&mut output, book),
+	}
+
+	output.push_str("}\n\n");
+	output.push_str("#[allow(dead_code)]\n");
+	output.push_str("mod runtime {\n");
+	for line in RUST_RUNTIME.lines() {
+		if line.is_empty() {
+			output.push('\n');
+		} else {
+			writeln!(output, "\t{}", line).unwrap();
+		}
+	}
+
+	output.push('\n');
+	let messages = ERROR_MESSAGES[..10].iter().map(|m| format!("\t\t{:?},\n", m)).collect::<String>();
+	writeln!(output, "\tconst ERROR_MESSAGES: [&str; 10] = [\n{}\t];\n", messages).unwrap();
+	writeln!(output, "\tconst DEFAULT_WRAPUP: &str = {:?};", DEFAULT_WRAPUP).unwrap();
+	writeln!(output, "\tconst DEFAULT_WRAPUP_QED: &str = {:?};", DEFAULT_WRAPUP_QED).unwrap();
+	output.push_str("}\n");
+	output
+}
@@ -3,6 +3,11 @@
 //           BY DIANE SPARKS
 // *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
 
+use crate::profiler;
+
+// Longer than any spellbook could mean a string to be, but short enough to still fit in memory
+const MAX_STRING_BYTES: usize = 1 << 28;
+
 #[derive(Debug, PartialEq, PartialOrd, Clone)]
 pub enum Variant {
 	Boolean(bool),
@@ -89,10 +94,15 @@ impl Variant {
 		if let (Some(left), Some(right)) = (self.try_unwrap_bool(), rhs.try_unwrap_bool()) {
 			Some(Variant::Boolean(left || right))
 		} else if let (Some(left), Some(right)) = (self.try_unwrap_int(), rhs.try_unwrap_int()) {
-			Some(Variant::Integer(left + right))
+			left.checked_add(right).map(Variant::Integer)
 		} else if let (Some(left), Some(right)) = (self.try_unwrap_float(), rhs.try_unwrap_float()) {
 			Some(Variant::Float(left + right))
 		} else if let (Some(left), Some(right)) = (self.try_unwrap_string(), rhs.try_unwrap_string()) {
+			if left.len() + right.len() > MAX_STRING_BYTES {
+				return None;
+			}
+
+			profiler::count_bytes(&profiler::ADD_BYTES, left.len() + right.len());
 			Some(Variant::Str(left + &right))
 		} else {
 			None
@@ -101,12 +111,13 @@ impl Variant {
 
 	pub fn sub(&self, rhs: Variant) -> Option<Variant> {
 		if let (Some(left), Some(right)) = (self.try_unwrap_int(), rhs.try_unwrap_int()) {
-			Some(Variant::Integer(left - right))
+			left.checked_sub(right).map(Variant::Integer)
 		} else if let (Some(left), Some(right)) = (self.try_unwrap_float(), rhs.try_unwrap_float()) {
 			Some(Variant::Float(left - right))
 		} else if let (Some(left), Some(right)) = (self.try_unwrap_string(), rhs.try_unwrap_int()) {
-			if right >= 0 {
-				Some(Variant::Str(left[..left.len() - right as usize].to_string()))
+			let length = left.chars().count();
+			if right >= 0 && right as usize <= length {
+				Some(Variant::Str(left.chars().take(length - right as usize).collect()))
 			} else {
 				None
 			}
@@ -124,14 +135,16 @@ impl Variant {
 
 	pub fn mul(&self, rhs: Variant) -> Option<Variant> {
 		if let (Some(left), Some(right)) = (self.try_unwrap_int(), rhs.try_unwrap_int()) {
-			Some(Variant::Integer(left * right))
+			left.checked_mul(right).map(Variant::Integer)
 		} else if let (Some(left), Some(right)) = (self.try_unwrap_float(), rhs.try_unwrap_float()) {
 			Some(Variant::Float(left * right))
 		} else if let (Some(left), Some(right)) = (self.try_unwrap_string(), rhs.try_unwrap_int()) {
-			if right >= 0 {
-				Some(Variant::Str(left.repeat(right as usize)))
-			} else {
-				None
+			match left.len().checked_mul(right as usize) {
+				Some(bytes) if right >= 0 && bytes <= MAX_STRING_BYTES => {
+					profiler::count_bytes(&profiler::MUL_BYTES, bytes);
+					Some(Variant::Str(left.repeat(right as usize)))
+				},
+				_ => None,
 			}
 		} else {
 			None
@@ -140,7 +153,7 @@ impl Variant {
 
 	pub fn div(&self, rhs: Variant) -> Option<Variant> {
 		if let (Some(left), Some(right)) = (self.try_unwrap_int(), rhs.try_unwrap_int()) {
-			Some(Variant::Integer(left / right))
+			left.checked_div(right).map(Variant::Integer)
 		} else if let (Some(left), Some(right)) = (self.try_unwrap_float(), rhs.try_unwrap_float()) {
 			Some(Variant::Float(left / right))
 		} else {
@@ -151,7 +164,7 @@ impl Variant {
 	pub fn inverted(&self) -> Option<Variant> {
 		match self {
 			Variant::Boolean(b) => Some(Variant::Boolean(!b)),
-			Variant::Integer(i) => Some(Variant::Integer(-i)),
+			Variant::Integer(i) => i.checked_neg().map(Variant::Integer),
 			Variant::Float(f) => Some(Variant::Float(-f)),
 			Variant::Str(s) => Some(Variant::Str(s.chars().rev().collect())),
 		}
@@ -0,0 +1,335 @@
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+//        SPELLBOOK INTERPRETER
+//           BY DIANE SPARKS
+// *~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*~*
+
+use crate::cauldron::CauldronMixMode;
+use crate::constants::*;
+use crate::errors::{self, set_catastrophe_hook};
+use crate::interpreter::Interpreter;
+use crate::page::Page;
+use crate::program::{CapturedOutput, Program};
+use crate::syntax;
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+use rand::prelude::*;
+
+use std::fs;
+use std::io::{self, BufWriter, Cursor, Write};
+use std::panic;
+use std::time::Duration;
+
+// Statements read per second while playing
+const SPEEDS: [u64; 9] = [1, 2, 4, 8, 16, 32, 64, 128, 256];
+const MIN_WIDTH: u16 = 72;
+const MIN_HEIGHT: u16 = 24;
+const CHARGE_METER: usize = 10;
+
+// Puts the terminal back the way it was, however the visualizer ends
+struct Fullscreen;
+
+impl Fullscreen {
+	fn enter() -> io::Result<Self> {
+		terminal::enable_raw_mode()?;
+		execute!(io::stdout(), EnterAlternateScreen, Hide)?;
+		Ok(Self)
+	}
+}
+
+impl Drop for Fullscreen {
+	fn drop(&mut self) {
+		let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+		let _ = terminal::disable_raw_mode();
+	}
+}
+
+fn clip(text: &str, width: u16) -> String {
+	text.chars().map(|c| if c.is_control() { ' ' } else { c }).take(width as usize).collect()
+}
+
+fn mixing_spell(mix_mode: &CauldronMixMode) -> &'static str {
+	match mix_mode {
+		CauldronMixMode::Add => "Entwinement",
+		CauldronMixMode::Sub => "Belittlement",
+		CauldronMixMode::Mul => "Reenactment",
+		CauldronMixMode::Div => "Apportionment",
+	}
+}
+
+struct Visualizer<W: Write> {
+	interpreter: Interpreter,
+	source: Vec<String>,
+	output: CapturedOutput,
+	published: String,
+	playing: bool,
+	speed: usize,
+	catastrophe: Option<usize>,
+	out: W,
+}
+
+impl<W: Write> Visualizer<W> {
+	fn text(&mut self, x: u16, y: u16, width: u16, text: &str) -> io::Result<()> {
+		queue!(self.out, MoveTo(x, y), Print(clip(text, width)))
+	}
+
+	fn frame(&mut self, x: u16, y: u16, width: u16, height: u16, title: &str, rounded: bool) -> io::Result<()> {
+		let (top_left, top_right, bottom_left, bottom_right) = if rounded { ('╭', '╮', '╰', '╯') } else { ('┌', '┐', '└', '┘') };
+		let title = clip(&format!(" {} ", title), width.saturating_sub(4));
+		let rest = (width as usize).saturating_sub(title.chars().count() + 3);
+		self.text(x, y, width, &format!("{}─{}{}{}", top_left, title, "─".repeat(rest), top_right))?;
+		for row in 1..height - 1 {
+			self.text(x, y + row, 1, "│")?;
+			self.text(x + width - 1, y + row, 1, "│")?;
+		}
+
+		self.text(x, y + height - 1, width, &format!("{}{}{}", bottom_left, "─".repeat(width as usize - 2), bottom_right))
+	}
+
+	fn slots(&mut self, x: u16, y: u16, width: u16, page: &Page) -> io::Result<()> {
+		for i in 0..3 {
+			let slot = if page.values[i].is_some() || !page.entry_names[i].is_empty() {
+				format!("{}: {}", page.entry_label(i), page.entry_text(i))
+			} else {
+				"·".into()
+			};
+
+			self.text(x, y + i as u16, width, &slot)?;
+		}
+
+		Ok(())
+	}
+
+	fn draw_source(&mut self, width: u16, height: u16) -> io::Result<()> {
+		self.frame(0, 0, width, height, "spellbook", false)?;
+		let current = self.interpreter.position().map(|(line, _)| self.interpreter.book.lines[line].number);
+		let rows = height as usize - 2;
+		let top = current.map_or(0, |line| line.saturating_sub(rows / 2 + 1)).min(self.source.len().saturating_sub(rows));
+		for row in 0..rows.min(self.source.len() - top) {
+			let number = top + row + 1;
+			let line = format!("{:>4} {}", number, self.source[top + row]);
+			if Some(number) == current {
+				queue!(self.out, SetAttribute(Attribute::Reverse))?;
+				self.text(1, row as u16 + 1, width - 2, &format!("{:<1$}", line, width as usize - 2))?;
+				queue!(self.out, SetAttribute(Attribute::Reset))?;
+			} else {
+				self.text(1, row as u16 + 1, width - 2, &line)?;
+			}
+		}
+
+		Ok(())
+	}
+
+	fn draw_desk(&mut self, x: u16, width: u16, height: u16) -> io::Result<()> {
+		let program = &self.interpreter.program;
+		let pages = program.pages.clone();
+		let turned_to = if program.turned_to_any_page { Some(program.current_page) } else { None };
+		let cauldron = program.cauldron.clone();
+		let drawer = program.drawer.clone();
+		let memory = program.memory.as_ref().map_or("nothing".into(), |m| m.print());
+
+		// Chapters as cards, two to a row
+		let card_width = width / 2;
+		for (chapter, page) in pages.iter().enumerate() {
+			let (card_x, card_y) = (x + (chapter as u16 % 2) * card_width, (chapter as u16 / 2) * 5);
+			let title = if turned_to == Some(chapter) { format!("{} ◂", BUILTINS_CHAPTERS[chapter]) } else { BUILTINS_CHAPTERS[chapter].to_string() };
+			if turned_to == Some(chapter) {
+				queue!(self.out, SetForegroundColor(Color::Yellow))?;
+			}
+
+			self.frame(card_x, card_y, card_width, 5, &title, false)?;
+			queue!(self.out, ResetColor)?;
+			self.slots(card_x + 2, card_y + 1, card_width - 4, page)?;
+		}
+
+		// The cauldron and the drawer side by side
+		queue!(self.out, SetForegroundColor(Color::Magenta))?;
+		self.frame(x, 10, card_width, 7, "cauldron", false)?;
+		queue!(self.out, ResetColor)?;
+		match &cauldron.page {
+			Some(page) => {
+				self.text(x + 2, 11, card_width - 4, &format!("{} page", BUILTINS_CHAPTERS[page.page_type.clone() as usize]))?;
+				self.slots(x + 2, 12, card_width - 4, page)?;
+			},
+			None => self.text(x + 2, 11, card_width - 4, "empty")?,
+		}
+
+		let filled = cauldron.spell_charge.min(CHARGE_METER);
+		let meter = format!("{}{} {} ×{}", "▰".repeat(filled), "▱".repeat(CHARGE_METER - filled), cauldron.spell_charge, cauldron.spell_charge_amplifier);
+		self.text(x + 2, 15, card_width - 4, &format!("{} · {}", meter, mixing_spell(&cauldron.mix_mode)))?;
+
+		self.frame(x + card_width, 10, card_width, 7, &format!("drawer ({})", drawer.len()), false)?;
+		for (row, page) in drawer.iter().rev().take(5).enumerate() {
+			let top = if row == 0 { "▸" } else { " " };
+			let line = format!("{} {} {}", top, BUILTINS_CHAPTERS[page.page_type.clone() as usize], page.describe());
+			self.text(x + card_width + 2, 11 + row as u16, card_width - 4, &line)?;
+		}
+
+		// Memory floats in its bubble
+		queue!(self.out, SetForegroundColor(Color::Cyan))?;
+		self.frame(x, 17, width, 3, "memory", true)?;
+		queue!(self.out, ResetColor)?;
+		self.text(x + 2, 18, width - 4, &memory)?;
+
+		// And whatever was published underneath
+		let rows = height.saturating_sub(22);
+		self.frame(x, 20, width, rows + 2, "published", false)?;
+		let published = self.published.lines().rev().take(rows as usize).collect::<Vec<&str>>().into_iter().rev().map(String::from).collect::<Vec<String>>();
+		for (row, line) in published.iter().enumerate() {
+			self.text(x + 2, 21 + row as u16, width - 4, line)?;
+		}
+
+		Ok(())
+	}
+
+	fn draw(&mut self) -> io::Result<()> {
+		let (width, height) = terminal::size()?;
+		queue!(self.out, Clear(ClearType::All))?;
+		if width < MIN_WIDTH || height < MIN_HEIGHT {
+			self.text(0, 0, width, &format!("The desk needs at least {}×{} to fit. Press q to quit.", MIN_WIDTH, MIN_HEIGHT))?;
+			return self.out.flush();
+		}
+
+		let source_width = width * 2 / 5;
+		self.draw_source(source_width, height - 1)?;
+		self.draw_desk(source_width, width - source_width, height - 1)?;
+
+		let state = match (self.catastrophe, self.interpreter.is_finished(), self.playing) {
+			(Some(line), _, _) => format!("Catastrophe! (Line {})", line),
+			(None, true, _) => "The spellbook has been closed.".into(),
+			(None, false, true) => "▶ playing".into(),
+			(None, false, false) => "⏸ paused".into(),
+		};
+
+		let status = format!("{}  ·  {} per second  ·  space play/pause  s step  +/- speed  q quit", state, SPEEDS[self.speed]);
+		queue!(self.out, SetAttribute(Attribute::Reverse))?;
+		self.text(0, height - 1, width, &format!("{:<1$}", status, width as usize))?;
+		queue!(self.out, SetAttribute(Attribute::Reset))?;
+		self.out.flush()
+	}
+
+	fn step(&mut self) {
+		if self.interpreter.is_finished() {
+			self.playing = false;
+			return;
+		}
+
+		if let Err(line) = self.interpreter.try_step(Interpreter::step_statement) {
+			self.catastrophe = Some(line);
+		}
+
+		self.published.push_str(&self.output.take_text());
+		if self.interpreter.is_finished() {
+			self.playing = false;
+		}
+	}
+
+	fn run(&mut self) -> io::Result<()> {
+		loop {
+			self.draw()?;
+			let timeout = if self.playing { Duration::from_millis(1000 / SPEEDS[self.speed]) } else { Duration::from_secs(60) };
+			if !event::poll(timeout)? {
+				if self.playing {
+					self.step();
+				}
+
+				continue;
+			}
+
+			let key = match event::read()? {
+				Event::Key(key) if key.kind == KeyEventKind::Press => key,
+				_ => continue,
+			};
+
+			match key.code {
+				KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+				KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+				KeyCode::Char(' ') | KeyCode::Char('p') => self.playing = !self.playing && !self.interpreter.is_finished(),
+				KeyCode::Char('s') | KeyCode::Char('n') | KeyCode::Right => {
+					self.playing = false;
+					self.step();
+				},
+				KeyCode::Char('+') | KeyCode::Char('=') | KeyCode::Up => self.speed = (self.speed + 1).min(SPEEDS.len() - 1),
+				KeyCode::Char('-') | KeyCode::Down => self.speed = self.speed.saturating_sub(1),
+				_ => {},
+			}
+		}
+	}
+}
+
+// Just the desk, as it would be drawn on a terminal of that size
+pub fn render_desk(interpreter: Interpreter, published: &str, width: u16, height: u16) -> io::Result<String> {
+	let mut visualizer = Visualizer{
+		interpreter,
+		source: vec![],
+		output: CapturedOutput::default(),
+		published: published.into(),
+		playing: false,
+		speed: 0,
+		catastrophe: None,
+		out: vec![],
+	};
+
+	visualizer.draw_desk(0, width, height)?;
+	Ok(String::from_utf8_lossy(&visualizer.out).into())
+}
+
+pub fn run(args: &[String]) {
+	let mut path = String::new();
+	let mut input = String::new();
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--input" => {
+				input = fs::read_to_string(args.next().unwrap()).unwrap();
+			},
+			_ => {
+				path = arg.into();
+			},
+		}
+	}
+
+	if path.is_empty() {
+		panic!();
+	}
+
+	set_catastrophe_hook(path.clone());
+	let source = syntax::read_source(&path).unwrap();
+	let output = CapturedOutput::default();
+	let mut program = Program::new(false);
+	program.input = Box::new(Cursor::new(input));
+	program.output = Box::new(output.clone());
+
+	let mut visualizer = Visualizer{
+		interpreter: Interpreter::new(syntax::parse_source(&source), program),
+		source: source.lines().map(String::from).collect(),
+		output,
+		published: String::new(),
+		playing: false,
+		speed: 2,
+		catastrophe: None,
+		out: BufWriter::new(io::stdout()),
+	};
+
+	// Catastrophes are shown on the screen, and told again once it's been put away
+	let hook = panic::take_hook();
+	let kept = errors::keep_catastrophes(path);
+	{
+		let _fullscreen = Fullscreen::enter().unwrap();
+		visualizer.run().unwrap();
+	}
+
+	panic::set_hook(hook);
+	print!("{}", visualizer.published);
+	if let Some(line) = visualizer.catastrophe {
+		let message = ERROR_MESSAGES[thread_rng().gen_range(0..10)];
+		match kept.lock().unwrap().take() {
+			Some(diagnostic) => eprintln!("{}\x1b[0;91mCatastrophe!\x1b[0m\n{}", diagnostic, message),
+			None => eprintln!("\x1b[0;91mCatastrophe!\x1b[0m\n{}\n(Line {})", message, line),
+		}
+	}
+}
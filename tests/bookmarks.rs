@@ -0,0 +1,61 @@
+// Spellbooks with bookmarks in them, checking that flips and skips land where they're pointed
+// once the rest of their line is read, and that the checker knows which ones can't.
+
+mod common;
+
+use common::spellbook;
+
+#[test]
+fn flips_land_once_their_line_is_read() {
+	let output = spellbook(&["run", "tests/bookmarks/flips.spb"]);
+	assert_eq!(String::from_utf8(output.stdout).unwrap(), "read\n|stayed\n|");
+
+	// Flipping to a bookmark that was never placed is a catastrophe on the flip's own line
+	assert!(String::from_utf8(output.stderr).unwrap().contains("(Line 17)"));
+}
+
+#[test]
+fn flips_can_go_back_to_the_very_first_line() {
+	let output = spellbook(&["run", "tests/bookmarks/first_line.spb"]);
+	assert_eq!(String::from_utf8(output.stdout).unwrap().matches("again").count(), 2);
+	assert!(output.status.success());
+}
+
+#[test]
+fn examples_loop_with_bookmarks() {
+	let output = spellbook(&["run", "examples/countdown.spb"]);
+	assert_eq!(String::from_utf8(output.stdout).unwrap(), "5\n4\n3\n2\n1\nLiftoff!\n");
+	assert!(output.status.success());
+}
+
+#[test]
+fn checker_knows_where_bookmarks_are() {
+	let output = spellbook(&["check", "tests/bookmarks/flips.spb", "tests/bookmarks/mistakes.spb"]);
+	assert_eq!(String::from_utf8(output.stdout).unwrap(), concat!(
+		"tests/bookmarks/flips.spb:13:1: UNKNOWN-BOOKMARK: no bookmark named `nowhere` is placed anywhere\n",
+		"tests/bookmarks/flips.spb:17:1: UNKNOWN-BOOKMARK: no bookmark named `nowhere` is placed anywhere\n",
+		"tests/bookmarks/mistakes.spb:2:1: SYNTAX: the bookmark `start` is on line 1, so there's no flipping that way to it (did you mean `flip back to bookmark start`?)\n",
+		"tests/bookmarks/mistakes.spb:3:1: SYNTAX: the bookmark `finish` is on line 6, so there's no flipping that way to it (did you mean `flip ahead to bookmark finish`?)\n",
+		"tests/bookmarks/mistakes.spb:4:1: UNKNOWN-BOOKMARK: no bookmark named `finnish` is placed anywhere (did you mean `finish`?)\n",
+		"tests/bookmarks/mistakes.spb:5:1: SYNTAX: there's already a bookmark named `start` on line 1\n",
+		"tests/bookmarks/mistakes.spb:7:30: SYNTAX: only Judgement can skip to a bookmark, not Vacancy\n",
+	));
+}
+
+#[test]
+fn lines_with_bookmarks_are_never_merged_into() {
+	let output = spellbook(&["minify", "examples/countdown.spb"]);
+	let minified = String::from_utf8(output.stdout).unwrap();
+	assert!(minified.lines().any(|line| line.starts_with("place a bookmark named tick")));
+	assert!(String::from_utf8(output.stderr).unwrap().contains("line 21 flips to a bookmark on it"));
+}
+
+#[test]
+fn headings_and_bookmarks_can_be_called_place_and_flip() {
+	let output = spellbook(&["run", "tests/bookmarks/names.spb"]);
+	assert!(String::from_utf8(output.stdout).unwrap().starts_with("3\n"));
+	assert!(output.status.success());
+
+	let output = spellbook(&["check", "tests/bookmarks/names.spb"]);
+	assert_eq!(String::from_utf8(output.stdout).unwrap(), "");
+}
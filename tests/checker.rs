@@ -0,0 +1,59 @@
+// Checks a spellbook for every rule that breaks it and one that comes close without breaking
+// it, then makes sure hushing a rule only hushes that rule on that line.
+
+mod common;
+
+use common::spellbook;
+
+use spellbook::checker::{self, RULES};
+use spellbook::syntax;
+
+use std::fs;
+use std::path::Path;
+
+// Each rule's spellbooks are named after it, the one that doesn't break it ending in `_ok`
+fn rules_found(rule: &str, suffix: &str) -> Vec<&'static str> {
+	let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(format!("tests/checker/{}{}.spb", rule.to_lowercase().replace('-', "_"), suffix));
+	let source = fs::read_to_string(&path).unwrap_or_else(|_| panic!("{} has no spellbook at {}", rule, path.display()));
+	checker::check(&syntax::parse_source(&source)).into_iter().map(|f| f.rule).collect()
+}
+
+#[test]
+fn every_rule_is_broken_by_its_spellbook() {
+	for (rule, _) in RULES {
+		assert_eq!(rules_found(rule, ""), vec![rule], "{}", rule);
+	}
+}
+
+#[test]
+fn nothing_is_broken_by_coming_close() {
+	for (rule, _) in RULES {
+		assert_eq!(rules_found(rule, "_ok"), Vec::<&str>::new(), "{}", rule);
+	}
+}
+
+#[test]
+fn clean_spellbooks_pass() {
+	let output = spellbook(&["check", "tests/checker/syntax_ok.spb", "tests/checker/empty_drawer_ok.spb"]);
+	assert!(output.status.success());
+	assert_eq!(String::from_utf8(output.stdout).unwrap(), "");
+
+	let output = spellbook(&["check", "tests/checker/syntax.spb"]);
+	assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn hushing_only_hushes_the_named_rule_on_its_line() {
+	let output = spellbook(&["check", "tests/checker/hush.spb"]);
+	assert_eq!(String::from_utf8(output.stdout).unwrap(), concat!(
+		"tests/checker/hush.spb:4:1: DUPLICATE-HEADING: `one` is already written in the Hexes chapter\n",
+		"tests/checker/hush.spb:7:1: DUPLICATE-HEADING: `one` is already written in the Illusions chapter\n",
+		"tests/checker/hush.spb:9:1: OVERFULL-CHAPTER: the Illusions chapter is already full\n",
+	));
+}
+
+#[test]
+fn columns_count_characters() {
+	let output = spellbook(&["check", "tests/checker/accents.spb"]);
+	assert_eq!(String::from_utf8(output.stdout).unwrap(), "tests/checker/accents.spb:2:36: UNKNOWN-SPELL: there's no spell called `Vacuity`\n");
+}
@@ -0,0 +1,196 @@
+// What compiling to C and transpiling to Rust are both held to: every spellbook here has to
+// publish just what the interpreter does, and end the same way. 10_print is left out, since
+// it never stops and remembering things badly is the whole point of it.
+
+// Each test only ever uses some of these
+#![allow(dead_code)]
+
+use serde_json::Value;
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+pub const SPELLBOOKS: [(&str, &str); 17] = [
+	("examples/99_bottles.spb", ""),
+	("examples/add_two_numbers.spb", "2\n3.25\n"),
+	("examples/cat.spb", "meow\n"),
+	("examples/countdown.spb", ""),
+	("examples/fibonacci.spb", ""),
+	("examples/hello_world.spb", ""),
+	("examples/oneline.spb", ""),
+	("examples/same_or_different.spb", "3\n3\n"),
+	("examples/same_or_different.spb", "3\n4\n"),
+	("examples/truth_machine.spb", "0\n"),
+	("examples/your_name.spb", "Diane\n"),
+	("tests/bookmarks/first_line.spb", ""),
+	("tests/bookmarks/flips.spb", ""),
+	("tests/conditionals/guards.spb", ""),
+	("tests/transcripts/dap/empty_drawer.spb", ""),
+	("fuzz/regressions/execute/reverberate_to_the_first_line.spb", ""),
+	("fuzz/regressions/execute/write_after_put_back.spb", ""),
+];
+
+#[derive(Clone, Copy)]
+pub enum Backend {
+	C,
+	Rust,
+}
+
+impl Backend {
+	fn target(self) -> &'static str {
+		match self {
+			Backend::C => "c",
+			Backend::Rust => "rust",
+		}
+	}
+
+	fn extension(self) -> &'static str {
+		match self {
+			Backend::C => "c",
+			Backend::Rust => "rs",
+		}
+	}
+
+	fn build(self, source: &Path, exe_path: &Path) -> Command {
+		let mut command = match self {
+			Backend::C => Command::new("cc"),
+			Backend::Rust => Command::new(env::var("RUSTC").unwrap_or("rustc".into())),
+		};
+
+		match self {
+			Backend::C => command.args(["-O1", "-Wall", "-Werror", "-o"]).arg(exe_path).arg(source).arg("-lm"),
+			Backend::Rust => command.args(["-O", "-D", "warnings", "-o"]).arg(exe_path).arg(source),
+		};
+
+		command
+	}
+}
+
+pub fn run_with_input(command: &mut Command, input: &str) -> Output {
+	let mut child = command.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().unwrap();
+	child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+	child.wait_with_output().unwrap()
+}
+
+// Paths are from the top of the repository, wherever the tests are run from
+pub fn spellbook(args: &[&str]) -> Output {
+	Command::new(env!("CARGO_BIN_EXE_spellbook"))
+		.args(args)
+		.current_dir(Path::new(env!("CARGO_MANIFEST_DIR")))
+		.output()
+		.unwrap()
+}
+
+pub fn spellbook_with_input(args: &[&str], input: &str) -> Output {
+	run_with_input(Command::new(env!("CARGO_BIN_EXE_spellbook")).args(args).current_dir(Path::new(env!("CARGO_MANIFEST_DIR"))), input)
+}
+
+// Which message a catastrophe picks is up to chance, but never the line it happened on
+pub fn catastrophe_line(output: &Output) -> Option<String> {
+	String::from_utf8_lossy(&output.stderr).lines().find(|l| l.starts_with("(Line ")).map(String::from)
+}
+
+// The source the spellbook turned into, for looking at what it turned into
+pub fn translate(backend: Backend, path: &Path, dir: &Path) -> PathBuf {
+	let name = path.file_stem().unwrap().to_str().unwrap();
+	let source = dir.join(format!("{}.{}", name, backend.extension()));
+	let status = Command::new(env!("CARGO_BIN_EXE_spellbook"))
+		.args(["compile", "--target", backend.target()])
+		.arg(path)
+		.arg("-o")
+		.arg(&source)
+		.status()
+		.unwrap();
+	assert!(status.success(), "{}: couldn't be compiled to {}", path.display(), backend.target());
+	source
+}
+
+pub fn compile(backend: Backend, path: &Path, dir: &Path) -> PathBuf {
+	let source = translate(backend, path, dir);
+	let exe_path = dir.join(path.file_stem().unwrap());
+	let status = backend.build(&source, &exe_path).status().unwrap();
+	assert!(status.success(), "{}: the {} compiler didn't like it", path.display(), backend.target());
+	exe_path
+}
+
+pub fn scratch_dir(backend: Backend, test: &str) -> PathBuf {
+	let dir = env::temp_dir().join(format!("spellbook-{}-{}-{}", test, backend.target(), std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	dir
+}
+
+pub fn match_the_interpreter(backend: Backend) {
+	let dir = scratch_dir(backend, "compile");
+	for (path, input) in SPELLBOOKS {
+		let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(path);
+		let exe_path = compile(backend, &path, &dir);
+		let interpreted = run_with_input(Command::new(env!("CARGO_BIN_EXE_spellbook")).arg("run").arg(&path), input);
+		let compiled = run_with_input(&mut Command::new(&exe_path), input);
+
+		assert_eq!(String::from_utf8_lossy(&compiled.stdout), String::from_utf8_lossy(&interpreted.stdout), "{}: published something else", path.display());
+		assert_eq!(compiled.status.code(), interpreted.status.code(), "{}: ended some other way", path.display());
+		assert_eq!(catastrophe_line(&compiled), catastrophe_line(&interpreted), "{}: had a catastrophe somewhere else", path.display());
+	}
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+// Transcripts are conversations with the editor servers, written down in tests/transcripts.rs
+
+fn matches(expected: &Value, actual: &Value) -> bool {
+	match (expected, actual) {
+		(Value::Object(expected), Value::Object(actual)) => expected.iter().all(|(k, v)| actual.get(k).is_some_and(|a| matches(v, a))),
+		(Value::Array(expected), Value::Array(actual)) => expected.len() == actual.len() && expected.iter().zip(actual).all(|(e, a)| matches(e, a)),
+		_ => expected == actual,
+	}
+}
+
+fn split_frames(mut output: &str) -> Vec<Value> {
+	let mut messages = vec![];
+	while let Some(start) = output.find("\r\n\r\n") {
+		let length = output[..start].trim().strip_prefix("Content-Length:").unwrap().trim().parse::<usize>().unwrap();
+		let body = &output[start + 4..start + 4 + length];
+		messages.push(serde_json::from_str(body).unwrap());
+		output = &output[start + 4 + length..];
+	}
+
+	messages
+}
+
+pub fn run_transcript(subcommand: &str, path: &Path) {
+	let transcript = fs::read_to_string(path).unwrap();
+	let mut input = String::new();
+	let mut expected = vec![];
+	for line in transcript.lines() {
+		if let Some(message) = line.strip_prefix("--> ") {
+			let message = serde_json::from_str::<Value>(message).unwrap().to_string();
+			input.push_str(&format!("Content-Length: {}\r\n\r\n{}", message.len(), message));
+		} else if let Some(message) = line.strip_prefix("<-- ") {
+			expected.push(serde_json::from_str::<Value>(message).unwrap());
+		}
+	}
+
+	let mut child = Command::new(env!("CARGO_BIN_EXE_spellbook"))
+		.arg(subcommand)
+		.current_dir(path.parent().unwrap())
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.spawn()
+		.unwrap();
+
+	child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+	let output = child.wait_with_output().unwrap();
+	let actual = split_frames(&String::from_utf8(output.stdout).unwrap());
+
+	for (i, expected) in expected.iter().enumerate() {
+		match actual.get(i) {
+			Some(actual) => assert!(matches(expected, actual), "{}: message {} was\n{}\nbut should have been\n{}", path.display(), i + 1, actual, expected),
+			None => panic!("{}: the server stopped after {} messages, but should have sent\n{}", path.display(), actual.len(), expected),
+		}
+	}
+
+	assert_eq!(actual.len(), expected.len(), "{}: the server sent more messages than expected", path.display());
+}
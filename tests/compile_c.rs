@@ -0,0 +1,11 @@
+// Compiles spellbooks to C with the system C compiler and checks the programs against the
+// interpreter.
+
+mod common;
+
+use common::Backend;
+
+#[test]
+fn compiled_spellbooks_match_the_interpreter() {
+	common::match_the_interpreter(Backend::C);
+}
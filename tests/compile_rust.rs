@@ -0,0 +1,44 @@
+// Transpiles spellbooks to Rust and builds them with rustc, checking the programs against the
+// interpreter, and that jumps only turn into a match over every line when they have to.
+
+mod common;
+
+use common::Backend;
+
+use std::fs;
+use std::path::Path;
+
+fn transpile(path: &str) -> String {
+	let dir = common::scratch_dir(Backend::Rust, "transpile");
+	let source = fs::read_to_string(common::translate(Backend::Rust, &Path::new(env!("CARGO_MANIFEST_DIR")).join(path), &dir)).unwrap();
+	fs::remove_dir_all(&dir).unwrap();
+	source
+}
+
+#[test]
+fn transpiled_spellbooks_match_the_interpreter() {
+	common::match_the_interpreter(Backend::Rust);
+}
+
+#[test]
+fn known_jumps_become_loops_and_blocks() {
+	// Judgement always skips the same distance and Reverberate always goes back to the same spell
+	let rust = transpile("examples/fibonacci.spb");
+	assert!(rust.contains(": loop {\n"), "{}", rust);
+	assert!(rust.contains("break '"), "{}", rust);
+	assert!(!rust.contains("match number {"), "{}", rust);
+
+	// A bookmark is a known place to go as well
+	let rust = transpile("examples/countdown.spb");
+	assert!(rust.contains("'line_6: loop {\n"), "{}", rust);
+	assert!(!rust.contains("match number {"), "{}", rust);
+}
+
+#[test]
+fn jumps_that_depend_on_the_desk_fall_back_to_a_match() {
+	// How far Judgement skips here depends on what was typed in
+	let rust = transpile("examples/truth_machine.spb");
+	assert!(rust.contains("\t\tmatch number {\n"), "{}", rust);
+	assert!(rust.contains("line = desk.finish_line();"), "{}", rust);
+	assert!(!rust.contains(": loop {"), "{}", rust);
+}
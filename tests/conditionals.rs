@@ -0,0 +1,43 @@
+// Spellbooks with `if` in them, checking that a condition that doesn't hold skips the rest
+// of its line and nothing else, and that the checker knows what can go wrong with one.
+
+mod common;
+
+use common::{spellbook, spellbook_with_input};
+
+#[test]
+fn conditions_guard_the_rest_of_their_line() {
+	let output = spellbook(&["run", "tests/conditionals/guards.spb"]);
+	assert_eq!(String::from_utf8(output.stdout).unwrap(), "true\nfalse\n|.");
+
+	// Looking at an empty entry is a catastrophe, just like reading one anywhere else
+	assert!(String::from_utf8(output.stderr).unwrap().contains("(Line 12)"));
+}
+
+#[test]
+fn examples_decide_with_conditions() {
+	for (input, expected) in [("3\n3\n", "They're the same!\n"), ("3\n4\n", "They're different!\n")] {
+		let output = spellbook_with_input(&["run", "examples/same_or_different.spb"], input);
+		assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+	}
+}
+
+#[test]
+fn checker_knows_what_conditions_need() {
+	let output = spellbook(&["check", "tests/conditionals/guards.spb", "tests/conditionals/mistakes.spb"]);
+	assert_eq!(String::from_utf8(output.stdout).unwrap(), concat!(
+		"tests/conditionals/guards.spb:12:1: EMPTY-CONDITION: the first entry of the Presages chapter is empty\n",
+		"tests/conditionals/mistakes.spb:3:1: CONDITION-WITHOUT-PRESAGES: the Hexes chapter is turned to\n",
+		"tests/conditionals/mistakes.spb:5:1: UNKNOWN-HEADING: `one` isn't written in the Presages chapter\n",
+		"tests/conditionals/mistakes.spb:6:1: EMPTY-CONDITION: the second entry of the Presages chapter is empty\n",
+		"tests/conditionals/mistakes.spb:7:24: SYNTAX: expected a statement for the condition to guard at the end of the line\n",
+	));
+}
+
+#[test]
+fn lines_with_conditions_are_never_merged_into() {
+	let output = spellbook(&["minify", "examples/same_or_different.spb"]);
+	let minified = String::from_utf8(output.stdout).unwrap();
+	assert!(minified.lines().next().unwrap().ends_with("if third entry memorize \"the same\""));
+	assert!(String::from_utf8(output.stderr).unwrap().contains("line 13 has an `if`"));
+}
@@ -0,0 +1,66 @@
+// Runs a spellbook with coverage and compares the lcov report with what it should be, down to
+// how many times each line was read and which way Judgement and Reverberate went.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+fn coverage(path: &str, test: &str) -> String {
+	let report = env::temp_dir().join(format!("spellbook-coverage-{}-{}.info", test, std::process::id()));
+	let status = Command::new(env!("CARGO_BIN_EXE_spellbook"))
+		.args(["run", "--coverage", report.to_str().unwrap(), path])
+		.current_dir(Path::new(env!("CARGO_MANIFEST_DIR")))
+		.output()
+		.unwrap()
+		.status;
+	assert!(status.success());
+
+	let lcov = fs::read_to_string(&report).unwrap();
+	fs::remove_file(&report).unwrap();
+	lcov
+}
+
+#[test]
+fn report_matches() {
+	let expected = fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/coverage/skips.info")).unwrap();
+	assert_eq!(coverage("tests/coverage/skips.spb", "report"), expected);
+}
+
+#[test]
+fn skipped_lines_are_never_hit() {
+	let lcov = coverage("tests/coverage/skips.spb", "skipped");
+	let hits = |line: usize| lcov.lines().find_map(|l| l.strip_prefix(&format!("DA:{},", line))).and_then(|n| n.parse::<usize>().ok());
+
+	// The loop is read twice, what's after Judgement only once, and the line it skips never
+	assert_eq!(hits(7), Some(2));
+	assert_eq!(hits(10), Some(2));
+	assert_eq!(hits(11), Some(1));
+	assert_eq!(hits(13), Some(1));
+	assert_eq!(hits(15), Some(0));
+	assert_eq!(hits(18), Some(1));
+	assert!(lcov.contains("\nLF:18\nLH:17\n"));
+
+	// Judgement on line 10 stayed once and skipped once, and the one on line 14 always skipped
+	assert!(lcov.contains("\nBRDA:10,0,0,1\nBRDA:10,0,1,1\n"));
+	assert!(lcov.contains("\nBRDA:14,2,0,0\nBRDA:14,2,1,1\n"));
+
+	// Reverberate went back to line 7
+	assert!(lcov.contains("\nBRDA:13,0,7,1\n"));
+}
+
+#[test]
+fn tracing_at_the_same_time_is_refused() {
+	for trace in ["--trace=jsonl", "--trace=diff"] {
+		let output = Command::new(env!("CARGO_BIN_EXE_spellbook"))
+			.args(["run", trace, "--coverage", "unwritten.info", "tests/coverage/skips.spb"])
+			.current_dir(Path::new(env!("CARGO_MANIFEST_DIR")))
+			.output()
+			.unwrap();
+
+		assert_eq!(output.status.code(), Some(1));
+		assert_eq!(String::from_utf8(output.stderr).unwrap(), format!("--coverage and {} can't be used together, so run the spellbook once for each.\n", trace));
+		assert!(output.stdout.is_empty());
+		assert!(!Path::new(env!("CARGO_MANIFEST_DIR")).join("unwritten.info").exists());
+	}
+}
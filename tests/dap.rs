@@ -0,0 +1,11 @@
+// The debug adapter, talked to the way editors really do it rather than in the tidy order of
+// the transcripts in tests/transcripts/dap.
+
+mod common;
+
+use std::path::Path;
+
+#[test]
+fn breakpoints_set_before_launching_hold() {
+	common::run_transcript("dap", &Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/dap/breakpoints_before_launch.txt"));
+}
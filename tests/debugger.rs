@@ -0,0 +1,126 @@
+// The debugger driven through its prompt, the way someone would type at it. Commands and
+// Coadjuvancy share stdin, so a whole session is just the lines piped into it.
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+fn debug(path: &str, commands: &str) -> String {
+	let mut child = Command::new(env!("CARGO_BIN_EXE_spellbook"))
+		.args(["debug", path])
+		.current_dir(Path::new(env!("CARGO_MANIFEST_DIR")))
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()
+		.unwrap();
+
+	child.stdin.take().unwrap().write_all(commands.as_bytes()).unwrap();
+	String::from_utf8(child.wait_with_output().unwrap().stdout).unwrap()
+}
+
+#[test]
+fn breakpoints_and_watches_stop_the_reading() {
+	let output = debug("tests/debugger/put_back.spb", "break 4\ncontinue\nprint Hexes.count\nwatch Hexes.count\ncontinue\nprint Hexes\nquit\n");
+	assert_eq!(output, concat!(
+		"Debugging tests/debugger/put_back.spb. Type `help` for commands.\n",
+		"   1 | turn to chapter Hexes\n",
+		"(spellbook) Breakpoint 1: line 4\n",
+		"(spellbook) Breakpoint 1\n",
+		"   4 | write 2 under count\n",
+		"(spellbook) nothing\n",
+		"(spellbook) Watching Hexes.count, now nothing\n",
+		"(spellbook) Hexes.count: nothing -> 2\n",
+		"   5 | take out a chapter from the drawer and put it back\n",
+		"(spellbook) Hexes (turned to)\n",
+		"  count: 2\n",
+		"  (second entry): —\n",
+		"  (third entry): —\n",
+		"(spellbook) ",
+	));
+}
+
+#[test]
+fn spells_and_conditions_stop_the_reading() {
+	let output = debug("examples/countdown.spb", "break on spell Judgement\nbreak when cauldron charge >= 3\nbreak\ncontinue\ndelete 1\ncontinue\nwhere\nquit\n");
+	assert_eq!(output, concat!(
+		"Debugging examples/countdown.spb. Type `help` for commands.\n",
+		"   1 | COUNTS DOWN FROM FIVE, THEN LIFTS OFF\n",
+		"(spellbook) Breakpoint 1: on spell Judgement\n",
+		"(spellbook) Breakpoint 2: when cauldron charge >= 3\n",
+		"(spellbook) 1: on spell Judgement\n",
+		"2: when cauldron charge >= 3\n",
+		"(spellbook) 5\n",
+		"Breakpoint 1\n",
+		"  19 | cast Judgement on the cauldron and skip to bookmark liftoff\n",
+		"(spellbook) Deleted breakpoint 1\n",
+		"(spellbook) 4\n",
+		"3\n",
+		"Breakpoint 1\n",
+		"  20 | knock over cauldron\n",
+		"(spellbook) -> line 20: knock over cauldron\n",
+		"   line 19: cast Judgement on the cauldron and skip to bookmark liftoff (charge 2, amplifier 1)\n",
+		"   line 19: cast Judgement on the cauldron and skip to bookmark liftoff (charge 1, amplifier 1)\n",
+		"   line 19: cast Judgement on the cauldron and skip to bookmark liftoff (charge 0, amplifier 1)\n",
+		"(spellbook) ",
+	));
+}
+
+#[test]
+fn coadjuvancy_reads_from_the_prompt() {
+	let output = debug("examples/add_two_numbers.spb", "continue\n3\n4\nquit\n");
+	assert!(output.contains("(spellbook) 7\n\nThe spellbook has been closed.\n"));
+}
+
+#[test]
+fn mistakes_are_told_about_and_forgiven() {
+	let output = debug("examples/countdown.spb", "break 99\nwatch Hexes\nfrobnicate\nstep\nquit\n");
+	assert_eq!(output.lines().skip(2).collect::<Vec<_>>(), [
+		"(spellbook) there's nothing to read on line 99",
+		"(spellbook) `Hexes` should look like <chapter>.<heading>",
+		"(spellbook) I don't know how to `frobnicate`. Type `help` for commands.",
+		"(spellbook)    2 | turn to chapter Hexes",
+		"(spellbook) ",
+	]);
+}
+
+#[test]
+fn putting_a_page_back_writes_its_entries() {
+	let output = debug("tests/debugger/put_back.spb", "step 6\nlast-write Hexes.count\nlast-write Hexes.other\nreverse-step 2\nlast-write Hexes.count\nquit\n");
+	let answers = output.lines().filter(|line| line.contains("last written")).collect::<Vec<_>>();
+	assert_eq!(answers, [
+		"(spellbook) Hexes.count was last written on line 5, 2 statement(s) ago.",
+		"(spellbook) Hexes.other was last written on line 6, 1 statement(s) ago.",
+		"(spellbook) Hexes.count was last written on line 4, 1 statement(s) ago.",
+	]);
+}
+
+#[test]
+fn going_back_and_forth_sees_the_same_changes() {
+	let output = debug("tests/debugger/put_back.spb", "watch Hexes.count\nbreak when Hexes.count == 2\nstep 4\nreverse-continue\ncontinue\nquit\n");
+	assert_eq!(output.lines().skip(4).collect::<Vec<_>>(), [
+		"(spellbook) Hexes.count: nothing -> 1",
+		"Hexes.count: 1 -> nothing",
+		"Hexes.count: nothing -> 2",
+		"Breakpoint 1",
+		"   5 | take out a chapter from the drawer and put it back",
+		"(spellbook) Hexes.count: 2 -> nothing",
+		"   4 | write 2 under count",
+		"(spellbook) Hexes.count: nothing -> 2",
+		"Breakpoint 1",
+		"   5 | take out a chapter from the drawer and put it back",
+		"(spellbook) ",
+	]);
+}
+
+#[test]
+fn history_needs_a_number() {
+	let output = Command::new(env!("CARGO_BIN_EXE_spellbook"))
+		.args(["debug", "tests/debugger/put_back.spb", "--history"])
+		.current_dir(Path::new(env!("CARGO_MANIFEST_DIR")))
+		.output()
+		.unwrap();
+
+	assert_eq!(output.status.code(), Some(1));
+	assert_eq!(String::from_utf8(output.stderr).unwrap(), "--history needs a number of statements to remember, like `--history 10000`.\n");
+}
@@ -0,0 +1,87 @@
+// Runs spellbooks with typos in them and makes sure the catastrophe points at the right words
+// and guesses what was meant. Which flavor line comes after is up to chance.
+
+mod common;
+
+use common::{spellbook, spellbook_with_input};
+
+fn run_error(path: &str) -> String {
+	let output = spellbook(&["run", path]);
+	assert!(!output.status.success());
+	String::from_utf8(output.stderr).unwrap().replace("\x1b[0;91m", "").replace("\x1b[0m", "")
+}
+
+#[test]
+fn mistyped_keyphrases_are_pointed_at() {
+	let stderr = run_error("tests/diagnostics/chaptr.spb");
+	assert!(stderr.starts_with(concat!(
+		"error: expected `to chapter` after `turn`\n",
+		" --> tests/diagnostics/chaptr.spb:1:9\n",
+		"  |\n",
+		"1 | turn to chaptr Hexes\n",
+		"  |         ^^^^^^\n",
+		"  |\n",
+		"  = help: did you mean `turn to chapter`?\n",
+		"Catastrophe!\n",
+	)), "{}", stderr);
+
+	let stderr = run_error("tests/diagnostics/on_cauldron.spb");
+	assert!(stderr.starts_with(concat!(
+		"error: expected `the cauldron` after `on`\n",
+		" --> tests/diagnostics/on_cauldron.spb:2:17\n",
+		"  |\n",
+		"2 | cast Vacancy on cauldron\n",
+		"  |                 ^^^^^^^^\n",
+		"  |\n",
+		"  = help: did you mean `on the cauldron`?\n",
+	)), "{}", stderr);
+
+	let stderr = run_error("tests/diagnostics/trun.spb");
+	assert!(stderr.contains("1 | trun to chapter Hexes\n  | ^^^^\n"), "{}", stderr);
+	assert!(stderr.contains("= help: did you mean `turn to chapter`?\n"), "{}", stderr);
+}
+
+#[test]
+fn mistyped_names_are_guessed() {
+	let stderr = run_error("tests/diagnostics/hexs.spb");
+	assert!(stderr.contains("1 | turn to chapter Hexs\n  |                 ^^^^\n"), "{}", stderr);
+	assert!(stderr.contains("= help: did you mean `Hexes`?\n"), "{}", stderr);
+
+	// The caret keeps the line's tab so it still lines up
+	let stderr = run_error("tests/diagnostics/judgment.spb");
+	assert!(stderr.starts_with("error: there's no spell called `Judgment`\n --> tests/diagnostics/judgment.spb:4:7\n"), "{}", stderr);
+	assert!(stderr.contains("4 | \tcast Judgment on the cauldron\n  | \t     ^^^^^^^^\n"), "{}", stderr);
+	assert!(stderr.contains("= help: did you mean `Judgement`?\n"), "{}", stderr);
+}
+
+#[test]
+fn checker_makes_the_same_guesses() {
+	let output = spellbook(&["check", "tests/diagnostics/hexs.spb", "tests/diagnostics/judgment.spb"]);
+	assert_eq!(String::from_utf8(output.stdout).unwrap(), concat!(
+		"tests/diagnostics/hexs.spb:1:17: SYNTAX: expected a chapter name, found the heading `Hexs` (did you mean `Hexes`?)\n",
+		"tests/diagnostics/judgment.spb:4:2: UNKNOWN-SPELL: there's no spell called `Judgment` (did you mean `Judgement`?)\n",
+	));
+}
+
+#[test]
+fn every_tool_that_reads_a_spellbook_names_it() {
+	let output = spellbook(&["profile", "tests/diagnostics/judgment.spb"]);
+	let stderr = String::from_utf8(output.stderr).unwrap();
+	assert!(stderr.contains(" --> tests/diagnostics/judgment.spb:4:7\n"), "{}", stderr);
+
+	let output = spellbook_with_input(&["debug", "tests/diagnostics/judgment.spb"], "continue\nquit\n");
+	let stderr = String::from_utf8(output.stderr).unwrap();
+	assert!(stderr.contains(" --> tests/diagnostics/judgment.spb:4:7\n"), "{}", stderr);
+}
+
+#[test]
+fn lines_that_cant_be_read_are_never_half_read() {
+	let output = spellbook(&["run", "tests/diagnostics/half_read.spb"]);
+	assert_eq!(String::from_utf8(output.stdout).unwrap(), "");
+	assert!(String::from_utf8(output.stderr).unwrap().contains("half_read.spb:2:52"));
+
+	// Unknown spells are only found once they're cast, after the rest of the line before them
+	let output = spellbook(&["run", "tests/diagnostics/half_cast.spb"]);
+	assert!(String::from_utf8(output.stdout).unwrap().starts_with("early\n"));
+	assert!(String::from_utf8(output.stderr).unwrap().contains("half_cast.spb:2:49"));
+}
@@ -0,0 +1,33 @@
+// Random spellbooks have to read like someone meant them, and every default way of running one
+// has to agree on what it does.
+
+use spellbook::generator;
+use spellbook::syntax;
+
+use std::process::Command;
+
+#[test]
+fn generated_spellbooks_parse_cleanly() {
+	for seed in 0..50 {
+		let source = generator::generate(seed, 40);
+		assert_eq!(source, generator::generate(seed, 40), "seed {}: came out differently the second time", seed);
+
+		let book = syntax::parse_source(&source);
+		for line in &book.lines {
+			assert!(line.statements.is_ok(), "seed {}: line {} doesn't parse: {}", seed, line.number, line.text);
+		}
+	}
+}
+
+#[test]
+fn every_engine_agrees() {
+	let output = Command::new(env!("CARGO_BIN_EXE_spellbook"))
+		.args(["differential", "--seed", "7", "--count", "20", "--fuel", "2000"])
+		.output()
+		.unwrap();
+
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	assert!(output.status.success(), "{}", stdout);
+	assert!(stdout.trim_end().ends_with(", 0 disagreed"), "{}", stdout);
+	assert!(!stdout.contains(" disagree\n"), "{}", stdout);
+}
@@ -0,0 +1,138 @@
+// Formats spellbooks and makes sure the canonical form is stable, reads exactly the same as
+// what it came from, and never splits a line that something might count.
+
+mod common;
+
+use common::spellbook_with_input;
+
+use spellbook::formatter;
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+// 10_print never stops, so it's only formatted, never run
+const BOOKS: [(&str, &str); 10] = [
+	("examples/99_bottles.spb", ""),
+	("examples/add_two_numbers.spb", "5\n7\n"),
+	("examples/cat.spb", "meow\n"),
+	("examples/countdown.spb", ""),
+	("examples/fibonacci.spb", ""),
+	("examples/hello_world.spb", ""),
+	("examples/oneline.spb", ""),
+	("examples/same_or_different.spb", "3\n4\n"),
+	("examples/truth_machine.spb", "0\n"),
+	("examples/your_name.spb", "Diane\n"),
+];
+
+fn read(path: &str) -> String {
+	fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join(path)).unwrap()
+}
+
+#[test]
+fn check_only_reports() {
+	let before = read("tests/fmt/messy.spb");
+	let output = spellbook_with_input(&["fmt", "--check", "tests/fmt/messy.spb"], "");
+	assert_eq!(output.status.code(), Some(1));
+	assert_eq!(String::from_utf8(output.stdout).unwrap(), "Would reformat tests/fmt/messy.spb\n");
+	assert_eq!(read("tests/fmt/messy.spb"), before);
+
+	let output = spellbook_with_input(&["fmt", "--check", "examples/hello_world.spb", "examples/fibonacci.spb"], "");
+	assert!(output.status.success());
+	assert_eq!(String::from_utf8(output.stdout).unwrap(), "");
+}
+
+#[test]
+fn statements_get_their_own_lines() {
+	assert_eq!(formatter::format_source(&read("tests/fmt/messy.spb")).output, concat!(
+		"TIDIED UP INTO ONE STATEMENT PER LINE\n",
+		"turn to chapter Incantations\n",
+		"write \"tab\\there\" under greeting\n",
+		"\n",
+		"sign acknowledgements page with \"\\n\"\n",
+		"publish spellbook\n",
+	));
+}
+
+#[test]
+fn formatting_twice_changes_nothing() {
+	let mut paths = fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("examples")).unwrap().map(|e| e.unwrap().path()).collect::<Vec<_>>();
+	paths.push(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fmt/messy.spb"));
+	paths.push(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fmt/refusals.spb"));
+	paths.push(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fmt/judgement.spb"));
+	paths.push(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fmt/unreadable.spb"));
+	for path in paths {
+		let once = formatter::format_source(&fs::read_to_string(&path).unwrap()).output;
+		let twice = formatter::format_source(&once).output;
+		assert_eq!(twice, once, "{}: changed again", path.display());
+	}
+}
+
+#[test]
+fn lines_that_might_be_counted_stay_whole() {
+	let output = spellbook_with_input(&["fmt", "--check", "tests/fmt/refusals.spb"], "");
+	assert_eq!(String::from_utf8(output.stderr).unwrap(), concat!(
+		"tests/fmt/refusals.spb:2: left 2 statements on one line because statements after slamming the spellbook shut would no longer run\n",
+		"tests/fmt/refusals.spb:3: left 2 statements on one line because flipping to the bookmark would no longer land on the statements before it\n",
+		"tests/fmt/refusals.spb:4: left 2 statements on one line because statements after flipping to a bookmark would no longer run\n",
+		"tests/fmt/refusals.spb:5: left 2 statements on one line because the line contains a conditional\n",
+		"tests/fmt/refusals.spb:6: left 2 statements on one line because the line casts a spell that can skip or jump lines\n",
+		"tests/fmt/refusals.spb:8: left 2 statements on one line because the line casts a spell that can skip or jump lines\n",
+	));
+
+	// Everything else that's safe to split still is
+	let formatted = formatter::format_source(&read("tests/fmt/refusals.spb")).output;
+	assert!(formatted.starts_with("turn to chapter Presages\nwrite true under yes\n"));
+	assert!(formatted.contains("\ncast Amplify on the cauldron\ncast Squelch on the cauldron\n"));
+}
+
+#[test]
+fn only_lines_a_judgement_counts_stay_whole() {
+	let result = formatter::format_source(&read("tests/fmt/judgement.spb"));
+	assert_eq!(result.refused.iter().map(|r| (r.line, r.reason)).collect::<Vec<_>>(), [(6, "the line is within reach of a Judgement skip")]);
+	assert!(result.output.ends_with(concat!(
+		"turn to chapter Incantations write \"skipped\" under word\n",
+		"turn to chapter Hexes\n",
+		"write 1 under count\n",
+		"write 2 under more\n",
+		"publish spellbook\n",
+	)));
+
+	let formatted = env::temp_dir().join(format!("spellbook-fmt-judgement-{}.spb", std::process::id()));
+	fs::write(&formatted, &result.output).unwrap();
+	let before = spellbook_with_input(&["run", "tests/fmt/judgement.spb"], "");
+	let after = spellbook_with_input(&["run", formatted.to_str().unwrap()], "");
+	fs::remove_file(&formatted).unwrap();
+	assert_eq!(after.stdout, before.stdout);
+}
+
+#[test]
+fn lines_that_cant_be_read_stay_whole() {
+	let source = read("tests/fmt/unreadable.spb");
+	let result = formatter::format_source(&source);
+	assert_eq!(result.output, source);
+	assert_eq!(result.refused.iter().map(|r| (r.line, r.statements, r.reason)).collect::<Vec<_>>(), [(1, 4, "the line can't be read")]);
+
+	let output = spellbook_with_input(&["fmt", "--check", "tests/fmt/unreadable.spb"], "");
+	assert!(output.status.success());
+}
+
+#[test]
+fn formatted_spellbooks_publish_the_same() {
+	let dir = env::temp_dir().join(format!("spellbook-fmt-{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+
+	for (path, input) in BOOKS {
+		let formatted_path = dir.join(Path::new(path).file_name().unwrap());
+		fs::write(&formatted_path, read(path)).unwrap();
+		let formatted = formatted_path.to_str().unwrap();
+		assert!(spellbook_with_input(&["fmt", formatted], "").status.success(), "{}: couldn't be formatted", path);
+
+		let before = spellbook_with_input(&["run", path], input);
+		let after = spellbook_with_input(&["run", formatted], input);
+		assert_eq!(after.status.code(), before.status.code(), "{}: ended differently", path);
+		assert_eq!(after.stdout, before.stdout, "{}: published something else", path);
+	}
+
+	fs::remove_dir_all(&dir).unwrap();
+}
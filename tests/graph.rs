@@ -0,0 +1,28 @@
+// Draws a spellbook's control flow and compares it with what it should look like, skips and
+// jumps back included.
+
+use spellbook::{analysis, graph, syntax};
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn skips_and_jumps_back_are_drawn() {
+	let output = Command::new(env!("CARGO_BIN_EXE_spellbook"))
+		.args(["graph", "tests/graph/loop.spb"])
+		.current_dir(Path::new(env!("CARGO_MANIFEST_DIR")))
+		.output()
+		.unwrap();
+
+	let expected = fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/graph/loop.dot")).unwrap();
+	assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+}
+
+#[test]
+fn only_lines_that_cant_be_read_are_red() {
+	let book = syntax::parse_source("SHOUTING ONLY\nturn to chaptr Hexes\n");
+	let dot = graph::render(&book, &analysis::analyze(&book));
+	assert!(dot.contains("\tl0 [label=\"1: SHOUTING ONLY\"];\n"), "{}", dot);
+	assert!(dot.contains("\tl1 [label=\"2: turn to chaptr Hexes\", color=red"), "{}", dot);
+}
@@ -0,0 +1,125 @@
+// Lexes spellbooks and makes sure nothing on a line goes missing: every byte belongs to exactly
+// one lexeme, in order, and the tokens they turn into are the ones the old parser found.
+
+use spellbook::lexer::{self, Lexeme, LexemeKind};
+use spellbook::parser::{Keyphrase, Token};
+use spellbook::variant::Variant;
+
+use std::fs;
+use std::path::Path;
+
+// The examples that were around before the lexer was, along with what the parser back then
+// tokenized each of their lines into
+const EXAMPLES: [&str; 9] = [
+	"10_print",
+	"99_bottles",
+	"add_two_numbers",
+	"cat",
+	"fibonacci",
+	"hello_world",
+	"oneline",
+	"truth_machine",
+	"your_name",
+];
+
+fn read(path: &str) -> String {
+	fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join(path)).unwrap()
+}
+
+fn assert_lossless(line: &str, lexemes: &[Lexeme]) {
+	let mut end = 0;
+	for lexeme in lexemes {
+		assert_eq!(lexeme.span.start, end, "{:?}: {:?} doesn't start where the last one ended", line, lexeme);
+		assert!(lexeme.span.end > lexeme.span.start, "{:?}: {:?} is empty", line, lexeme);
+		end = lexeme.span.end;
+	}
+
+	assert_eq!(end, line.len(), "{:?}: the lexemes stop short", line);
+	assert_eq!(lexemes.iter().map(|l| &line[l.span.clone()]).collect::<String>(), line);
+}
+
+#[test]
+fn spans_cover_every_line() {
+	let mut paths = fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("examples")).unwrap().map(|e| e.unwrap().path()).collect::<Vec<_>>();
+	paths.retain(|p| p.extension().is_some_and(|e| e == "spb"));
+	for path in paths {
+		for line in fs::read_to_string(&path).unwrap().lines() {
+			assert_lossless(line, &lexer::lex_line(line).unwrap());
+		}
+	}
+
+	let line = "  \twrite \"a \\\"quoted\\\" word\"\tunder   quote    SHOUTED!   ";
+	assert_lossless(line, &lexer::lex_line(line).unwrap());
+}
+
+#[test]
+fn shouting_in_strings_is_written_down() {
+	let line = "write \"I SAID HELLO\" under greeting";
+	let lexemes = lexer::lex_line(line).unwrap();
+	let significant = lexemes.iter().filter(|l| !l.is_trivia()).collect::<Vec<&Lexeme>>();
+	assert_eq!(significant.len(), 4);
+	assert_eq!(significant[1].kind, LexemeKind::Literal(Variant::Str("I SAID HELLO".into())));
+	assert_eq!(&line[significant[1].span.clone()], "\"I SAID HELLO\"");
+
+	// Keyphrases in a string are just words in it
+	let line = "write \"turn to chapter Hexes and publish spellbook\" under plan";
+	let tokens = lexer::lex_line(line).unwrap().iter().filter_map(Lexeme::to_token).collect::<Vec<Token>>();
+	assert_eq!(tokens, vec![
+		Token::Keyphrase(Keyphrase::Write),
+		Token::Literal(Variant::Str("turn to chapter Hexes and publish spellbook".into())),
+		Token::Keyphrase(Keyphrase::Under),
+		Token::Identifier("plan".into()),
+	]);
+}
+
+#[test]
+fn keyphrases_match_the_old_parser() {
+	for name in EXAMPLES {
+		let source = read(&format!("examples/{}.spb", name));
+		let tokens = read(&format!("tests/lexer/{}.tokens", name));
+		assert_eq!(source.lines().count(), tokens.lines().count(), "{}: lines went missing", name);
+		for (number, (line, expected)) in source.lines().zip(tokens.lines()).enumerate() {
+			let lexed = lexer::lex_line(line).ok().map(|l| l.iter().filter_map(Lexeme::to_token).collect::<Vec<Token>>());
+			assert_eq!(format!("{:?}", lexed), expected, "{}.spb:{}", name, number + 1);
+		}
+	}
+}
+
+#[test]
+fn comments_in_the_middle_of_a_keyphrase_stay_comments() {
+	let line = "turn to THE chapter Hexes take out a DUSTY chapter from the OLD drawer and put it back";
+	let lexemes = lexer::lex_line(line).unwrap();
+	assert_lossless(line, &lexemes);
+
+	let comments = lexemes.iter().filter(|l| l.kind == LexemeKind::Comment).map(|l| &line[l.span.clone()]).collect::<Vec<&str>>();
+	assert_eq!(comments, ["THE", "DUSTY", "OLD"]);
+
+	let pieces = lexemes.iter()
+		.filter(|l| matches!(l.kind, LexemeKind::Keyphrase(_) | LexemeKind::KeyphraseContinued))
+		.map(|l| &line[l.span.clone()])
+		.collect::<Vec<&str>>();
+	assert_eq!(pieces, ["turn to", "chapter", "take out a", "chapter from the", "drawer and put it back"]);
+
+	let tokens = lexemes.iter().filter_map(Lexeme::to_token).collect::<Vec<Token>>();
+	assert_eq!(tokens, vec![
+		Token::Keyphrase(Keyphrase::TurnToChapter),
+		Token::Builtin("Hexes".into()),
+		Token::Keyphrase(Keyphrase::TakeOutAChapterFromTheDrawerAndPutItBack),
+	]);
+}
+
+#[test]
+fn quotes_never_stop_a_line_from_being_read() {
+	// A string ends its word, and a quote in the middle of one keeps it going to the next quote
+	let line = "write \"a\"b under x\"y z\"w";
+	let lexemes = lexer::lex_line(line).unwrap();
+	assert_lossless(line, &lexemes);
+	assert_eq!(lexemes.iter().filter_map(Lexeme::to_token).collect::<Vec<Token>>(), vec![
+		Token::Keyphrase(Keyphrase::Write),
+		Token::Literal(Variant::Str("a".into())),
+		Token::Identifier("b".into()),
+		Token::Keyphrase(Keyphrase::Under),
+		Token::Identifier("x\"y z\"".into()),
+		Token::Identifier("w".into()),
+	]);
+}
@@ -0,0 +1,62 @@
+// Minifies spellbooks and makes sure they still publish exactly what they did before, on
+// fewer lines. How the lines got merged doesn't matter here.
+
+mod common;
+
+use common::spellbook_with_input;
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+const BOOKS: [(&str, &str); 6] = [
+	("examples/99_bottles.spb", ""),
+	("examples/add_two_numbers.spb", "5\n7\n"),
+	("examples/fibonacci.spb", ""),
+	("examples/truth_machine.spb", "0\n"),
+	("examples/your_name.spb", "Diane\n"),
+	("tests/minify/skips.spb", ""),
+];
+
+#[test]
+fn minified_spellbooks_publish_the_same() {
+	let dir = env::temp_dir().join(format!("spellbook-minify-{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+
+	for (path, input) in BOOKS {
+		let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(path);
+		let minified_path = dir.join(path.file_name().unwrap());
+		let (path, minified) = (path.to_str().unwrap(), minified_path.to_str().unwrap());
+
+		let minify = spellbook_with_input(&["minify", path, "-o", minified], "");
+		assert!(minify.status.success(), "{}: couldn't be minified\n{}", path, String::from_utf8_lossy(&minify.stderr));
+		assert!(fs::read_to_string(minified).unwrap().lines().count() < fs::read_to_string(path).unwrap().lines().count(), "{}: didn't get any shorter", path);
+
+		let before = spellbook_with_input(&["run", path], input);
+		let after = spellbook_with_input(&["run", minified], input);
+		assert_eq!(before.status.success(), after.status.success(), "{}: ended differently", path);
+		assert_eq!(String::from_utf8_lossy(&after.stdout), String::from_utf8_lossy(&before.stdout), "{}: published something else", path);
+	}
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn skips_are_recharged_and_comments_dropped() {
+	let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/minify/skips.spb");
+	let minify = spellbook_with_input(&["minify", path.to_str().unwrap()], "");
+	let minified = String::from_utf8_lossy(&minify.stdout);
+	assert_eq!(minified.lines().count(), 3);
+	assert_eq!(minified.matches("cast Diminish on the cauldron").count(), 4);
+	assert!(!minified.contains("CHARGE"));
+}
+
+#[test]
+fn refused_merges_are_reported() {
+	let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/truth_machine.spb");
+	let minify = spellbook_with_input(&["minify", path.to_str().unwrap()], "");
+	let report = String::from_utf8_lossy(&minify.stderr);
+	assert!(report.contains("truth_machine.spb:3: kept on its own line because Reverberate on line 17 can go back to it\n"));
+	assert!(report.contains("truth_machine.spb:12: kept on its own line because line 11 casts Judgement, and the rest of its line would still be read\n"));
+	assert!(report.contains("truth_machine.spb:13: kept on its own line because Judgement on line 11 can land on it\n"));
+}
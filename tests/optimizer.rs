@@ -0,0 +1,57 @@
+// Runs spellbooks with and without the optimizer, which should never change what they
+// publish, and checks what --explain-opt says was changed.
+
+mod common;
+
+use common::spellbook_with_input;
+
+use std::path::Path;
+
+const BOOKS: [(&str, &str); 5] = [
+	("examples/99_bottles.spb", ""),
+	("examples/fibonacci.spb", ""),
+	("examples/truth_machine.spb", "0\n"),
+	("examples/your_name.spb", "Diane\n"),
+	("tests/optimizer/leftovers.spb", ""),
+];
+
+#[test]
+fn optimized_spellbooks_publish_the_same() {
+	for (path, input) in BOOKS {
+		let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(path);
+		let path = path.to_str().unwrap();
+
+		let before = spellbook_with_input(&["run", path], input);
+		let after = spellbook_with_input(&["run", "--optimize", path], input);
+		assert_eq!(before.status.success(), after.status.success(), "{}: ended differently", path);
+		assert_eq!(String::from_utf8_lossy(&after.stdout), String::from_utf8_lossy(&before.stdout), "{}: published something else", path);
+	}
+}
+
+#[test]
+fn every_change_is_explained() {
+	let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/optimizer/leftovers.spb");
+	let run = spellbook_with_input(&["run", "--explain-opt", path.to_str().unwrap()], "");
+	let explained = String::from_utf8_lossy(&run.stderr).lines()
+		.map(|l| l.rsplit("leftovers.spb:").next().unwrap().to_string())
+		.collect::<Vec<String>>();
+
+	assert_eq!(explained, [
+		"10: constant-mixes: its part of the mix is done on line 12 now",
+		"11: constant-mixes: its part of the mix is done on line 12 now",
+		"12: constant-mixes: multiplies the chapters tossed in the cauldron on lines 12 and 16 ahead of time, writing 42 under sixseven, 20 under fourfive instead",
+		"14: constant-mixes: its part of the mix is done on line 12 now",
+		"15: constant-mixes: its part of the mix is done on line 12 now",
+		"16: constant-mixes: its part of the mix is done on line 12 now",
+		"3: dead-stores: removed `write 10 under scratch`, since the Hexes chapter is thrown in the trash before anything reads it",
+		"4: dead-stores: removed `write 20 under more_scratch`, since the Hexes chapter is thrown in the trash before anything reads it",
+		"18: idle-vacancies: removed `cast Vacancy on the cauldron`, which is never reverberated to and whose charge nothing counts on",
+	]);
+}
+
+#[test]
+fn vacancies_that_are_reverberated_to_stay() {
+	let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/truth_machine.spb");
+	let run = spellbook_with_input(&["run", "--explain-opt", path.to_str().unwrap()], "0\n");
+	assert!(!String::from_utf8_lossy(&run.stderr).contains("idle-vacancies"));
+}
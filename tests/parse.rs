@@ -0,0 +1,53 @@
+// Exports a spellbook with every kind of thing on its lines and reads the JSON back, the way
+// an outside tool would.
+
+use serde_json::{json, Value};
+
+use std::path::Path;
+use std::process::Command;
+
+fn export(path: &str) -> Value {
+	let output = Command::new(env!("CARGO_BIN_EXE_spellbook"))
+		.args(["parse", "--json", path])
+		.current_dir(Path::new(env!("CARGO_MANIFEST_DIR")))
+		.output()
+		.unwrap();
+
+	assert!(output.status.success());
+	serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn statements_keep_their_kinds_values_and_spans() {
+	let lines = export("tests/parse/everything.spb")["lines"].as_array().unwrap().clone();
+
+	// The blank line is left out, but the others keep their numbers
+	assert_eq!(lines.iter().map(|l| l["line"].as_u64().unwrap()).collect::<Vec<u64>>(), vec![1, 2, 4, 5, 6, 7, 8, 9]);
+
+	assert_eq!(lines[0]["statements"], json!([{ "kind": "turn_to_chapter", "chapter": "Hexes", "span": { "start": 0, "end": 21 } }]));
+	assert_eq!(lines[0]["comments"], json!([
+		{ "text": "SCRATCH", "span": { "start": 25, "end": 32 } },
+		{ "text": "SPACE", "span": { "start": 33, "end": 38 } },
+	]));
+
+	assert_eq!(lines[1]["statements"], json!([
+		{ "kind": "write", "heading": "half", "source": { "type": "float", "value": 3.5 }, "span": { "start": 0, "end": 20 } },
+		{ "kind": "write", "heading": "never", "source": { "type": "boolean", "value": false }, "span": { "start": 23, "end": 46 } },
+	]));
+
+	assert_eq!(lines[2]["statements"][0]["source"], json!({ "heading": "half" }));
+	assert_eq!(lines[3]["statements"][0]["source"], json!({ "entry": 1 }));
+	assert_eq!(lines[4]["statements"][0]["destination"], json!("drawer"));
+	assert_eq!(lines[5]["statements"][0]["spell"], json!("Judgement"));
+	assert_eq!(lines[6]["statements"][0]["target"], json!("out\n"));
+	assert!(lines[..7].iter().all(|l| l["error"].is_null()));
+}
+
+#[test]
+fn lines_that_cant_be_read_carry_their_error() {
+	let lines = export("tests/parse/everything.spb")["lines"].as_array().unwrap().clone();
+	let last = &lines[7];
+	assert_eq!(last["statements"], json!([]));
+	assert_eq!(last["error"]["span"], json!({ "start": 8, "end": 12 }));
+	assert!(last["error"]["message"].as_str().unwrap().contains("`under`"));
+}
@@ -0,0 +1,61 @@
+// Profiles of the examples. Times change from run to run, so only what can't is checked: the
+// spellbook still publishes the same, every line is counted as often as it was read, and the
+// folded stacks are there for flamegraph tools.
+
+mod common;
+
+use common::spellbook;
+
+use std::env;
+use std::fs;
+
+// How many times each line was read, by line number, leaving out the ones that never were
+fn counts(report: &str) -> Vec<(usize, usize)> {
+	report.lines().filter_map(|line| {
+		let (columns, _) = line.split_once(" | ")?;
+		let columns = columns.split_whitespace().collect::<Vec<_>>();
+		match columns[..] {
+			[count, _, _, _, number] => Some((number.parse().unwrap(), count.parse().unwrap())),
+			_ => None,
+		}
+	}).collect()
+}
+
+#[test]
+fn lines_are_counted_as_often_as_theyre_read() {
+	let output = spellbook(&["profile", "examples/countdown.spb"]);
+	assert!(output.status.success());
+	assert_eq!(String::from_utf8(output.stdout).unwrap(), "5\n4\n3\n2\n1\nLiftoff!\n");
+
+	let report = String::from_utf8(output.stderr).unwrap();
+	let counts = counts(&report);
+	assert_eq!(counts.len(), 24);
+	assert!(counts.contains(&(7, 5)));
+	assert!(counts.contains(&(20, 4)));
+	assert!(counts.contains(&(27, 1)));
+	assert!(report.contains("\nlexing and parsing: "));
+	assert!(report.contains("\nexecuting:          "));
+	assert!(report.contains("\nstring bytes built: 0 by adding, 0 by multiplying, 10 by writing from memory\n"));
+}
+
+#[test]
+fn folded_stacks_end_at_the_line_being_read() {
+	let folded = env::temp_dir().join("spellbook_profile_countdown.folded");
+	let output = spellbook(&["profile", "examples/countdown.spb", "--folded", folded.to_str().unwrap()]);
+	assert!(output.status.success());
+
+	let stacks = fs::read_to_string(&folded).unwrap();
+	fs::remove_file(&folded).unwrap();
+	assert!(stacks.lines().any(|line| line.starts_with("7: publish spellbook ")));
+	for line in stacks.lines() {
+		let (_, nanoseconds) = line.rsplit_once(' ').unwrap();
+		assert!(nanoseconds.parse::<u128>().is_ok(), "{}", line);
+	}
+}
+
+#[test]
+fn catastrophes_still_get_a_profile() {
+	let output = spellbook(&["profile", "fuzz/regressions/execute/divide_by_zero.spb"]);
+	assert_eq!(output.status.code(), Some(1));
+	assert!(String::from_utf8(output.stderr).unwrap().contains("\nexecuting: "));
+}
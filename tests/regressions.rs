@@ -0,0 +1,144 @@
+// Inputs that used to crash the interpreter with a Rust panic. Each one has to end in a
+// catastrophe on the line that went wrong, or not go wrong at all. They live with the fuzz
+// targets, so `cargo fuzz run execute fuzz/regressions/execute` starts from them too.
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+const REGRESSIONS: &str = "fuzz/regressions/execute";
+
+fn run(name: &str) -> Output {
+	Command::new(env!("CARGO_BIN_EXE_spellbook"))
+		.args(["run", &format!("{}/{}", REGRESSIONS, name)])
+		.current_dir(Path::new(env!("CARGO_MANIFEST_DIR")))
+		.output()
+		.unwrap()
+}
+
+// Where the catastrophe happened, whether it was pointed out in the source or only numbered
+fn catastrophe(name: &str) -> usize {
+	let output = run(name);
+	let stderr = String::from_utf8(output.stderr).unwrap();
+	assert!(!stderr.contains("panicked") && !stderr.contains("overflow"), "{}: {}", name, stderr);
+	assert!(stderr.contains("Catastrophe!"), "{}: {}", name, stderr);
+	assert_eq!(output.status.code(), Some(101), "{}: {}", name, stderr);
+
+	let pointed = format!(" --> {}/{}:", REGRESSIONS, name);
+	stderr.lines()
+		.find_map(|l| l.strip_prefix("(Line ").or_else(|| l.strip_prefix(&pointed)))
+		.and_then(|l| l.split([')', ':']).next())
+		.and_then(|n| n.parse().ok())
+		.unwrap_or_else(|| panic!("{}: the catastrophe doesn't say where it happened\n{}", name, stderr))
+}
+
+fn published(name: &str) -> String {
+	let output = run(name);
+	let stderr = String::from_utf8(output.stderr).unwrap();
+	assert!(output.status.success(), "{}: {}", name, stderr);
+	String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn trailing_backslash() {
+	assert_eq!(catastrophe("trailing_backslash.spb"), 2);
+}
+
+#[test]
+fn lone_quote() {
+	assert_eq!(catastrophe("lone_quote.spb"), 2);
+}
+
+#[test]
+fn taking_more_characters_than_a_string_has() {
+	assert_eq!(catastrophe("short_string.spb"), 7);
+}
+
+#[test]
+fn taking_characters_off_multibyte_text() {
+	// Characters are taken off, not bytes
+	assert_eq!(published("multibyte_string.spb"), "héllo wö\n");
+}
+
+#[test]
+fn forgetting_the_first_word_again() {
+	// Every word is the same, so dropping any of them finds the first
+	let forgotten = published("forgotten_first_word.spb");
+	assert!(forgotten.split_whitespace().all(|w| w == "x" || w == "something"), "{}", forgotten);
+}
+
+#[test]
+fn adding_past_the_biggest_integer() {
+	assert_eq!(catastrophe("overflowing_add.spb"), 5);
+}
+
+#[test]
+fn multiplying_past_the_biggest_integer() {
+	assert_eq!(catastrophe("overflowing_mul.spb"), 6);
+}
+
+#[test]
+fn negating_the_smallest_integer() {
+	assert_eq!(catastrophe("negated_minimum.spb"), 7);
+}
+
+#[test]
+fn dividing_by_zero() {
+	assert_eq!(catastrophe("divide_by_zero.spb"), 6);
+}
+
+#[test]
+fn reverberating_past_the_first_spell() {
+	assert_eq!(catastrophe("reverberate_too_far.spb"), 5);
+}
+
+#[test]
+fn reverberating_before_the_first_line() {
+	assert_eq!(catastrophe("reverberate_to_the_first_line.spb"), 3);
+}
+
+#[test]
+fn writing_past_the_entries_put_back() {
+	// The put back page's two entries count as written, so there's no third to write in
+	assert_eq!(catastrophe("write_after_put_back.spb"), 7);
+}
+
+#[test]
+fn writing_in_a_page_that_was_put_back_full() {
+	assert_eq!(catastrophe("overflowing_page.spb"), 8);
+}
+
+#[test]
+fn putting_back_a_page_that_cant_be_converted() {
+	assert_eq!(catastrophe("bad_conversion.spb"), 5);
+}
+
+#[test]
+fn repeating_a_string_past_what_fits_in_memory() {
+	assert_eq!(catastrophe("oversized_string.spb"), 7);
+}
+
+#[test]
+fn lines_that_arent_utf8_are_left_out() {
+	assert_eq!(published("invalid_utf8.spb"), "ok\n");
+
+	let output = Command::new(env!("CARGO_BIN_EXE_spellbook"))
+		.args(["check", &format!("{}/invalid_utf8.spb", REGRESSIONS)])
+		.current_dir(Path::new(env!("CARGO_MANIFEST_DIR")))
+		.output()
+		.unwrap();
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn every_regression_is_tested() {
+	let mut names = fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join(REGRESSIONS)).unwrap()
+		.map(|e| e.unwrap().file_name().into_string().unwrap())
+		.collect::<Vec<String>>();
+	names.sort();
+
+	let source = include_str!("regressions.rs");
+	for name in names {
+		assert!(source.contains(&format!("\"{}\"", name)), "{} isn't run by any test", name);
+	}
+}
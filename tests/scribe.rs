@@ -0,0 +1,51 @@
+// Writes scribe programs out as spellbooks, then runs them through the interpreter and the
+// checker. The programs' own output is all that matters here, not how the spellbooks look.
+
+mod common;
+
+use common::spellbook_with_input;
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+const PROGRAMS: [(&str, &str, &str); 6] = [
+	("examples/factorial.scribe", "10\n", "The factorial of 10 is 3628800\n"),
+	("examples/factorial.scribe", "-2\n", "There's no factorial of -2\n"),
+	("examples/fizzbuzz.scribe", "", "1\n2\nFizz\n4\nBuzz\nFizz\n7\n8\nFizz\nBuzz\n11\nFizz\n13\n14\nFizzBuzz\n"),
+	("tests/scribe/full_desk.scribe", "", "11 20 100 -1000 10000 12469135689\n"),
+	("tests/scribe/odds_and_ends.scribe", "not a number\n3\n7\n", "11 6\n120\nzero\none 4\ntwo 7 2\n3 < 7\nle\nne\n-3 20\n"),
+	("tests/scribe/tables.scribe", "", "1 x 1 = 1\n1 x 2 = 2\n1 x 3 = 3\n2 x 1 = 2\n2 x 2 = 4\n2 x 3 = 6\n3 x 1 = 3\n3 x 2 = 6\n3 x 3 = 9\n4 -3\n1234567890120\n"),
+];
+
+#[test]
+fn scribed_spellbooks_run() {
+	let dir = env::temp_dir().join(format!("spellbook-scribe-{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+
+	for (path, input, expected) in PROGRAMS {
+		let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(path);
+		let book_path = dir.join(path.file_stem().unwrap()).with_extension("spb");
+		let (path, book) = (path.to_str().unwrap(), book_path.to_str().unwrap());
+
+		let scribed = spellbook_with_input(&["scribe", path, "-o", book], "");
+		assert!(scribed.status.success(), "{}: couldn't be written out\n{}", path, String::from_utf8_lossy(&scribed.stderr));
+
+		let checked = spellbook_with_input(&["check", book], "");
+		assert!(checked.status.success(), "{}: the checker found problems\n{}", path, String::from_utf8_lossy(&checked.stdout));
+
+		let run = spellbook_with_input(&["run", book], input);
+		assert!(run.status.success(), "{}: had a catastrophe", path);
+		assert_eq!(String::from_utf8_lossy(&run.stdout), expected, "{}: published something else", path);
+	}
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn too_much_for_the_desk_is_refused() {
+	let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scribe/overfull_desk.scribe");
+	let scribed = spellbook_with_input(&["scribe", path.to_str().unwrap()], "");
+	assert!(!scribed.status.success());
+	assert!(String::from_utf8_lossy(&scribed.stderr).ends_with("overfull_desk.scribe:5:1: there's no room on the desk for `e`: only 6 numbers fit at once\n"));
+}
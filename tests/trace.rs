@@ -0,0 +1,101 @@
+// Traces of spellbooks being read, checking that every way an entry gets written shows up in
+// the jsonl records and that the diff trace only shows what changed.
+
+use serde_json::{json, Value};
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+fn trace(mode: &str, path: &str, input: &str) -> String {
+	let mut child = Command::new(env!("CARGO_BIN_EXE_spellbook"))
+		.args(["run", &format!("--trace={}", mode), path])
+		.current_dir(Path::new(env!("CARGO_MANIFEST_DIR")))
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()
+		.unwrap();
+
+	child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+	String::from_utf8(child.wait_with_output().unwrap().stderr).unwrap()
+}
+
+// What each line wrote, as (line, chapter, heading, value)
+fn written(path: &str, input: &str) -> Vec<(u64, String, String, Value)> {
+	let mut written = vec![];
+	for record in trace("jsonl", path, input).lines().map(|line| serde_json::from_str::<Value>(line).unwrap()) {
+		for entry in record["effects"]["written"].as_array().into_iter().flatten() {
+			written.push((record["line"].as_u64().unwrap(), entry["chapter"].as_str().unwrap().into(), entry["heading"].as_str().unwrap().into(), entry["value"]["value"].clone()));
+		}
+	}
+
+	written
+}
+
+#[test]
+fn writes_and_put_backs_are_written() {
+	assert_eq!(written("tests/trace/written.spb", ""), [
+		(2, "Hexes".into(), "count".into(), json!(2)),
+		(4, "Hexes".into(), "other".into(), json!(9)),
+		(5, "Hexes".into(), "count".into(), json!(2)),
+	]);
+}
+
+#[test]
+fn coadjuvancy_writes_into_the_cauldron() {
+	assert_eq!(written("examples/add_two_numbers.spb", "3\n4\n"), [
+		(3, "cauldron".into(), "".into(), json!(3.0)),
+		(4, "Illusions".into(), "".into(), json!(3.0)),
+		(7, "cauldron".into(), "".into(), json!(4.0)),
+		(8, "Illusions".into(), "".into(), json!(3.0)),
+		(10, "Illusions".into(), "".into(), json!(7.0)),
+	]);
+}
+
+#[test]
+fn conversions_are_told_about() {
+	let records = trace("jsonl", "tests/trace/written.spb", "");
+	let write = serde_json::from_str::<Value>(records.lines().nth(1).unwrap()).unwrap();
+	assert_eq!(write["effects"]["conversion"], json!({ "from": { "type": "float", "value": 2.5 }, "to": { "type": "integer", "value": 2 } }));
+}
+
+#[test]
+fn catastrophes_end_the_trace() {
+	let records = trace("jsonl", "fuzz/regressions/execute/divide_by_zero.spb", "");
+	assert_eq!(records.lines().last().unwrap(), r#"{"catastrophe":true,"line":6}"#);
+}
+
+#[test]
+fn diffs_only_show_what_changed() {
+	assert_eq!(trace("diff", "tests/trace/written.spb", ""), concat!(
+		"   1 | turn to chapter Hexes\n",
+		"       turned to Hexes\n",
+		"   2 | write 2.5 under count\n",
+		"       Hexes[0] count: — → 2\n",
+		"   3 | tear out chapter and put it in the drawer\n",
+		"       Hexes[0] count: 2 → (torn out)\n",
+		"       drawer depth 0 → 1\n",
+		"   4 | write 9 under other\n",
+		"       Hexes[0] other: — → 9\n",
+		"   5 | take out a chapter from the drawer and put it back\n",
+		"       Hexes[0] other → count: 9 → 2\n",
+		"       drawer depth 1 → 0\n",
+	));
+}
+
+#[test]
+fn diffs_follow_the_cauldron() {
+	let diff = trace("diff", "examples/add_two_numbers.spb", "3\n4\n");
+	let lines = diff.lines().collect::<Vec<_>>();
+	assert_eq!(&lines[2..9], [
+		"   2 | tear out chapter and toss it in the cauldron",
+		"       cauldron: Illusions page tossed in → [—, —, —]",
+		"   3 | cast Coadjuvancy on the cauldron",
+		"       cauldron: [—, —, —] → [3.0, —, —]",
+		"       charge 0 → 1",
+		"   4 | take out chapter from the cauldron and put it back",
+		"       Illusions[0]: — → 3.0",
+	]);
+	assert!(diff.contains("       cauldron: Illusions page mixed with Entwinement → [7.0, —, —]\n"));
+}
@@ -0,0 +1,27 @@
+// Drives the editor servers with scripted conversations. Each transcript is a list of
+// messages, one per line: `-->` for what the editor sends and `<--` for what the server
+// should answer, in order. Objects in answers only need to have the fields written down.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+fn run_transcripts(subcommand: &str) {
+	let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/transcripts").join(subcommand);
+	let mut paths = fs::read_dir(directory).unwrap().map(|e| e.unwrap().path()).collect::<Vec<_>>();
+	paths.sort();
+	for path in paths.iter().filter(|p| p.extension().is_some_and(|e| e == "txt")) {
+		common::run_transcript(subcommand, path);
+	}
+}
+
+#[test]
+fn lsp_transcripts() {
+	run_transcripts("lsp");
+}
+
+#[test]
+fn dap_transcripts() {
+	run_transcripts("dap");
+}
@@ -0,0 +1,102 @@
+// Draws the desk of a spellbook that's been read to the end, checking everything on it shows up
+// where someone watching would look for it.
+
+use spellbook::interpreter::Interpreter;
+use spellbook::program::Program;
+use spellbook::syntax;
+use spellbook::visualizer;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// What ends up on the screen once the cursor moves are followed, leaving out the colours
+fn screen(drawn: &str, width: usize, height: usize) -> Vec<String> {
+	let mut grid = vec![vec![' '; width]; height];
+	let (mut row, mut column) = (0, 0);
+	let mut chars = drawn.chars();
+	while let Some(c) = chars.next() {
+		if c != '\x1b' {
+			grid[row][column] = c;
+			column += 1;
+			continue;
+		}
+
+		let mut sequence = String::new();
+		for c in chars.by_ref() {
+			if c.is_ascii_alphabetic() {
+				if c == 'H' {
+					let (r, c) = sequence.trim_start_matches('[').split_once(';').unwrap();
+					(row, column) = (r.parse::<usize>().unwrap() - 1, c.parse::<usize>().unwrap() - 1);
+				}
+
+				break;
+			}
+
+			sequence.push(c);
+		}
+	}
+
+	grid.into_iter().map(|r| r.into_iter().collect::<String>().trim_end().to_string()).collect()
+}
+
+fn desk(path: &str, published: &str, width: u16, height: u16) -> Vec<String> {
+	let source = fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join(path)).unwrap();
+	let mut program = Program::new(false);
+	program.forgetful = false;
+	program.input = Box::new(io::empty());
+	program.output = Box::new(io::sink());
+
+	let mut interpreter = Interpreter::new(syntax::parse_source(&source), program);
+	while !interpreter.is_finished() {
+		interpreter.step_statement();
+	}
+
+	let drawn = visualizer::render_desk(interpreter, published, width, height).unwrap();
+	screen(&drawn, width as usize, height as usize)
+}
+
+#[test]
+fn everything_on_the_desk_is_drawn() {
+	assert_eq!(desk("tests/visualizer/desk.spb", "hello\nworld\n", 48, 26), vec![
+		"┌─ Presages ───────────┐┌─ Hexes ◂ ────────────┐",
+		"│ ·                    ││ one: 1               │",
+		"│ ·                    ││ ·                    │",
+		"│ ·                    ││ ·                    │",
+		"└──────────────────────┘└──────────────────────┘",
+		"┌─ Illusions ──────────┐┌─ Incantations ───────┐",
+		"│ ·                    ││ ·                    │",
+		"│ ·                    ││ ·                    │",
+		"│ ·                    ││ ·                    │",
+		"└──────────────────────┘└──────────────────────┘",
+		"┌─ cauldron ───────────┐┌─ drawer (1) ─────────┐",
+		"│ Incantations page    ││ ▸ Illusions [5, —, — │",
+		"│ familiar: frog       ││                      │",
+		"│ ·                    ││                      │",
+		"│ ·                    ││                      │",
+		"│ ▰▰▱▱▱▱▱▱▱▱ 2 ×1 · En ││                      │",
+		"└──────────────────────┘└──────────────────────┘",
+		"╭─ memory ─────────────────────────────────────╮",
+		"│ frog                                         │",
+		"╰──────────────────────────────────────────────╯",
+		"┌─ published ──────────────────────────────────┐",
+		"│ hello                                        │",
+		"│ world                                        │",
+		"│                                              │",
+		"│                                              │",
+		"└──────────────────────────────────────────────┘",
+	]);
+}
+
+#[test]
+fn only_the_end_of_what_was_published_fits() {
+	let published = (1..=10).map(|i| format!("line {}\n", i)).collect::<String>();
+	let desk = desk("tests/visualizer/desk.spb", &published, 48, 25);
+	assert_eq!(desk[20..], [
+		"┌─ published ──────────────────────────────────┐",
+		"│ line 8                                       │",
+		"│ line 9                                       │",
+		"│ line 10                                      │",
+		"└──────────────────────────────────────────────┘",
+	]);
+}